@@ -1,13 +1,21 @@
 // use std::fs::create_dir;
 use clap::Parser;
 use clap::Subcommand;
+use mors_common::lock::DBLockGuardBuilder;
+use mors_encrypt::cipher::AesCipher;
+use mors_encrypt::registry::{rotate_master_key, MorsKmsBuilder};
 use mors_levelctl::manifest::error::ManifestError;
 use mors_levelctl::manifest::ManifestBuilder;
-use mors_traits::default::DEFAULT_DIR;
-// use tabled::builder::Builder;
+use mors_sstable::table::TableBuilder;
+use mors_traits::default::{WithDir, WithReadOnly, DEFAULT_DIR};
+use mors_traits::kms::{EncryptionAlgo, Kms, KmsBuilder};
+use mors_traits::sstable::{TableBuilderTrait, TableTrait};
+use tabled::builder::Builder;
 // use clap::ValueEnum;
 
+use std::error::Error;
 use std::path::PathBuf;
+use std::process::ExitCode;
 // use morsdb::MorsBuilder;
 
 #[derive(Parser)]
@@ -40,24 +48,65 @@ enum Commands {
         #[arg(short, long, default_value = DEFAULT_DIR)]
         dir: PathBuf,
     },
+    /// Print the properties of a plaintext table
+    PrintTableProperties {
+        #[arg(short, long, default_value = DEFAULT_DIR)]
+        dir: PathBuf,
+        /// Id of the table, as in the manifest
+        #[arg(long)]
+        id: u32,
+    },
+    /// Re-encrypt the key registry with a new master key, the db must be closed
+    RotateMasterKey {
+        #[arg(short, long, default_value = DEFAULT_DIR)]
+        dir: PathBuf,
+        /// File holding the current master key, empty if not encrypted
+        #[arg(long)]
+        old_key_file: PathBuf,
+        /// File holding the new 16 or 32 bytes master key
+        #[arg(long)]
+        new_key_file: PathBuf,
+        /// Start a new data key with this algorithm: aes-gcm, aes-gcm-siv
+        /// or chacha20-poly1305
+        #[arg(long)]
+        encryption_algo: Option<EncryptionAlgo>,
+    },
 }
 // #[derive(Subcommand, Clone)]
 // enum TestSubCmd {
 //     Test,
 // }
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
-    if let Some(command) = cli.command {
-        match command {
-            Commands::PrintManifest { dir } => {
-                match handle_print_manifest(dir).await {
-                    Ok(_) => {}
-                    Err(e) => {
-                        eprint!("{}", e);
-                    }
-                };
-            }
+    let Some(command) = cli.command else {
+        return ExitCode::SUCCESS;
+    };
+    let result: Result<(), Box<dyn Error>> = match command {
+        Commands::PrintManifest { dir } => {
+            handle_print_manifest(dir).await.map_err(Into::into)
+        }
+        Commands::PrintTableProperties { dir, id } => {
+            handle_print_table_properties(dir, id).await
+        }
+        Commands::RotateMasterKey {
+            dir,
+            old_key_file,
+            new_key_file,
+            encryption_algo,
+        } => handle_rotate_master_key(
+            dir,
+            old_key_file,
+            new_key_file,
+            encryption_algo,
+        )
+        .map(|_| println!("master key rotated")),
+    };
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
         }
     }
 }
@@ -71,6 +120,76 @@ async fn handle_print_manifest(dir: PathBuf) -> Result<(), ManifestError> {
     println!("{}", info);
     Ok(())
 }
+async fn handle_print_table_properties(
+    dir: PathBuf,
+    id: u32,
+) -> Result<(), Box<dyn Error>> {
+    let mut builder = ManifestBuilder::default();
+    builder.set_dir(dir.clone());
+    builder.set_read_only(true);
+    let manifest = builder.build()?;
+    let options = manifest.lock().await.info().table_options(id.into());
+    let (compression, key_id) =
+        options.ok_or(format!("table {} is not in the manifest", id))?;
+    if key_id.is_some() {
+        return Err(format!("table {} is encrypted", id).into());
+    }
+    let mut table_builder = TableBuilder::<AesCipher>::default();
+    table_builder
+        .set_compression(compression)
+        .set_dir(dir)
+        .set_read_only(true);
+    let table = table_builder
+        .open(id.into(), None)
+        .await?
+        .ok_or(format!("table {} is empty", id))?;
+    let properties = table.properties();
+    let mut builder = Builder::default();
+    builder.push_record(["Property", "Value"]);
+    for (name, value) in [
+        ("num_deletions", properties.num_deletions.to_string()),
+        (
+            "num_merge_entries",
+            properties.num_merge_entries.to_string(),
+        ),
+        ("min_version", properties.min_version.to_string()),
+        ("max_version", properties.max_version.to_string()),
+        (
+            "min_expires_at",
+            u64::from(properties.min_expires_at).to_string(),
+        ),
+        (
+            "max_expires_at",
+            u64::from(properties.max_expires_at).to_string(),
+        ),
+    ] {
+        builder.push_record([name.to_string(), value]);
+    }
+    for (name, value) in properties.user_properties.iter() {
+        builder.push_record([name.clone(), format!("{:?}", value)]);
+    }
+    println!("{}", builder.build());
+    Ok(())
+}
+fn handle_rotate_master_key(
+    dir: PathBuf,
+    old_key_file: PathBuf,
+    new_key_file: PathBuf,
+    encryption_algo: Option<EncryptionAlgo>,
+) -> Result<(), Box<dyn Error>> {
+    let old = std::fs::read(old_key_file)?;
+    let new = std::fs::read(new_key_file)?;
+    rotate_master_key(&dir, &old, &new)?;
+    if let Some(algo) = encryption_algo {
+        let _lock_guard =
+            DBLockGuardBuilder::new().add_dir(dir.clone()).build()?;
+        // data keys of another algorithm are replaced when asked for.
+        let mut builder = MorsKmsBuilder::new(new);
+        builder.set_dir(dir).set_encryption_algo(algo);
+        builder.build()?.latest_cipher()?;
+    }
+    Ok(())
+}
 
 #[test]
 fn test_tabled() {
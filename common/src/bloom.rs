@@ -78,7 +78,7 @@ impl Bloom {
         BloomBorrow(self).may_contain(hash)
     }
     #[inline]
-    pub(crate) fn bits_per_key(false_positive_rate: f64) -> u32 {
+    pub fn bits_per_key(false_positive_rate: f64) -> u32 {
         (-1.0 * false_positive_rate.log2()).ceil().max(0.0) as u32
     }
 
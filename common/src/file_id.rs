@@ -119,3 +119,28 @@ impl Add<u32> for VlogId {
         VlogId(self.0 + rhs)
     }
 }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct BlobFileId(u32);
+impl From<u32> for BlobFileId {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+impl From<BlobFileId> for u32 {
+    fn from(val: BlobFileId) -> Self {
+        val.0
+    }
+}
+impl From<SSTableId> for BlobFileId {
+    fn from(value: SSTableId) -> Self {
+        Self(value.0)
+    }
+}
+impl FileId for BlobFileId {
+    const SUFFIX: &'static str = ".blob";
+}
+impl Display for BlobFileId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:06}.blob", self.0)
+    }
+}
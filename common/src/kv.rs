@@ -8,12 +8,17 @@ use crate::{
 };
 
 pub trait Key {}
+/// id of a column family, every entry belongs to exactly one family.
+pub type ColumnFamilyId = u32;
+/// the family used when no column family is given.
+pub const DEFAULT_COLUMN_FAMILY: ColumnFamilyId = 0;
 #[derive(Debug, Default, Clone)]
 pub struct Entry {
     key_ts: KeyTs,
     value_meta: ValueMeta,
     offset: usize,
     value_threshold: usize,
+    column_family: ColumnFamilyId,
 }
 impl From<(KeyTs, ValueMeta)> for Entry {
     fn from(value: (KeyTs, ValueMeta)) -> Self {
@@ -22,6 +27,7 @@ impl From<(KeyTs, ValueMeta)> for Entry {
             value_meta: value.1,
             offset: Default::default(),
             value_threshold: Default::default(),
+            column_family: DEFAULT_COLUMN_FAMILY,
         }
     }
 }
@@ -39,6 +45,7 @@ impl Entry {
             offset: 0,
             value_meta,
             value_threshold: 0,
+            column_family: DEFAULT_COLUMN_FAMILY,
         }
     }
     #[inline]
@@ -55,6 +62,7 @@ impl Entry {
             offset,
             value_meta,
             value_threshold: 0,
+            column_family: DEFAULT_COLUMN_FAMILY,
         }
     }
     pub fn key_ts(&self) -> &KeyTs {
@@ -127,6 +135,13 @@ impl Entry {
         self.value_threshold = value_threshold;
         self
     }
+    pub fn column_family(&self) -> ColumnFamilyId {
+        self.column_family
+    }
+    pub fn set_column_family(&mut self, cf: ColumnFamilyId) -> &mut Self {
+        self.column_family = cf;
+        self
+    }
     pub fn estimate_size(&self, threshold: usize) -> usize {
         if self.value().len() < threshold {
             self.key_ts().key().len() + self.value().len() + 2
@@ -270,6 +285,12 @@ bitflags::bitflags! {
         const VALUE_POINTER = 1 << 1;
         const DISCARD_EARLIER_VERSIONS = 1 << 2;
         const MERGE_ENTRY=1<<3;
+        const RANGE_DELETE=1<<4;
+        /// only set in wal headers, which then carry a column family id.
+        const COLUMN_FAMILY=1<<5;
+        /// only set in tables, whose value is then a [`ValuePointer`] into
+        /// a blob file. It shares its bit with COLUMN_FAMILY.
+        const BLOB_INDEX=1<<5;
         const TXN=1<<6;
         const FIN_TXN=1<<7;
     }
@@ -284,3 +305,136 @@ impl std::fmt::Display for Meta {
         bitflags::parser::to_writer(self, f)
     }
 }
+
+/// A range tombstone deletes every version older than `version` of the keys in `[start, end)`.
+/// It travels through the write path as an entry keyed by `start` whose value is `end`,
+/// with `Meta::DELETE | Meta::RANGE_DELETE` set.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RangeTombstone {
+    start: Bytes,
+    end: Bytes,
+    version: TxnTs,
+}
+impl RangeTombstone {
+    pub fn new(start: Bytes, end: Bytes, version: TxnTs) -> Self {
+        Self {
+            start,
+            end,
+            version,
+        }
+    }
+    pub fn start(&self) -> &Bytes {
+        &self.start
+    }
+    pub fn end(&self) -> &Bytes {
+        &self.end
+    }
+    pub fn version(&self) -> TxnTs {
+        self.version
+    }
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.start.as_ref() <= key && key < self.end.as_ref()
+    }
+    /// whether `key` at version `txn_ts` is deleted by this tombstone.
+    pub fn covers(&self, key: &[u8], txn_ts: TxnTs) -> bool {
+        txn_ts < self.version && self.contains(key)
+    }
+    /// the newest tombstone version visible at `key.txn_ts()` that contains `key.key()`.
+    pub fn max_covering<'a, I: IntoIterator<Item = &'a Self>>(
+        tombstones: I,
+        key: &KeyTs,
+    ) -> Option<TxnTs> {
+        tombstones
+            .into_iter()
+            .filter(|t| t.version <= key.txn_ts() && t.contains(key.key()))
+            .map(|t| t.version)
+            .max()
+    }
+    /// the delete marker returned to readers for a key covered by a tombstone.
+    pub fn value_meta() -> ValueMeta {
+        ValueMeta {
+            meta: Meta::DELETE | Meta::RANGE_DELETE,
+            ..Default::default()
+        }
+    }
+    pub fn to_entry(&self) -> Entry {
+        let mut entry = Entry::new(self.start.clone(), self.end.clone());
+        entry.set_version(self.version);
+        entry.set_meta(Meta::DELETE | Meta::RANGE_DELETE);
+        entry
+    }
+    pub fn from_entry(entry: &Entry) -> Option<Self> {
+        entry.meta().contains(Meta::RANGE_DELETE).then(|| Self {
+            start: entry.key().clone(),
+            end: entry.value().clone(),
+            version: entry.version(),
+        })
+    }
+    // +-----------+-------+---------+-----+---------+
+    // | start len | start | end len | end | version |
+    // +-----------+-------+---------+-----+---------+
+    pub fn encode_slice(tombstones: &[Self]) -> Vec<u8> {
+        let mut v = Vec::new();
+        for t in tombstones {
+            v.put_slice(&(t.start.len() as u32).encode_var_vec());
+            v.put_slice(&t.start);
+            v.put_slice(&(t.end.len() as u32).encode_var_vec());
+            v.put_slice(&t.end);
+            v.put_u64(t.version.to_u64());
+        }
+        v
+    }
+    pub fn decode_slice(mut data: &[u8]) -> Option<Vec<Self>> {
+        let mut out = Vec::new();
+        while !data.is_empty() {
+            let (start_len, n) = u32::decode_var(data)?;
+            data = data.get(n..)?;
+            let start = Bytes::copy_from_slice(data.get(..start_len as usize)?);
+            data = &data[start_len as usize..];
+            let (end_len, n) = u32::decode_var(data)?;
+            data = data.get(n..)?;
+            let end = Bytes::copy_from_slice(data.get(..end_len as usize)?);
+            data = &data[end_len as usize..];
+            if data.len() < 8 {
+                return None;
+            }
+            let version = data.get_u64().into();
+            out.push(Self {
+                start,
+                end,
+                version,
+            });
+        }
+        Some(out)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::RangeTombstone;
+    use crate::ts::KeyTs;
+
+    #[test]
+    fn test_range_tombstone() {
+        let tombstones = vec![
+            RangeTombstone::new("b".into(), "d".into(), 10.into()),
+            RangeTombstone::new("c".into(), "z".into(), 5.into()),
+        ];
+        let encoded = RangeTombstone::encode_slice(&tombstones);
+        let decoded = RangeTombstone::decode_slice(&encoded).unwrap();
+        assert_eq!(decoded, tombstones);
+
+        let max = |k: &'static str, ts: u64| {
+            RangeTombstone::max_covering(
+                &tombstones,
+                &KeyTs::new(k.into(), ts.into()),
+            )
+        };
+        assert_eq!(max("a", 20), None);
+        assert_eq!(max("c", 20), Some(10.into()));
+        assert_eq!(max("c", 7), Some(5.into()));
+        assert_eq!(max("d", 20), Some(5.into()));
+        assert_eq!(max("z", 20), None);
+        assert!(tombstones[0].covers(b"b", 9.into()));
+        assert!(!tombstones[0].covers(b"b", 10.into()));
+    }
+}
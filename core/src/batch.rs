@@ -0,0 +1,277 @@
+use std::mem::take;
+
+use bytes::Bytes;
+use log::error;
+use mors_common::{kv::Entry, ts::TxnTs};
+use mors_encrypt::{cipher::AesCipher, registry::MorsKms};
+use mors_skip_list::any::AnyList;
+use mors_sstable::table::Table;
+use tokio::{runtime::Handle, sync::oneshot};
+
+use crate::core::Core;
+use crate::error::MorsError;
+use crate::{
+    ColumnFamily, KvEntry, Mors, MorsLevelCtl, MorsMemtable, MorsVlog, Result,
+};
+
+type BatchCore = Core<
+    MorsMemtable,
+    MorsKms,
+    MorsLevelCtl,
+    Table<AesCipher>,
+    AnyList,
+    MorsVlog,
+>;
+
+/// writes many entries without a transaction, for bulk loading.
+///
+/// The entries are sent to the write channel as they come, in write
+/// requests of at most the memtable batch size. Every write request is
+/// a commit of its own with its own commit timestamp, readers see it once
+/// it is applied, before [`Self::commit`], and the commits of transactions
+/// do not wait for the batch.
+///
+/// The batch is not atomic: readers may see part of it, a failed write
+/// request does not undo the others and a crash may keep some of its write
+/// requests only. It is not checked for conflicts either, a key should be
+/// written once per batch.
+pub struct WriteBatch {
+    core: BatchCore,
+    // the commit timestamp of the last write request sent.
+    last_ts: Option<TxnTs>,
+    entries: Vec<Entry>,
+    size: usize,
+    // the number of write requests of the batch so far.
+    requests: usize,
+    // results of the write requests sent, with their index.
+    pending: Vec<(usize, oneshot::Receiver<Result<()>>)>,
+    errors: Vec<(usize, MorsError)>,
+    // runs the sync api and publishes the write requests.
+    handle: Handle,
+}
+impl WriteBatch {
+    fn new(core: BatchCore, handle: Handle) -> Self {
+        Self {
+            core,
+            last_ts: None,
+            entries: Vec::new(),
+            size: 0,
+            requests: 0,
+            pending: Vec::new(),
+            errors: Vec::new(),
+            handle,
+        }
+    }
+    async fn push(&mut self, mut entry: Entry) -> Result<()> {
+        let inner = self.core.inner();
+        inner.prepare_entry(&mut entry)?;
+        let size = entry.estimate_size(entry.value_threshold());
+        let (max_batch_count, max_batch_size) = inner.max_batch();
+        if !self.entries.is_empty()
+            && (self.entries.len() + 1 >= max_batch_count
+                || self.size + size >= max_batch_size)
+        {
+            self.send().await;
+        }
+        self.entries.push(entry);
+        self.size += size;
+        Ok(())
+    }
+    // hands the buffered entries to the write channel as one request.
+    async fn send(&mut self) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let entries = take(&mut self.entries);
+        self.size = 0;
+        let index = self.requests;
+        self.requests += 1;
+        match self.send_request(entries).await {
+            Ok((commit_ts, receiver)) => {
+                self.last_ts = Some(commit_ts);
+                self.pending.push((index, receiver));
+            }
+            Err(e) => self.errors.push((index, e)),
+        }
+    }
+    // commits `entries` at a timestamp of their own, which is published
+    // once they are applied, even if that failed, as the commits after it
+    // wait for it.
+    async fn send_request(
+        &self,
+        mut entries: Vec<Entry>,
+    ) -> Result<(TxnTs, oneshot::Receiver<Result<()>>)> {
+        let inner = self.core.inner();
+        let txn_manager = inner.txn_manager().clone();
+        let commit_ts = txn_manager.generate_ts().await?;
+        for entry in entries.iter_mut() {
+            entry.set_version(commit_ts);
+        }
+        let written = match inner.send_to_write_channel(entries).await {
+            Ok(written) => written,
+            Err(e) => {
+                txn_manager.done_commit(commit_ts).await?;
+                return Err(e);
+            }
+        };
+        let (sender, receiver) = oneshot::channel();
+        self.handle.spawn(async move {
+            let result = written
+                .await
+                .unwrap_or_else(|e| Err(MorsError::RecvError(e.to_string())));
+            let published = txn_manager.done_commit(commit_ts).await;
+            if let Err(e) = &published {
+                error!("write batch {} error:{}", commit_ts, e);
+            }
+            let _ = sender.send(result.and(published.map_err(Into::into)));
+        });
+        Ok((commit_ts, receiver))
+    }
+    async fn commit_impl(&mut self) -> Result<()> {
+        self.send().await;
+        self.requests = 0;
+        let pending = take(&mut self.pending);
+        let mut errors = take(&mut self.errors);
+        for (index, receiver) in pending {
+            match receiver.await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => errors.push((index, e)),
+                Err(e) => {
+                    errors.push((index, MorsError::RecvError(e.to_string())))
+                }
+            }
+        }
+        if let Some(last_ts) = self.last_ts.take() {
+            self.core
+                .inner()
+                .txn_manager()
+                .wait_published(last_ts)
+                .await?;
+        }
+        if errors.is_empty() {
+            return Ok(());
+        }
+        errors.sort_by_key(|(i, _)| *i);
+        Err(MorsError::WriteBatchError(errors))
+    }
+}
+impl WriteBatch {
+    fn kv_entry(
+        cf: &ColumnFamily,
+        key: Bytes,
+        value: Bytes,
+        delete: bool,
+    ) -> Entry {
+        let mut entry = KvEntry::new(key, value);
+        if delete {
+            entry.set_delete();
+        }
+        let mut entry = entry.entry;
+        entry.set_column_family(cf.id());
+        entry
+    }
+    #[cfg(not(feature = "sync"))]
+    pub async fn set(&mut self, key: Bytes, value: Bytes) -> Result<()> {
+        self.set_cf(&ColumnFamily::default_family(), key, value)
+            .await
+    }
+    #[cfg(feature = "sync")]
+    pub fn set(&mut self, key: Bytes, value: Bytes) -> Result<()> {
+        self.set_cf(&ColumnFamily::default_family(), key, value)
+    }
+    #[cfg(not(feature = "sync"))]
+    pub async fn set_entry(&mut self, entry: KvEntry) -> Result<()> {
+        self.set_entry_cf(&ColumnFamily::default_family(), entry)
+            .await
+    }
+    #[cfg(feature = "sync")]
+    pub fn set_entry(&mut self, entry: KvEntry) -> Result<()> {
+        self.set_entry_cf(&ColumnFamily::default_family(), entry)
+    }
+    #[cfg(not(feature = "sync"))]
+    pub async fn delete(&mut self, key: Bytes) -> Result<()> {
+        self.delete_cf(&ColumnFamily::default_family(), key).await
+    }
+    #[cfg(feature = "sync")]
+    pub fn delete(&mut self, key: Bytes) -> Result<()> {
+        self.delete_cf(&ColumnFamily::default_family(), key)
+    }
+    #[cfg(not(feature = "sync"))]
+    pub async fn set_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        key: Bytes,
+        value: Bytes,
+    ) -> Result<()> {
+        self.push(Self::kv_entry(cf, key, value, false)).await
+    }
+    #[cfg(feature = "sync")]
+    pub fn set_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        key: Bytes,
+        value: Bytes,
+    ) -> Result<()> {
+        let handle = self.handle.clone();
+        handle.block_on(self.push(Self::kv_entry(cf, key, value, false)))
+    }
+    #[cfg(not(feature = "sync"))]
+    pub async fn set_entry_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        entry: KvEntry,
+    ) -> Result<()> {
+        let mut entry = entry.entry;
+        entry.set_column_family(cf.id());
+        self.push(entry).await
+    }
+    #[cfg(feature = "sync")]
+    pub fn set_entry_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        entry: KvEntry,
+    ) -> Result<()> {
+        let mut entry = entry.entry;
+        entry.set_column_family(cf.id());
+        let handle = self.handle.clone();
+        handle.block_on(self.push(entry))
+    }
+    #[cfg(not(feature = "sync"))]
+    pub async fn delete_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        key: Bytes,
+    ) -> Result<()> {
+        self.push(Self::kv_entry(cf, key, Bytes::new(), true)).await
+    }
+    #[cfg(feature = "sync")]
+    pub fn delete_cf(&mut self, cf: &ColumnFamily, key: Bytes) -> Result<()> {
+        let handle = self.handle.clone();
+        handle.block_on(self.push(Self::kv_entry(cf, key, Bytes::new(), true)))
+    }
+    /// sends the rest of the batch and returns once every write request is
+    /// visible, the ones which failed are reported.
+    #[cfg(not(feature = "sync"))]
+    pub async fn commit(&mut self) -> Result<()> {
+        self.commit_impl().await
+    }
+    #[cfg(feature = "sync")]
+    pub fn commit(&mut self) -> Result<()> {
+        let handle = self.handle.clone();
+        handle.block_on(self.commit_impl())
+    }
+}
+impl Mors {
+    /// a batch writing to the db without a transaction, see [`WriteBatch`].
+    #[cfg(not(feature = "sync"))]
+    pub fn write_batch(&self) -> WriteBatch {
+        WriteBatch::new(self.inner.core.clone(), Handle::current())
+    }
+    #[cfg(feature = "sync")]
+    pub fn write_batch(&self) -> WriteBatch {
+        WriteBatch::new(
+            self.inner.core.clone(),
+            self.inner.runtime.handle().clone(),
+        )
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, read_dir};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use mors_common::closer::Closer;
+use mors_common::kv::{ColumnFamilyId, DEFAULT_COLUMN_FAMILY};
+use mors_traits::{
+    cache::{CacheBuilder, CacheTrait},
+    default::WithDir,
+    kms::Kms,
+    levelctl::{LevelCtlBuilderTrait, LevelCtlTrait},
+    sstable::{SSTableError, TableTrait},
+    vlog::DiscardTrait,
+};
+
+use crate::Result;
+
+pub const DEFAULT_COLUMN_FAMILY_NAME: &str = "default";
+
+/// Handle of a column family, returned by `create_cf` and `cf`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnFamily {
+    id: ColumnFamilyId,
+    name: Arc<str>,
+}
+impl ColumnFamily {
+    pub(crate) fn new(id: ColumnFamilyId, name: Arc<str>) -> Self {
+        Self { id, name }
+    }
+    pub(crate) fn default_family() -> Self {
+        Self::new(DEFAULT_COLUMN_FAMILY, DEFAULT_COLUMN_FAMILY_NAME.into())
+    }
+    pub fn id(&self) -> ColumnFamilyId {
+        self.id
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+/// The level controller of an opened column family.
+pub(crate) struct ColumnFamilyCtl<L> {
+    pub(crate) name: Arc<str>,
+    pub(crate) levelctl: L,
+    pub(crate) compact_task: Closer,
+}
+/// directory holding the tables of a column family,
+/// the default family keeps using the db directory.
+pub(crate) fn cf_dir(dir: &Path, id: ColumnFamilyId) -> PathBuf {
+    if id == DEFAULT_COLUMN_FAMILY {
+        return dir.to_path_buf();
+    }
+    dir.join(format!("cf-{:06}", id))
+}
+/// Builds the level controller of a column family and starts its compaction.
+pub(crate) async fn open_cf<L, T, K, D>(
+    options: &L::LevelCtlBuilder,
+    dir: &Path,
+    id: ColumnFamilyId,
+    name: Arc<str>,
+    kms: K,
+    discard: D,
+) -> Result<ColumnFamilyCtl<L>>
+where
+    L: LevelCtlTrait<T, K>,
+    T: TableTrait<K::Cipher>,
+    K: Kms,
+    D: DiscardTrait,
+{
+    let mut builder = options.clone();
+    if id != DEFAULT_COLUMN_FAMILY {
+        let dir = cf_dir(dir, id);
+        create_dir_all(&dir)?;
+        builder.set_dir(dir);
+        // table ids are only unique inside a family, so every family gets its own cache.
+        let cache = <T::Cache as CacheTrait>::CacheBuilder::default()
+            .build()
+            .map_err(SSTableError::new)?;
+        builder.set_cache(cache);
+    }
+    let levelctl = builder.build(kms.clone()).await?;
+
+    let compact_task = Closer::new("levectl compact");
+    compact_task.set_joinhandle(tokio::spawn(levelctl.clone().spawn_compact(
+        compact_task.clone(),
+        kms,
+        discard,
+    )));
+    Ok(ColumnFamilyCtl {
+        name,
+        levelctl,
+        compact_task,
+    })
+}
+
+/// directories of column families not in `families`, left by a drop
+/// that was recorded but did not finish removing them.
+pub(crate) fn stale_cf_dirs(
+    dir: &Path,
+    families: &BTreeMap<ColumnFamilyId, Arc<str>>,
+) -> Result<Vec<PathBuf>> {
+    let mut stale = Vec::new();
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(id) = name
+            .to_str()
+            .and_then(|n| n.strip_prefix("cf-"))
+            .and_then(|id| id.parse::<ColumnFamilyId>().ok())
+        else {
+            continue;
+        };
+        if entry.file_type()?.is_dir() && !families.contains_key(&id) {
+            stale.push(entry.path());
+        }
+    }
+    Ok(stale)
+}
+#[cfg(test)]
+mod tests {
+    #[cfg(not(feature = "sync"))]
+    use {
+        crate::MorsBuilder,
+        mors_common::{kv::DEFAULT_COLUMN_FAMILY, ts::KeyTs},
+    };
+
+    #[cfg(not(feature = "sync"))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_column_family() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut builder = MorsBuilder::default();
+        builder.set_dir(dir.path().to_path_buf());
+        let mors = builder.build().await.unwrap();
+
+        let cf = mors.create_cf("a", None).await.unwrap();
+        assert_eq!(mors.cf("a"), Some(cf.clone()));
+        assert!(mors.create_cf("a", None).await.is_err());
+
+        let mut txn = mors.begin_write().await.unwrap();
+        txn.set("k".into(), "default".into()).unwrap();
+        txn.set_cf(&cf, "k".into(), "a".into()).unwrap();
+        txn.commit().await.unwrap();
+
+        let key = KeyTs::new("k".into(), u64::MAX.into());
+        let get = |cf| {
+            let mors = mors.clone();
+            let key = key.clone();
+            async move { mors.inner().get(cf, &key).await }
+        };
+        let (_, value) = get(DEFAULT_COLUMN_FAMILY).await.unwrap().unwrap();
+        assert_eq!(value.unwrap().value().as_ref(), b"default");
+        let (_, value) = get(cf.id()).await.unwrap().unwrap();
+        assert_eq!(value.unwrap().value().as_ref(), b"a");
+
+        mors.drop_cf(&cf).await.unwrap();
+        assert!(mors.cf("a").is_none());
+        assert!(get(cf.id()).await.is_err());
+        let mut txn = mors.begin_write().await.unwrap();
+        assert!(txn.set_cf(&cf, "k".into(), "a".into()).is_err());
+    }
+}
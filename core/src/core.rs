@@ -1,31 +1,41 @@
-use std::collections::VecDeque;
-use std::fs::create_dir;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{create_dir, remove_dir_all};
 use std::marker::PhantomData;
 use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::sync::RwLock;
+use std::time::Duration;
 
+use crate::cf::{
+    cf_dir, open_cf, stale_cf_dirs, ColumnFamily, ColumnFamilyCtl,
+    DEFAULT_COLUMN_FAMILY_NAME,
+};
+use crate::error::MorsError;
 use crate::txn::manager::TxnManager;
 use crate::txn::manager::TxnManagerBuilder;
+use crate::txn::ConflictMode;
 use crate::write::WriteRequest;
 use crate::Result;
-use log::info;
+use log::{info, warn};
 use mors_common::{
     closer::Closer,
+    kv::{ColumnFamilyId, DEFAULT_COLUMN_FAMILY},
     lock::{DBLockGuard, DBLockGuardBuilder},
     rayon::init_global_rayon_pool,
 };
 use mors_traits::{
     default::{WithDir, WithReadOnly, DEFAULT_DIR},
-    kms::{Kms, KmsBuilder},
-    levelctl::{LevelCtlBuilderTrait, LevelCtlTrait},
+    kms::{EncryptionAlgo, Kms, KmsBuilder},
+    levelctl::{CompactionFilter, LevelCtlBuilderTrait, LevelCtlTrait},
     memtable::{MemtableBuilderTrait, MemtableTrait},
+    recovery::RecoveryReport,
     skip_list::SkipListTrait,
-    sstable::TableTrait,
-    vlog::{VlogCtlBuilderTrait, VlogCtlTrait},
+    sstable::{TablePropertiesCollectorFactory, TableTrait},
+    vlog::{ValueSeparation, VlogCtlBuilderTrait, VlogCtlTrait},
 };
-use tokio::sync::mpsc::Sender;
+use parking_lot::RwLock as FamilyLock;
+use tokio::sync::{mpsc::Sender, Mutex};
 
 pub struct Core<
     M: MemtableTrait<S, K>,
@@ -104,8 +114,94 @@ impl<
     pub(crate) fn kms(&self) -> &K {
         &self.kms
     }
-    pub(crate) fn levelctl(&self) -> &L {
-        &self.levelctl
+    pub(crate) fn recovery(&self) -> &RecoveryReport {
+        &self.recovery
+    }
+    /// level controller of the column family `cf`, `None` once it is dropped.
+    pub(crate) fn levelctl(&self, cf: ColumnFamilyId) -> Option<L> {
+        self.families.read().get(&cf).map(|f| f.levelctl.clone())
+    }
+    /// level controllers of all opened column families.
+    pub(crate) fn levelctls(&self) -> Vec<L> {
+        self.families
+            .read()
+            .values()
+            .map(|f| f.levelctl.clone())
+            .collect()
+    }
+    fn default_levelctl(&self) -> L {
+        self.families.read()[&DEFAULT_COLUMN_FAMILY]
+            .levelctl
+            .clone()
+    }
+    pub(crate) fn has_cf(&self, cf: ColumnFamilyId) -> bool {
+        self.families.read().contains_key(&cf)
+    }
+    pub(crate) fn cf(&self, name: &str) -> Option<ColumnFamily> {
+        self.families
+            .read()
+            .iter()
+            .find(|(_, f)| f.name.as_ref() == name)
+            .map(|(id, f)| ColumnFamily::new(*id, f.name.clone()))
+    }
+    pub(crate) async fn create_cf(
+        &self,
+        name: &str,
+        options: Option<L::LevelCtlBuilder>,
+    ) -> Result<ColumnFamily> {
+        let _guard = self.cf_lock.lock().await;
+        if self.memtable.is_none() {
+            return Err(MorsError::ReadOnlyColumnFamily);
+        }
+        if name == DEFAULT_COLUMN_FAMILY_NAME || self.cf(name).is_some() {
+            return Err(MorsError::ColumnFamilyExists(name.to_string()));
+        }
+        if name.is_empty() || name.len() > u16::MAX as usize {
+            return Err(MorsError::InvalidColumnFamily(name.to_string()));
+        }
+        let id = self.default_levelctl().create_column_family(name).await?;
+        let cf = ColumnFamily::new(id, name.into());
+        let options = options
+            .or_else(|| self.cf_options.get(name).cloned())
+            .unwrap_or_else(|| self.levelctl_builder.clone());
+        let ctl = open_cf::<L, T, K, V::Discard>(
+            &options,
+            &self.dir,
+            cf.id(),
+            cf.name().into(),
+            self.kms.clone(),
+            self.discard.clone(),
+        )
+        .await?;
+        ctl.levelctl
+            .set_snapshot_list(Arc::new(self.txn_manager.clone()));
+        self.families.write().insert(cf.id(), ctl);
+        info!("created column family {} with id {}", cf.name(), cf.id());
+        Ok(cf)
+    }
+    pub(crate) async fn drop_cf(&self, cf: &ColumnFamily) -> Result<()> {
+        let guard = self.cf_lock.lock().await;
+        if self.memtable.is_none() {
+            return Err(MorsError::ReadOnlyColumnFamily);
+        }
+        if cf.id() == DEFAULT_COLUMN_FAMILY {
+            return Err(MorsError::InvalidColumnFamily(cf.name().to_string()));
+        }
+        if !self.has_cf(cf.id()) {
+            return Err(MorsError::ColumnFamilyNotFound(cf.name().to_string()));
+        }
+        self.default_levelctl().drop_column_family(cf.id()).await?;
+        let family = self.families.write().remove(&cf.id());
+        drop(guard);
+        if let Some(family) = family {
+            family.compact_task.cancel();
+            if let Err(e) = family.compact_task.wait().await {
+                warn!("waiting compaction of {} failed: {}", cf.name(), e);
+            }
+            remove_dir_all(cf_dir(&self.dir, cf.id()))?;
+        }
+        info!("dropped column family {} with id {}", cf.name(), cf.id());
+        Ok(())
     }
     pub(crate) fn vlogctl(&self) -> &V {
         &self.vlogctl
@@ -113,6 +209,9 @@ impl<
     pub(crate) fn block_write(&self) -> &AtomicBool {
         &self.block_write
     }
+    pub(crate) fn memtable_rotation(&self) -> &Mutex<()> {
+        &self.memtable_rotation
+    }
 }
 pub(crate) struct CoreInner<M, K, L, T, S, V>
 where
@@ -128,12 +227,20 @@ where
     immut_memtable: RwLock<VecDeque<Arc<M>>>,
     memtable: Option<RwLock<Arc<M>>>,
     memtable_builder: M::MemtableBuilder,
-    levelctl: L,
+    families: FamilyLock<HashMap<ColumnFamilyId, ColumnFamilyCtl<L>>>,
+    // serializes creating and dropping column families.
+    cf_lock: Mutex<()>,
+    cf_options: HashMap<String, L::LevelCtlBuilder>,
+    levelctl_builder: L::LevelCtlBuilder,
+    discard: V::Discard,
+    dir: PathBuf,
     vlogctl: V,
     txn_manager: TxnManager,
     write_sender: Sender<WriteRequest>,
     flush_sender: Sender<Arc<M>>,
+    memtable_rotation: Mutex<()>,
     block_write: AtomicBool,
+    recovery: RecoveryReport,
     t: PhantomData<T>,
 }
 
@@ -151,8 +258,12 @@ pub struct CoreBuilder<
     kms: K::KmsBuilder,
     memtable: M::MemtableBuilder,
     pub(crate) levelctl: L::LevelCtlBuilder,
+    pub(crate) cf_options: HashMap<String, L::LevelCtlBuilder>,
     vlogctl: V::VlogCtlBuilder,
     txn_manager: TxnManagerBuilder,
+    reencryption: bool,
+    value_separation: ValueSeparation,
+    blob_threshold: Option<usize>,
 }
 impl<
         M: MemtableTrait<S, K>,
@@ -171,8 +282,12 @@ impl<
             kms: K::KmsBuilder::default(),
             memtable: M::MemtableBuilder::default(),
             levelctl: L::LevelCtlBuilder::default(),
+            cf_options: HashMap::new(),
             txn_manager: TxnManagerBuilder::default(),
             vlogctl: V::VlogCtlBuilder::default(),
+            reencryption: false,
+            value_separation: ValueSeparation::default(),
+            blob_threshold: None,
         }
     }
 }
@@ -210,6 +325,17 @@ impl<
     pub(crate) fn num_memtables(&self) -> usize {
         self.num_memtables
     }
+    /// key management options, like the master encryption key.
+    pub fn set_kms(&mut self, kms: K::KmsBuilder) -> &mut Self {
+        self.kms = kms;
+        self
+    }
+    /// algorithm of newly created data keys, default aes-gcm. Set it after
+    /// [`Self::set_kms`], which replaces it.
+    pub fn set_encryption_algo(&mut self, algo: EncryptionAlgo) -> &mut Self {
+        self.kms.set_encryption_algo(algo);
+        self
+    }
     pub fn set_num_memtables(&mut self, num_memtables: usize) -> &mut Self {
         self.num_memtables = num_memtables;
         self.memtable.set_num_memtables(num_memtables);
@@ -218,6 +344,132 @@ impl<
     pub fn set_memtable_size(&mut self, memtable_size: usize) -> &mut Self {
         self.memtable.set_memtable_size(memtable_size);
         self.levelctl.set_level0_table_size(memtable_size);
+        self.cf_options.values_mut().for_each(|o| {
+            o.set_level0_table_size(memtable_size);
+        });
+        self
+    }
+    /// options of the skip lists behind new memtables, for
+    /// [`crate::MorsBuilder`] the [`crate::MemtableKind`] to use.
+    pub fn set_memtable_options(&mut self, options: S::Options) -> &mut Self {
+        self.memtable.set_list_options(options);
+        self
+    }
+    /// how reads are checked against committed writes, default [`ConflictMode::Hash`].
+    pub fn set_conflict_mode(
+        &mut self,
+        conflict_mode: ConflictMode,
+    ) -> &mut Self {
+        self.txn_manager.set_conflict_mode(conflict_mode);
+        self
+    }
+    /// how long a transaction waits for a key lock, default 5s.
+    pub fn set_lock_timeout(&mut self, lock_timeout: Duration) -> &mut Self {
+        self.txn_manager.set_lock_timeout(lock_timeout);
+        self
+    }
+    /// keeps every version written within the window for time travel reads,
+    /// default None.
+    pub fn set_retention_window(
+        &mut self,
+        retention_window: Option<Duration>,
+    ) -> &mut Self {
+        self.txn_manager.set_retention_window(retention_window);
+        self
+    }
+    /// re-encrypts tables and vlog files whose data key is not the latest one
+    /// in the background and retires the unused data keys, default false.
+    /// Together with [`crate::Mors::rotate_master_key`] from an empty key
+    /// it turns on encryption for a plaintext db without downtime.
+    pub fn set_reencryption(&mut self, reencryption: bool) -> &mut Self {
+        self.reencryption = reencryption;
+        self.levelctl.set_reencryption(reencryption);
+        self.cf_options.values_mut().for_each(|o| {
+            o.set_reencryption(reencryption);
+        });
+        self
+    }
+    /// which values are written to the vlog instead of inline, by key prefix
+    /// and value size, on top of the threshold the vlog adjusts on its own.
+    pub fn set_value_separation(
+        &mut self,
+        separation: ValueSeparation,
+    ) -> &mut Self {
+        self.value_separation = separation;
+        self.update_value_separation();
+        self
+    }
+    /// keeps values in the wal and memtables and moves the ones of at least
+    /// `threshold` bytes into blob files when flushing and compacting, so
+    /// they are not written twice. It turns off the separation into the
+    /// vlog by value size, the prefixes of [`Self::set_value_separation`]
+    /// still apply, default None.
+    pub fn set_blob_threshold(
+        &mut self,
+        threshold: Option<usize>,
+    ) -> &mut Self {
+        self.blob_threshold = threshold;
+        self.update_value_separation();
+        self.levelctl.set_blob_threshold(threshold);
+        self.cf_options.values_mut().for_each(|o| {
+            o.set_blob_threshold(threshold);
+        });
+        self
+    }
+    // the value separation set, without its size rules if values of any
+    // size go to blob files instead.
+    fn update_value_separation(&mut self) {
+        let mut separation = self.value_separation.clone();
+        if self.blob_threshold.is_some() {
+            separation
+                .set_min_value_size(usize::MAX)
+                .set_max_value_size(None);
+        }
+        self.vlogctl.set_value_separation(separation);
+    }
+    /// compactions move the values they keep out of the oldest `cutoff` of
+    /// the live blob files, in all column families, so partly dead blob
+    /// files are eventually dropped, default 0.25.
+    pub fn set_blob_gc_age_cutoff(&mut self, cutoff: f64) -> &mut Self {
+        self.levelctl.set_blob_gc_age_cutoff(cutoff);
+        self.cf_options.values_mut().for_each(|o| {
+            o.set_blob_gc_age_cutoff(cutoff);
+        });
+        self
+    }
+    /// gathers user properties of every table written, in all column
+    /// families, they are read back with [`TableTrait::properties`].
+    pub fn add_properties_collector(
+        &mut self,
+        factory: Arc<dyn TablePropertiesCollectorFactory>,
+    ) -> &mut Self {
+        self.levelctl.add_properties_collector(factory.clone());
+        self.cf_options.values_mut().for_each(|o| {
+            o.add_properties_collector(factory.clone());
+        });
+        self
+    }
+    /// drops or rewrites versions no reader needs anymore while compacting,
+    /// in all column families, default None.
+    pub fn set_compaction_filter(
+        &mut self,
+        filter: Option<Arc<dyn CompactionFilter>>,
+    ) -> &mut Self {
+        self.levelctl.set_compaction_filter(filter.clone());
+        self.cf_options.values_mut().for_each(|o| {
+            o.set_compaction_filter(filter.clone());
+        });
+        self
+    }
+    /// level controller options (compaction, compression..) of the column family `name`.
+    /// They are used whenever the family is opened or created without options,
+    /// families without options use a copy of the default family ones.
+    pub fn set_cf_options(
+        &mut self,
+        name: &str,
+        options: L::LevelCtlBuilder,
+    ) -> &mut Self {
+        self.cf_options.insert(name.to_string(), options);
         self
     }
 }
@@ -242,8 +494,25 @@ impl<
         let _lock_guard = guard_builder.build()?;
 
         let kms = self.kms.build()?;
-        let immut_memtable = self.memtable.open_exist(kms.clone())?;
+        // the vlog is recovered first, memtable batches with values in its
+        // cut tail are dropped.
+        let vlogctl = self.vlogctl.build(kms.clone()).await?;
+        let vlog_recovery = vlogctl.recovery();
+        let vlog_tail =
+            vlog_recovery.as_ref().map(|(id, r)| (*id, r.valid_len));
+        let (immut_memtable, memtable_recovery) =
+            self.memtable.open_exist(kms.clone(), vlog_tail)?;
         info!("open {} immut_memtable", immut_memtable.len());
+        let recovery = RecoveryReport {
+            memtables: memtable_recovery,
+            vlog: vlog_recovery.map(|(_, r)| r),
+        };
+        if !recovery.is_clean() {
+            warn!(
+                "discarded {} incomplete entries on open",
+                recovery.discarded_entries()
+            );
+        }
 
         let mut memtable = None;
         if !self.memtable.read_only() {
@@ -251,26 +520,61 @@ impl<
                 RwLock::new(Arc::new(self.memtable.build(kms.clone())?)).into();
         }
         let discard = self.vlogctl.build_discard()?;
-        let levelctl = self.levelctl.build(kms.clone()).await?;
-
-        let compact_task = Closer::new("levectl compact");
-        compact_task.set_joinhandle(tokio::spawn(
-            levelctl.clone().spawn_compact(
-                compact_task.clone(),
+        let mut families = HashMap::new();
+        families.insert(
+            DEFAULT_COLUMN_FAMILY,
+            open_cf::<L, T, K, V::Discard>(
+                &self.levelctl,
+                &self.dir,
+                DEFAULT_COLUMN_FAMILY,
+                DEFAULT_COLUMN_FAMILY_NAME.into(),
                 kms.clone(),
-                discard,
-            ),
-        ));
+                discard.clone(),
+            )
+            .await?,
+        );
+        // the default family records the others in its manifest.
+        let column_families = families[&DEFAULT_COLUMN_FAMILY]
+            .levelctl
+            .column_families()
+            .await;
+        if !self.read_only {
+            for dir in stale_cf_dirs(&self.dir, &column_families)? {
+                warn!("removing directory of dropped column family {:?}", dir);
+                remove_dir_all(dir)?;
+            }
+        }
+        for (id, name) in column_families.iter() {
+            let options =
+                self.cf_options.get(name.as_ref()).unwrap_or(&self.levelctl);
+            let ctl = open_cf::<L, T, K, V::Discard>(
+                options,
+                &self.dir,
+                *id,
+                name.clone(),
+                kms.clone(),
+                discard.clone(),
+            )
+            .await?;
+            info!("open column family {} with id {}", name, id);
+            families.insert(*id, ctl);
+        }
 
-        let mut max_version = levelctl.max_version();
+        let mut max_version = families
+            .values()
+            .map(|f| f.levelctl.max_version())
+            .max()
+            .unwrap_or_default();
         immut_memtable.iter().for_each(|m| {
             max_version = max_version.max(m.max_version());
         });
 
         let txn_manager = self.txn_manager.build(max_version).await?;
+        families.values().for_each(|f| {
+            f.levelctl.set_snapshot_list(Arc::new(txn_manager.clone()))
+        });
         let immut_memtable = RwLock::new(immut_memtable);
 
-        let vlogctl = self.vlogctl.build(kms.clone()).await?;
         let (write_sender, receiver) = Self::init_write_channel();
         let (flush_sender, flush_receiver) =
             Self::init_flush_channel(self.num_memtables);
@@ -280,14 +584,21 @@ impl<
             kms,
             immut_memtable,
             memtable,
-            levelctl,
+            families: FamilyLock::new(families),
+            cf_lock: Mutex::new(()),
+            cf_options: self.cf_options.clone(),
+            levelctl_builder: self.levelctl.clone(),
+            discard,
+            dir: self.dir.clone(),
             t: PhantomData,
             write_sender,
             memtable_builder: self.memtable.clone(),
             flush_sender,
             vlogctl,
             txn_manager,
+            memtable_rotation: Mutex::new(()),
             block_write: AtomicBool::new(false),
+            recovery,
         });
 
         let write_task = Closer::new("write request task");
@@ -302,6 +613,15 @@ impl<
             flush_receiver,
             flush_task.clone(),
         )));
+        if self.reencryption && !self.read_only {
+            let reencrypt_task = Closer::new("re-encryption task");
+            reencrypt_task.set_joinhandle(tokio::spawn(
+                CoreInner::do_reencrypt_task(
+                    inner.clone(),
+                    reencrypt_task.clone(),
+                ),
+            ));
+        }
         let core = Core { inner };
         Ok(core)
     }
@@ -1,6 +1,7 @@
 use std::sync::PoisonError;
 
 use mors_traits::{
+    iter::IterError,
     kms::{EncryptError, KmsError},
     levelctl::LevelCtlError,
     memtable::MemtableError,
@@ -29,6 +30,8 @@ pub enum MorsError {
     SSTableError(#[from] SSTableError),
     #[error("Vlog Error: {0}")]
     VlogError(#[from] VlogError),
+    #[error("Iterator Error: {0}")]
+    IterError(#[from] IterError),
     #[error("Poisoned RwLock: {0}")]
     RwLockPoisoned(String),
     #[error("Send Error: {0}")]
@@ -43,6 +46,20 @@ pub enum MorsError {
     PoisonError(String),
     #[error("Writes are blocked, possibly due to DropAll or Close")]
     BlockedWrites,
+    #[error("Column family {0} already exists")]
+    ColumnFamilyExists(String),
+    #[error("Column family {0} not found")]
+    ColumnFamilyNotFound(String),
+    #[error("Invalid column family: {0}")]
+    InvalidColumnFamily(String),
+    #[error("Column families cannot be changed in read only mode")]
+    ReadOnlyColumnFamily,
+    #[error("Files cannot be ingested in read only mode")]
+    ReadOnlyIngest,
+    #[error("Cannot ingest {0:?}: {1}")]
+    InvalidExternalFile(std::path::PathBuf, String),
+    #[error("{} write requests of the batch failed, the first: {}", .0.len(), .0[0].1)]
+    WriteBatchError(Vec<(usize, MorsError)>),
 }
 impl<T> From<PoisonError<T>> for MorsError {
     fn from(e: PoisonError<T>) -> MorsError {
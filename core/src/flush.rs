@@ -1,11 +1,16 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
+use bytes::Bytes;
 use log::{debug, error, info};
-use mors_common::closer::Closer;
+use mors_common::{
+    closer::Closer,
+    kv::ColumnFamilyId,
+    ts::{KeyTs, KeyTsBorrow, TxnTs},
+};
 use mors_traits::{
     kms::Kms,
     levelctl::LevelCtlTrait,
-    memtable::MemtableTrait,
+    memtable::{MemtableError, MemtableTrait},
     skip_list::SkipListTrait,
     sstable::{TableBuilderTrait, TableTrait},
     vlog::VlogCtlTrait,
@@ -98,24 +103,88 @@ where
     }
     pub(crate) async fn handle_flush(&self, memtable: Arc<M>) -> Result<()> {
         let cipher = self.kms().latest_cipher()?;
-        let next_id = self.levelctl().next_id();
-        debug!(
-            "building table for memtable {} with next_id {:?}",
-            memtable.id(),
-            next_id
-        );
-        let skip_list = memtable.skip_list();
-        if let Some(t) = self
-            .levelctl()
-            .table_builder()
-            .build_l0(skip_list.iter(), next_id, cipher)
-            .await?
+        for (cf, skip_list) in memtable.skip_lists() {
+            if skip_list.is_empty() {
+                continue;
+            }
+            // entries of a dropped column family are discarded with the wal.
+            let Some(levelctl) = self.levelctl(cf) else {
+                debug!("skip flushing dropped column family {}", cf);
+                continue;
+            };
+            let next_id = levelctl.next_id();
+            debug!(
+                "building table for memtable {} column family {} with next_id {:?}",
+                memtable.id(),
+                cf,
+                next_id
+            );
+            if let Some(t) = levelctl
+                .table_builder()
+                .build_l0(skip_list.iter(), next_id, cipher.clone())
+                .await?
+            {
+                debug!(
+                    "pushing table for memtable {} to level 0 of column family {}",
+                    memtable.id(),
+                    cf
+                );
+                levelctl.push_level0(t).await?;
+            };
+        }
+        Ok(())
+    }
+    /// flushes every memtable holding a key of `cf` inside one of the
+    /// user key `ranges`, both ends included, and waits until they are
+    /// on level 0.
+    pub(crate) async fn flush_overlapping(
+        &self,
+        cf: ColumnFamilyId,
+        ranges: &[(Bytes, Bytes)],
+    ) -> Result<()> {
+        if let Some(mem) = self.read_memtable()? {
+            if Self::memtable_overlaps(&mem, cf, ranges)? {
+                debug!("flushing memtable {} before ingesting", mem.id());
+                self.rotate_memtable(true).await?;
+            }
+        }
+        let mut pending = Vec::new();
+        for mem in self.immut_memtable().read()?.iter() {
+            if Self::memtable_overlaps(mem, cf, ranges)? {
+                pending.push(mem.id());
+            }
+        }
+        while self
+            .immut_memtable()
+            .read()?
+            .iter()
+            .any(|mem| pending.contains(&mem.id()))
         {
-            debug!("pushing table for memtable {} to level 0", memtable.id());
-            self.levelctl().push_level0(t).await?;
-        };
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
         Ok(())
     }
+    fn memtable_overlaps(
+        mem: &M,
+        cf: ColumnFamilyId,
+        ranges: &[(Bytes, Bytes)],
+    ) -> Result<bool> {
+        let Some((_, skip_list)) =
+            mem.skip_lists().into_iter().find(|(id, _)| *id == cf)
+        else {
+            return Ok(false);
+        };
+        for (start, end) in ranges {
+            let start = KeyTs::new(start.clone(), TxnTs::from(u64::MAX));
+            let next = skip_list
+                .get_key_value(&start.encode(), true)
+                .map_err(MemtableError::new)?;
+            if next.is_some_and(|(k, _)| KeyTsBorrow::from(k).key() <= end) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
 }
 #[tokio::test]
 async fn test_recv() {
@@ -0,0 +1,208 @@
+use std::{
+    fs::{copy, hard_link, remove_file},
+    path::Path,
+    sync::atomic::Ordering,
+};
+
+use log::{info, warn};
+use mors_common::{
+    file_id::{FileId, SSTableId},
+    kv::ColumnFamilyId,
+    ts::TxnTs,
+};
+use mors_traits::{
+    default::WithDir,
+    kms::Kms,
+    levelctl::LevelCtlTrait,
+    memtable::MemtableTrait,
+    skip_list::SkipListTrait,
+    sstable::{ExternalFile, TableBuilderTrait, TableTrait},
+    vlog::VlogCtlTrait,
+};
+
+use crate::core::CoreInner;
+use crate::error::MorsError;
+use crate::Result;
+impl<M, K, L, T, S, V> CoreInner<M, K, L, T, S, V>
+where
+    M: MemtableTrait<S, K>,
+    K: Kms,
+    L: LevelCtlTrait<T, K>,
+    T: TableTrait<K::Cipher>,
+    S: SkipListTrait,
+    V: VlogCtlTrait<K>,
+{
+    /// adds `files` to the column family `cf`, every key of them at a
+    /// single new version. The files are linked into the db, or copied
+    /// when they are on another filesystem, and can be removed afterwards.
+    pub(crate) async fn ingest(
+        &self,
+        cf: ColumnFamilyId,
+        files: Vec<ExternalFile>,
+    ) -> Result<()> {
+        if self.memtable().is_none() {
+            return Err(MorsError::ReadOnlyIngest);
+        }
+        let levelctl = self
+            .levelctl(cf)
+            .ok_or_else(|| MorsError::ColumnFamilyNotFound(cf.to_string()))?;
+        let mut linked = Vec::with_capacity(files.len());
+        let result =
+            self.ingest_linked(cf, &levelctl, &files, &mut linked).await;
+        if result.is_err() {
+            for (id, _) in linked {
+                let path = id.join_dir(levelctl.table_builder().dir());
+                if let Err(e) = remove_file(&path) {
+                    warn!("removing ingested file {:?} failed: {}", path, e);
+                }
+            }
+        }
+        result
+    }
+    async fn ingest_linked(
+        &self,
+        cf: ColumnFamilyId,
+        levelctl: &L,
+        files: &[ExternalFile],
+        linked: &mut Vec<(SSTableId, Option<K::Cipher>)>,
+    ) -> Result<()> {
+        let dir = levelctl.table_builder().dir().clone();
+        let mut ranges = Vec::with_capacity(files.len());
+        for file in files {
+            let id: SSTableId =
+                levelctl.next_id().fetch_add(1, Ordering::AcqRel).into();
+            let path = id.join_dir(&dir);
+            if hard_link(file.path(), &path).is_err() {
+                copy(file.path(), &path)?;
+            }
+            let cipher = match file.cipher_key_id() {
+                Some(key_id) => {
+                    Some(self.kms().get_cipher(key_id)?.ok_or_else(|| {
+                        Self::invalid(file.path(), "unknown data key")
+                    })?)
+                }
+                None => None,
+            };
+            linked.push((id, cipher.clone()));
+            let table = Self::open_external(levelctl, file, id, cipher, None)
+                .await?
+                .ok_or_else(|| Self::invalid(file.path(), "corrupted"))?;
+            if table.max_version() != TxnTs::default() {
+                return Err(Self::invalid(file.path(), "keys have versions"));
+            }
+            ranges.push((
+                table.smallest().key().clone(),
+                table.biggest().key().clone(),
+            ));
+        }
+        self.flush_overlapping(cf, &ranges).await?;
+
+        let version = self.txn_manager().generate_ts().await?;
+        let result = self
+            .ingest_at(levelctl, files, linked.as_slice(), version)
+            .await;
+        self.txn_manager().done_commit(version).await?;
+        result?;
+        Ok(self.txn_manager().wait_published(version).await?)
+    }
+    async fn ingest_at(
+        &self,
+        levelctl: &L,
+        files: &[ExternalFile],
+        linked: &[(SSTableId, Option<K::Cipher>)],
+        version: TxnTs,
+    ) -> Result<()> {
+        let mut tables = Vec::with_capacity(files.len());
+        for (file, (id, cipher)) in files.iter().zip(linked) {
+            let table = Self::open_external(
+                levelctl,
+                file,
+                *id,
+                cipher.clone(),
+                Some(version),
+            )
+            .await?
+            .ok_or_else(|| Self::invalid(file.path(), "corrupted"))?;
+            tables.push(table);
+        }
+        levelctl.ingest(tables).await?;
+        info!("ingested {} files at version {}", files.len(), version);
+        Ok(())
+    }
+    async fn open_external(
+        levelctl: &L,
+        file: &ExternalFile,
+        id: SSTableId,
+        cipher: Option<K::Cipher>,
+        version: Option<TxnTs>,
+    ) -> Result<Option<T>> {
+        let mut builder = levelctl.table_builder().clone();
+        builder
+            .set_compression(file.compression())
+            .set_global_version(version);
+        Ok(builder.open(id, cipher).await?)
+    }
+    fn invalid(path: &Path, reason: &str) -> MorsError {
+        MorsError::InvalidExternalFile(path.to_path_buf(), reason.to_string())
+    }
+}
+#[cfg(test)]
+mod test {
+    #[cfg(not(feature = "sync"))]
+    use {
+        crate::{error::MorsError, MorsBuilder},
+        mors_common::{kv::DEFAULT_COLUMN_FAMILY, ts::KeyTs},
+    };
+
+    #[cfg(not(feature = "sync"))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = tempfile::tempdir().unwrap();
+        let mut builder = MorsBuilder::default();
+        builder.set_dir(dir.path().to_path_buf());
+        let mors = builder.build().await.unwrap();
+
+        let mut txn = mors.begin_write().await.unwrap();
+        txn.set("b".into(), "txn".into()).unwrap();
+        txn.set("z".into(), "txn".into()).unwrap();
+        txn.commit().await.unwrap();
+
+        let mut writer = mors.sst_file_writer().unwrap();
+        writer.put("a".into(), "ingested".into()).unwrap();
+        writer.put("b".into(), "ingested".into()).unwrap();
+        assert!(writer.put("b".into(), "again".into()).is_err());
+        writer.delete("c".into()).unwrap();
+        let first = writer.finish(files.path().join("1.sst")).await.unwrap();
+        let mut writer = mors.sst_file_writer().unwrap();
+        writer.put("m".into(), "ingested".into()).unwrap();
+        let second = writer.finish(files.path().join("2.sst")).await.unwrap();
+        mors.ingest(vec![first.clone(), second]).await.unwrap();
+
+        let get = |key: &'static str| {
+            let mors = mors.clone();
+            async move {
+                let key = KeyTs::new(key.into(), u64::MAX.into());
+                mors.inner().get(DEFAULT_COLUMN_FAMILY, &key).await.unwrap()
+            }
+        };
+        // the memtable holding "b" was flushed, the ingested version is newer.
+        let (version, value) = get("b").await.unwrap();
+        assert_eq!(value.unwrap().value().as_ref(), b"ingested");
+        let (txn_version, value) = get("z").await.unwrap();
+        assert_eq!(value.unwrap().value().as_ref(), b"txn");
+        assert!(version > txn_version);
+        let (_, value) = get("m").await.unwrap();
+        assert_eq!(value.unwrap().value().as_ref(), b"ingested");
+        assert!(get("c").await.unwrap().1.unwrap().is_deleted_or_expired());
+
+        // the file was linked into the db, removing it changes nothing.
+        std::fs::remove_file(first.path()).unwrap();
+        let (_, value) = get("a").await.unwrap();
+        assert_eq!(value.unwrap().value().as_ref(), b"ingested");
+        assert!(matches!(
+            mors.ingest(vec![first]).await,
+            Err(MorsError::IOErr(_))
+        ));
+    }
+}
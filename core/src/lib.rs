@@ -1,49 +1,81 @@
 use core::{Core, CoreBuilder};
+use std::future::Future;
 use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::task::{Context, Poll};
 
 use std::time::{Duration, SystemTime};
 
 use bytes::Bytes;
 use error::MorsError;
 
-use mors_common::ts::PhyTs;
 use mors_encrypt::{cipher::AesCipher, registry::MorsKms};
-use mors_levelctl::ctl::LevelCtl;
+use mors_levelctl::ctl::{LevelCtl, LevelCtlBuilder};
 use mors_memtable::memtable::Memtable;
 
-use mors_skip_list::skip_list::SkipList;
+use mors_skip_list::any::AnyList;
 use mors_sstable::cache::MorsCacheBuilder;
 use mors_sstable::table::Table;
 use mors_traits::cache::CacheBuilder;
-use mors_traits::levelctl::LevelCtlBuilderTrait;
+use mors_traits::kms::KmsError;
+use mors_traits::kms::Kms;
+use mors_traits::levelctl::{LevelCtlBuilderTrait, LevelCtlTrait};
 use mors_vlog::vlogctl::VlogCtl;
 use mors_wal::storage::mmap::MmapFile;
 use tokio::runtime::Builder;
+use tokio::sync::oneshot::{self, error::TryRecvError};
 #[cfg(feature = "sync")]
 use {std::sync::Arc, tokio::runtime::Handle};
 
+pub use batch::WriteBatch;
+pub use cf::{ColumnFamily, DEFAULT_COLUMN_FAMILY_NAME};
+pub use mors_common::ts::{PhyTs, TxnTs};
+pub use mors_skip_list::any::MemtableKind;
+pub use mors_sstable::external::SstFileWriter;
+pub use mors_traits::kms::EncryptionAlgo;
+pub use mors_traits::recovery::{LogRecovery, RecoveryReport};
+pub use mors_traits::sstable::ExternalFile;
+pub use mors_traits::vlog::{Separation, ValueSeparation};
+pub use txn::{ConflictMode, TxnIter};
+pub use versions::{KeyVersion, Versions};
 use txn::WriteTxn;
+mod batch;
+mod cf;
 pub mod core;
 mod error;
 mod flush;
+mod ingest;
 mod read;
+mod reencrypt;
 mod test;
 mod txn;
+mod versions;
 mod write;
-use mors_common::kv::{Entry, Meta};
+use mors_common::kv::{Entry, Meta, DEFAULT_COLUMN_FAMILY};
 pub type Result<T> = std::result::Result<T, MorsError>;
 
-type MorsMemtable = Memtable<SkipList, MorsKms, MmapFile>;
+type MorsMemtable = Memtable<AnyList, MorsKms, MmapFile>;
 type MorsLevelCtl = LevelCtl<Table<AesCipher>, MorsKms>;
 type MorsTable = Table<AesCipher>;
 type MorsLevelCtlType = LevelCtl<MorsTable, MorsKms>;
 type MorsVlog = VlogCtl<MorsKms, MmapFile>;
+pub type MorsLevelCtlBuilder = LevelCtlBuilder<Table<AesCipher>, MorsKms>;
 type WriteTxnType = WriteTxn<
     MorsMemtable,
     MorsKms,
     MorsLevelCtlType,
     MorsTable,
-    SkipList,
+    AnyList,
+    MorsVlog,
+>;
+/// the iterator of [`WriteTransaction::iter`].
+pub type TxnIterator<'a> = TxnIter<
+    'a,
+    MorsMemtable,
+    MorsKms,
+    MorsLevelCtlType,
+    MorsTable,
+    AnyList,
     MorsVlog,
 >;
 pub struct WriteTransaction {
@@ -72,7 +104,7 @@ struct MorsInner {
         MorsKms,
         MorsLevelCtl,
         Table<AesCipher>,
-        SkipList,
+        AnyList,
         MorsVlog,
     >,
     #[cfg(feature = "sync")]
@@ -93,7 +125,7 @@ pub struct MorsBuilder {
         MorsKms,
         MorsLevelCtlType,
         MorsTable,
-        SkipList,
+        AnyList,
         MorsVlog,
     >,
     #[cfg(feature = "sync")]
@@ -118,7 +150,7 @@ impl Deref for Mors {
         MorsKms,
         LevelCtl<Table<AesCipher>, MorsKms>,
         Table<AesCipher>,
-        SkipList,
+        AnyList,
         MorsVlog,
     >;
 
@@ -132,7 +164,7 @@ impl Deref for MorsBuilder {
         MorsKms,
         LevelCtl<Table<AesCipher>, MorsKms>,
         Table<AesCipher>,
-        SkipList,
+        AnyList,
         MorsVlog,
     >;
 
@@ -178,20 +210,142 @@ impl MorsBuilder {
 impl Mors {
     #[cfg(not(feature = "sync"))]
     pub async fn begin_write(&self) -> Result<WriteTransaction> {
-        let txn = WriteTxnType::new(self.inner.core.clone(), None).await?;
+        let txn =
+            WriteTxnType::new(self.inner.core.clone(), None, false).await?;
         Ok(WriteTransaction { txn })
     }
     #[cfg(feature = "sync")]
     pub fn begin_write(&self) -> Result<WriteTransaction> {
-        let txn = self
-            .inner
-            .runtime
-            .block_on(WriteTxnType::new(self.inner.core.clone(), None))?;
+        let txn = self.inner.runtime.block_on(WriteTxnType::new(
+            self.inner.core.clone(),
+            None,
+            false,
+        ))?;
         Ok(WriteTransaction {
             txn,
             handler: self.inner.runtime.handle().clone(),
         })
     }
+    /// begins a transaction whose commit never conflicts. Keys read with
+    /// `get_for_update` and written keys are locked instead, reads with
+    /// `get` are plain snapshot reads. Other transactions fail to commit
+    /// writes to its locked keys.
+    #[cfg(not(feature = "sync"))]
+    pub async fn begin_pessimistic(&self) -> Result<WriteTransaction> {
+        let txn =
+            WriteTxnType::new(self.inner.core.clone(), None, true).await?;
+        Ok(WriteTransaction { txn })
+    }
+    #[cfg(feature = "sync")]
+    pub fn begin_pessimistic(&self) -> Result<WriteTransaction> {
+        let txn = self.inner.runtime.block_on(WriteTxnType::new(
+            self.inner.core.clone(),
+            None,
+            true,
+        ))?;
+        Ok(WriteTransaction {
+            txn,
+            handler: self.inner.runtime.handle().clone(),
+        })
+    }
+    /// re-wraps the data keys of the key registry with the master key `new`,
+    /// the db must be reopened with `new` afterwards.
+    pub fn rotate_master_key(&self, old: &[u8], new: &[u8]) -> Result<()> {
+        self.inner
+            .core
+            .inner()
+            .kms()
+            .rotate_master_key(old, new)
+            .map_err(KmsError::from)?;
+        Ok(())
+    }
+    /// what reopening the db discarded: incomplete transactions and the
+    /// torn tails of the memtable wals and the vlog.
+    pub fn recovery_report(&self) -> &RecoveryReport {
+        self.inner.core.inner().recovery()
+    }
+}
+impl Mors {
+    /// the column family every key without an explicit family belongs to.
+    pub fn default_cf(&self) -> ColumnFamily {
+        ColumnFamily::default_family()
+    }
+    pub fn cf(&self, name: &str) -> Option<ColumnFamily> {
+        self.inner.core.inner().cf(name)
+    }
+    /// creates the column family `name`, recorded in the cf manifest.
+    /// Without `options` the ones set by [`CoreBuilder::set_cf_options`]
+    /// or the default family ones are used.
+    #[cfg(not(feature = "sync"))]
+    pub async fn create_cf(
+        &self,
+        name: &str,
+        options: Option<MorsLevelCtlBuilder>,
+    ) -> Result<ColumnFamily> {
+        self.inner.core.inner().create_cf(name, options).await
+    }
+    #[cfg(feature = "sync")]
+    pub fn create_cf(
+        &self,
+        name: &str,
+        options: Option<MorsLevelCtlBuilder>,
+    ) -> Result<ColumnFamily> {
+        self.inner
+            .runtime
+            .block_on(self.inner.core.inner().create_cf(name, options))
+    }
+    /// drops the column family `cf` and deletes its tables.
+    #[cfg(not(feature = "sync"))]
+    pub async fn drop_cf(&self, cf: &ColumnFamily) -> Result<()> {
+        self.inner.core.inner().drop_cf(cf).await
+    }
+    #[cfg(feature = "sync")]
+    pub fn drop_cf(&self, cf: &ColumnFamily) -> Result<()> {
+        self.inner
+            .runtime
+            .block_on(self.inner.core.inner().drop_cf(cf))
+    }
+}
+impl Mors {
+    /// a writer of table files to [`Self::ingest`], encrypted with the latest
+    /// data key and compressed like the default column family.
+    pub fn sst_file_writer(&self) -> Result<SstFileWriter<AesCipher>> {
+        let inner = self.inner.core.inner();
+        let levelctl = inner.levelctl(DEFAULT_COLUMN_FAMILY).ok_or_else(|| {
+            MorsError::ColumnFamilyNotFound(DEFAULT_COLUMN_FAMILY.to_string())
+        })?;
+        let cipher = inner.kms().latest_cipher()?;
+        Ok(SstFileWriter::new(levelctl.table_builder().clone(), cipher))
+    }
+    /// adds table files built by [`Self::sst_file_writer`] to the default
+    /// column family, all of their keys at one new version. Files must not
+    /// overlap each other, memtables holding their keys are flushed first.
+    #[cfg(not(feature = "sync"))]
+    pub async fn ingest(&self, files: Vec<ExternalFile>) -> Result<()> {
+        self.ingest_cf(&self.default_cf(), files).await
+    }
+    #[cfg(feature = "sync")]
+    pub fn ingest(&self, files: Vec<ExternalFile>) -> Result<()> {
+        self.ingest_cf(&self.default_cf(), files)
+    }
+    #[cfg(not(feature = "sync"))]
+    pub async fn ingest_cf(
+        &self,
+        cf: &ColumnFamily,
+        files: Vec<ExternalFile>,
+    ) -> Result<()> {
+        self.inner.core.inner().ingest(cf.id(), files).await
+    }
+    #[cfg(feature = "sync")]
+    pub fn ingest_cf(
+        &self,
+        cf: &ColumnFamily,
+        files: Vec<ExternalFile>,
+    ) -> Result<()> {
+        self.inner
+            .runtime
+            .block_on(self.inner.core.inner().ingest(cf.id(), files))
+    }
 }
 #[derive(Debug)]
 pub(crate) enum PrefetchStatus {
@@ -228,6 +382,10 @@ impl KvEntry {
     pub fn value(&self) -> &Bytes {
         self.entry.value()
     }
+    /// the commit timestamp of the version read.
+    pub fn version(&self) -> TxnTs {
+        self.entry.version()
+    }
     pub fn set_meta(&mut self, meta: u8) {
         self.entry.set_user_meta(meta);
     }
@@ -275,13 +433,102 @@ impl WriteTransaction {
     }
     #[cfg(feature = "sync")]
     pub fn get(&self, key: Bytes) -> Result<KvEntry> {
-        self.handler.block_on(self.txn.get(key))
+        self.handler
+            .block_on(self.txn.get(DEFAULT_COLUMN_FAMILY, key))
+    }
+    /// locks `key` until the transaction finishes and reads its latest value.
+    #[cfg(not(feature = "sync"))]
+    pub async fn get_for_update(&mut self, key: Bytes) -> Result<KvEntry> {
+        self.txn.get_for_update(DEFAULT_COLUMN_FAMILY, key).await
+    }
+    #[cfg(feature = "sync")]
+    pub fn get_for_update(&mut self, key: Bytes) -> Result<KvEntry> {
+        self.handler
+            .block_on(self.txn.get_for_update(DEFAULT_COLUMN_FAMILY, key))
     }
     pub fn delete(&mut self, key: Bytes) -> Result<()> {
         let mut entry = KvEntry::new(key, Bytes::new());
         entry.set_delete();
         self.set_entry(entry)
     }
+    /// iterates the live keys in key order as of the read timestamp, with
+    /// the writes of this transaction. Keys deleted by a range delete are
+    /// skipped.
+    pub fn iter(&self) -> Result<TxnIterator<'_>> {
+        self.txn.iter(DEFAULT_COLUMN_FAMILY)
+    }
+    pub fn iter_cf(&self, cf: &ColumnFamily) -> Result<TxnIterator<'_>> {
+        self.txn.iter(cf.id())
+    }
+    /// deletes every key in `[start, end)` with a single range tombstone.
+    pub fn delete_range(&mut self, start: Bytes, end: Bytes) -> Result<()> {
+        Ok(self.txn.delete_range(DEFAULT_COLUMN_FAMILY, start, end)?)
+    }
+    /// records a scan of the keys in `[start, end)`, an empty `end` is unbounded.
+    /// The commit then conflicts with any transaction that wrote into the range.
+    pub fn track_read_range(&self, start: Bytes, end: Bytes) -> Result<()> {
+        Ok(self
+            .txn
+            .track_read_range(DEFAULT_COLUMN_FAMILY, start, end)?)
+    }
+    pub fn track_read_range_cf(
+        &self,
+        cf: &ColumnFamily,
+        start: Bytes,
+        end: Bytes,
+    ) -> Result<()> {
+        Ok(self.txn.track_read_range(cf.id(), start, end)?)
+    }
+    pub fn set_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        key: Bytes,
+        value: Bytes,
+    ) -> Result<()> {
+        self.set_entry_cf(cf, KvEntry::new(key, value))
+    }
+    pub fn set_entry_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        mut entry: KvEntry,
+    ) -> Result<()> {
+        entry.entry.set_column_family(cf.id());
+        self.set_entry(entry)
+    }
+    #[cfg(feature = "sync")]
+    pub fn get_cf(&self, cf: &ColumnFamily, key: Bytes) -> Result<KvEntry> {
+        self.handler.block_on(self.txn.get(cf.id(), key))
+    }
+    #[cfg(not(feature = "sync"))]
+    pub async fn get_for_update_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        key: Bytes,
+    ) -> Result<KvEntry> {
+        self.txn.get_for_update(cf.id(), key).await
+    }
+    #[cfg(feature = "sync")]
+    pub fn get_for_update_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        key: Bytes,
+    ) -> Result<KvEntry> {
+        self.handler.block_on(self.txn.get_for_update(cf.id(), key))
+    }
+    pub fn delete_cf(&mut self, cf: &ColumnFamily, key: Bytes) -> Result<()> {
+        let mut entry = KvEntry::new(key, Bytes::new());
+        entry.set_delete();
+        self.set_entry_cf(cf, entry)
+    }
+    /// like [`Self::delete_range`] but inside the column family `cf`.
+    pub fn delete_range_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        start: Bytes,
+        end: Bytes,
+    ) -> Result<()> {
+        Ok(self.txn.delete_range(cf.id(), start, end)?)
+    }
     #[cfg(not(feature = "sync"))]
     pub async fn commit(&mut self) -> Result<()> {
         self.txn.commit().await
@@ -290,4 +537,55 @@ impl WriteTransaction {
     pub fn commit(&mut self) -> Result<()> {
         self.handler.block_on(self.txn.commit())
     }
+    /// sends the writes without waiting for them to be applied, so the next
+    /// transaction can start meanwhile. Readers still see the commits in
+    /// commit order, the returned future resolves to the result.
+    #[cfg(not(feature = "sync"))]
+    pub async fn commit_async(self) -> Result<CommitFuture> {
+        let receiver = self.txn.commit_async().await?;
+        Ok(CommitFuture { receiver })
+    }
+    /// like the async one, only waits for the writes to be sent.
+    #[cfg(feature = "sync")]
+    pub fn commit_async(self) -> Result<CommitFuture> {
+        let receiver = self.handler.block_on(self.txn.commit_async())?;
+        Ok(CommitFuture {
+            receiver,
+            handler: self.handler,
+        })
+    }
+}
+/// the result of a commit sent by [`WriteTransaction::commit_async`].
+pub struct CommitFuture {
+    receiver: oneshot::Receiver<Result<()>>,
+    #[cfg(feature = "sync")]
+    handler: Handle,
+}
+impl CommitFuture {
+    /// the result if the commit is done, without waiting for it.
+    pub fn try_result(&mut self) -> Option<Result<()>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(e) => Some(Err(MorsError::RecvError(e.to_string()))),
+        }
+    }
+    /// blocks until the commit is done.
+    #[cfg(feature = "sync")]
+    pub fn wait(self) -> Result<()> {
+        let handler = self.handler.clone();
+        handler.block_on(self)
+    }
+}
+impl Future for CommitFuture {
+    type Output = Result<()>;
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Self::Output> {
+        Pin::new(&mut self.receiver).poll(cx).map(|result| {
+            result.map_err(|e| MorsError::RecvError(e.to_string()))?
+        })
+    }
 }
@@ -1,5 +1,6 @@
+use bytes::Bytes;
 use mors_common::{
-    kv::ValueMeta,
+    kv::{ColumnFamilyId, Entry, ValueMeta},
     ts::{KeyTs, TxnTs},
 };
 use mors_traits::{
@@ -8,7 +9,9 @@ use mors_traits::{
 };
 
 use crate::core::CoreInner;
-use crate::Result;
+use crate::error::MorsError;
+use crate::txn::error::TxnError;
+use crate::{KvEntry, Result};
 impl<M, K, L, T, S, V> CoreInner<M, K, L, T, S, V>
 where
     M: MemtableTrait<S, K>,
@@ -20,13 +23,17 @@ where
 {
     pub(crate) async fn get(
         &self,
+        cf: ColumnFamilyId,
         key: &KeyTs,
     ) -> Result<Option<(TxnTs, Option<ValueMeta>)>> {
+        let levelctl = self
+            .levelctl(cf)
+            .ok_or_else(|| MorsError::ColumnFamilyNotFound(cf.to_string()))?;
         let mut max_txn_ts = TxnTs::default();
         let mut max_value = None;
 
         if let Some(mem) = self.read_memtable()? {
-            if let Some((txn_ts, value)) = mem.get(key)? {
+            if let Some((txn_ts, value)) = mem.get(cf, key)? {
                 if txn_ts == key.txn_ts() {
                     return Ok(Some((txn_ts, value)));
                 }
@@ -39,7 +46,7 @@ where
         {
             let immut_r = self.immut_memtable().read()?;
             for mem in immut_r.iter() {
-                if let Some((txn_ts, value)) = mem.get(key)? {
+                if let Some((txn_ts, value)) = mem.get(cf, key)? {
                     if txn_ts == key.txn_ts() {
                         return Ok(Some((txn_ts, value)));
                     }
@@ -50,7 +57,7 @@ where
                 };
             }
         }
-        if let Some((txn_ts, value)) = self.levelctl().get(key).await? {
+        if let Some((txn_ts, value)) = levelctl.get(key).await? {
             if txn_ts == key.txn_ts() {
                 return Ok(Some((txn_ts, value)));
             }
@@ -64,4 +71,31 @@ where
         }
         Ok(None)
     }
+    /// the newest live version of `key` at or below `read_ts`.
+    pub(crate) async fn read_at(
+        &self,
+        cf: ColumnFamilyId,
+        key: Bytes,
+        read_ts: TxnTs,
+    ) -> Result<KvEntry> {
+        let key_ts = KeyTs::new(key, read_ts);
+        match self.get(cf, &key_ts).await? {
+            Some((txn_ts, value)) => {
+                if value.is_none() {
+                    return Err(TxnError::ValueNotFound.into());
+                }
+                let value = value.unwrap();
+                if (value.meta().is_empty() && value.value().is_empty())
+                    || value.is_deleted_or_expired()
+                {
+                    return Err(TxnError::ValueNotFound.into());
+                }
+                let mut entry: Entry = (key_ts, value).into();
+                entry.set_version(txn_ts);
+                let kv_entry: KvEntry = entry.into();
+                Ok(kv_entry)
+            }
+            None => Err(TxnError::KeyNotFound.into()),
+        }
+    }
 }
@@ -0,0 +1,264 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{error, info};
+use mors_common::{
+    closer::Closer,
+    file_id::VlogId,
+    kv::{Meta, ValuePointer},
+};
+use mors_traits::{
+    kms::{CipherKeyId, Kms, KmsCipher},
+    levelctl::LevelCtlTrait,
+    memtable::MemtableTrait,
+    skip_list::SkipListTrait,
+    sstable::TableTrait,
+    vlog::VlogCtlTrait,
+};
+use tokio::{select, time::interval};
+
+use crate::core::CoreInner;
+use crate::error::MorsError;
+use crate::Result;
+
+const REENCRYPT_INTERVAL: Duration = Duration::from_secs(10);
+// bytes of vlog entries checked and rewritten at once.
+const REWRITE_BATCH_SIZE: usize = 4 << 20;
+impl<M, K, L, T, S, V> CoreInner<M, K, L, T, S, V>
+where
+    M: MemtableTrait<S, K>,
+    K: Kms,
+    L: LevelCtlTrait<T, K>,
+    T: TableTrait<K::Cipher>,
+    S: SkipListTrait,
+    V: VlogCtlTrait<K>,
+{
+    /// Re-encrypts the vlog with the latest data key and retires the data
+    /// keys no file uses anymore, tables are rewritten by the compactors.
+    pub(crate) async fn do_reencrypt_task(this: Arc<Self>, closer: Closer) {
+        let mut ticker = interval(REENCRYPT_INTERVAL);
+        loop {
+            select! {
+                _ = ticker.tick() => {
+                    if let Err(e) = this.reencrypt().await {
+                        error!("re-encryption error: {}", e);
+                    }
+                }
+                _ = closer.cancelled() => {
+                    info!("re-encryption task closed");
+                    break;
+                }
+            }
+        }
+    }
+    pub(crate) async fn reencrypt(&self) -> Result<()> {
+        let latest = self
+            .kms()
+            .latest_cipher()?
+            .map(|c| c.cipher_key_id())
+            .unwrap_or_default();
+        for id in self.vlogctl().stale_files(latest)? {
+            self.rewrite_vlog(id).await?;
+        }
+        self.vlogctl()
+            .purge_deleted(self.txn_manager().oldest_snapshot())?;
+        self.retire_data_keys(latest)
+    }
+    // rewrites the entries of the vlog file `id` which are still referenced
+    // by the lsm at the same version, then deletes the file. Transactions
+    // begun before may have read pointers into it, so it is unlinked once
+    // they are done.
+    async fn rewrite_vlog(&self, id: VlogId) -> Result<()> {
+        let mut offset = 0;
+        let mut rewritten = 0;
+        loop {
+            let (entries, next) =
+                self.vlogctl()
+                    .read_entries(id, offset, REWRITE_BATCH_SIZE)?;
+            if entries.is_empty() {
+                break;
+            }
+            offset = next;
+            let mut live = Vec::with_capacity(entries.len());
+            for (entry, vp) in entries {
+                let Ok(Some((txn, Some(value)))) =
+                    self.get(entry.column_family(), entry.key_ts()).await
+                else {
+                    continue;
+                };
+                if txn == entry.version()
+                    && value.meta().contains(Meta::VALUE_POINTER)
+                    && ValuePointer::decode(value.value()) == Some(vp)
+                {
+                    live.push(entry);
+                }
+            }
+            if live.is_empty() {
+                continue;
+            }
+            rewritten += live.len();
+            self.send_to_write_channel(live)
+                .await?
+                .await
+                .map_err(|e| MorsError::RecvError(e.to_string()))??;
+        }
+        let read_ts = self.txn_manager().latest_read_ts();
+        self.vlogctl().delete_file(id, read_ts)?;
+        info!("re-encrypted vlog {} with {} live entries", id, rewritten);
+        Ok(())
+    }
+    // keys newer than `latest` may be in use by files created meanwhile,
+    // the kms keeps the ones of files still being written by their ciphers.
+    fn retire_data_keys(&self, latest: CipherKeyId) -> Result<()> {
+        let mut in_use = self.vlogctl().cipher_key_ids()?;
+        in_use.extend(self.levelctls().iter().flat_map(|l| l.cipher_key_ids()));
+        if let Some(memtable) = self.read_memtable()? {
+            in_use.insert(memtable.cipher_key_id());
+        }
+        for memtable in self.immut_memtable().read()?.iter() {
+            in_use.insert(memtable.cipher_key_id());
+        }
+        let retired = self
+            .kms()
+            .data_key_ids()?
+            .into_iter()
+            .filter(|id| latest == CipherKeyId::default() || *id < latest)
+            .filter(|id| !in_use.contains(id))
+            .collect::<Vec<_>>();
+        if retired.is_empty() {
+            return Ok(());
+        }
+        self.kms().retire_data_keys(&retired)?;
+        info!("retired data keys {:?}", retired);
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use bytes::Bytes;
+    use mors_common::kv::{ValuePointer, DEFAULT_COLUMN_FAMILY};
+    use mors_common::ts::KeyTs;
+    use mors_traits::kms::{CipherKeyId, Kms, KmsCipher};
+    use mors_traits::vlog::VlogCtlTrait;
+
+    #[cfg(not(feature = "sync"))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_reencrypt_vlog() {
+        use crate::MorsBuilder;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut builder = MorsBuilder::default();
+        builder.set_dir(dir.path().to_path_buf());
+        let mors = builder.build().await.unwrap();
+        let core = mors.inner().clone();
+        // values above the threshold always go to the vlog.
+        let value = Bytes::from(vec![7; 2 << 20]);
+        let set = |key: &'static str| {
+            let mors = mors.clone();
+            let value = value.clone();
+            async move {
+                let mut txn = mors.begin_write().await.unwrap();
+                txn.set(key.into(), value).unwrap();
+                txn.commit().await.unwrap();
+            }
+        };
+        let vlog_fid = |key: &'static str| {
+            let core = core.clone();
+            async move {
+                let key = KeyTs::new(key.into(), u64::MAX.into());
+                let (_, value) = core
+                    .get(DEFAULT_COLUMN_FAMILY, &key)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                ValuePointer::decode(value.unwrap().value()).unwrap().fid()
+            }
+        };
+
+        set("a").await;
+        let plaintext = vlog_fid("a").await;
+        assert_eq!(
+            core.vlogctl().cipher_key_ids().unwrap(),
+            HashSet::from([CipherKeyId::default()])
+        );
+        mors.rotate_master_key(&[], &[1; 32]).unwrap();
+        let latest = core.kms().latest_cipher().unwrap().unwrap();
+
+        // the file being written is only rolled over by the next write.
+        core.reencrypt().await.unwrap();
+        set("b").await;
+        core.reencrypt().await.unwrap();
+
+        assert_ne!(vlog_fid("a").await, plaintext);
+        assert_eq!(
+            core.vlogctl().cipher_key_ids().unwrap(),
+            HashSet::from([latest.cipher_key_id()])
+        );
+    }
+
+    #[cfg(not(feature = "sync"))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_reencrypt_vlog_reader() {
+        use crate::MorsBuilder;
+        use mors_common::file_id::{FileId, VlogId};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut builder = MorsBuilder::default();
+        builder.set_dir(dir.path().to_path_buf());
+        let mors = builder.build().await.unwrap();
+        let core = mors.inner().clone();
+        let set = |key: &'static str| {
+            let mors = mors.clone();
+            async move {
+                let mut txn = mors.begin_write().await.unwrap();
+                txn.set(key.into(), vec![7; 2 << 20].into()).unwrap();
+                txn.commit().await.unwrap();
+            }
+        };
+        set("a").await;
+        let plaintext = VlogId::parse_set_from_dir(dir.path());
+        // it may still have read pointers into the files rewritten.
+        let reader = mors.begin_write().await.unwrap();
+        mors.rotate_master_key(&[], &[1; 32]).unwrap();
+        let latest = core.kms().latest_cipher().unwrap().unwrap();
+        core.reencrypt().await.unwrap();
+        set("b").await;
+        core.reencrypt().await.unwrap();
+
+        let files = VlogId::parse_set_from_dir(dir.path());
+        assert!(plaintext.is_subset(&files));
+        assert!(core
+            .vlogctl()
+            .cipher_key_ids()
+            .unwrap()
+            .contains(&CipherKeyId::default()));
+
+        drop(reader);
+        core.reencrypt().await.unwrap();
+        let files = VlogId::parse_set_from_dir(dir.path());
+        assert!(plaintext.is_disjoint(&files));
+        assert_eq!(
+            core.vlogctl().cipher_key_ids().unwrap(),
+            HashSet::from([latest.cipher_key_id()])
+        );
+    }
+
+    #[cfg(not(feature = "sync"))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_encryption_algo() {
+        use crate::{EncryptionAlgo, MorsBuilder};
+        use mors_encrypt::registry::MorsKmsBuilder;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut builder = MorsBuilder::default();
+        builder
+            .set_dir(dir.path().to_path_buf())
+            .set_kms(MorsKmsBuilder::new(vec![1; 32]))
+            .set_encryption_algo(EncryptionAlgo::ChaCha20Poly1305);
+        let mors = builder.build().await.unwrap();
+        let cipher = mors.inner().kms().latest_cipher().unwrap().unwrap();
+        assert_eq!(cipher.encryption_algo(), EncryptionAlgo::ChaCha20Poly1305);
+    }
+}
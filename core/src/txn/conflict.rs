@@ -0,0 +1,241 @@
+use std::collections::{BTreeSet, HashSet};
+
+use bytes::Bytes;
+use mors_common::kv::ColumnFamilyId;
+
+use super::HASH;
+
+/// How point reads are compared with the writes of committed transactions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictMode {
+    /// compare 64-bit key hashes, cheap but two keys can collide.
+    #[default]
+    Hash,
+    /// compare the keys themselves, no false conflicts.
+    Exact,
+}
+/// Keys in `[start, end)` of one column family, an empty `end` is unbounded.
+#[derive(Debug, Clone)]
+pub(crate) struct KeyRange {
+    cf: ColumnFamilyId,
+    start: Bytes,
+    end: Bytes,
+}
+impl KeyRange {
+    pub(crate) fn new(cf: ColumnFamilyId, start: Bytes, end: Bytes) -> Self {
+        Self { cf, start, end }
+    }
+    fn contains(&self, cf: ColumnFamilyId, key: &[u8]) -> bool {
+        self.cf == cf
+            && self.start.as_ref() <= key
+            && (self.end.is_empty() || key < self.end.as_ref())
+    }
+    fn overlaps(&self, other: &KeyRange) -> bool {
+        self.cf == other.cf
+            && (other.end.is_empty() || self.start < other.end)
+            && (self.end.is_empty() || other.start < self.end)
+    }
+}
+#[derive(Debug)]
+struct PointRead {
+    hash: u64,
+    cf: ColumnFamilyId,
+    key: Bytes,
+}
+/// Keys and key ranges read by a transaction.
+#[derive(Debug, Default)]
+pub(crate) struct ReadSet {
+    points: Vec<PointRead>,
+    ranges: Vec<KeyRange>,
+}
+impl ReadSet {
+    pub(crate) fn add_key(&mut self, cf: ColumnFamilyId, key: Bytes) {
+        self.points.push(PointRead {
+            hash: HASH.hash_one((cf, &key)),
+            cf,
+            key,
+        });
+    }
+    pub(crate) fn add_range(&mut self, range: KeyRange) {
+        self.ranges.push(range);
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.points.is_empty() && self.ranges.is_empty()
+    }
+}
+/// Keys and deleted ranges written by a transaction.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct WriteSet {
+    mode: ConflictMode,
+    hashes: HashSet<u64>,
+    keys: BTreeSet<(ColumnFamilyId, Bytes)>,
+    ranges: Vec<KeyRange>,
+}
+impl WriteSet {
+    pub(crate) fn new(mode: ConflictMode) -> Self {
+        Self {
+            mode,
+            ..Default::default()
+        }
+    }
+    pub(crate) fn add_key(&mut self, cf: ColumnFamilyId, key: Bytes) {
+        if self.mode == ConflictMode::Hash {
+            self.hashes.insert(HASH.hash_one((cf, &key)));
+        }
+        self.keys.insert((cf, key));
+    }
+    pub(crate) fn add_range(&mut self, range: KeyRange) {
+        self.ranges.push(range);
+    }
+    /// whether any read of `reads` saw a key written here.
+    pub(crate) fn conflicts_with(&self, reads: &ReadSet) -> bool {
+        let point = reads.points.iter().any(|p| {
+            let written = match self.mode {
+                ConflictMode::Hash => self.hashes.contains(&p.hash),
+                ConflictMode::Exact => {
+                    self.keys.contains(&(p.cf, p.key.clone()))
+                }
+            };
+            written || self.ranges.iter().any(|r| r.contains(p.cf, &p.key))
+        });
+        point
+            || reads.ranges.iter().any(|read| {
+                self.keys
+                    .range((read.cf, read.start.clone())..)
+                    .next()
+                    .is_some_and(|(cf, key)| read.contains(*cf, key))
+                    || self.ranges.iter().any(|r| r.overlaps(read))
+            })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::{ConflictMode, KeyRange, ReadSet, WriteSet};
+
+    #[test]
+    fn test_conflicts_with() {
+        let mut writes = WriteSet::new(ConflictMode::Exact);
+        writes.add_key(0, "b".into());
+        writes.add_range(KeyRange::new(1, "m".into(), "p".into()));
+
+        let check = |f: &dyn Fn(&mut ReadSet)| {
+            let mut reads = ReadSet::default();
+            f(&mut reads);
+            writes.conflicts_with(&reads)
+        };
+        assert!(check(&|r| r.add_key(0, "b".into())));
+        assert!(!check(&|r| r.add_key(1, "b".into())));
+        assert!(check(&|r| r.add_key(1, "n".into())));
+        assert!(!check(&|r| r.add_key(1, "p".into())));
+        assert!(check(&|r| r.add_range(KeyRange::new(
+            0,
+            "a".into(),
+            "c".into()
+        ))));
+        assert!(!check(&|r| r.add_range(KeyRange::new(
+            0,
+            "c".into(),
+            "".into()
+        ))));
+        assert!(!check(&|r| r.add_range(KeyRange::new(
+            0,
+            "a".into(),
+            "b".into()
+        ))));
+        assert!(check(&|r| r.add_range(KeyRange::new(
+            1,
+            "o".into(),
+            "".into()
+        ))));
+        assert!(!check(&|r| r.add_range(KeyRange::new(
+            1,
+            "a".into(),
+            "m".into()
+        ))));
+    }
+
+    #[cfg(not(feature = "sync"))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_range_read_conflict() {
+        use crate::{error::MorsError, txn::error::TxnError, MorsBuilder};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut builder = MorsBuilder::default();
+        builder.set_dir(dir.path().to_path_buf());
+        let mors = builder.build().await.unwrap();
+
+        let mut scan = mors.begin_write().await.unwrap();
+        scan.track_read_range("a".into(), "c".into()).unwrap();
+        scan.set("x".into(), "1".into()).unwrap();
+        let mut outside = mors.begin_write().await.unwrap();
+        outside.track_read_range("c".into(), "".into()).unwrap();
+        outside.set("y".into(), "1".into()).unwrap();
+
+        let mut writer = mors.begin_write().await.unwrap();
+        writer.set("b".into(), "1".into()).unwrap();
+        writer.commit().await.unwrap();
+
+        assert!(matches!(
+            scan.commit().await,
+            Err(MorsError::TxnManagerError(TxnError::Conflict))
+        ));
+        outside.commit().await.unwrap();
+    }
+
+    #[cfg(not(feature = "sync"))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_iter_read_conflict() {
+        use crate::{error::MorsError, txn::error::TxnError, MorsBuilder};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut builder = MorsBuilder::default();
+        builder.set_dir(dir.path().to_path_buf());
+        let mors = builder.build().await.unwrap();
+        let mut txn = mors.begin_write().await.unwrap();
+        for key in ["a", "b", "d"] {
+            txn.set(key.into(), "1".into()).unwrap();
+        }
+        txn.commit().await.unwrap();
+
+        // reads "a" and "b" only.
+        let mut head = mors.begin_write().await.unwrap();
+        {
+            let mut iter = head.iter().unwrap();
+            assert_eq!(iter.next().await.unwrap().unwrap().key(), "a");
+            assert_eq!(iter.next().await.unwrap().unwrap().key(), "b");
+        }
+        head.set("x".into(), "1".into()).unwrap();
+        // reads every key from "c" on.
+        let mut tail = mors.begin_write().await.unwrap();
+        {
+            let mut iter = tail.iter().unwrap();
+            iter.seek("c".into());
+            assert_eq!(iter.next().await.unwrap().unwrap().key(), "d");
+            assert!(iter.next().await.unwrap().is_none());
+        }
+        tail.set("y".into(), "1".into()).unwrap();
+        // reads "a" only, then nothing past "e".
+        let mut skip = mors.begin_write().await.unwrap();
+        {
+            let mut iter = skip.iter().unwrap();
+            assert_eq!(iter.next().await.unwrap().unwrap().key(), "a");
+            iter.seek("e".into());
+        }
+        skip.set("z".into(), "1".into()).unwrap();
+
+        let mut writer = mors.begin_write().await.unwrap();
+        writer.set("ab".into(), "1".into()).unwrap();
+        writer.set("e".into(), "1".into()).unwrap();
+        writer.commit().await.unwrap();
+
+        assert!(matches!(
+            head.commit().await,
+            Err(MorsError::TxnManagerError(TxnError::Conflict))
+        ));
+        assert!(matches!(
+            tail.commit().await,
+            Err(MorsError::TxnManagerError(TxnError::Conflict))
+        ));
+        skip.commit().await.unwrap();
+    }
+}
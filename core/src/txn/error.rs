@@ -15,10 +15,18 @@ pub enum TxnError {
     ExceedSize(&'static str, usize, usize),
     #[error("Key is using a reserved {0} prefix")]
     InvalidKey(&'static str),
+    #[error("Range start {0:?} must be less than end {1:?}")]
+    InvalidRange(bytes::Bytes, bytes::Bytes),
+    #[error("Column family {0} not found")]
+    ColumnFamilyNotFound(u32),
     #[error("Txn is too big to fit into one request")]
     TxnTooBig,
     #[error("Transaction Conflict. Please retry")]
     Conflict,
+    #[error("Timed out waiting for a key lock")]
+    LockTimeout,
+    #[error("Deadlock detected while waiting for a key lock")]
+    Deadlock,
     #[error("Core Error: {0}")]
     CoreError(String),
 }
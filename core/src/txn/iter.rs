@@ -0,0 +1,355 @@
+use bytes::Bytes;
+use mors_common::kv::{ColumnFamilyId, Entry, Meta, ValueMeta};
+use mors_common::ts::{KeyTs, KeyTsBorrow, TxnTs};
+use mors_traits::iter::{
+    CacheIterator, IterError, KvCacheIter, KvCacheIterator,
+    KvCacheMergeIterator, KvSeekIter, RangeDeleteIter,
+};
+use mors_traits::kms::Kms;
+use mors_traits::levelctl::LevelCtlTrait;
+use mors_traits::memtable::MemtableTrait;
+use mors_traits::skip_list::SkipListTrait;
+use mors_traits::sstable::TableTrait;
+use mors_traits::vlog::VlogCtlTrait;
+
+use super::conflict::KeyRange;
+use super::error::TxnError;
+use super::WriteTxn;
+use crate::error::MorsError;
+use crate::KvEntry;
+
+type Result<T> = std::result::Result<T, MorsError>;
+
+/// the live keys of a column family in key order, as the transaction it
+/// was created from reads them: the commits at its read timestamp without
+/// what range tombstones delete, and its own writes on top. The range it
+/// reads conflicts with later writes into it, as if tracked with
+/// `track_read_range`.
+pub struct TxnIter<
+    'a,
+    M: MemtableTrait<S, K>,
+    K: Kms,
+    L: LevelCtlTrait<T, K>,
+    T: TableTrait<K::Cipher>,
+    S: SkipListTrait,
+    V: VlogCtlTrait<K>,
+> {
+    txn: &'a WriteTxn<M, K, L, T, S, V>,
+    cf: ColumnFamilyId,
+    levelctl: L,
+    // None without memtables and tables.
+    iter: Option<RangeDeleteIter<KvCacheMergeIterator<'static>>>,
+    // where the next call starts, None once it did.
+    seek: Option<Bytes>,
+    // the key seeked last, where the range read from it starts.
+    start: Bytes,
+    // where the range read from `start` ends, empty past the last key, None
+    // before the first call.
+    read_end: Option<Bytes>,
+    // whether `iter` holds a version not looked at yet.
+    valid: bool,
+    // the key whose newest visible version was taken already.
+    last_key: Vec<u8>,
+    // the next committed key and its newest visible version.
+    committed: Option<(Bytes, TxnTs, ValueMeta)>,
+    // the keys written by the transaction from the seek key on, by key.
+    pending: Vec<Entry>,
+    pending_index: usize,
+}
+impl<
+        'a,
+        M: MemtableTrait<S, K>,
+        K: Kms,
+        L: LevelCtlTrait<T, K>,
+        T: TableTrait<K::Cipher>,
+        S: SkipListTrait,
+        V: VlogCtlTrait<K>,
+    > TxnIter<'a, M, K, L, T, S, V>
+{
+    pub(super) fn new(
+        txn: &'a WriteTxn<M, K, L, T, S, V>,
+        cf: ColumnFamilyId,
+    ) -> Result<Self> {
+        if txn.discard {
+            return Err(TxnError::DiscardTxn.into());
+        }
+        let inner = txn.core.inner();
+        let levelctl = inner
+            .levelctl(cf)
+            .ok_or(TxnError::ColumnFamilyNotFound(cf))?;
+
+        let mut memtables = Vec::from_iter(inner.read_memtable()?);
+        memtables.extend(inner.immut_memtable().read()?.iter().cloned());
+        let mut iters: Vec<Box<dyn KvCacheIterator<ValueMeta>>> = Vec::new();
+        let mut tombstones = Vec::new();
+        let mut max_version = levelctl.max_version();
+        for memtable in memtables {
+            tombstones.extend(memtable.range_tombstones(cf));
+            max_version = max_version.max(memtable.max_version());
+            for (id, list) in memtable.skip_lists() {
+                if id == cf {
+                    iters.push(Box::new(MemIter::new(list)));
+                }
+            }
+        }
+        let (level_iters, level_tombstones) = levelctl.iters();
+        iters.extend(level_iters);
+        tombstones.extend(level_tombstones);
+
+        let iter = KvCacheMergeIterator::new(iters).map(|merge| {
+            let mut iter = RangeDeleteIter::new(merge, tombstones, txn.read_ts);
+            iter.set_max_version(max_version);
+            iter
+        });
+        let mut txn_iter = Self {
+            txn,
+            cf,
+            levelctl,
+            iter,
+            seek: None,
+            start: Bytes::new(),
+            read_end: None,
+            valid: false,
+            last_key: Vec::new(),
+            committed: None,
+            pending: Vec::new(),
+            pending_index: 0,
+        };
+        txn_iter.seek(Bytes::new());
+        Ok(txn_iter)
+    }
+    /// moves to the first key at or after `key`, read by the next call.
+    pub fn seek(&mut self, key: Bytes) {
+        self.track_read();
+        self.pending = self
+            .txn
+            .pending_writes
+            .iter()
+            .filter(|((cf, k), _)| *cf == self.cf && *k >= key)
+            .map(|(_, entry)| entry.clone())
+            .collect();
+        self.pending.sort_by(|a, b| a.key().cmp(b.key()));
+        self.pending_index = 0;
+        self.committed = None;
+        self.last_key.clear();
+        self.start = key.clone();
+        self.seek = Some(key);
+    }
+    // records the keys read since the last seek, a commit fails if another
+    // transaction wrote into them.
+    fn track_read(&mut self) {
+        if let Some(end) = self.read_end.take() {
+            let range = KeyRange::new(self.cf, self.start.clone(), end);
+            self.txn.reads.lock().add_range(range);
+        }
+    }
+    // the newest visible version of the next committed key, deletes
+    // included.
+    fn next_committed(&mut self) -> Result<Option<(Bytes, TxnTs, ValueMeta)>> {
+        let read_ts = self.txn.read_ts;
+        let Some(iter) = self.iter.as_mut() else {
+            return Ok(None);
+        };
+        if let Some(key) = self.seek.take() {
+            let seek = KeyTs::new(key, read_ts).encode();
+            self.valid = iter.seek(seek.as_slice().into())?;
+        }
+        while self.valid {
+            let (Some(k), Some(value)) = (iter.key(), iter.value()) else {
+                break;
+            };
+            let (key, txn_ts) = (k.key(), k.txn_ts());
+            let newest = txn_ts <= read_ts
+                && key != self.last_key.as_slice()
+                && !value.meta().contains(Meta::RANGE_DELETE);
+            let version = newest.then(|| (Bytes::copy_from_slice(key), txn_ts));
+            if let Some((key, _)) = version.as_ref() {
+                self.last_key = key.to_vec();
+            }
+            self.valid = iter.next()?;
+            if let Some((key, txn_ts)) = version {
+                return Ok(Some((key, txn_ts, value)));
+            }
+        }
+        Ok(None)
+    }
+    fn next_entry(&mut self) -> Result<Option<KvEntry>> {
+        let entry = self.next_live()?;
+        // up to the key returned, or every key after the start once none is.
+        let end = match entry.as_ref() {
+            Some(entry) => {
+                let mut end = entry.key().to_vec();
+                end.push(0);
+                end.into()
+            }
+            None => Bytes::new(),
+        };
+        self.read_end = Some(end);
+        Ok(entry)
+    }
+    fn next_live(&mut self) -> Result<Option<KvEntry>> {
+        loop {
+            if self.committed.is_none() {
+                self.committed = self.next_committed()?;
+            }
+            let pending = self.pending.get(self.pending_index);
+            let take_pending = match (pending, self.committed.as_ref()) {
+                (None, None) => return Ok(None),
+                (Some(p), Some((key, _, _))) => p.key() <= key,
+                (pending, _) => pending.is_some(),
+            };
+            if take_pending {
+                let entry = self.pending[self.pending_index].clone();
+                self.pending_index += 1;
+                if self
+                    .committed
+                    .as_ref()
+                    .is_some_and(|(key, _, _)| key == entry.key())
+                {
+                    self.committed = None;
+                }
+                if entry.is_deleted_or_expired() {
+                    continue;
+                }
+                let mut entry = entry;
+                entry.set_version(self.txn.read_ts);
+                return Ok(Some(entry.into()));
+            }
+            let (key, txn_ts, value) = self.committed.take().unwrap();
+            // deleted by a range delete of the transaction itself.
+            if self
+                .txn
+                .range_deletes
+                .iter()
+                .any(|(cf, t)| *cf == self.cf && t.contains(&key))
+            {
+                continue;
+            }
+            if (value.meta().is_empty() && value.value().is_empty())
+                || value.is_deleted_or_expired()
+            {
+                continue;
+            }
+            let value = self.levelctl.resolve_blob(value)?;
+            let mut entry: Entry = (KeyTs::new(key, txn_ts), value).into();
+            entry.set_version(txn_ts);
+            return Ok(Some(entry.into()));
+        }
+    }
+    /// the next live key and its value, None past the last one.
+    #[cfg(not(feature = "sync"))]
+    pub async fn next(&mut self) -> Result<Option<KvEntry>> {
+        self.next_entry()
+    }
+}
+impl<
+        M: MemtableTrait<S, K>,
+        K: Kms,
+        L: LevelCtlTrait<T, K>,
+        T: TableTrait<K::Cipher>,
+        S: SkipListTrait,
+        V: VlogCtlTrait<K>,
+    > Drop for TxnIter<'_, M, K, L, T, S, V>
+{
+    fn drop(&mut self) {
+        self.track_read();
+    }
+}
+#[cfg(feature = "sync")]
+impl<
+        M: MemtableTrait<S, K>,
+        K: Kms,
+        L: LevelCtlTrait<T, K>,
+        T: TableTrait<K::Cipher>,
+        S: SkipListTrait,
+        V: VlogCtlTrait<K>,
+    > Iterator for TxnIter<'_, M, K, L, T, S, V>
+{
+    type Item = Result<KvEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+// the entries of a memtable list copied out a chunk at a time, so the merge
+// iterator does not borrow the list and new writes are not waited for.
+struct MemIter<S> {
+    list: S,
+    chunk: Vec<(Vec<u8>, ValueMeta)>,
+    index: usize,
+    // the list holds nothing past the chunk.
+    last: bool,
+}
+impl<S: SkipListTrait> MemIter<S> {
+    const CHUNK_LEN: usize = 256;
+    fn new(list: S) -> Self {
+        Self {
+            list,
+            chunk: Vec::new(),
+            index: 0,
+            last: false,
+        }
+    }
+    // copies the entries from `key` on, past it unless `inclusive`.
+    fn fill(
+        &mut self,
+        key: KeyTsBorrow<'_>,
+        inclusive: bool,
+    ) -> std::result::Result<bool, IterError> {
+        let mut iter = self.list.iter();
+        let mut valid = iter.seek(key)?;
+        if !inclusive && valid && iter.key().is_some_and(|k| k == key) {
+            valid = iter.next()?;
+        }
+        let mut chunk = Vec::with_capacity(Self::CHUNK_LEN);
+        while valid && chunk.len() < Self::CHUNK_LEN {
+            match (iter.key(), iter.value()) {
+                (Some(k), Some(v)) => chunk.push((k.to_vec(), v)),
+                _ => break,
+            }
+            valid = iter.next()?;
+        }
+        self.chunk = chunk;
+        self.index = 0;
+        self.last = !valid;
+        Ok(!self.chunk.is_empty())
+    }
+}
+impl<S: SkipListTrait> CacheIterator for MemIter<S> {
+    fn next(&mut self) -> std::result::Result<bool, IterError> {
+        if self.chunk.is_empty() && !self.last {
+            let first = KeyTs::new(Bytes::new(), u64::MAX.into()).encode();
+            return self.fill(first.as_slice().into(), true);
+        }
+        if self.index + 1 < self.chunk.len() {
+            self.index += 1;
+            return Ok(true);
+        }
+        if self.last {
+            self.index = self.chunk.len();
+            return Ok(false);
+        }
+        let (key, _) = self.chunk.pop().unwrap();
+        self.fill(key.as_slice().into(), false)
+    }
+}
+impl<S: SkipListTrait> KvCacheIter<ValueMeta> for MemIter<S> {
+    fn key(&self) -> Option<KeyTsBorrow<'_>> {
+        let (key, _) = self.chunk.get(self.index)?;
+        Some(key.as_slice().into())
+    }
+
+    fn value(&self) -> Option<ValueMeta> {
+        let (_, value) = self.chunk.get(self.index)?;
+        Some(value.clone())
+    }
+}
+impl<S: SkipListTrait> KvSeekIter for MemIter<S> {
+    fn seek(
+        &mut self,
+        k: KeyTsBorrow<'_>,
+    ) -> std::result::Result<bool, IterError> {
+        self.fill(k, true)
+    }
+}
+impl<S: SkipListTrait> KvCacheIterator<ValueMeta> for MemIter<S> {}
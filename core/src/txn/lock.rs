@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bytes::Bytes;
+use mors_common::kv::{ColumnFamilyId, RangeTombstone};
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+use tokio::time::{timeout_at, Instant};
+
+use super::error::TxnError;
+use super::Result;
+
+pub(crate) type LockKey = (ColumnFamilyId, Bytes);
+
+/// Per-key exclusive locks held by transactions until they finish.
+///
+/// A waiting transaction waits for exactly one lock, so the wait-for graph
+/// is a set of chains and a deadlock is found by following the chain
+/// from the owner back to the waiter.
+#[derive(Default)]
+pub(crate) struct LockTable {
+    state: Mutex<LockState>,
+    released: Notify,
+}
+#[derive(Default)]
+struct LockState {
+    owners: HashMap<LockKey, u64>,
+    waits_for: HashMap<u64, u64>,
+}
+impl LockState {
+    fn would_deadlock(&self, txn: u64, owner: u64) -> bool {
+        let mut next = Some(owner);
+        for _ in 0..=self.waits_for.len() {
+            match next {
+                Some(n) if n == txn => return true,
+                Some(n) => next = self.waits_for.get(&n).copied(),
+                None => return false,
+            }
+        }
+        false
+    }
+}
+impl LockTable {
+    /// locks `key` for `txn`, waiting up to `wait` for the owner to release it.
+    /// Returns false if `txn` already held the lock.
+    pub(crate) async fn lock(
+        &self,
+        txn: u64,
+        key: &LockKey,
+        wait: Duration,
+    ) -> Result<bool> {
+        let deadline = Instant::now() + wait;
+        loop {
+            let notified = self.released.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            {
+                let mut state = self.state.lock();
+                match state.owners.get(key).copied() {
+                    None => {
+                        state.owners.insert(key.clone(), txn);
+                        state.waits_for.remove(&txn);
+                        return Ok(true);
+                    }
+                    Some(owner) if owner == txn => {
+                        state.waits_for.remove(&txn);
+                        return Ok(false);
+                    }
+                    Some(owner) => {
+                        if state.would_deadlock(txn, owner) {
+                            state.waits_for.remove(&txn);
+                            return Err(TxnError::Deadlock);
+                        }
+                        state.waits_for.insert(txn, owner);
+                    }
+                }
+            }
+            if timeout_at(deadline, notified).await.is_err() {
+                self.state.lock().waits_for.remove(&txn);
+                return Err(TxnError::LockTimeout);
+            }
+        }
+    }
+    /// whether a transaction other than `txn` holds the lock of a key in
+    /// `keys` or in one of `ranges`.
+    pub(crate) fn is_locked<'a>(
+        &self,
+        txn: u64,
+        mut keys: impl Iterator<Item = &'a LockKey>,
+        ranges: &[(ColumnFamilyId, RangeTombstone)],
+    ) -> bool {
+        let state = self.state.lock();
+        let other = |owner: &u64| *owner != txn;
+        keys.any(|key| state.owners.get(key).is_some_and(other))
+            || (!ranges.is_empty()
+                && state.owners.iter().any(|((cf, key), owner)| {
+                    other(owner)
+                        && ranges
+                            .iter()
+                            .any(|(c, t)| c == cf && t.contains(key))
+                }))
+    }
+    pub(crate) fn unlock<'a>(
+        &self,
+        txn: u64,
+        keys: impl Iterator<Item = &'a LockKey>,
+    ) {
+        let mut state = self.state.lock();
+        let mut released = false;
+        for key in keys {
+            if state.owners.get(key) == Some(&txn) {
+                state.owners.remove(key);
+                released = true;
+            }
+        }
+        drop(state);
+        if released {
+            self.released.notify_waiters();
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::LockTable;
+    use crate::txn::error::TxnError;
+
+    #[tokio::test]
+    async fn test_lock_table() {
+        let table = Arc::new(LockTable::default());
+        let wait = Duration::from_secs(5);
+        let (a, b) = ((0, "a".into()), (0, "b".into()));
+        assert!(table.lock(1, &a, wait).await.unwrap());
+        assert!(!table.lock(1, &a, wait).await.unwrap());
+        assert!(table.lock(2, &b, wait).await.unwrap());
+
+        assert!(matches!(
+            table.lock(2, &a, Duration::from_millis(10)).await,
+            Err(TxnError::LockTimeout)
+        ));
+        let waiter = {
+            let table = table.clone();
+            let a = a.clone();
+            tokio::spawn(async move { table.lock(2, &a, wait).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(matches!(
+            table.lock(1, &b, wait).await,
+            Err(TxnError::Deadlock)
+        ));
+        table.unlock(1, [a].iter());
+        assert!(waiter.await.unwrap().unwrap());
+    }
+
+    #[cfg(not(feature = "sync"))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_pessimistic_counter() {
+        use std::{cell::RefCell, rc::Rc};
+
+        use crate::MorsBuilder;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut builder = MorsBuilder::default();
+        builder.set_dir(dir.path().to_path_buf());
+        let mors = builder.build().await.unwrap();
+
+        // every increment must see the version committed by the previous one.
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        // commit futures are not Send, so the workers run on a LocalSet.
+        let local = tokio::task::LocalSet::new();
+        for _ in 0..4 {
+            let mors = mors.clone();
+            let seen = seen.clone();
+            local.spawn_local(async move {
+                for _ in 0..25 {
+                    let mut txn = mors.begin_pessimistic().await.unwrap();
+                    let version = txn
+                        .get_for_update("n".into())
+                        .await
+                        .map(|e| e.entry.version().to_u64())
+                        .unwrap_or_default();
+                    seen.borrow_mut().push(version);
+                    txn.set("n".into(), "+1".into()).unwrap();
+                    txn.commit().await.unwrap();
+                }
+            });
+        }
+        local.await;
+        let mut seen = seen.take();
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 100);
+    }
+
+    #[cfg(not(feature = "sync"))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_optimistic_write_locked() {
+        use std::ops::Deref;
+
+        use mors_common::kv::DEFAULT_COLUMN_FAMILY;
+
+        use crate::{error::MorsError, txn::error::TxnError, MorsBuilder};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut builder = MorsBuilder::default();
+        builder.set_dir(dir.path().to_path_buf());
+        let mors = builder.build().await.unwrap();
+
+        let mut locker = mors.begin_pessimistic().await.unwrap();
+        assert!(locker.get_for_update("k".into()).await.is_err());
+        // writing the locked key fails instead of waiting for it.
+        let mut txn = mors.begin_write().await.unwrap();
+        txn.set("k".into(), "1".into()).unwrap();
+        assert!(matches!(
+            txn.commit().await,
+            Err(MorsError::TxnManagerError(TxnError::Conflict))
+        ));
+        let mut txn = mors.begin_write().await.unwrap();
+        txn.delete_range("a".into(), "z".into()).unwrap();
+        assert!(matches!(
+            txn.commit().await,
+            Err(MorsError::TxnManagerError(TxnError::Conflict))
+        ));
+        // other keys are written without locking them.
+        let mut txn = mors.begin_write().await.unwrap();
+        txn.set("l".into(), "1".into()).unwrap();
+        txn.commit().await.unwrap();
+        locker.set("l".into(), "2".into()).unwrap();
+        locker.commit().await.unwrap();
+
+        // the locked key is read at the latest commit, not the snapshot.
+        let mut locker = mors.begin_pessimistic().await.unwrap();
+        let mut txn = mors.begin_write().await.unwrap();
+        txn.set("k".into(), "1".into()).unwrap();
+        txn.commit().await.unwrap();
+        let snapshot = locker.deref().get(DEFAULT_COLUMN_FAMILY, "k".into());
+        assert!(snapshot.await.is_err());
+        assert!(locker.get_for_update("k".into()).await.is_ok());
+    }
+}
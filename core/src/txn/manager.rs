@@ -1,17 +1,21 @@
-use std::sync::atomic::Ordering;
-use std::{collections::HashSet, sync::Arc};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use mors_common::ts::TxnTs;
 
 use mors_traits::kms::Kms;
-use mors_traits::levelctl::LevelCtlTrait;
+use mors_traits::levelctl::{LevelCtlTrait, SnapshotList};
 use mors_traits::memtable::MemtableTrait;
 use mors_traits::skip_list::SkipListTrait;
 use mors_traits::sstable::TableTrait;
 use mors_traits::vlog::VlogCtlTrait;
 use parking_lot::Mutex;
 
+use super::conflict::{ConflictMode, WriteSet};
 use super::error::TxnError;
+use super::lock::{LockKey, LockTable};
 use super::mark::WaterMark;
 use super::{Result, WriteTxn};
 
@@ -23,6 +27,13 @@ pub(crate) struct TxnManagerInner {
     txn_mark: WaterMark,
     config: TxnManagerBuilder,
     send_write_req: Mutex<()>,
+    lock_table: LockTable,
+    next_txn_id: AtomicU64,
+    // read timestamps of the live transactions and how many use each.
+    snapshots: Mutex<BTreeMap<TxnTs, usize>>,
+    // commit timestamps by the time they were taken, at most one a second,
+    // used to find the versions within the retention window.
+    history: Mutex<VecDeque<(SystemTime, TxnTs)>>,
 }
 #[derive(Debug, Default)]
 pub(crate) struct TxnManagerCore {
@@ -34,7 +45,7 @@ pub(crate) struct TxnManagerCore {
 #[derive(Debug, Default, Clone)]
 struct CommittedTxn {
     ts: TxnTs,
-    conflict_keys: HashSet<u64>,
+    conflict_keys: WriteSet,
 }
 #[derive(Debug, Clone, Copy)]
 pub struct TxnManagerBuilder {
@@ -47,6 +58,9 @@ pub struct TxnManagerBuilder {
     // This is only useful for databases built on top of Badger (like Dgraph).
     // Not recommended for most users.
     managed: bool,
+    conflict_mode: ConflictMode,
+    lock_timeout: Duration,
+    retention_window: Option<Duration>,
 }
 impl Default for TxnManagerBuilder {
     fn default() -> Self {
@@ -54,9 +68,36 @@ impl Default for TxnManagerBuilder {
             read_only: false,
             detect_conflicts: true,
             managed: false,
+            conflict_mode: ConflictMode::default(),
+            lock_timeout: Duration::from_secs(5),
+            retention_window: None,
         }
     }
 }
+impl TxnManagerBuilder {
+    /// how reads are checked against committed writes, default [`ConflictMode::Hash`].
+    pub fn set_conflict_mode(
+        &mut self,
+        conflict_mode: ConflictMode,
+    ) -> &mut Self {
+        self.conflict_mode = conflict_mode;
+        self
+    }
+    /// how long a transaction waits for a key lock, default 5s.
+    pub fn set_lock_timeout(&mut self, lock_timeout: Duration) -> &mut Self {
+        self.lock_timeout = lock_timeout;
+        self
+    }
+    /// keeps every version written within the window for time travel reads,
+    /// default None.
+    pub fn set_retention_window(
+        &mut self,
+        retention_window: Option<Duration>,
+    ) -> &mut Self {
+        self.retention_window = retention_window;
+        self
+    }
+}
 
 impl TxnManagerBuilder {
     pub(crate) async fn build(&self, max_version: TxnTs) -> Result<TxnManager> {
@@ -72,20 +113,29 @@ impl TxnManagerBuilder {
             ),
             txn_mark: WaterMark::new("TxnManager TxnTs Process", max_version),
             send_write_req: Mutex::new(()),
+            lock_table: LockTable::default(),
+            next_txn_id: AtomicU64::new(1),
+            snapshots: Mutex::new(BTreeMap::new()),
+            history: Mutex::new(VecDeque::from([(
+                SystemTime::now(),
+                max_version,
+            )])),
             config: *self,
         })))
     }
 }
 impl TxnManager {
+    // reads at the newest commit with every commit at or below it applied,
+    // the commits still in flight are not waited for.
     pub(super) async fn generate_read_ts(&self) -> Result<TxnTs> {
-        let read_ts = {
-            let core_lock = self.0.core.lock();
-            core_lock.next - 1
-        };
+        let read_ts = self.begin_snapshot();
         self.0.read_mark.begin(read_ts).await?;
-        self.0.txn_mark.wait_for_mark(read_ts).await?;
         Ok(read_ts)
     }
+    // the newest commit timestamp readers may see.
+    fn published_ts(&self) -> TxnTs {
+        self.0.txn_mark.done_until().load(Ordering::Acquire).into()
+    }
     #[allow(clippy::await_holding_lock)]
     pub(super) async fn generate_commit_ts<
         M: MemtableTrait<S, K>,
@@ -98,20 +148,28 @@ impl TxnManager {
         &self,
         txn: &WriteTxn<M, K, L, T, S, V>,
     ) -> Result<TxnTs> {
-        let read_key_hash = txn.read_key_hash.lock();
+        let reads = txn.reads.lock();
         let mut core = self.0.core.lock();
 
-        if !read_key_hash.is_empty() {
+        if !txn.pessimistic && !reads.is_empty() {
             for committed_txn in
                 core.committed.iter().filter(|c| c.ts > txn.read_ts)
             {
-                for hash in read_key_hash.iter() {
-                    if committed_txn.conflict_keys.contains(hash) {
-                        return Err(TxnError::Conflict);
-                    }
+                if committed_txn.conflict_keys.conflicts_with(&reads) {
+                    return Err(TxnError::Conflict);
                 }
             }
         }
+        // checked under the core lock, see `Self::locked_read_ts`.
+        if !txn.pessimistic
+            && self.0.lock_table.is_locked(
+                txn.id,
+                txn.pending_writes.keys(),
+                &txn.range_deletes,
+            )
+        {
+            return Err(TxnError::Conflict);
+        }
         self.0.read_mark.done(txn.read_ts).await?;
         if self.0.config.detect_conflicts {
             let max_read_tx: TxnTs =
@@ -130,6 +188,7 @@ impl TxnManager {
 
         let commit_ts = core.next;
         core.next += 1;
+        self.record_commit_ts(commit_ts);
         self.0.txn_mark.begin(commit_ts).await?;
 
         debug_assert!(commit_ts >= core.last_cleanup);
@@ -143,10 +202,134 @@ impl TxnManager {
 
         Ok(commit_ts)
     }
+    /// a commit timestamp for writes made outside transactions, like
+    /// ingested tables. [`Self::done_commit`] publishes them to readers.
+    #[allow(clippy::await_holding_lock)]
+    pub(crate) async fn generate_ts(&self) -> Result<TxnTs> {
+        let mut core = self.0.core.lock();
+        let commit_ts = core.next;
+        core.next += 1;
+        self.record_commit_ts(commit_ts);
+        self.0.txn_mark.begin(commit_ts).await?;
+        Ok(commit_ts)
+    }
+    fn record_commit_ts(&self, commit_ts: TxnTs) {
+        if self.0.config.retention_window.is_none() {
+            return;
+        }
+        let now = SystemTime::now();
+        let mut history = self.0.history.lock();
+        let recent = history.back().is_some_and(|(t, _)| {
+            now.duration_since(*t).unwrap_or_default() < Duration::from_secs(1)
+        });
+        if !recent {
+            history.push_back((now, commit_ts));
+        }
+    }
+    /// a read timestamp compaction keeps the versions of until
+    /// [`Self::release_snapshot`], the commits at or below it are applied.
+    pub(crate) fn begin_snapshot(&self) -> TxnTs {
+        let _core_lock = self.0.core.lock();
+        let read_ts = self.published_ts();
+        *self.0.snapshots.lock().entry(read_ts).or_default() += 1;
+        read_ts
+    }
+    /// the transaction reading at `read_ts` is done, compaction may drop
+    /// the versions only it reads.
+    pub(crate) fn release_snapshot(&self, read_ts: TxnTs) {
+        let mut snapshots = self.0.snapshots.lock();
+        if let Some(count) = snapshots.get_mut(&read_ts) {
+            *count -= 1;
+            if *count == 0 {
+                snapshots.remove(&read_ts);
+            }
+        }
+    }
+    /// the read timestamp of a transaction beginning now.
+    pub(crate) fn latest_read_ts(&self) -> TxnTs {
+        self.published_ts()
+    }
+    /// the read timestamp of the oldest live transaction.
+    pub(crate) fn oldest_snapshot(&self) -> Option<TxnTs> {
+        self.0.snapshots.lock().keys().next().copied()
+    }
+    /// `ts` capped at the latest commit timestamp readers may see.
+    pub(crate) fn visible_ts(&self, ts: TxnTs) -> TxnTs {
+        ts.min(self.published_ts())
+    }
+    /// publishes the commit `txn`, readers see it once the commits below
+    /// it are published as well.
     pub async fn done_commit(&self, txn: TxnTs) -> Result<()> {
         self.0.txn_mark.done(txn).await
     }
+    /// returns once readers see the commit `txn`, so a transaction begun
+    /// afterwards reads its writes.
+    pub(crate) async fn wait_published(&self, txn: TxnTs) -> Result<()> {
+        self.0.txn_mark.wait_for_mark(txn).await
+    }
     pub fn detect_conflicts(&self) -> bool {
         self.0.config.detect_conflicts
     }
+    pub fn conflict_mode(&self) -> ConflictMode {
+        self.0.config.conflict_mode
+    }
+    pub(super) fn next_txn_id(&self) -> u64 {
+        self.0.next_txn_id.fetch_add(1, Ordering::Relaxed)
+    }
+    pub(super) async fn lock(
+        &self,
+        txn_id: u64,
+        key: &LockKey,
+    ) -> Result<bool> {
+        self.0
+            .lock_table
+            .lock(txn_id, key, self.0.config.lock_timeout)
+            .await
+    }
+    /// the timestamp to read a key just locked at. An optimistic commit
+    /// fails once the key is locked, the ones taking their timestamp before
+    /// are published by then.
+    pub(super) async fn locked_read_ts(&self) -> Result<TxnTs> {
+        let ts = self.0.core.lock().next - 1;
+        self.0.txn_mark.wait_for_mark(ts).await?;
+        Ok(ts)
+    }
+    pub(super) fn unlock<'a>(
+        &self,
+        txn_id: u64,
+        keys: impl Iterator<Item = &'a LockKey>,
+    ) {
+        self.0.lock_table.unlock(txn_id, keys)
+    }
+}
+impl SnapshotList for TxnManager {
+    // a transaction beginning later reads at the latest published commit,
+    // which may be below commits already applied.
+    fn snapshots(&self) -> Vec<TxnTs> {
+        let mut snapshots =
+            self.0.snapshots.lock().keys().copied().collect::<Vec<_>>();
+        let published = self.published_ts();
+        if snapshots.last().is_none_or(|s| *s < published) {
+            snapshots.push(published);
+        }
+        snapshots
+    }
+    // a commit timestamp taken before the window, the newest version at or
+    // below it is kept too so the start of the window stays readable.
+    fn retained_since(&self) -> TxnTs {
+        let Some(window) = self.0.config.retention_window else {
+            return u64::MAX.into();
+        };
+        let Some(cutoff) = SystemTime::now().checked_sub(window) else {
+            return TxnTs::default();
+        };
+        let mut history = self.0.history.lock();
+        while history.len() > 1 && history[1].0 <= cutoff {
+            history.pop_front();
+        }
+        match history.front() {
+            Some((t, ts)) if *t <= cutoff => *ts,
+            _ => TxnTs::default(),
+        }
+    }
 }
@@ -4,26 +4,33 @@ use manager::TxnManager;
 use parking_lot::Mutex;
 use tokio::sync::oneshot;
 
+mod conflict;
 pub mod error;
+mod iter;
+mod lock;
 pub mod manager;
 mod mark;
+pub use conflict::ConflictMode;
+use conflict::{KeyRange, ReadSet, WriteSet};
+pub use iter::TxnIter;
+use lock::LockKey;
 type Result<T> = std::result::Result<T, TxnError>;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use std::str::from_utf8;
 use std::sync::atomic::AtomicI32;
 
 use bytes::Bytes;
-use mors_common::kv::{Entry, Meta};
-use mors_common::ts::{KeyTs, TxnTs};
+use mors_common::kv::{ColumnFamilyId, Entry, RangeTombstone};
+use mors_common::ts::TxnTs;
 use mors_traits::kms::Kms;
 use mors_traits::levelctl::LevelCtlTrait;
 use mors_traits::memtable::{MemtableBuilderTrait, MemtableTrait};
 use mors_traits::skip_list::SkipListTrait;
 use mors_traits::sstable::TableTrait;
 
-use crate::core::Core;
+use crate::core::{Core, CoreInner};
 use crate::error::MorsError;
 use crate::KvEntry;
 use lazy_static::lazy_static;
@@ -33,8 +40,8 @@ use rand::{thread_rng, Rng};
 
 /// Prefix for internal keys used by badger.
 const MORS_PREFIX: &[u8] = b"!mors!";
-/// For indicating end of entries in txn.
-const TXN_KEY: &[u8] = b"!mors!txn";
+/// Smallest key greater than every key with the reserved prefix.
+const MORS_PREFIX_END: &[u8] = b"!mors\"";
 /// For storing the banned namespaces.
 const BANNED_NAMESPACES_KEY: &[u8] = b"!mors!banned";
 lazy_static! {
@@ -67,12 +74,21 @@ pub struct WriteTxn<
     size: usize,
     count: usize,
     txn: TxnManager,
-    conflict_keys: Option<HashSet<u64>>,
-    pub(super) read_key_hash: Mutex<Vec<u64>>,
-    pending_writes: HashMap<Bytes, Entry>,
+    id: u64,
+    // commit skips conflict detection, keys are locked instead. Optimistic
+    // commits fail on keys locked by these.
+    pub(super) pessimistic: bool,
+    locks: HashSet<LockKey>,
+    pub(super) conflict_keys: Option<WriteSet>,
+    pub(super) reads: Mutex<ReadSet>,
+    pending_writes: HashMap<(ColumnFamilyId, Bytes), Entry>,
     duplicate_writes: Vec<Entry>,
+    range_deletes: Vec<(ColumnFamilyId, RangeTombstone)>,
     num_iters: AtomicI32,
     discard: bool,
+    // the read timestamp was taken from the txn manager, which keeps the
+    // versions it reads until the txn commits or is dropped.
+    snapshot: bool,
 }
 
 impl<
@@ -87,6 +103,7 @@ impl<
     pub(crate) async fn new(
         core: Core<M, K, L, T, S, V>,
         custom_txn: Option<TxnTs>,
+        pessimistic: bool,
     ) -> Result<Self> {
         let txn = core.inner().txn_manager().clone();
         let read_ts = match custom_txn {
@@ -94,76 +111,55 @@ impl<
             None => txn.generate_read_ts().await?,
         };
 
-        let conflict_keys = if txn.detect_conflicts() {
-            Some(HashSet::new())
-        } else {
-            None
-        };
+        let conflict_keys = txn
+            .detect_conflicts()
+            .then(|| WriteSet::new(txn.conflict_mode()));
         let write_txn = Self {
             read_ts,
             commit_ts: TxnTs::default(),
-            size: TXN_KEY.len() + 10,
-            count: 1,
+            size: 0,
+            count: 0,
+            id: txn.next_txn_id(),
             txn,
+            pessimistic,
+            locks: HashSet::new(),
             conflict_keys,
-            read_key_hash: Default::default(),
+            reads: Default::default(),
             pending_writes: HashMap::new(),
             duplicate_writes: Default::default(),
+            range_deletes: Default::default(),
             num_iters: AtomicI32::new(0),
             discard: false,
+            snapshot: custom_txn.is_none(),
             core,
         };
         Ok(write_txn)
     }
     pub(crate) fn modify(&mut self, mut entry: Entry) -> Result<()> {
-        const MAX_KEY_SIZE: usize = 65000;
         let core_inner = self.core.inner();
-        let threshold = core_inner.vlogctl().value_threshold();
-        let vlog_file_size = core_inner.vlogctl().vlog_file_size();
         let max_batch_count = core_inner.memtable_builder().max_batch_count();
         let max_batch_size = core_inner.memtable_builder().max_batch_size();
 
         if self.discard {
             return Err(TxnError::DiscardTxn);
         }
-        if entry.key().is_empty() {
-            return Err(TxnError::EmptyKey);
-        }
-        if entry.key().starts_with(MORS_PREFIX) {
-            return Err(TxnError::InvalidKey(from_utf8(MORS_PREFIX).unwrap()));
-        }
-        if entry.key().len() > MAX_KEY_SIZE {
-            return Err(TxnError::ExceedSize(
-                "Key",
-                entry.key().len(),
-                MAX_KEY_SIZE,
-            ));
-        }
-        if entry.value().len() > vlog_file_size {
-            return Err(TxnError::ExceedSize(
-                "Value",
-                entry.value().len(),
-                vlog_file_size,
-            ));
-        }
+        core_inner.prepare_entry(&mut entry)?;
 
         self.count += 1;
-        if entry.value_threshold() == 0 {
-            entry.set_value_threshold(threshold);
-        }
         self.size += entry.estimate_size(entry.value_threshold());
 
         if self.count >= max_batch_count || self.size >= max_batch_size {
             return Err(TxnError::TxnTooBig);
         }
 
+        let cf = entry.column_family();
         if let Some(c) = self.conflict_keys.as_mut() {
-            c.insert(HASH.hash_one(entry.key()));
+            c.add_key(cf, entry.key().clone());
         }
 
         let new_version = entry.version();
         if let Some(old) =
-            self.pending_writes.insert(entry.key().clone(), entry)
+            self.pending_writes.insert((cf, entry.key().clone()), entry)
         {
             if old.version() != new_version {
                 self.duplicate_writes.push(old);
@@ -171,8 +167,62 @@ impl<
         };
         Ok(())
     }
+    pub(crate) fn delete_range(
+        &mut self,
+        cf: ColumnFamilyId,
+        start: Bytes,
+        end: Bytes,
+    ) -> Result<()> {
+        const MAX_KEY_SIZE: usize = 65000;
+        let max_batch_count =
+            self.core.inner().memtable_builder().max_batch_count();
+        let max_batch_size =
+            self.core.inner().memtable_builder().max_batch_size();
+
+        if self.discard {
+            return Err(TxnError::DiscardTxn);
+        }
+        if !self.core.inner().has_cf(cf) {
+            return Err(TxnError::ColumnFamilyNotFound(cf));
+        }
+        if start.is_empty() {
+            return Err(TxnError::EmptyKey);
+        }
+        if start >= end {
+            return Err(TxnError::InvalidRange(start, end));
+        }
+        if start.as_ref() < MORS_PREFIX_END && end.as_ref() > MORS_PREFIX {
+            return Err(TxnError::InvalidKey(from_utf8(MORS_PREFIX).unwrap()));
+        }
+        for key in [&start, &end] {
+            if key.len() > MAX_KEY_SIZE {
+                return Err(TxnError::ExceedSize(
+                    "Key",
+                    key.len(),
+                    MAX_KEY_SIZE,
+                ));
+            }
+        }
+
+        self.count += 1;
+        self.size += start.len() + end.len() + 2;
+        if self.count >= max_batch_count || self.size >= max_batch_size {
+            return Err(TxnError::TxnTooBig);
+        }
+
+        // writes made earlier in this transaction are deleted as well.
+        self.pending_writes
+            .retain(|(c, k), _| !(*c == cf && start <= k && k < &end));
+        if let Some(c) = self.conflict_keys.as_mut() {
+            c.add_range(KeyRange::new(cf, start.clone(), end.clone()));
+        }
+        self.range_deletes
+            .push((cf, RangeTombstone::new(start, end, TxnTs::default())));
+        Ok(())
+    }
     pub(crate) async fn get(
         &self,
+        cf: ColumnFamilyId,
         key: Bytes,
     ) -> std::result::Result<KvEntry, MorsError> {
         if key.is_empty() {
@@ -181,52 +231,123 @@ impl<
         if self.discard {
             return Err(TxnError::DiscardTxn.into());
         }
-        if let Some(entry) = self.pending_writes.get(&key) {
-            if entry.key() == &key {
-                if entry.is_deleted_or_expired() {
-                    return Err(TxnError::KeyNotFound.into());
-                }
-                let mut entry_clone = entry.clone();
-                entry_clone.set_version(self.read_ts);
-
-                let mut kv_entry: KvEntry = entry_clone.into();
-                kv_entry.set_status(crate::PrefetchStatus::Prefetched);
-                return Ok(kv_entry);
+        if let Some(pending) = self.get_pending(cf, &key) {
+            return pending;
+        }
+        self.reads.lock().add_key(cf, key.clone());
+        self.core.inner().read_at(cf, key, self.read_ts).await
+    }
+    /// locks `key` until this transaction finishes and reads its latest
+    /// committed value, which no other transaction can change meanwhile.
+    pub(crate) async fn get_for_update(
+        &mut self,
+        cf: ColumnFamilyId,
+        key: Bytes,
+    ) -> std::result::Result<KvEntry, MorsError> {
+        if key.is_empty() {
+            return Err(TxnError::EmptyKey.into());
+        }
+        if self.discard {
+            return Err(TxnError::DiscardTxn.into());
+        }
+        if !self.core.inner().has_cf(cf) {
+            return Err(TxnError::ColumnFamilyNotFound(cf).into());
+        }
+        let lock_key = (cf, key.clone());
+        if self.txn.lock(self.id, &lock_key).await? {
+            self.locks.insert(lock_key);
+        }
+        if let Some(pending) = self.get_pending(cf, &key) {
+            return pending;
+        }
+        let read_ts = self.txn.locked_read_ts().await?;
+        self.core.inner().read_at(cf, key, read_ts).await
+    }
+    /// iterates the live keys of `cf` as this transaction reads them.
+    pub(crate) fn iter(
+        &self,
+        cf: ColumnFamilyId,
+    ) -> std::result::Result<TxnIter<'_, M, K, L, T, S, V>, MorsError> {
+        TxnIter::new(self, cf)
+    }
+    // the value written or deleted earlier in this transaction.
+    fn get_pending(
+        &self,
+        cf: ColumnFamilyId,
+        key: &Bytes,
+    ) -> Option<std::result::Result<KvEntry, MorsError>> {
+        if let Some(entry) = self.pending_writes.get(&(cf, key.clone())) {
+            if entry.is_deleted_or_expired() {
+                return Some(Err(TxnError::KeyNotFound.into()));
             }
+            let mut entry_clone = entry.clone();
+            entry_clone.set_version(self.read_ts);
+
+            let mut kv_entry: KvEntry = entry_clone.into();
+            kv_entry.set_status(crate::PrefetchStatus::Prefetched);
+            return Some(Ok(kv_entry));
         };
-        let hash = HASH.hash_one(&key);
+        if self
+            .range_deletes
+            .iter()
+            .any(|(c, t)| *c == cf && t.contains(key))
         {
-            let mut read_key_hash = self.read_key_hash.lock();
-            read_key_hash.push(hash);
-        }
-        let key_ts = KeyTs::new(key, self.read_ts);
-        match self.core.inner().get(&key_ts).await? {
-            Some((txn_ts, value)) => {
-                if value.is_none() {
-                    return Err(TxnError::ValueNotFound.into());
-                }
-                let value = value.unwrap();
-                if value.meta().is_empty() || value.is_deleted_or_expired() {
-                    return Err(TxnError::ValueNotFound.into());
-                }
-                let mut entry: Entry = (key_ts, value).into();
-                entry.set_version(txn_ts);
-                let kv_entry: KvEntry = entry.into();
-                Ok(kv_entry)
-            }
-            None => Err(TxnError::KeyNotFound.into()),
+            return Some(Err(TxnError::KeyNotFound.into()));
+        }
+        None
+    }
+    /// records that every key of `cf` in `[start, end)` was read, an empty `end`
+    /// is unbounded. The commit fails if another transaction writes into the range.
+    pub(crate) fn track_read_range(
+        &self,
+        cf: ColumnFamilyId,
+        start: Bytes,
+        end: Bytes,
+    ) -> Result<()> {
+        if self.discard {
+            return Err(TxnError::DiscardTxn);
         }
+        if !end.is_empty() && start >= end {
+            return Err(TxnError::InvalidRange(start, end));
+        }
+        self.reads.lock().add_range(KeyRange::new(cf, start, end));
+        Ok(())
     }
     pub(crate) async fn commit(
         &mut self,
     ) -> std::result::Result<(), MorsError> {
-        if self.pending_writes.is_empty() {
+        if self.pending_writes.is_empty() && self.range_deletes.is_empty() {
+            self.unlock_all();
+            self.release_snapshot();
             return Ok(());
         }
         if self.discard {
             return Err(TxnError::DiscardTxn.into());
         }
+        let result = self.commit_locked().await;
+        self.unlock_all();
+        self.release_snapshot();
+        // waited for without holding the keys.
+        self.txn.wait_published(result?).await?;
+        Ok(())
+    }
+    async fn commit_locked(
+        &mut self,
+    ) -> std::result::Result<TxnTs, MorsError> {
+        if self.pessimistic {
+            self.lock_writes().await?;
+        }
         let (commit_ts, recv) = self.commit_send().await?;
+        self.finish_commit(commit_ts, recv).await?;
+        Ok(commit_ts)
+    }
+    // waits for the write of `commit_ts` and publishes it to readers, in
+    // commit order through the txn mark.
+    async fn finish_commit(
+        &self,
+        commit_ts: TxnTs,
+        recv: oneshot::Receiver<std::result::Result<(), MorsError>>,
+    ) -> std::result::Result<(), MorsError> {
         let result = recv.await;
         self.core
             .inner()
@@ -236,6 +357,99 @@ impl<
         result.map_err(|e| MorsError::RecvError(e.to_string()))??;
         Ok(())
     }
+    /// sends the writes and returns without waiting for them, the receiver
+    /// gets the result of the commit. The keys of a pessimistic transaction
+    /// stay locked until then.
+    pub(crate) async fn commit_async(
+        mut self,
+    ) -> std::result::Result<
+        oneshot::Receiver<std::result::Result<(), MorsError>>,
+        MorsError,
+    > {
+        let (sender, receiver) = oneshot::channel();
+        if self.pending_writes.is_empty() && self.range_deletes.is_empty() {
+            let _ = sender.send(Ok(()));
+            return Ok(receiver);
+        }
+        if self.discard {
+            return Err(TxnError::DiscardTxn.into());
+        }
+        if self.pessimistic {
+            self.lock_writes().await?;
+        }
+        let (commit_ts, recv) = self.commit_send().await?;
+        tokio::spawn(async move {
+            let mut result = self.finish_commit(commit_ts, recv).await;
+            let txn_manager = self.txn.clone();
+            // unlocks the keys and releases the snapshot first.
+            drop(self);
+            if result.is_ok() {
+                result = txn_manager
+                    .wait_published(commit_ts)
+                    .await
+                    .map_err(Into::into);
+            }
+            let _ = sender.send(result);
+        });
+        Ok(receiver)
+    }
+    // every key a pessimistic transaction writes stays locked until its
+    // write is applied, so a transaction holding the lock reads the latest
+    // version.
+    async fn lock_writes(&mut self) -> Result<()> {
+        let mut keys = self
+            .pending_writes
+            .keys()
+            .filter(|k| !self.locks.contains(*k))
+            .cloned()
+            .collect::<Vec<_>>();
+        // a fixed order, so committing transactions never deadlock each other.
+        keys.sort();
+        for key in keys {
+            if self.txn.lock(self.id, &key).await? {
+                self.locks.insert(key);
+            }
+        }
+        Ok(())
+    }
+    fn unlock_all(&mut self) {
+        self.txn.unlock(self.id, self.locks.iter());
+        self.locks.clear();
+    }
+    // a committed txn reads nothing more.
+    fn release_snapshot(&mut self) {
+        if std::mem::take(&mut self.snapshot) {
+            self.txn.release_snapshot(self.read_ts);
+        }
+    }
+    // range tombstones of this txn as entries at commit_ts. The entry of a tombstone is
+    // keyed by its start, so the start moves past the keys this txn also writes;
+    // those keys are shadowed by the new writes anyway.
+    fn range_tombstone_entries(&mut self, commit_ts: TxnTs) -> Vec<Entry> {
+        let mut ranges = BTreeMap::<(ColumnFamilyId, Bytes), Bytes>::new();
+        for (cf, t) in self.range_deletes.drain(..) {
+            let end = ranges.entry((cf, t.start().clone())).or_default();
+            if *end < *t.end() {
+                *end = t.end().clone();
+            }
+        }
+        let mut entries = Vec::with_capacity(ranges.len());
+        for ((cf, start), end) in ranges {
+            let mut start = start;
+            while self.pending_writes.contains_key(&(cf, start.clone())) {
+                let mut next = start.to_vec();
+                next.push(0);
+                start = next.into();
+            }
+            if start < end {
+                let t = RangeTombstone::new(start, end, commit_ts);
+                let mut entry = t.to_entry();
+                entry.set_column_family(cf);
+                entries.push(entry);
+            }
+        }
+        entries
+    }
     pub(crate) async fn commit_send(
         &mut self,
     ) -> std::result::Result<
@@ -249,7 +463,6 @@ impl<
             .generate_commit_ts(self)
             .await?;
 
-        let mut keep_together = true;
         for entry in self
             .pending_writes
             .iter_mut()
@@ -258,36 +471,19 @@ impl<
         {
             if entry.version().is_empty() {
                 entry.set_version(commit_ts);
-            } else {
-                keep_together = false;
             }
         }
 
-        let mut entries = Vec::with_capacity(
-            self.pending_writes.len() + self.duplicate_writes.len() + 1,
-        );
-        for mut entry in self
+        let range_deletes = self.range_tombstone_entries(commit_ts);
+        // the entries are sent as one write request, which the wal appends
+        // as a single checksummed batch, so they are replayed all or nothing.
+        let entries = self
             .pending_writes
             .drain()
             .map(|x| x.1)
             .chain(self.duplicate_writes.drain(..))
-        {
-            if keep_together {
-                entry.meta_mut().insert(Meta::TXN);
-            }
-            entries.push(entry);
-        }
-
-        if keep_together {
-            debug_assert!(!commit_ts.is_empty());
-            let mut entry = Entry::new(
-                TXN_KEY.into(),
-                commit_ts.to_u64().to_string().into(),
-            );
-            entry.set_version(commit_ts);
-            entry.set_meta(Meta::FIN_TXN);
-            entries.push(entry);
-        }
+            .chain(range_deletes)
+            .collect::<Vec<_>>();
         let r = match self.core.inner().send_to_write_channel(entries).await {
             Ok(r) => r,
             Err(e) => {
@@ -302,6 +498,74 @@ impl<
         Ok((commit_ts, r))
     }
 }
+impl<
+        M: MemtableTrait<S, K>,
+        K: Kms,
+        L: LevelCtlTrait<T, K>,
+        T: TableTrait<K::Cipher>,
+        S: SkipListTrait,
+        V: VlogCtlTrait<K>,
+    > CoreInner<M, K, L, T, S, V>
+{
+    /// the most entries and bytes one write request may carry.
+    pub(crate) fn max_batch(&self) -> (usize, usize) {
+        let builder = self.memtable_builder();
+        (builder.max_batch_count(), builder.max_batch_size())
+    }
+    /// rejects the entries no write may carry and picks the value threshold
+    /// of the others.
+    pub(crate) fn prepare_entry(&self, entry: &mut Entry) -> Result<()> {
+        const MAX_KEY_SIZE: usize = 65000;
+        let threshold = self.vlogctl().value_threshold();
+        let vlog_file_size = self.vlogctl().vlog_file_size();
+
+        if !self.has_cf(entry.column_family()) {
+            return Err(TxnError::ColumnFamilyNotFound(entry.column_family()));
+        }
+        if entry.key().is_empty() {
+            return Err(TxnError::EmptyKey);
+        }
+        if entry.key().starts_with(MORS_PREFIX) {
+            return Err(TxnError::InvalidKey(from_utf8(MORS_PREFIX).unwrap()));
+        }
+        if entry.key().len() > MAX_KEY_SIZE {
+            return Err(TxnError::ExceedSize(
+                "Key",
+                entry.key().len(),
+                MAX_KEY_SIZE,
+            ));
+        }
+        if entry.value().len() > vlog_file_size {
+            return Err(TxnError::ExceedSize(
+                "Value",
+                entry.value().len(),
+                vlog_file_size,
+            ));
+        }
+        if entry.value_threshold() == 0 {
+            entry.set_value_threshold(
+                self.vlogctl()
+                    .value_separation()
+                    .threshold(entry.key(), threshold),
+            );
+        }
+        Ok(())
+    }
+}
+impl<
+        M: MemtableTrait<S, K>,
+        K: Kms,
+        L: LevelCtlTrait<T, K>,
+        T: TableTrait<K::Cipher>,
+        S: SkipListTrait,
+        V: VlogCtlTrait<K>,
+    > Drop for WriteTxn<M, K, L, T, S, V>
+{
+    fn drop(&mut self) {
+        self.unlock_all();
+        self.release_snapshot();
+    }
+}
 // impl<
 //         M: MemtableTrait<S, K>,
 //         K: Kms,
@@ -0,0 +1,340 @@
+use bytes::Bytes;
+use mors_common::{
+    kv::{Meta, RangeTombstone, ValueMeta},
+    ts::{KeyTs, KeyTsBorrow, PhyTs, TxnTs},
+};
+use mors_encrypt::{cipher::AesCipher, registry::MorsKms};
+use mors_skip_list::any::AnyList;
+use mors_sstable::table::Table;
+use mors_traits::{
+    iter::{
+        CacheIterator, IterError, KvCacheIter, KvCacheIterator,
+        KvCacheMergeIterator, KvSeekIter,
+    },
+    levelctl::LevelCtlTrait,
+    memtable::MemtableTrait,
+    skip_list::SkipListTrait,
+};
+
+use crate::core::Core;
+use crate::txn::error::TxnError;
+use crate::{
+    ColumnFamily, KvEntry, Mors, MorsLevelCtl, MorsMemtable, MorsVlog, Result,
+};
+
+/// one version of a key, as yielded by [`Versions`].
+#[derive(Debug, Clone)]
+pub struct KeyVersion {
+    version: TxnTs,
+    value: ValueMeta,
+}
+impl KeyVersion {
+    /// the commit timestamp of the version.
+    pub fn version(&self) -> TxnTs {
+        self.version
+    }
+    /// the value, empty for a delete.
+    pub fn value(&self) -> &Bytes {
+        self.value.value()
+    }
+    /// when the version expires, zero if it never does.
+    pub fn expires_at(&self) -> PhyTs {
+        self.value.expires_at()
+    }
+    pub fn user_meta(&self) -> u8 {
+        self.value.user_meta()
+    }
+    /// whether the version deletes the key, by itself or by a range delete.
+    pub fn is_deleted(&self) -> bool {
+        self.value.meta().contains(Meta::DELETE)
+    }
+}
+/// the retained versions of a key, newest first, merged across the
+/// memtables and the levels. They are read at the snapshot taken when it
+/// is created, compaction keeps what it reads until it is dropped.
+pub struct Versions {
+    core: Core<
+        MorsMemtable,
+        MorsKms,
+        MorsLevelCtl,
+        Table<AesCipher>,
+        AnyList,
+        MorsVlog,
+    >,
+    levelctl: MorsLevelCtl,
+    key: Bytes,
+    read_ts: TxnTs,
+    // the versions of the key in the memtables and the tables that may
+    // hold it, None once past them.
+    iter: Option<KvCacheMergeIterator<'static>>,
+    // whether `iter` was sought to the key yet.
+    started: bool,
+    // the next version of `iter`, not yielded yet.
+    point: Option<(TxnTs, ValueMeta)>,
+    // versions of the range tombstones containing the key, oldest first.
+    tombstones: Vec<TxnTs>,
+}
+impl Versions {
+    fn next_point(&mut self) -> Result<Option<(TxnTs, ValueMeta)>> {
+        let Some(iter) = self.iter.as_mut() else {
+            return Ok(None);
+        };
+        loop {
+            let valid = if self.started {
+                iter.next()?
+            } else {
+                self.started = true;
+                let key = KeyTs::new(self.key.clone(), self.read_ts).encode();
+                iter.seek(key.as_slice().into())?
+            };
+            let point = match (valid, iter.key(), iter.value()) {
+                (true, Some(k), Some(v)) if k.key() == self.key => {
+                    (k.txn_ts(), v)
+                }
+                _ => {
+                    self.iter = None;
+                    return Ok(None);
+                }
+            };
+            // the tombstone itself is yielded from `tombstones`.
+            if !point.1.meta().contains(Meta::RANGE_DELETE) {
+                return Ok(Some(point));
+            }
+        }
+    }
+    fn next_version(&mut self) -> Result<Option<KeyVersion>> {
+        if self.point.is_none() {
+            self.point = self.next_point()?;
+        }
+        let tombstone = self.tombstones.last().copied();
+        match self.point.take() {
+            Some((version, value))
+                if tombstone.is_none_or(|t| version >= t) =>
+            {
+                if tombstone == Some(version) {
+                    self.tombstones.pop();
+                }
+                let value = self.levelctl.resolve_blob(value)?;
+                Ok(Some(KeyVersion { version, value }))
+            }
+            point => {
+                self.point = point;
+                Ok(self.tombstones.pop().map(|version| KeyVersion {
+                    version,
+                    value: RangeTombstone::value_meta(),
+                }))
+            }
+        }
+    }
+    #[cfg(not(feature = "sync"))]
+    pub async fn next(&mut self) -> Result<Option<KeyVersion>> {
+        self.next_version()
+    }
+}
+#[cfg(feature = "sync")]
+impl Iterator for Versions {
+    type Item = Result<KeyVersion>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_version().transpose()
+    }
+}
+impl Drop for Versions {
+    fn drop(&mut self) {
+        self.core
+            .inner()
+            .txn_manager()
+            .release_snapshot(self.read_ts);
+    }
+}
+// the versions of a key copied out of the memtables, which the merge
+// iterator outlives.
+struct MemVersions {
+    versions: Vec<(Vec<u8>, ValueMeta)>,
+    index: Option<usize>,
+}
+impl CacheIterator for MemVersions {
+    fn next(&mut self) -> std::result::Result<bool, IterError> {
+        let index = self.index.map_or(0, |i| i + 1).min(self.versions.len());
+        self.index = Some(index);
+        Ok(index < self.versions.len())
+    }
+}
+impl KvCacheIter<ValueMeta> for MemVersions {
+    fn key(&self) -> Option<KeyTsBorrow<'_>> {
+        let (key, _) = self.versions.get(self.index?)?;
+        Some(key.as_slice().into())
+    }
+
+    fn value(&self) -> Option<ValueMeta> {
+        let (_, value) = self.versions.get(self.index?)?;
+        Some(value.clone())
+    }
+}
+impl KvSeekIter for MemVersions {
+    fn seek(
+        &mut self,
+        k: KeyTsBorrow<'_>,
+    ) -> std::result::Result<bool, IterError> {
+        let index = self
+            .versions
+            .partition_point(|(key, _)| KeyTsBorrow::from(key.as_slice()) < k);
+        self.index = Some(index);
+        Ok(index < self.versions.len())
+    }
+}
+impl KvCacheIterator<ValueMeta> for MemVersions {}
+impl Mors {
+    async fn get_at_impl(
+        &self,
+        cf: &ColumnFamily,
+        key: Bytes,
+        ts: TxnTs,
+    ) -> Result<KvEntry> {
+        if key.is_empty() {
+            return Err(TxnError::EmptyKey.into());
+        }
+        let inner = self.inner.core.inner();
+        let ts = inner.txn_manager().visible_ts(ts);
+        inner.read_at(cf.id(), key, ts).await
+    }
+    async fn versions_impl(
+        &self,
+        cf: &ColumnFamily,
+        key: Bytes,
+    ) -> Result<Versions> {
+        if key.is_empty() {
+            return Err(TxnError::EmptyKey.into());
+        }
+        let core = self.inner.core.clone();
+        let inner = core.inner();
+        let levelctl = inner
+            .levelctl(cf.id())
+            .ok_or(TxnError::ColumnFamilyNotFound(cf.id()))?;
+        let read_ts = inner.txn_manager().begin_snapshot();
+        let mut versions = Versions {
+            core: core.clone(),
+            levelctl,
+            key,
+            read_ts,
+            iter: None,
+            started: false,
+            point: None,
+            tombstones: Vec::new(),
+        };
+        let seek = KeyTs::new(versions.key.clone(), read_ts);
+
+        let mut memtables = Vec::from_iter(inner.read_memtable()?);
+        memtables.extend(inner.immut_memtable().read()?.iter().cloned());
+        let mut mem_versions = Vec::new();
+        for memtable in memtables {
+            versions.tombstones.extend(
+                memtable
+                    .range_tombstones(cf.id())
+                    .iter()
+                    .filter(|t| t.contains(&versions.key))
+                    .map(|t| t.version()),
+            );
+            for (id, list) in memtable.skip_lists() {
+                if id != cf.id() {
+                    continue;
+                }
+                let mut iter = list.iter();
+                let mut valid = iter.seek(seek.encode().as_slice().into())?;
+                while valid {
+                    match (iter.key(), iter.value()) {
+                        (Some(k), Some(v)) if k.key() == versions.key => {
+                            mem_versions.push((k.to_vec(), v));
+                        }
+                        _ => break,
+                    }
+                    valid = iter.next()?;
+                }
+            }
+        }
+        mem_versions.sort_by(|(a, _), (b, _)| {
+            KeyTsBorrow::from(a.as_slice()).cmp(&b.as_slice().into())
+        });
+        mem_versions.dedup_by(|(a, _), (b, _)| a == b);
+
+        let (mut iters, tombstones) = versions.levelctl.key_iters(&seek);
+        versions.tombstones.extend(
+            tombstones
+                .iter()
+                .filter(|t| t.contains(&versions.key))
+                .map(|t| t.version()),
+        );
+        versions.tombstones.retain(|t| *t <= read_ts);
+        versions.tombstones.sort_unstable();
+        versions.tombstones.dedup();
+        iters.insert(
+            0,
+            Box::new(MemVersions {
+                versions: mem_versions,
+                index: None,
+            }),
+        );
+        versions.iter = KvCacheMergeIterator::new(iters);
+        Ok(versions)
+    }
+    /// reads the newest version of `key` at or below `ts`, versions
+    /// compaction already dropped are not found.
+    #[cfg(not(feature = "sync"))]
+    pub async fn get_at(&self, key: Bytes, ts: TxnTs) -> Result<KvEntry> {
+        self.get_at_impl(&self.default_cf(), key, ts).await
+    }
+    #[cfg(feature = "sync")]
+    pub fn get_at(&self, key: Bytes, ts: TxnTs) -> Result<KvEntry> {
+        self.inner.runtime.block_on(self.get_at_impl(
+            &self.default_cf(),
+            key,
+            ts,
+        ))
+    }
+    #[cfg(not(feature = "sync"))]
+    pub async fn get_at_cf(
+        &self,
+        cf: &ColumnFamily,
+        key: Bytes,
+        ts: TxnTs,
+    ) -> Result<KvEntry> {
+        self.get_at_impl(cf, key, ts).await
+    }
+    #[cfg(feature = "sync")]
+    pub fn get_at_cf(
+        &self,
+        cf: &ColumnFamily,
+        key: Bytes,
+        ts: TxnTs,
+    ) -> Result<KvEntry> {
+        self.inner.runtime.block_on(self.get_at_impl(cf, key, ts))
+    }
+    /// the history of `key`: every version compaction kept, deletes
+    /// included, newest first.
+    #[cfg(not(feature = "sync"))]
+    pub async fn versions(&self, key: Bytes) -> Result<Versions> {
+        self.versions_impl(&self.default_cf(), key).await
+    }
+    #[cfg(feature = "sync")]
+    pub fn versions(&self, key: Bytes) -> Result<Versions> {
+        self.inner
+            .runtime
+            .block_on(self.versions_impl(&self.default_cf(), key))
+    }
+    #[cfg(not(feature = "sync"))]
+    pub async fn versions_cf(
+        &self,
+        cf: &ColumnFamily,
+        key: Bytes,
+    ) -> Result<Versions> {
+        self.versions_impl(cf, key).await
+    }
+    #[cfg(feature = "sync")]
+    pub fn versions_cf(
+        &self,
+        cf: &ColumnFamily,
+        key: Bytes,
+    ) -> Result<Versions> {
+        self.inner.runtime.block_on(self.versions_impl(cf, key))
+    }
+}
@@ -239,12 +239,18 @@ where
         Ok(())
     }
     async fn ensure_room_for_write(&self) -> Result<()> {
+        self.rotate_memtable(false).await
+    }
+    /// swaps the active memtable for a new one and sends it to be flushed,
+    /// unless it is not full and `force` is false.
+    pub(crate) async fn rotate_memtable(&self, force: bool) -> Result<()> {
+        let _rotation = self.memtable_rotation().lock().await;
         let memtable = self.memtable().unwrap();
         let new_memtable = {
             let memtable_r = memtable
                 .read()
                 .map_err(|e| MorsError::RwLockPoisoned(e.to_string()))?;
-            if !memtable_r.is_full() {
+            if !force && !memtable_r.is_full() {
                 return Ok(());
             }
             debug!(
@@ -292,8 +298,13 @@ where
                 entry.meta_mut().insert(Meta::VALUE_POINTER);
                 entry.set_value(vptr.encode());
             }
-            memtable_w.push(entry)?;
         }
+        let entries = request
+            .entries_vptrs
+            .iter()
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>();
+        memtable_w.push_batch(&entries)?;
         memtable_w.flush()?;
         Ok(())
     }
@@ -339,6 +350,7 @@ mod test {
     use crate::MorsBuilder;
     use log::LevelFilter;
     use log::{debug, info};
+    use mors_common::kv::DEFAULT_COLUMN_FAMILY;
     use mors_common::test::{gen_random_entries, get_rng};
     use mors_traits::default::DEFAULT_DIR;
     use std::{fs::create_dir, path::PathBuf};
@@ -407,7 +419,11 @@ mod test {
                 let mut count = 0;
                 let mut not_found = 0;
                 for entry in random_read {
-                    match db.inner().get(entry.key_ts()).await {
+                    match db
+                        .inner()
+                        .get(DEFAULT_COLUMN_FAMILY, entry.key_ts())
+                        .await
+                    {
                         Ok(r) => {
                             if let Some((txn_ts, _value)) = r {
                                 assert_eq!(txn_ts, entry.key_ts().txn_ts());
@@ -427,9 +443,9 @@ mod test {
                             eprintln!("Error: {:?}", e.to_string());
                         }
                     }
-                    // let k = db.inner().get(entry.key_ts()).await.map_err(|e|{eprintln!("Error: {:?}",e.to_string());});;
+                    // let k = db.inner().get(DEFAULT_COLUMN_FAMILY, entry.key_ts()).await.map_err(|e|{eprintln!("Error: {:?}",e.to_string());});;
                     // let (txn_ts, _value) =
-                    //     db.inner().get(entry.key_ts()).await.;
+                    //     db.inner().get(DEFAULT_COLUMN_FAMILY, entry.key_ts()).await.;
                 }
                 info!("{} Read completed", seed);
                 info!("{} Not found count:{}", seed, not_found);
@@ -443,4 +459,728 @@ mod test {
 
         Ok(())
     }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_memtable_kind() {
+        use crate::MemtableKind;
+        use mors_common::ts::KeyTs;
+
+        for kind in [
+            MemtableKind::SkipList,
+            MemtableKind::BTree,
+            MemtableKind::SortedVec,
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            let mut builder = MorsBuilder::default();
+            builder
+                .set_dir(dir.path().to_path_buf())
+                .set_memtable_options(kind);
+            let mors = builder.build().await.unwrap();
+
+            // out of order, the sorted vector has to insert in the middle.
+            let mut txn = mors.begin_write().await.unwrap();
+            for i in (0..100).rev() {
+                let key = format!("key{i:03}");
+                txn.set(key.into(), format!("{i}").into()).unwrap();
+            }
+            txn.commit().await.unwrap();
+
+            for i in 0..100 {
+                let key =
+                    KeyTs::new(format!("key{i:03}").into(), u64::MAX.into());
+                let (_, value) = mors
+                    .inner()
+                    .get(DEFAULT_COLUMN_FAMILY, &key)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(
+                    value.unwrap().value().as_ref(),
+                    format!("{i}").as_bytes()
+                );
+            }
+        }
+    }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_value_separation() {
+        use crate::{Separation, ValueSeparation};
+        use mors_common::{kv::Meta, ts::KeyTs};
+
+        let mut separation = ValueSeparation::default();
+        separation
+            .set_min_value_size(16)
+            .set_max_value_size(Some(64))
+            .add_prefix("scan/".into(), Separation::Inline)
+            .add_prefix("scan/blob/".into(), Separation::Separate);
+        let mut disabled = separation.clone();
+        disabled.set_disabled(true);
+        let values = [
+            ("small", 8, false),
+            ("medium", 32, false),
+            ("big", 64, true),
+            ("scan/big", 4096, false),
+            ("scan/blob/small", 1, true),
+            ("scan/blob/empty", 0, false),
+        ];
+
+        for (separation, disabled) in [(separation, false), (disabled, true)] {
+            let dir = tempfile::tempdir().unwrap();
+            let mut builder = MorsBuilder::default();
+            builder
+                .set_dir(dir.path().to_path_buf())
+                .set_value_separation(separation);
+            let mors = builder.build().await.unwrap();
+
+            let mut txn = mors.begin_write().await.unwrap();
+            for (key, len, _) in values {
+                txn.set(key.into(), vec![b'v'; len].into()).unwrap();
+            }
+            txn.commit().await.unwrap();
+
+            for (key, _, separated) in values {
+                let key = KeyTs::new(key.into(), u64::MAX.into());
+                let (_, value) = mors
+                    .inner()
+                    .get(DEFAULT_COLUMN_FAMILY, &key)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                let meta = value.unwrap().meta();
+                assert_eq!(
+                    meta.contains(Meta::VALUE_POINTER),
+                    separated && !disabled,
+                    "{key:?}"
+                );
+            }
+
+            // tombstones stay inline, even under a separated prefix.
+            let mut txn = mors.begin_write().await.unwrap();
+            txn.delete("scan/blob/small".into()).unwrap();
+            txn.commit().await.unwrap();
+            let key = KeyTs::new("scan/blob/small".into(), u64::MAX.into());
+            let (_, value) = mors
+                .inner()
+                .get(DEFAULT_COLUMN_FAMILY, &key)
+                .await
+                .unwrap()
+                .unwrap();
+            let meta = value.unwrap().meta();
+            assert!(meta.contains(Meta::DELETE));
+            assert!(!meta.contains(Meta::VALUE_POINTER));
+        }
+    }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_blob_files() {
+        use crate::{Separation, ValueSeparation};
+        use mors_common::{
+            file_id::{BlobFileId, FileId},
+            kv::Meta,
+            ts::KeyTs,
+        };
+        use std::time::Duration;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut separation = ValueSeparation::default();
+        separation.add_prefix("vlog/".into(), Separation::Separate);
+        let mut builder = MorsBuilder::default();
+        builder
+            .set_dir(dir.path().to_path_buf())
+            .set_value_separation(separation)
+            .set_blob_threshold(Some(1024));
+        let mors = builder.build().await.unwrap();
+        let core = mors.inner().clone();
+        let blob_files = || BlobFileId::parse_set_from_dir(dir.path());
+
+        // every flush writes a blob file, compacting the five level 0 tables
+        // drops the ones only overwritten values were in. The value of
+        // "partial" is moved out of the oldest one, which is dropped too.
+        let mut first = Default::default();
+        for i in 0..5 {
+            let mut txn = mors.begin_write().await.unwrap();
+            txn.set("big".into(), vec![i; 4096].into()).unwrap();
+            txn.set("small".into(), vec![i; 16].into()).unwrap();
+            if i == 0 {
+                txn.set("partial".into(), vec![i; 4096].into()).unwrap();
+                txn.set("vlog/small".into(), vec![i; 16].into()).unwrap();
+            }
+            txn.commit().await.unwrap();
+            core.flush_overlapping(
+                DEFAULT_COLUMN_FAMILY,
+                &[("big".into(), "vlog/small".into())],
+            )
+            .await
+            .unwrap();
+            if i == 0 {
+                first = blob_files();
+            }
+        }
+        assert_eq!(first.len(), 1);
+        for _ in 0..300 {
+            let files = blob_files();
+            if files.len() == 2 && files.is_disjoint(&first) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(blob_files().len(), 2);
+        assert!(blob_files().is_disjoint(&first));
+
+        for (key, len, i) in
+            [("big", 4096, 4), ("small", 16, 4), ("partial", 4096, 0)]
+        {
+            let key = KeyTs::new(key.into(), u64::MAX.into());
+            let (_, value) = core
+                .get(DEFAULT_COLUMN_FAMILY, &key)
+                .await
+                .unwrap()
+                .unwrap();
+            let value = value.unwrap();
+            assert!(!value.meta().contains(Meta::VALUE_POINTER));
+            assert!(!value.meta().contains(Meta::BLOB_INDEX));
+            assert_eq!(value.value().as_ref(), vec![i; len]);
+        }
+        // the separated prefixes still go to the vlog.
+        let key = KeyTs::new("vlog/small".into(), u64::MAX.into());
+        let (_, value) = core
+            .get(DEFAULT_COLUMN_FAMILY, &key)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(value.unwrap().meta().contains(Meta::VALUE_POINTER));
+    }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_compaction_filter() {
+        use mors_common::{kv::ValueMeta, ts::KeyTs, ts::TxnTs};
+        use mors_traits::{
+            levelctl::{CompactionDecision, CompactionFilter, Level},
+            vlog::ValueSeparation,
+        };
+        use std::{sync::Arc, time::Duration};
+
+        #[derive(Debug)]
+        struct ExpiredFilter;
+        impl CompactionFilter for ExpiredFilter {
+            fn filter(
+                &self,
+                _level: Level,
+                _key: &[u8],
+                _version: TxnTs,
+                value: &ValueMeta,
+            ) -> CompactionDecision {
+                match value.value().as_ref() {
+                    b"expired" => CompactionDecision::Remove,
+                    b"old" => CompactionDecision::Replace("new".into()),
+                    _ => CompactionDecision::Keep,
+                }
+            }
+        }
+        let dir = tempfile::tempdir().unwrap();
+        // values stay inline, so the filter sees them.
+        let mut separation = ValueSeparation::default();
+        separation.set_disabled(true);
+        let mut builder = MorsBuilder::default();
+        builder
+            .set_dir(dir.path().to_path_buf())
+            .set_value_separation(separation)
+            .set_compaction_filter(Some(Arc::new(ExpiredFilter)));
+        let mors = builder.build().await.unwrap();
+        let core = mors.inner().clone();
+        let get = |key: &'static str| {
+            let core = core.clone();
+            async move {
+                let key = KeyTs::new(key.into(), u64::MAX.into());
+                core.get(DEFAULT_COLUMN_FAMILY, &key)
+                    .await
+                    .unwrap()
+                    .and_then(|(_, v)| v)
+                    .filter(|v| !v.is_deleted_or_expired())
+                    .map(|v| v.value().clone())
+            }
+        };
+
+        // the first versions are below the discard timestamp once the later
+        // transactions are done, compacting the five level 0 tables filters
+        // them.
+        for i in 0..5u8 {
+            let mut txn = mors.begin_write().await.unwrap();
+            if i == 0 {
+                txn.set("a".into(), "expired".into()).unwrap();
+                txn.set("b".into(), "old".into()).unwrap();
+                txn.set("c".into(), "kept".into()).unwrap();
+            }
+            txn.set("d".into(), vec![i].into()).unwrap();
+            txn.commit().await.unwrap();
+            core.flush_overlapping(
+                DEFAULT_COLUMN_FAMILY,
+                &[("a".into(), "d".into())],
+            )
+            .await
+            .unwrap();
+        }
+        for _ in 0..300 {
+            if get("b").await.is_some_and(|v| v == "new") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(get("a").await, None);
+        assert_eq!(get("b").await, Some("new".into()));
+        assert_eq!(get("c").await, Some("kept".into()));
+        assert_eq!(get("d").await, Some(vec![4].into()));
+    }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_snapshot_retention() {
+        use mors_common::{
+            file_id::{FileId, SSTableId},
+            ts::KeyTs,
+            ts::TxnTs,
+        };
+        use mors_traits::vlog::ValueSeparation;
+        use std::{ops::Deref, time::Duration};
+
+        for window in [None, Some(Duration::from_secs(3600))] {
+            let dir = tempfile::tempdir().unwrap();
+            let mut separation = ValueSeparation::default();
+            separation.set_disabled(true);
+            let mut builder = MorsBuilder::default();
+            builder
+                .set_dir(dir.path().to_path_buf())
+                .set_value_separation(separation)
+                .set_retention_window(window);
+            let mors = builder.build().await.unwrap();
+            let core = mors.inner().clone();
+            let get = |ts: TxnTs| {
+                let core = core.clone();
+                async move {
+                    let key = KeyTs::new("k".into(), ts);
+                    let (version, value) = core
+                        .get(DEFAULT_COLUMN_FAMILY, &key)
+                        .await
+                        .unwrap()
+                        .unwrap();
+                    (version, value.unwrap().value().clone())
+                }
+            };
+
+            // the snapshot reads the first version, compacting the five
+            // level 0 tables drops the versions no one reads.
+            let mut versions = Vec::new();
+            let mut snapshot = None;
+            for i in 0..5u8 {
+                let mut txn = mors.begin_write().await.unwrap();
+                txn.set("k".into(), vec![i].into()).unwrap();
+                txn.commit().await.unwrap();
+                versions.push(get(u64::MAX.into()).await.0);
+                if i == 0 {
+                    snapshot = Some(mors.begin_write().await.unwrap());
+                }
+                core.flush_overlapping(
+                    DEFAULT_COLUMN_FAMILY,
+                    &[("k".into(), "k".into())],
+                )
+                .await
+                .unwrap();
+            }
+            let tables = || SSTableId::parse_set_from_dir(dir.path()).len();
+            for _ in 0..300 {
+                if tables() == 1 {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            assert_eq!(tables(), 1);
+
+            let snapshot = snapshot.unwrap();
+            let entry = snapshot
+                .deref()
+                .get(DEFAULT_COLUMN_FAMILY, "k".into())
+                .await
+                .unwrap();
+            assert_eq!(entry.value().as_ref(), [0]);
+            assert_eq!(get(versions[4]).await, (versions[4], vec![4].into()));
+            let expected = match window {
+                None => (versions[0], vec![0].into()),
+                Some(_) => (versions[2], vec![2].into()),
+            };
+            assert_eq!(get(versions[2]).await, expected);
+        }
+    }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_range_delete_snapshot() {
+        use mors_common::file_id::{FileId, SSTableId};
+        use mors_traits::vlog::ValueSeparation;
+        use std::{ops::Deref, time::Duration};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut separation = ValueSeparation::default();
+        separation.set_disabled(true);
+        let mut builder = MorsBuilder::default();
+        builder
+            .set_dir(dir.path().to_path_buf())
+            .set_value_separation(separation);
+        let mors = builder.build().await.unwrap();
+        let core = mors.inner().clone();
+
+        // the snapshot is taken before the range delete, compacting the five
+        // level 0 tables keeps the tombstone and the keys it covers.
+        let tables = || SSTableId::parse_set_from_dir(dir.path());
+        let mut snapshot = None;
+        let mut flushed = Default::default();
+        for i in 0..5u8 {
+            // the fifth table starts the compaction, which may end before
+            // the flush returns.
+            if i == 4 {
+                flushed = tables();
+            }
+            let mut txn = mors.begin_write().await.unwrap();
+            match i {
+                0 => {
+                    for key in ["a", "b", "c"] {
+                        txn.set(key.into(), key.into()).unwrap();
+                    }
+                }
+                1 => txn.delete_range("a".into(), "c".into()).unwrap(),
+                _ => {}
+            }
+            txn.set("d".into(), vec![i].into()).unwrap();
+            txn.commit().await.unwrap();
+            if i == 0 {
+                snapshot = Some(mors.begin_write().await.unwrap());
+            }
+            core.flush_overlapping(
+                DEFAULT_COLUMN_FAMILY,
+                &[("a".into(), "d".into())],
+            )
+            .await
+            .unwrap();
+        }
+        // the output may be split, so wait for the inputs to be removed.
+        for _ in 0..300 {
+            if tables().is_disjoint(&flushed) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(tables().is_disjoint(&flushed));
+
+        let snapshot = snapshot.unwrap();
+        for key in ["a", "b", "c"] {
+            let entry = snapshot
+                .deref()
+                .get(DEFAULT_COLUMN_FAMILY, key.into())
+                .await
+                .unwrap();
+            assert_eq!(entry.value().as_ref(), key.as_bytes());
+        }
+        for key in ["a", "b"] {
+            assert!(mors.get_at(key.into(), u64::MAX.into()).await.is_err());
+        }
+        let entry = mors.get_at("c".into(), u64::MAX.into()).await.unwrap();
+        assert_eq!(entry.value().as_ref(), b"c");
+    }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_iter() {
+        use crate::TxnIterator;
+        use mors_traits::vlog::ValueSeparation;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut separation = ValueSeparation::default();
+        separation.set_disabled(true);
+        let mut builder = MorsBuilder::default();
+        builder
+            .set_dir(dir.path().to_path_buf())
+            .set_value_separation(separation);
+        let mors = builder.build().await.unwrap();
+        let core = mors.inner().clone();
+
+        async fn collect(iter: &mut TxnIterator<'_>) -> Vec<(String, String)> {
+            let mut out = Vec::new();
+            while let Some(entry) = iter.next().await.unwrap() {
+                let key = String::from_utf8(entry.key().to_vec()).unwrap();
+                let value = String::from_utf8(entry.value().to_vec()).unwrap();
+                out.push((key, value));
+            }
+            out
+        }
+        let pairs = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        // a to e are in a table, f to j in the memtable.
+        let keys = ["a", "b", "c", "d", "e", "f", "g", "h", "i", "j"];
+        for half in keys.chunks(5) {
+            let mut txn = mors.begin_write().await.unwrap();
+            for key in half {
+                txn.set(key.to_string().into(), key.to_string().into())
+                    .unwrap();
+            }
+            txn.commit().await.unwrap();
+            if half[0] == "a" {
+                core.flush_overlapping(
+                    DEFAULT_COLUMN_FAMILY,
+                    &[("a".into(), "f".into())],
+                )
+                .await
+                .unwrap();
+            }
+        }
+        let snapshot = mors.begin_write().await.unwrap();
+
+        // the tombstone spans the table and the memtable, c is written
+        // again after it by the same transaction.
+        let mut txn = mors.begin_write().await.unwrap();
+        txn.delete_range("b".into(), "g".into()).unwrap();
+        txn.set("c".into(), "new".into()).unwrap();
+        txn.commit().await.unwrap();
+
+        let mut txn = mors.begin_write().await.unwrap();
+        txn.set("h".into(), "pending".into()).unwrap();
+        txn.delete("i".into()).unwrap();
+        txn.delete_range("j".into(), "k".into()).unwrap();
+        let mut iter = txn.iter().unwrap();
+        assert_eq!(
+            collect(&mut iter).await,
+            pairs(&[("a", "a"), ("c", "new"), ("g", "g"), ("h", "pending")])
+        );
+        iter.seek("d".into());
+        assert_eq!(
+            collect(&mut iter).await,
+            pairs(&[("g", "g"), ("h", "pending")])
+        );
+        drop(iter);
+
+        let mut iter = snapshot.iter().unwrap();
+        let all = keys.map(|k| (k, k));
+        assert_eq!(collect(&mut iter).await, pairs(&all));
+        drop(iter);
+        drop(txn);
+
+        // more keys than one chunk copied out of the memtable at a time.
+        let mut txn = mors.begin_write().await.unwrap();
+        for i in 0..1000 {
+            txn.set(format!("m{i:04}").into(), "m".into()).unwrap();
+        }
+        txn.commit().await.unwrap();
+        let txn = mors.begin_write().await.unwrap();
+        let mut iter = txn.iter().unwrap();
+        iter.seek("m".into());
+        let keys = collect(&mut iter).await;
+        assert_eq!(keys.len(), 1000);
+        for (i, (key, _)) in keys.iter().enumerate() {
+            assert_eq!(*key, format!("m{i:04}"));
+        }
+    }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_versions() {
+        use crate::KvEntry;
+        use mors_traits::vlog::ValueSeparation;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut separation = ValueSeparation::default();
+        separation.set_disabled(true);
+        let mut builder = MorsBuilder::default();
+        builder
+            .set_dir(dir.path().to_path_buf())
+            .set_value_separation(separation);
+        let mors = builder.build().await.unwrap();
+        let core = mors.inner().clone();
+
+        // the first two versions are flushed into level 0, the others stay
+        // in the memtable.
+        for i in 0..4u8 {
+            let mut txn = mors.begin_write().await.unwrap();
+            if i == 3 {
+                txn.delete("k".into()).unwrap();
+            } else {
+                let mut entry = KvEntry::new("k".into(), vec![i].into());
+                entry.set_meta(i);
+                txn.set_entry(entry).unwrap();
+            }
+            txn.commit().await.unwrap();
+            if i < 2 {
+                core.flush_overlapping(
+                    DEFAULT_COLUMN_FAMILY,
+                    &[("k".into(), "k".into())],
+                )
+                .await
+                .unwrap();
+            }
+        }
+
+        let mut versions = mors.versions("k".into()).await.unwrap();
+        let mut history = Vec::new();
+        while let Some(version) = versions.next().await.unwrap() {
+            history.push(version);
+        }
+        drop(versions);
+        assert_eq!(history.len(), 4);
+        assert!(history[0].is_deleted());
+        for (i, version) in history[1..].iter().rev().enumerate() {
+            assert!(!version.is_deleted());
+            assert_eq!(version.value().as_ref(), [i as u8]);
+            assert_eq!(version.user_meta(), i as u8);
+            assert!(version.version() < history[0].version());
+        }
+
+        let latest = history[0].version();
+        assert!(mors.get_at("k".into(), latest).await.is_err());
+        for version in &history[1..] {
+            let entry = mors.get_at("k".into(), version.version()).await;
+            let entry = entry.unwrap();
+            assert_eq!(entry.version(), version.version());
+            assert_eq!(entry.value(), version.value());
+        }
+
+        // the iterator holds a snapshot, later writes are not yielded.
+        let mut versions = mors.versions("k".into()).await.unwrap();
+        let txn_manager = core.txn_manager();
+        assert_eq!(txn_manager.oldest_snapshot(), Some(latest));
+        let mut txn = mors.begin_write().await.unwrap();
+        txn.set("k".into(), vec![4].into()).unwrap();
+        txn.commit().await.unwrap();
+        assert_eq!(versions.next().await.unwrap().unwrap().version(), latest);
+        drop(versions);
+        assert_eq!(txn_manager.oldest_snapshot(), None);
+
+        // a range tombstone is a version of the keys it contains.
+        let mut txn = mors.begin_write().await.unwrap();
+        txn.delete_range("j".into(), "l".into()).unwrap();
+        txn.commit().await.unwrap();
+        let mut versions = mors.versions("k".into()).await.unwrap();
+        let tombstone = versions.next().await.unwrap().unwrap();
+        assert!(tombstone.is_deleted());
+        let set = versions.next().await.unwrap().unwrap();
+        assert_eq!(set.value().as_ref(), [4]);
+        assert!(set.version() < tombstone.version());
+        let mut len = 2;
+        while versions.next().await.unwrap().is_some() {
+            len += 1;
+        }
+        assert_eq!(len, history.len() + 2);
+    }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_write_batch() {
+        use bytes::Bytes;
+        use mors_traits::vlog::ValueSeparation;
+        use std::time::Duration;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut separation = ValueSeparation::default();
+        separation.set_disabled(true);
+        let mut builder = MorsBuilder::default();
+        builder
+            .set_dir(dir.path().to_path_buf())
+            .set_memtable_size(1 << 20)
+            .set_value_separation(separation);
+        let mors = builder.build().await.unwrap();
+
+        // far more than one write request holds, across memtables.
+        let key = |i: usize| Bytes::from(format!("key{i:05}"));
+        let mut txn = mors.begin_write().await.unwrap();
+        txn.set(key(20000), "deleted".into()).unwrap();
+        txn.commit().await.unwrap();
+        let mut batch = mors.write_batch();
+        assert!(batch.set(Bytes::new(), "v".into()).await.is_err());
+        for i in 0..20000 {
+            batch.set(key(i), vec![i as u8; 100].into()).await.unwrap();
+        }
+        batch.delete(key(20000)).await.unwrap();
+
+        // a transaction commits while the batch is open.
+        let commit = async {
+            let mut txn = mors.begin_write().await?;
+            txn.set("other".into(), "txn".into())?;
+            txn.commit().await
+        };
+        tokio::time::timeout(Duration::from_secs(5), commit)
+            .await
+            .unwrap()
+            .unwrap();
+        batch.commit().await.unwrap();
+
+        assert!(mors.get_at(key(20000), u64::MAX.into()).await.is_err());
+        let entry = mors.get_at("other".into(), u64::MAX.into()).await;
+        assert_eq!(entry.unwrap().value().as_ref(), b"txn");
+        // the write requests commit in the order they were sent.
+        let mut previous = None;
+        for i in 0..20000 {
+            let entry = mors.get_at(key(i), u64::MAX.into()).await.unwrap();
+            assert!(previous.is_none_or(|p| p <= entry.version()));
+            previous = Some(entry.version());
+            assert_eq!(entry.value().as_ref(), vec![i as u8; 100]);
+        }
+    }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_commit_async() {
+        use mors_traits::vlog::ValueSeparation;
+        use std::ops::Deref;
+        use std::time::Duration;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut separation = ValueSeparation::default();
+        separation.set_disabled(true);
+        let mut builder = MorsBuilder::default();
+        builder
+            .set_dir(dir.path().to_path_buf())
+            .set_value_separation(separation);
+        let mors = builder.build().await.unwrap();
+
+        // every transaction begins before the commit of the previous one
+        // resolves, the commits still become visible in order.
+        let mut commits = Vec::new();
+        for i in 0..100u8 {
+            let mut txn = mors.begin_write().await.unwrap();
+            txn.set("k".into(), vec![i].into()).unwrap();
+            txn.set(vec![b'k', i].into(), vec![i].into()).unwrap();
+            commits.push(txn.commit_async().await.unwrap());
+        }
+        let mut last = commits.pop().unwrap();
+        for commit in commits {
+            commit.await.unwrap();
+        }
+        loop {
+            if let Some(result) = last.try_result() {
+                result.unwrap();
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+
+        let txn = mors.begin_write().await.unwrap();
+        for i in 0..100u8 {
+            let key = vec![b'k', i].into();
+            let entry = txn.deref().get(DEFAULT_COLUMN_FAMILY, key).await;
+            assert_eq!(entry.unwrap().value().as_ref(), [i]);
+        }
+        let mut versions = mors.versions("k".into()).await.unwrap();
+        let mut previous = None;
+        for i in (0..100u8).rev() {
+            let version = versions.next().await.unwrap().unwrap();
+            assert_eq!(version.value().as_ref(), [i]);
+            assert!(previous.is_none_or(|p| version.version() < p));
+            previous = Some(version.version());
+        }
+        assert!(versions.next().await.unwrap().is_none());
+        drop(versions);
+
+        // an open batch does not hold back the commit after it.
+        let mut batch = mors.write_batch();
+        batch.set("b".into(), "batch".into()).await.unwrap();
+        let mut txn = mors.begin_write().await.unwrap();
+        txn.set("k".into(), "async".into()).unwrap();
+        let commit = txn.commit_async().await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), commit)
+            .await
+            .unwrap()
+            .unwrap();
+        let txn = mors.begin_write().await.unwrap();
+        let entry = txn.deref().get(DEFAULT_COLUMN_FAMILY, "k".into()).await;
+        assert_eq!(entry.unwrap().value().as_ref(), b"async");
+        drop(txn);
+        batch.commit().await.unwrap();
+        let entry = mors.get_at("b".into(), u64::MAX.into()).await;
+        assert_eq!(entry.unwrap().value().as_ref(), b"batch");
+    }
 }
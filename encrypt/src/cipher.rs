@@ -1,15 +1,13 @@
 use std::fmt::Debug;
+use std::sync::Arc;
 
 use aead::consts::U12;
 use aead::generic_array::GenericArray;
 use aead::{Aead, AeadCore, KeyInit, OsRng};
-#[cfg(feature = "aes-gcm")]
 use aes_gcm::{Aes128Gcm, Aes256Gcm};
-#[cfg(feature = "aes-gcm-siv")]
-use aes_gcm_siv::Aes128GcmSiv as Aes128Gcm;
-#[cfg(feature = "aes-gcm-siv")]
-use aes_gcm_siv::Aes256GcmSiv as Aes256Gcm;
-use mors_traits::kms::{CipherKeyId, EncryptError, KmsCipher};
+use aes_gcm_siv::{Aes128GcmSiv, Aes256GcmSiv};
+use chacha20poly1305::ChaCha20Poly1305;
+use mors_traits::kms::{CipherKeyId, EncryptError, EncryptionAlgo, KmsCipher};
 
 use crate::error::MorsEncryptError;
 use crate::NONCE_SIZE;
@@ -17,17 +15,49 @@ use crate::NONCE_SIZE;
 pub type Nonce = GenericArray<u8, U12>;
 type Result<T> = std::result::Result<T, MorsEncryptError>;
 
-pub enum AesCipher {
-    Aes128(Box<Aes128Gcm>, CipherKeyId),
-    Aes256(Box<Aes256Gcm>, CipherKeyId),
+#[derive(Clone)]
+pub struct AesCipher {
+    aead: AeadCipher,
+    id: CipherKeyId,
+    // shared by the clones and counted by the key registry, which does not
+    // retire the data key while a cipher of it is alive.
+    pin: Option<Arc<()>>,
+}
+#[derive(Clone)]
+enum AeadCipher {
+    Aes128(Box<Aes128Gcm>),
+    Aes256(Box<Aes256Gcm>),
+    Aes128Siv(Box<Aes128GcmSiv>),
+    Aes256Siv(Box<Aes256GcmSiv>),
+    ChaCha20(Box<ChaCha20Poly1305>),
+}
+// runs `$body` with the inner aead cipher of any variant bound to `$c`.
+macro_rules! with_cipher {
+    ($self:expr, $c:ident => $body:expr) => {
+        match &$self.aead {
+            AeadCipher::Aes128($c) => $body,
+            AeadCipher::Aes256($c) => $body,
+            AeadCipher::Aes128Siv($c) => $body,
+            AeadCipher::Aes256Siv($c) => $body,
+            AeadCipher::ChaCha20($c) => $body,
+        }
+    };
 }
 impl KmsCipher for AesCipher {
     type ErrorType = MorsEncryptError;
 
     fn cipher_key_id(&self) -> CipherKeyId {
-        match self {
-            AesCipher::Aes128(_, id) => *id,
-            AesCipher::Aes256(_, id) => *id,
+        self.id
+    }
+    fn encryption_algo(&self) -> EncryptionAlgo {
+        match self.aead {
+            AeadCipher::Aes128(_) | AeadCipher::Aes256(_) => {
+                EncryptionAlgo::AesGcm
+            }
+            AeadCipher::Aes128Siv(_) | AeadCipher::Aes256Siv(_) => {
+                EncryptionAlgo::AesGcmSiv
+            }
+            AeadCipher::ChaCha20(_) => EncryptionAlgo::ChaCha20Poly1305,
         }
     }
 
@@ -35,6 +65,8 @@ impl KmsCipher for AesCipher {
         AesCipher::generate_nonce().to_vec()
     }
     const NONCE_SIZE: usize = 12;
+    // all supported ciphers have a 16 byte authentication tag.
+    const TAG_SIZE: usize = 16;
     fn decrypt_with_slice(
         &self,
         nonce: &[u8],
@@ -70,97 +102,98 @@ impl KmsCipher for AesCipher {
         Ok(ciphertext)
     }
 }
-impl Clone for AesCipher {
-    fn clone(&self) -> Self {
-        match self {
-            AesCipher::Aes128(cipher, id) => {
-                AesCipher::Aes128(cipher.clone(), *id)
-            }
-            AesCipher::Aes256(cipher, id) => {
-                AesCipher::Aes256(cipher.clone(), *id)
-            }
-        }
-    }
-    
-}
 impl Debug for AesCipher {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        #[cfg(feature = "aes-gcm")]
-        match self {
-            Self::Aes128(_, id) => f.debug_tuple("Aes128:").field(id).finish(),
-            Self::Aes256(_, id) => f.debug_tuple("Aes256:").field(id).finish(),
-        }
-        #[cfg(feature = "aes-gcm-siv")]
-        match self {
-            Self::Aes128(_, id) => {
-                f.debug_tuple("Aes128Siv:").field(id).finish()
-            }
-            Self::Aes256(_, id) => {
-                f.debug_tuple("Aes256Siv:").field(id).finish()
-            }
-        }
+        let name = match self.aead {
+            AeadCipher::Aes128(_) => "Aes128:",
+            AeadCipher::Aes256(_) => "Aes256:",
+            AeadCipher::Aes128Siv(_) => "Aes128Siv:",
+            AeadCipher::Aes256Siv(_) => "Aes256Siv:",
+            AeadCipher::ChaCha20(_) => "ChaCha20Poly1305:",
+        };
+        f.debug_tuple(name).field(&self.id).finish()
     }
 }
 
 impl AesCipher {
+    /// an AES-GCM cipher.
     #[inline]
     pub fn new(key: &[u8], id: CipherKeyId) -> Result<Self> {
-        let cipher = match key.len() {
-            16 => Self::Aes128(
-                Box::new(Aes128Gcm::new_from_slice(key).unwrap()),
-                id,
-            ),
-            32 => Self::Aes256(
-                Box::new(Aes256Gcm::new_from_slice(key).unwrap()),
-                id,
+        Self::new_with_algo(EncryptionAlgo::AesGcm, key, id)
+    }
+    /// a cipher of `algo`, ChaCha20-Poly1305 only takes 32 byte keys.
+    pub fn new_with_algo(
+        algo: EncryptionAlgo,
+        key: &[u8],
+        id: CipherKeyId,
+    ) -> Result<Self> {
+        let aead = match (algo, key.len()) {
+            (EncryptionAlgo::AesGcm, 16) => AeadCipher::Aes128(Box::new(
+                Aes128Gcm::new_from_slice(key).unwrap(),
+            )),
+            (EncryptionAlgo::AesGcm, 32) => AeadCipher::Aes256(Box::new(
+                Aes256Gcm::new_from_slice(key).unwrap(),
+            )),
+            (EncryptionAlgo::AesGcmSiv, 16) => AeadCipher::Aes128Siv(Box::new(
+                Aes128GcmSiv::new_from_slice(key).unwrap(),
+            )),
+            (EncryptionAlgo::AesGcmSiv, 32) => AeadCipher::Aes256Siv(Box::new(
+                Aes256GcmSiv::new_from_slice(key).unwrap(),
+            )),
+            (EncryptionAlgo::ChaCha20Poly1305, 32) => AeadCipher::ChaCha20(
+                Box::new(ChaCha20Poly1305::new_from_slice(key).unwrap()),
             ),
             _ => return Err(MorsEncryptError::InvalidEncryptionKey),
         };
-        Ok(cipher)
+        Ok(Self {
+            aead,
+            id,
+            pin: None,
+        })
+    }
+    /// keeps `pin` alive as long as this cipher or a clone of it.
+    pub(crate) fn with_pin(mut self, pin: Arc<()>) -> Self {
+        self.pin = Some(pin);
+        self
+    }
+    /// the key length in bytes, 16 or 32.
+    pub(crate) fn key_len(&self) -> usize {
+        match self.aead {
+            AeadCipher::Aes128(_) | AeadCipher::Aes128Siv(_) => 16,
+            _ => 32,
+        }
     }
     #[inline]
     pub fn encrypt(&self, nonce: &Nonce, plaintext: &[u8]) -> Result<Vec<u8>> {
-        match self {
-            AesCipher::Aes128(ref cipher, _) => cipher
-                .encrypt(nonce, plaintext)
-                .map_err(|_| MorsEncryptError::EncryptError {
-                    nonce: format!("{:?}", nonce),
-                    plaintext: format!("{:?}", plaintext),
-                }),
-            AesCipher::Aes256(ref cipher, _) => cipher
-                .encrypt(nonce, plaintext)
-                .map_err(|_| MorsEncryptError::EncryptError {
-                    nonce: format!("{:?}", nonce),
-                    plaintext: format!("{:?}", plaintext),
-                }),
-        }
+        with_cipher!(self, cipher => cipher.encrypt(nonce, plaintext)).map_err(
+            |_| MorsEncryptError::EncryptError {
+                nonce: format!("{:?}", nonce),
+                plaintext: format!("{:?}", plaintext),
+            },
+        )
     }
     #[inline]
     pub fn decrypt(&self, nonce: &Nonce, ciphertext: &[u8]) -> Result<Vec<u8>> {
-        match self {
-            AesCipher::Aes128(ref cipher, _) => cipher
-                .decrypt(nonce, ciphertext)
-                .map_err(|_| MorsEncryptError::DecryptError {
-                    nonce: format!("{:?}", nonce),
-                    ciphertext: format!("{:?}", ciphertext),
-                }),
-            AesCipher::Aes256(ref cipher, _) => cipher
-                .decrypt(nonce, ciphertext)
-                .map_err(|_| MorsEncryptError::DecryptError {
-                    nonce: format!("{:?}", nonce),
-                    ciphertext: format!("{:?}", ciphertext),
-                }),
-        }
+        with_cipher!(self, cipher => cipher.decrypt(nonce, ciphertext)).map_err(
+            |_| MorsEncryptError::DecryptError {
+                nonce: format!("{:?}", nonce),
+                ciphertext: format!("{:?}", ciphertext),
+            },
+        )
     }
     #[inline]
     pub fn generate_key(&self) -> Vec<u8> {
-        match self {
-            AesCipher::Aes128(_, _) => {
-                Aes128Gcm::generate_key(&mut OsRng).to_vec()
-            }
-            AesCipher::Aes256(_, _) => {
-                Aes256Gcm::generate_key(&mut OsRng).to_vec()
-            }
+        match self.key_len() {
+            16 => Aes128Gcm::generate_key(&mut OsRng).to_vec(),
+            _ => Aes256Gcm::generate_key(&mut OsRng).to_vec(),
+        }
+    }
+    /// a random key of `len` bytes, either 16 or 32.
+    pub(crate) fn generate_key_of_len(len: usize) -> Result<Vec<u8>> {
+        match len {
+            16 => Ok(Aes128Gcm::generate_key(&mut OsRng).to_vec()),
+            32 => Ok(Aes256Gcm::generate_key(&mut OsRng).to_vec()),
+            _ => Err(MorsEncryptError::InvalidEncryptionKey),
         }
     }
     #[inline]
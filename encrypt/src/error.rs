@@ -13,6 +13,10 @@ pub enum MorsKmsError {
     EncryptionKeyMismatch,
     #[error("Invalid data key id: {0}")]
     InvalidDataKeyID(CipherKeyId),
+    #[error("{0} data keys of the key registry can not be read")]
+    CorruptedDataKeys(usize),
+    #[error("Key provider error: {0}")]
+    KeyProviderError(String),
     #[error(transparent)]
     MorsEncryptError(#[from] MorsEncryptError),
 }
@@ -25,6 +29,10 @@ pub enum MorsEncryptError {
     EncryptError { nonce: String, plaintext: String },
     #[error("Invalid nonce: {nonce}, ciphertext: {ciphertext}")]
     DecryptError { nonce: String, ciphertext: String },
+    #[error("Nonce should be 12 bytes, got {0}")]
+    InvalidNonce(usize),
+    #[error("Unknown encryption algorithm: {0}")]
+    UnknownEncryptionAlgo(u32),
 }
 
 impl From<MorsEncryptError> for EncryptError {
@@ -1,6 +1,7 @@
 pub mod cipher;
 pub mod error;
 mod pb;
+pub mod provider;
 pub mod registry;
 // mod iter;
 
@@ -12,4 +12,7 @@ pub struct DataKey {
     pub iv: ::prost::alloc::vec::Vec<u8>,
     #[prost(uint64, tag = "4")]
     pub created_at: u64,
+    /// EncryptionAlgo the key is used with, 0 is aes-gcm
+    #[prost(uint32, tag = "5")]
+    pub algo: u32,
 }
@@ -0,0 +1,279 @@
+use std::fmt::Debug;
+use std::fs::read;
+use std::io::{Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+
+use log::error;
+use prost::bytes::{Buf, BufMut};
+
+use crate::cipher::{AesCipher, Nonce};
+use crate::error::{MorsEncryptError, MorsKmsError};
+use crate::NONCE_SIZE;
+
+type Result<T> = std::result::Result<T, MorsKmsError>;
+
+/// Master key operations of envelope encryption.
+///
+/// Data keys are stored wrapped by a master key that never has to leave
+/// the provider, so it can live in a key server instead of the process.
+pub trait KeyProvider: Debug + Send + Sync + 'static {
+    /// wraps a data key, `iv` is a random nonce stored next to the result.
+    fn wrap(&self, iv: &[u8], plaintext: &[u8]) -> Result<Vec<u8>>;
+    /// unwraps a data key, fails with
+    /// [`MorsKmsError::EncryptionKeyMismatch`] if it was wrapped by another key.
+    fn unwrap(&self, iv: &[u8], wrapped: &[u8]) -> Result<Vec<u8>>;
+    /// length of the generated data keys, either 16 or 32 bytes.
+    fn data_key_len(&self) -> Result<usize> {
+        Ok(32)
+    }
+}
+
+/// Master key kept in process memory.
+#[derive(Debug, Clone)]
+pub struct LocalKeyProvider(AesCipher);
+impl LocalKeyProvider {
+    pub fn new(master_key: &[u8]) -> Result<Self> {
+        Ok(Self(AesCipher::new(master_key, 0.into())?))
+    }
+}
+fn nonce(iv: &[u8]) -> Result<&Nonce> {
+    if iv.len() != NONCE_SIZE {
+        return Err(MorsEncryptError::InvalidNonce(iv.len()).into());
+    }
+    Ok(Nonce::from_slice(iv))
+}
+impl KeyProvider for LocalKeyProvider {
+    fn wrap(&self, iv: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        Ok(self.0.encrypt(nonce(iv)?, plaintext)?)
+    }
+    fn unwrap(&self, iv: &[u8], wrapped: &[u8]) -> Result<Vec<u8>> {
+        self.0
+            .decrypt(nonce(iv)?, wrapped)
+            .map_err(|_| MorsKmsError::EncryptionKeyMismatch)
+    }
+    fn data_key_len(&self) -> Result<usize> {
+        Ok(self.0.key_len())
+    }
+}
+
+/// Master key read from a file, like a mounted secret, on every operation
+/// so it is never kept in memory.
+#[derive(Debug, Clone)]
+pub struct FileKeyProvider {
+    path: PathBuf,
+}
+impl FileKeyProvider {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+    fn local(&self) -> Result<LocalKeyProvider> {
+        LocalKeyProvider::new(&read(&self.path)?)
+    }
+}
+impl KeyProvider for FileKeyProvider {
+    fn wrap(&self, iv: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        self.local()?.wrap(iv, plaintext)
+    }
+    fn unwrap(&self, iv: &[u8], wrapped: &[u8]) -> Result<Vec<u8>> {
+        self.local()?.unwrap(iv, wrapped)
+    }
+    fn data_key_len(&self) -> Result<usize> {
+        self.local()?.data_key_len()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyOp {
+    Wrap = 0,
+    Unwrap = 1,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyStatus {
+    Ok = 0,
+    Mismatch = 1,
+    Error = 2,
+}
+// Request and response frames of the key server protocol.
+// +----+--------+----+-------------+---------+
+// | Op | Iv Len | Iv | Payload Len | Payload |
+// +----+--------+----+-------------+---------+
+// +--------+-------------+---------+
+// | Status | Payload Len | Payload |
+// +--------+-------------+---------+
+fn write_frame(
+    stream: &mut UnixStream,
+    head: u8,
+    iv: Option<&[u8]>,
+    payload: &[u8],
+) -> std::io::Result<()> {
+    let mut buf = Vec::with_capacity(1 + 2 + 12 + 4 + payload.len());
+    buf.put_u8(head);
+    if let Some(iv) = iv {
+        buf.put_u16(iv.len() as u16);
+        buf.put_slice(iv);
+    }
+    buf.put_u32(payload.len() as u32);
+    buf.put_slice(payload);
+    stream.write_all(&buf)
+}
+fn read_vec(stream: &mut UnixStream, len: usize) -> std::io::Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    stream.read_exact(&mut buf)?;
+    Ok(buf)
+}
+fn read_u16(stream: &mut UnixStream) -> std::io::Result<usize> {
+    Ok(read_vec(stream, 2)?.as_slice().get_u16() as usize)
+}
+fn read_u32(stream: &mut UnixStream) -> std::io::Result<usize> {
+    Ok(read_vec(stream, 4)?.as_slice().get_u32() as usize)
+}
+
+/// Master key operations sent to a key server listening on a Unix socket,
+/// a local stand-in for a cloud KMS. See [`serve_key_provider`].
+#[derive(Debug, Clone)]
+pub struct UnixSocketKeyProvider {
+    path: PathBuf,
+    data_key_len: usize,
+}
+impl UnixSocketKeyProvider {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            data_key_len: 32,
+        }
+    }
+    /// length of the generated data keys, default 32.
+    pub fn with_data_key_len(mut self, data_key_len: usize) -> Self {
+        self.data_key_len = data_key_len;
+        self
+    }
+    fn request(&self, op: KeyOp, iv: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+        let mut stream = UnixStream::connect(&self.path)?;
+        write_frame(&mut stream, op as u8, Some(iv), payload)?;
+        let status = read_vec(&mut stream, 1)?[0];
+        let len = read_u32(&mut stream)?;
+        let payload = read_vec(&mut stream, len)?;
+        match status {
+            s if s == KeyStatus::Ok as u8 => Ok(payload),
+            s if s == KeyStatus::Mismatch as u8 => {
+                Err(MorsKmsError::EncryptionKeyMismatch)
+            }
+            _ => Err(MorsKmsError::KeyProviderError(
+                String::from_utf8_lossy(&payload).to_string(),
+            )),
+        }
+    }
+}
+impl KeyProvider for UnixSocketKeyProvider {
+    fn wrap(&self, iv: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        self.request(KeyOp::Wrap, iv, plaintext)
+    }
+    fn unwrap(&self, iv: &[u8], wrapped: &[u8]) -> Result<Vec<u8>> {
+        self.request(KeyOp::Unwrap, iv, wrapped)
+    }
+    fn data_key_len(&self) -> Result<usize> {
+        Ok(self.data_key_len)
+    }
+}
+
+/// Answers the requests of [`UnixSocketKeyProvider`] with `provider`,
+/// one connection at a time, until accepting a connection fails.
+pub fn serve_key_provider<P: KeyProvider>(
+    listener: UnixListener,
+    provider: P,
+) -> std::io::Result<()> {
+    loop {
+        let (mut stream, _) = listener.accept()?;
+        if let Err(e) = serve_request(&mut stream, &provider) {
+            error!("failed to serve key provider request: {e}");
+        }
+    }
+}
+fn serve_request<P: KeyProvider>(
+    stream: &mut UnixStream,
+    provider: &P,
+) -> std::io::Result<()> {
+    let op = read_vec(stream, 1)?[0];
+    let iv_len = read_u16(stream)?;
+    let iv = read_vec(stream, iv_len)?;
+    let len = read_u32(stream)?;
+    let payload = read_vec(stream, len)?;
+    let result = match op {
+        o if o == KeyOp::Wrap as u8 => provider.wrap(&iv, &payload),
+        o if o == KeyOp::Unwrap as u8 => provider.unwrap(&iv, &payload),
+        o => Err(MorsKmsError::KeyProviderError(format!(
+            "unknown operation {o}"
+        ))),
+    };
+    match result {
+        Ok(r) => write_frame(stream, KeyStatus::Ok as u8, None, &r),
+        Err(MorsKmsError::EncryptionKeyMismatch) => {
+            write_frame(stream, KeyStatus::Mismatch as u8, None, &[])
+        }
+        Err(e) => write_frame(
+            stream,
+            KeyStatus::Error as u8,
+            None,
+            e.to_string().as_bytes(),
+        ),
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixListener;
+    use std::sync::Arc;
+
+    use mors_traits::default::WithDir;
+    use mors_traits::kms::{Kms, KmsBuilder, KmsCipher};
+
+    use super::{
+        serve_key_provider, FileKeyProvider, KeyProvider, UnixSocketKeyProvider,
+    };
+    use crate::registry::MorsKmsBuilder;
+
+    #[test]
+    fn test_unix_socket_provider() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("master.key");
+        std::fs::write(&key_path, [7u8; 32]).unwrap();
+        let socket = dir.path().join("kms.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        std::thread::spawn(move || {
+            serve_key_provider(listener, FileKeyProvider::new(key_path))
+        });
+
+        let db = dir.path().join("db");
+        std::fs::create_dir(&db).unwrap();
+        let open = || {
+            let provider = UnixSocketKeyProvider::new(socket.clone());
+            let mut builder =
+                MorsKmsBuilder::default().with_key_provider(Arc::new(provider));
+            builder.set_dir(db.clone());
+            builder.build()
+        };
+        let kms = open().unwrap();
+        let cipher = kms.latest_cipher().unwrap().unwrap();
+        let e_text = KmsCipher::encrypt(&cipher, b"text").unwrap();
+        drop(kms);
+
+        let kms = open().unwrap();
+        let cipher = kms.get_cipher(cipher.cipher_key_id()).unwrap().unwrap();
+        assert_eq!(KmsCipher::decrypt(&cipher, &e_text).unwrap(), b"text");
+
+        std::fs::write(dir.path().join("master.key"), [8u8; 32]).unwrap();
+        assert!(open().is_err());
+    }
+
+    #[test]
+    fn test_file_provider_data_key_len() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("master.key");
+        let provider = FileKeyProvider::new(key_path.clone());
+        assert!(provider.data_key_len().is_err());
+        std::fs::write(&key_path, [7u8; 5]).unwrap();
+        assert!(provider.data_key_len().is_err());
+        std::fs::write(&key_path, [7u8; 16]).unwrap();
+        assert_eq!(provider.data_key_len().unwrap(), 16);
+    }
+}
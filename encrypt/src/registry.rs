@@ -12,23 +12,27 @@ use std::{
 use log::error;
 use mors_traits::{
     default::{WithDir, WithReadOnly, DEFAULT_DIR},
-    kms::{CipherKeyId, Kms, KmsBuilder, KmsCipher, KmsError},
+    kms::{CipherKeyId, EncryptionAlgo, Kms, KmsBuilder, KmsError},
 };
 use prost::{
     bytes::{Buf, BufMut},
     Message,
 };
 
+use mors_common::lock::DBLockGuardBuilder;
 use mors_common::ts::PhyTs;
 
 use crate::cipher::{AesCipher, Nonce};
 use crate::error::{MorsEncryptError, MorsKmsError};
 use crate::pb::encryption::DataKey;
+use crate::provider::{KeyProvider, LocalKeyProvider};
 use crate::{
     KEY_REGISTRY_FILE_NAME, KEY_REGISTRY_REWRITE_FILE_NAME, SANITY_TEXT,
 };
 
 type Result<T> = std::result::Result<T, MorsKmsError>;
+/// provider wrapping the data keys, none if encryption is disabled.
+type MasterKey = Option<Arc<dyn KeyProvider>>;
 #[derive(Debug, Default, Clone)]
 pub struct MorsKms(Arc<RwLock<KmsInner>>);
 impl Deref for MorsKms {
@@ -45,22 +49,25 @@ impl Kms for MorsKms {
         &self,
         key_id: CipherKeyId,
     ) -> std::result::Result<Option<AesCipher>, KmsError> {
-        if let Some(dk) = self.get_data_key(key_id)? {
-            let cipher = AesCipher::new(&dk.data, key_id)?;
-            return Ok(cipher.into());
-        };
-        Ok(None)
+        Ok(self.get_cipher_impl(key_id)?)
     }
 
     fn latest_cipher(
         &self,
     ) -> std::result::Result<Option<Self::Cipher>, KmsError> {
-        if let Some(data_key) = self.latest_datakey()? {
-            return Ok(
-                AesCipher::new(&data_key.data, data_key.key_id.into())?.into()
-            );
-        };
-        Ok(None)
+        Ok(self.latest_cipher_impl()?)
+    }
+    fn data_key_ids(&self) -> std::result::Result<Vec<CipherKeyId>, KmsError> {
+        let inner_r = self
+            .read()
+            .map_err(|e| MorsKmsError::RwLockPoisoned(format!("{e}")))?;
+        Ok(inner_r.data_keys.keys().copied().collect())
+    }
+    fn retire_data_keys(
+        &self,
+        key_ids: &[CipherKeyId],
+    ) -> std::result::Result<(), KmsError> {
+        Ok(self.retire_data_keys_impl(key_ids)?)
     }
     type KmsBuilder = MorsKmsBuilder;
 }
@@ -68,16 +75,34 @@ impl Kms for MorsKms {
 #[derive(Debug, Default)]
 pub struct KmsInner {
     data_keys: HashMap<CipherKeyId, DataKey>,
+    // one per data key, cloned into its ciphers so a data key a file is
+    // being written with is not retired.
+    pins: HashMap<CipherKeyId, Arc<()>>,
     last_created: PhyTs, //last_created is the timestamp(seconds) of the last data key,
     next_key_id: CipherKeyId,
     file: Option<File>,
-    cipher: Option<AesCipher>,
+    master: MasterKey,
     data_key_rotation_duration: Duration,
+    encryption_algo: EncryptionAlgo,
+    dir: PathBuf,
+}
+impl DataKey {
+    fn cipher(&self) -> Result<AesCipher> {
+        let algo = EncryptionAlgo::try_from(self.algo as u8)
+            .map_err(|_| MorsEncryptError::UnknownEncryptionAlgo(self.algo))?;
+        Ok(AesCipher::new_with_algo(
+            algo,
+            &self.data,
+            self.key_id.into(),
+        )?)
+    }
 }
 #[derive(Debug, Clone)]
 pub struct MorsKmsBuilder {
     encrypt_key: Vec<u8>,                 // encryption key
     data_key_rotation_duration: Duration, // key rotation duration
+    encryption_algo: EncryptionAlgo,
+    key_provider: MasterKey,
     read_only: bool,
     dir: PathBuf,
 }
@@ -87,6 +112,8 @@ impl Default for MorsKmsBuilder {
         Self {
             encrypt_key: Default::default(),
             data_key_rotation_duration: Duration::from_secs(10 * 24 * 60 * 60),
+            encryption_algo: EncryptionAlgo::default(),
+            key_provider: None,
             read_only: false,
             dir: PathBuf::from(DEFAULT_DIR),
         }
@@ -106,23 +133,34 @@ impl MorsKmsBuilder {
         self.data_key_rotation_duration = duration;
         self
     }
+    /// algorithm of newly created data keys, default aes-gcm.
+    ///
+    /// Data keys remember their algorithm, so changing it starts a new
+    /// data key while files encrypted with older keys stay readable.
+    pub fn with_encryption_algo(mut self, algo: EncryptionAlgo) -> Self {
+        self.encryption_algo = algo;
+        self
+    }
+    /// wraps the data keys with `provider` instead of the in-memory
+    /// encryption key, see [`crate::provider`].
+    pub fn with_key_provider(mut self, provider: Arc<dyn KeyProvider>) -> Self {
+        self.key_provider = Some(provider);
+        self
+    }
     fn build_impl(&self) -> Result<MorsKms> {
-        let keys_len = self.encrypt_key.len();
-
-        if keys_len > 0 && ![16, 32].contains(&keys_len) {
-            return Err(MorsEncryptError::InvalidEncryptionKey.into());
-        }
         let mut key_registry = KmsInner {
             data_keys: Default::default(),
+            pins: Default::default(),
             last_created: PhyTs::default(),
             next_key_id: 1.into(),
             file: None,
-            cipher: if keys_len > 0 {
-                AesCipher::new(&self.encrypt_key, 0.into()).ok()
-            } else {
-                None
+            master: match &self.key_provider {
+                Some(provider) => Some(provider.clone()),
+                None => master_key(&self.encrypt_key)?,
             },
             data_key_rotation_duration: self.data_key_rotation_duration,
+            encryption_algo: self.encryption_algo,
+            dir: self.dir.clone(),
         };
         let key_registry_path = self.dir.join(KEY_REGISTRY_FILE_NAME);
 
@@ -147,10 +185,34 @@ impl MorsKmsBuilder {
         Ok(MorsKms(Arc::new(RwLock::new(key_registry))))
     }
 }
+// in-memory master key, an empty key disables encryption.
+fn master_key(encrypt_key: &[u8]) -> Result<MasterKey> {
+    match encrypt_key.len() {
+        0 => Ok(None),
+        16 | 32 => Ok(Some(Arc::new(LocalKeyProvider::new(encrypt_key)?))),
+        _ => Err(MorsEncryptError::InvalidEncryptionKey.into()),
+    }
+}
+/// Re-wraps every data key of the key registry in `dir` from the master key
+/// `old` to `new`, it fails if the db is open.
+///
+/// Only the key registry is rewritten, so tables, wal and vlog stay untouched.
+pub fn rotate_master_key(dir: &Path, old: &[u8], new: &[u8]) -> Result<()> {
+    let _lock_guard = DBLockGuardBuilder::new()
+        .add_dir(dir.to_path_buf())
+        .build()?;
+    let mut builder = MorsKmsBuilder::new(old.to_vec());
+    builder.set_dir(dir.to_path_buf());
+    builder.build_impl()?.rotate_master_key(old, new)
+}
 impl KmsBuilder<MorsKms> for MorsKmsBuilder {
     fn build(&self) -> std::result::Result<MorsKms, KmsError> {
         Ok(self.build_impl()?)
     }
+    fn set_encryption_algo(&mut self, algo: EncryptionAlgo) -> &mut Self {
+        self.encryption_algo = algo;
+        self
+    }
 }
 impl WithDir for MorsKmsBuilder {
     fn set_dir(&mut self, dir: PathBuf) -> &mut Self {
@@ -181,16 +243,16 @@ impl KmsInner {
         let nonce: Nonce = AesCipher::generate_nonce();
         let mut e_sanity = SANITY_TEXT.to_vec();
 
-        if let Some(c) = &self.cipher {
-            e_sanity = c.encrypt(&nonce, &e_sanity)?;
+        if let Some(m) = &self.master {
+            e_sanity = m.wrap(&nonce, &e_sanity)?;
         }
         let mut buf = Vec::with_capacity(12 + 4 + 12 + 16);
         buf.put_slice(nonce.as_slice());
         buf.put_u32(e_sanity.len() as u32);
         buf.put_slice(&e_sanity);
 
-        for (_, data_key) in self.data_keys.iter_mut() {
-            Self::store_data_key(&mut buf, &self.cipher, data_key)?;
+        for data_key in self.data_keys.values() {
+            Self::store_data_key(&mut buf, &self.master, data_key)?;
         }
 
         let rewrite_path = dir.join(KEY_REGISTRY_REWRITE_FILE_NAME);
@@ -206,29 +268,82 @@ impl KmsInner {
         rename(rewrite_path, dir.join(KEY_REGISTRY_FILE_NAME))?;
 
         rewrite_fp.sync_all()?;
+        File::open(dir)?.sync_all()?;
         Ok(())
     }
+    // rewrites the registry in place and reopens it for appending.
+    fn rewrite(&mut self) -> Result<()> {
+        let dir = self.dir.clone();
+        self.write_to_file(&dir)?;
+        if self.file.is_some() {
+            let file = OpenOptions::new()
+                .read(true)
+                .append(true)
+                .custom_flags(libc::O_DSYNC)
+                .open(dir.join(KEY_REGISTRY_FILE_NAME))?;
+            self.file = Some(file);
+        }
+        Ok(())
+    }
+    // fails if a data key can not be read, the files encrypted with it
+    // would be lost.
     fn read(&mut self, fp: &File) -> Result<()> {
-        let key_iter = KeyRegistryIter::new(fp, &self.cipher)?;
-        for data_key in key_iter {
+        let master = self.master.clone();
+        let mut key_iter = KeyRegistryIter::new(fp, &master)?;
+        for data_key in key_iter.by_ref() {
             self.next_key_id = self.next_key_id.max(data_key.key_id.into());
             self.last_created =
                 self.last_created.max(data_key.created_at.into());
-            self.data_keys.insert(data_key.key_id.into(), data_key);
+            self.insert_data_key(data_key);
+        }
+        if key_iter.skipped > 0 {
+            return Err(MorsKmsError::CorruptedDataKeys(key_iter.skipped));
         }
         Ok(())
     }
+    // reads every data key of the registry file with `master` and counts
+    // them, it fails if one can not be read.
+    fn check_file(&self, master: &MasterKey) -> Result<usize> {
+        let file = File::open(self.dir.join(KEY_REGISTRY_FILE_NAME))?;
+        let mut iter = KeyRegistryIter::new(&file, master)?;
+        let count = iter.by_ref().count();
+        if iter.skipped > 0 {
+            return Err(MorsKmsError::CorruptedDataKeys(iter.skipped));
+        }
+        Ok(count)
+    }
+    fn insert_data_key(&mut self, data_key: DataKey) {
+        let key_id = data_key.key_id.into();
+        self.pins.entry(key_id).or_default();
+        self.data_keys.insert(key_id, data_key);
+    }
+    // a cipher of `data_key`, which pins it until the cipher and its clones
+    // are dropped.
+    fn cipher(&self, data_key: &DataKey) -> Result<AesCipher> {
+        let cipher = data_key.cipher()?;
+        Ok(match self.pins.get(&data_key.key_id.into()) {
+            Some(pin) => cipher.with_pin(pin.clone()),
+            None => cipher,
+        })
+    }
+    fn is_pinned(&self, key_id: &CipherKeyId) -> bool {
+        self.pins
+            .get(key_id)
+            .is_some_and(|pin| Arc::strong_count(pin) > 1)
+    }
     fn store_data_key(
         buf: &mut Vec<u8>,
-        cipher: &Option<AesCipher>,
-        data_key: &mut DataKey,
+        master: &MasterKey,
+        data_key: &DataKey,
     ) -> Result<()> {
-        let nonce = Nonce::from_slice(&data_key.iv);
-        if let Some(c) = &cipher {
-            data_key.data = c.encrypt(nonce, &data_key.data)?;
-        }
-
-        let e_data_key = data_key.encode_to_vec();
+        let e_data_key = match master {
+            Some(m) => DataKey {
+                data: m.wrap(&data_key.iv, &data_key.data)?,
+                ..data_key.clone()
+            }
+            .encode_to_vec(),
+            None => data_key.encode_to_vec(),
+        };
 
         let mut len_crc_buf = Vec::with_capacity(8);
         len_crc_buf.put_u32(e_data_key.len() as u32);
@@ -236,17 +351,15 @@ impl KmsInner {
 
         buf.put(len_crc_buf.as_ref());
         buf.put(e_data_key.as_ref());
-
-        if let Some(c) = &cipher {
-            data_key.data = c.decrypt(nonce, &data_key.data)?;
-        }
         Ok(())
     }
 }
 struct KeyRegistryIter<'a> {
     reader: BufReader<&'a File>,
-    cipher: &'a Option<AesCipher>,
+    master: &'a MasterKey,
     len_crc_buf: Vec<u8>,
+    // data keys skipped because they could not be read.
+    skipped: usize,
 }
 impl<'a> KeyRegistryIter<'a> {
     fn valid(&mut self) -> Result<()> {
@@ -261,8 +374,8 @@ impl<'a> KeyRegistryIter<'a> {
         let mut e_saintytext = vec![0; len_e_saintytext as usize];
         self.reader.read_exact(e_saintytext.as_mut())?;
 
-        let saintytext = match self.cipher {
-            Some(c) => c.decrypt(&nonce, &e_saintytext)?,
+        let saintytext = match self.master {
+            Some(m) => m.unwrap(&nonce, &e_saintytext)?,
             None => e_saintytext.to_vec(),
         };
 
@@ -271,13 +384,14 @@ impl<'a> KeyRegistryIter<'a> {
         };
         Ok(())
     }
-    fn new(fp: &'a File, cipher: &'a Option<AesCipher>) -> Result<Self> {
+    fn new(fp: &'a File, master: &'a MasterKey) -> Result<Self> {
         let mut reader = BufReader::new(fp);
         reader.seek(std::io::SeekFrom::Start(0))?;
         let mut s = Self {
             reader,
-            cipher,
+            master,
             len_crc_buf: vec![0; 8],
+            skipped: 0,
         };
         s.valid()?;
         Ok(s)
@@ -325,6 +439,7 @@ impl<'a> Iterator for KeyRegistryIter<'a> {
                 e_data_key
             );
             //skip
+            self.skipped += 1;
             return self.next();
         };
         let mut data_key = match DataKey::decode(e_data_key.as_ref()) {
@@ -335,17 +450,19 @@ impl<'a> Iterator for KeyRegistryIter<'a> {
                     e_data_key, e
                 );
                 //skip
+                self.skipped += 1;
                 return self.next();
             }
         };
-        if let Some(c) = self.cipher {
-            match c.decrypt_with_slice(&data_key.iv, &data_key.data) {
+        if let Some(m) = self.master {
+            match m.unwrap(&data_key.iv, &data_key.data) {
                 Ok(data) => {
                     data_key.data = data;
                 }
                 Err(e) => {
                     error!("Error while use aes cipher to decrypt datakey.data for {e}");
                     //skip
+                    self.skipped += 1;
                     return self.next();
                 }
             };
@@ -354,18 +471,88 @@ impl<'a> Iterator for KeyRegistryIter<'a> {
     }
 }
 impl MorsKms {
-    fn latest_datakey(&self) -> Result<Option<DataKey>> {
+    /// Re-wraps every data key with the master key `new` while the db is open.
+    ///
+    /// The registry is written to [`KEY_REGISTRY_REWRITE_FILE_NAME`] and
+    /// renamed over [`KEY_REGISTRY_FILE_NAME`], `old` must be the current
+    /// master key and the db has to be reopened with `new` afterwards.
+    pub fn rotate_master_key(&self, old: &[u8], new: &[u8]) -> Result<()> {
+        self.rotate_key_provider(master_key(old)?, master_key(new)?)
+    }
+    /// like [`Self::rotate_master_key`] but the master keys are behind
+    /// key providers, `None` disables encryption.
+    pub fn rotate_key_provider(
+        &self,
+        old: Option<Arc<dyn KeyProvider>>,
+        new: Option<Arc<dyn KeyProvider>>,
+    ) -> Result<()> {
+        let mut inner_w = self
+            .write()
+            .map_err(|e| MorsKmsError::RwLockPoisoned(format!("{e}")))?;
+        // fails with EncryptionKeyMismatch if `old` is not the master key.
+        let count = inner_w.check_file(&old)?;
+        debug_assert_eq!(count, inner_w.data_keys.len());
+        let old = std::mem::replace(&mut inner_w.master, new);
+        if let Err(e) = inner_w.rewrite() {
+            inner_w.master = old;
+            return Err(e);
+        }
+        Ok(())
+    }
+    /// drops data keys no file is encrypted with anymore and rewrites
+    /// the registry, the latest data key and the ones a cipher is alive of
+    /// are kept.
+    fn retire_data_keys_impl(&self, key_ids: &[CipherKeyId]) -> Result<()> {
+        let mut inner_w = self
+            .write()
+            .map_err(|e| MorsKmsError::RwLockPoisoned(format!("{e}")))?;
+        if inner_w.file.is_none() {
+            return Ok(());
+        }
+        // the rewrite only keeps the data keys read into memory.
+        let master = inner_w.master.clone();
+        inner_w.check_file(&master)?;
+        let latest = inner_w.next_key_id;
+        let retired = key_ids
+            .iter()
+            .filter(|id| **id != latest && !inner_w.is_pinned(id))
+            .copied()
+            .collect::<Vec<_>>();
+        let retired = retired
+            .iter()
+            .filter_map(|id| inner_w.data_keys.remove(id))
+            .collect::<Vec<_>>();
+        if retired.is_empty() {
+            return Ok(());
+        }
+        if let Err(e) = inner_w.rewrite() {
+            for data_key in retired {
+                inner_w.insert_data_key(data_key);
+            }
+            return Err(e);
+        }
+        for data_key in retired.iter() {
+            inner_w.pins.remove(&data_key.key_id.into());
+        }
+        Ok(())
+    }
+    fn latest_cipher_impl(&self) -> Result<Option<AesCipher>> {
         let inner_r = self
             .read()
             .map_err(|e| MorsKmsError::RwLockPoisoned(format!("{e}")))?;
-        if inner_r.cipher.is_none() {
+        if inner_r.master.is_none() {
             return Ok(None);
         }
 
         let valid_key = |inner: &KmsInner| {
             let last = inner.last_created.into();
+            let algo = inner
+                .data_keys
+                .get(&inner.next_key_id)
+                .map(|k| k.algo == inner.encryption_algo as u32)
+                .unwrap_or(true);
             if let Ok(diff) = SystemTime::now().duration_since(last) {
-                if diff < inner.data_key_rotation_duration {
+                if algo && diff < inner.data_key_rotation_duration {
                     return (
                         inner
                             .data_keys
@@ -379,7 +566,7 @@ impl MorsKms {
         };
         let (key, valid) = valid_key(&inner_r);
         if valid {
-            return Ok(key);
+            return key.map(|k| inner_r.cipher(&k)).transpose();
         }
         drop(inner_r);
         let mut inner_w = self
@@ -387,36 +574,43 @@ impl MorsKms {
             .map_err(|e| MorsKmsError::RwLockPoisoned(format!("{e}")))?;
         let (key, valid) = valid_key(&inner_w);
         if valid {
-            return Ok(key);
+            return key.map(|k| inner_w.cipher(&k)).transpose();
         }
 
-        let cipher = inner_w.cipher.as_ref().unwrap();
+        let master = inner_w.master.as_ref().unwrap();
 
-        let key = cipher.generate_key();
+        let algo = inner_w.encryption_algo;
+        let key_len = match algo {
+            EncryptionAlgo::ChaCha20Poly1305 => 32,
+            _ => master.data_key_len()?,
+        };
+        let key = AesCipher::generate_key_of_len(key_len)?;
         let nonce: Nonce = AesCipher::generate_nonce();
         inner_w.next_key_id += 1;
         let key_id = inner_w.next_key_id;
         let created_at = PhyTs::now()?;
-        let mut data_key = DataKey {
+        let data_key = DataKey {
             key_id: key_id.into(),
             data: key,
             iv: nonce.to_vec(),
             created_at: created_at.into(),
+            algo: algo as u32,
         };
         let mut buf = Vec::new();
-        KmsInner::store_data_key(&mut buf, &inner_w.cipher, &mut data_key)?;
+        KmsInner::store_data_key(&mut buf, &inner_w.master, &data_key)?;
         if let Some(f) = &mut inner_w.file {
             f.write_all(&buf)?;
         }
 
         inner_w.last_created = created_at;
-        inner_w.data_keys.insert(key_id, data_key.clone());
-        Ok(Some(data_key))
+        let cipher = data_key.cipher()?;
+        inner_w.insert_data_key(data_key);
+        Ok(Some(cipher.with_pin(inner_w.pins[&key_id].clone())))
     }
-    fn get_data_key(
+    fn get_cipher_impl(
         &self,
         cipher_key_id: CipherKeyId,
-    ) -> Result<Option<DataKey>> {
+    ) -> Result<Option<AesCipher>> {
         let inner_r = self
             .read()
             .map_err(|e| MorsKmsError::RwLockPoisoned(format!("{e}")))?;
@@ -424,8 +618,146 @@ impl MorsKms {
             return Ok(None);
         }
         match inner_r.data_keys.get(&cipher_key_id) {
-            Some(s) => Ok(Some(s.clone())),
+            Some(s) => Ok(Some(inner_r.cipher(s)?)),
             None => Err(MorsKmsError::InvalidDataKeyID(cipher_key_id)),
         }
     }
 }
+#[cfg(test)]
+mod tests {
+    use mors_common::lock::DBLockGuardBuilder;
+    use mors_traits::default::WithDir;
+    use mors_traits::kms::{EncryptionAlgo, Kms, KmsCipher};
+
+    use super::{rotate_master_key, MorsKmsBuilder, KEY_REGISTRY_FILE_NAME};
+    use crate::error::MorsKmsError;
+
+    #[test]
+    fn test_rotate_master_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let open = |key: &[u8]| {
+            let mut builder = MorsKmsBuilder::new(key.to_vec());
+            builder.set_dir(dir.path().to_path_buf());
+            builder.build_impl()
+        };
+        let (a, b, c) = ([1u8; 16], [2u8; 32], [3u8; 16]);
+
+        let kms = open(&a).unwrap();
+        let first = kms.latest_cipher().unwrap().unwrap();
+        let e_text = first.encrypt_with_slice(&[0; 12], b"text").unwrap();
+        assert!(matches!(
+            kms.rotate_master_key(&b, &c),
+            Err(MorsKmsError::EncryptionKeyMismatch)
+        ));
+        kms.rotate_master_key(&a, &b).unwrap();
+        // data keys created after an online rotation use the new master key.
+        kms.write().unwrap().last_created = Default::default();
+        let second = kms.latest_cipher().unwrap().unwrap();
+        drop(kms);
+
+        assert!(matches!(open(&a), Err(MorsKmsError::EncryptionKeyMismatch)));
+        // not while the db holds its directory lock.
+        let guard = DBLockGuardBuilder::new()
+            .add_dir(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        assert!(matches!(
+            rotate_master_key(dir.path(), &b, &c),
+            Err(MorsKmsError::IOErr(_))
+        ));
+        drop(guard);
+        rotate_master_key(dir.path(), &b, &c).unwrap();
+        let kms = open(&c).unwrap();
+        let cipher = kms.get_cipher(first.cipher_key_id()).unwrap().unwrap();
+        assert_eq!(
+            cipher.decrypt_with_slice(&[0; 12], &e_text).unwrap(),
+            b"text"
+        );
+        assert!(kms.get_cipher(second.cipher_key_id()).unwrap().is_some());
+    }
+    #[test]
+    fn test_retire_data_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let open = || {
+            let mut builder = MorsKmsBuilder::new(vec![1; 32]);
+            builder.set_dir(dir.path().to_path_buf());
+            builder.build_impl()
+        };
+        let kms = open().unwrap();
+        let writing = kms.latest_cipher().unwrap().unwrap();
+        let old = writing.cipher_key_id();
+        kms.write().unwrap().last_created = Default::default();
+        let latest = kms.latest_cipher().unwrap().unwrap().cipher_key_id();
+        assert_ne!(old, latest);
+
+        // a file may still be written with a cipher of the old key.
+        kms.retire_data_keys(&[old, latest]).unwrap();
+        assert_eq!(kms.data_key_ids().unwrap().len(), 2);
+        drop(writing);
+        kms.retire_data_keys(&[old, latest]).unwrap();
+        assert_eq!(kms.data_key_ids().unwrap(), vec![latest]);
+        drop(kms);
+
+        let kms = open().unwrap();
+        assert!(kms.get_cipher(old).is_err());
+        assert!(kms.get_cipher(latest).unwrap().is_some());
+    }
+    #[test]
+    fn test_corrupted_data_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let open = || {
+            let mut builder = MorsKmsBuilder::new(vec![1; 32]);
+            builder.set_dir(dir.path().to_path_buf());
+            builder.build_impl()
+        };
+        open().unwrap().latest_cipher().unwrap().unwrap();
+
+        // a data key whose checksum holds but which the master key can not
+        // unwrap anymore.
+        let path = dir.path().join(KEY_REGISTRY_FILE_NAME);
+        let mut buf = std::fs::read(&path).unwrap();
+        let sanity_len = u32::from_be_bytes(buf[12..16].try_into().unwrap());
+        let offset = 16 + sanity_len as usize;
+        let len =
+            u32::from_be_bytes(buf[offset..offset + 4].try_into().unwrap());
+        let e_data_key = offset + 8..offset + 8 + len as usize;
+        assert_eq!(e_data_key.end, buf.len());
+        // within the wrapped key, after the key id and the field header.
+        buf[e_data_key.start + 5] ^= 1;
+        let crc = crc32fast::hash(&buf[e_data_key]);
+        buf[offset + 4..offset + 8].copy_from_slice(&crc.to_be_bytes());
+        std::fs::write(&path, buf).unwrap();
+
+        assert!(matches!(open(), Err(MorsKmsError::CorruptedDataKeys(1))));
+    }
+    #[test]
+    fn test_mixed_encryption_algo() {
+        let dir = tempfile::tempdir().unwrap();
+        let open = |algo: EncryptionAlgo| {
+            let mut builder =
+                MorsKmsBuilder::new(vec![1; 16]).with_encryption_algo(algo);
+            builder.set_dir(dir.path().to_path_buf());
+            builder.build_impl()
+        };
+        let mut texts = Vec::new();
+        for algo in [
+            EncryptionAlgo::AesGcm,
+            EncryptionAlgo::AesGcmSiv,
+            EncryptionAlgo::ChaCha20Poly1305,
+        ] {
+            // changing the algorithm starts a new data key.
+            let cipher = open(algo).unwrap().latest_cipher().unwrap().unwrap();
+            assert_eq!(cipher.encryption_algo(), algo);
+            let e_text = KmsCipher::encrypt(&cipher, b"text").unwrap();
+            texts.push((cipher.cipher_key_id(), algo, e_text));
+        }
+
+        let kms = open(EncryptionAlgo::AesGcm).unwrap();
+        assert_eq!(kms.data_key_ids().unwrap().len(), 3);
+        for (key_id, algo, e_text) in texts {
+            let cipher = kms.get_cipher(key_id).unwrap().unwrap();
+            assert_eq!(cipher.encryption_algo(), algo);
+            assert_eq!(KmsCipher::decrypt(&cipher, &e_text).unwrap(), b"text");
+        }
+    }
+}
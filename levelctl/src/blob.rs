@@ -0,0 +1,140 @@
+use std::collections::{HashMap, HashSet};
+
+use log::{error, info};
+use mors_common::{
+    file_id::{BlobFileId, FileId},
+    kv::{Meta, ValueMeta, ValuePointer},
+};
+use mors_traits::{
+    default::WithDir,
+    kms::{CipherKeyId, Kms},
+    levelctl::LevelCtlTrait,
+    sstable::{TableBuilderTrait, TableTrait},
+};
+use parking_lot::Mutex;
+
+use crate::ctl::LevelCtl;
+use crate::error::MorsLevelCtlError;
+type Result<T> = std::result::Result<T, MorsLevelCtlError>;
+
+/// number of live tables pointing into each blob file.
+#[derive(Debug, Default)]
+pub(crate) struct BlobRefs(Mutex<HashMap<BlobFileId, usize>>);
+impl BlobRefs {
+    fn add(&self, ids: impl Iterator<Item = BlobFileId>) {
+        let mut refs = self.0.lock();
+        for id in ids {
+            *refs.entry(id).or_default() += 1;
+        }
+    }
+    // the blob files no live table points into anymore.
+    fn remove(&self, ids: impl Iterator<Item = BlobFileId>) -> Vec<BlobFileId> {
+        let mut refs = self.0.lock();
+        let mut dead = Vec::new();
+        for id in ids {
+            if let Some(n) = refs.get_mut(&id) {
+                *n -= 1;
+                if *n == 0 {
+                    refs.remove(&id);
+                    dead.push(id);
+                }
+            }
+        }
+        dead
+    }
+    fn ids(&self) -> Vec<BlobFileId> {
+        self.0.lock().keys().copied().collect()
+    }
+}
+impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
+    /// counts the blob files `tables` point into as live, before the
+    /// tables are added to a level.
+    pub(crate) fn add_blob_refs(&self, tables: &[T]) {
+        self.blob_refs()
+            .add(tables.iter().flat_map(|t| t.blob_files().iter().copied()));
+    }
+    /// deletes the blob files only `tables` pointed into, after the tables
+    /// were removed from their level.
+    pub(crate) fn remove_blob_refs(&self, tables: &[T]) {
+        let dead = self
+            .blob_refs()
+            .remove(tables.iter().flat_map(|t| t.blob_files().iter().copied()));
+        for id in dead {
+            info!("Blob file {} not referenced by any table, Deleting it", id);
+            if let Err(e) = self.table_builder().delete_blob(id) {
+                error!("Delete blob file error: {:?}", e);
+            }
+        }
+    }
+    /// deletes the blob files left by tables never added to the manifest.
+    pub(crate) fn remove_orphan_blobs(&self) -> Result<()> {
+        let live = self.blob_refs().ids().into_iter().collect::<HashSet<_>>();
+        let dir = self.table_builder().dir();
+        for id in BlobFileId::parse_set_from_dir(dir) {
+            if !live.contains(&id) {
+                info!(
+                    "Blob file {} not referenced by any table, Deleting it",
+                    id
+                );
+                std::fs::remove_file(id.join_dir(dir))?;
+            }
+        }
+        Ok(())
+    }
+    /// the oldest live blob files, the blob gc age cutoff of them, whose
+    /// values compactions move into the blob files they write.
+    pub(crate) fn blob_gc_files(&self) -> HashSet<BlobFileId> {
+        let mut ids = self.blob_refs().ids();
+        ids.sort_unstable();
+        let len = ids.len() as f64 * self.config().blob_gc_age_cutoff();
+        ids.truncate(len as usize);
+        ids.into_iter().collect()
+    }
+    /// whether `table` points into a blob file whose data key is not `latest`.
+    pub(crate) fn has_stale_blobs(
+        &self,
+        table: &T,
+        latest: CipherKeyId,
+    ) -> bool {
+        table.blob_files().iter().any(|id| {
+            self.table_builder()
+                .blob_cipher_key_id(*id)
+                .is_ok_and(|key_id| key_id != latest)
+        })
+    }
+    /// data keys of the live blob files.
+    pub(crate) fn blob_cipher_key_ids(&self) -> Vec<CipherKeyId> {
+        self.blob_refs()
+            .ids()
+            .into_iter()
+            .filter_map(|id| {
+                self.table_builder()
+                    .blob_cipher_key_id(id)
+                    .inspect_err(|e| error!("Blob file {} error: {}", id, e))
+                    .ok()
+            })
+            .collect()
+    }
+    /// replaces a value pointing into a blob file by the value itself.
+    pub(crate) fn resolve_blob(
+        &self,
+        mut value: ValueMeta,
+    ) -> Result<ValueMeta> {
+        if !value.meta().contains(Meta::BLOB_INDEX) {
+            return Ok(value);
+        }
+        let vp = ValuePointer::decode(value.value()).ok_or_else(|| {
+            MorsLevelCtlError::InvalidBlobIndex(value.value().clone())
+        })?;
+        let key_id =
+            self.table_builder().blob_cipher_key_id(vp.fid().into())?;
+        let cipher = if key_id == CipherKeyId::default() {
+            None
+        } else {
+            self.kms().get_cipher(key_id)?
+        };
+        value.set_value(self.table_builder().read_blob(&vp, cipher.as_ref())?);
+        value.set_meta(value.meta() - Meta::BLOB_INDEX);
+        Ok(value)
+    }
+}
@@ -1,12 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{atomic::Ordering, Arc};
 use std::time::SystemTime;
 
 use log::{debug, info};
 use mors_common::{
-    file_id::{FileId, SSTableId},
-    kv::{Meta, ValueMeta, ValuePointer},
-    ts::KeyTs,
+    file_id::{BlobFileId, FileId, SSTableId},
+    kv::{Meta, RangeTombstone, ValueMeta, ValuePointer},
+    rayon,
+    ts::{KeyTs, KeyTsBorrow, TxnTs},
 };
 use mors_traits::{
     default::WithDir,
@@ -14,15 +15,17 @@ use mors_traits::{
         CacheIterator, KvCacheIter, KvCacheIterator, KvCacheMergeIterator,
         KvSeekIter,
     },
-    kms::{Kms, KmsCipher},
-    levelctl::{Level, LevelCtlTrait, LEVEL0},
+    kms::{CipherKeyId, Kms, KmsCipher},
+    levelctl::{
+        CompactionDecision, Level, LevelCtlTrait, SnapshotList, LEVEL0,
+    },
     sstable::{
         CacheTableConcatIter, SSTableError, TableBuilderTrait, TableTrait,
         TableWriterTrait,
     },
     vlog::DiscardTrait,
 };
-use tokio::task::JoinHandle;
+use tokio::{runtime::Handle, task::JoinHandle};
 
 use crate::manifest::manifest_change::ManifestChange;
 use crate::manifest::Manifest;
@@ -31,6 +34,8 @@ use crate::{ctl::LevelCtl, error::MorsLevelCtlError};
 use super::plan::{CompactPlan, CompactPlanReadGuard, KeyTsRange};
 use super::{CompactContext, Result};
 
+type TableTask<T> = JoinHandle<std::result::Result<Option<T>, SSTableError>>;
+
 impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
     pub(crate) async fn compact<D: DiscardTrait>(
         &self,
@@ -53,7 +58,7 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
         debug_assert!(plan.splits().is_empty());
 
         if this_level.level() != next_level.level() {
-            plan.add_splits();
+            plan.add_splits(self.config().max_sub_compactions());
         }
         if plan.splits().is_empty() {
             plan.push_split(KeyTsRange::default());
@@ -70,8 +75,11 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
             plan.top().iter().fold(0, |acc, x| acc + x.size())
                 + plan.bottom().iter().fold(0, |acc, x| acc + x.size());
 
+        self.add_blob_refs(&new_tables);
         plan.next_level().replace(plan.bottom(), &new_tables);
         plan.this_level().delete(plan.top());
+        self.remove_blob_refs(plan.top());
+        self.remove_blob_refs(plan.bottom());
 
         let table_to_string = |tables: &[T]| {
             let mut v = Vec::with_capacity(tables.len());
@@ -132,12 +140,14 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
             new_tables.len() + plan.top().len() + plan.bottom().len(),
         );
         for table in new_tables {
-            let cipher = table.cipher().map(|c| c.cipher_key_id());
+            let cipher = table.cipher();
             changes.push(ManifestChange::new_create(
                 table.id(),
                 plan.next_level().level(),
-                cipher,
+                cipher.map(|c| c.cipher_key_id()),
+                cipher.map(|c| c.encryption_algo()).unwrap_or_default(),
                 table.compression(),
+                table.global_version(),
             ));
         }
 
@@ -190,23 +200,28 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
             out.push(Box::new(CacheTableConcatIter::new(valid.clone(), true)));
             out
         };
+        // sub-compactions are cpu bound, so run them on the rayon pool;
+        // the number of splits bounds the parallelism of one compaction.
+        let handle = Handle::current();
         let mut compact_task = Vec::new();
         let plan_clone = Arc::new(plan.clone());
         for kr in plan.splits() {
             let iters = new_iter();
             if let Some(merge) = KvCacheMergeIterator::new(iters) {
-                compact_task.push(tokio::spawn(self.clone().sub_compact(
-                    merge,
-                    kr.clone(),
-                    plan_clone.clone(),
-                    context.clone(),
-                )));
+                let ctl = self.clone();
+                let kr = kr.clone();
+                let plan = plan_clone.clone();
+                let context = context.clone();
+                let handle = handle.clone();
+                compact_task.push(rayon::spawn(move || {
+                    ctl.sub_compact(merge, kr, plan, context, handle)
+                }));
             };
         }
 
         let mut tables = Vec::new();
         for compact in compact_task {
-            for table_task in compact.await?? {
+            for table_task in compact.await? {
                 if let Some(t) = table_task.await?? {
                     tables.push(t);
                 }
@@ -216,19 +231,23 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
         tables.sort_by(|a, b| a.biggest().cmp(b.biggest()));
         Ok(tables)
     }
-    async fn sub_compact<D: DiscardTrait>(
+    fn sub_compact<D: DiscardTrait>(
         self,
-        mut merge_iter: KvCacheMergeIterator,
+        mut merge_iter: KvCacheMergeIterator<'static>,
         kr: KeyTsRange,
         plan: Arc<CompactPlan<T, K>>,
         context: CompactContext<K, D>,
-    ) -> Result<Vec<JoinHandle<std::result::Result<Option<T>, SSTableError>>>>
-    {
+        handle: Handle,
+    ) -> Result<Vec<TableTask<T>>> {
         let mut all_tables = plan.top().to_vec();
         all_tables.extend_from_slice(plan.bottom());
 
         let is_intersect =
             self.check_intersect(&all_tables, plan.next_level().level());
+        let range_tombstones = all_tables
+            .iter()
+            .flat_map(|t| t.range_tombstones().iter().cloned())
+            .collect::<Vec<_>>();
 
         let target = plan.priority().target();
 
@@ -242,9 +261,11 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
 
         let mut discard_stats = HashMap::new();
         let mut table_task = Vec::new();
+        let retention = Retention::new(self.snapshot_list().as_deref());
+        let gc_blobs = self.blob_gc_files();
         while merge_iter.valid() {
             if !kr.right().is_empty()
-                && merge_iter.key().unwrap() == *kr.right()
+                && merge_iter.key().unwrap() >= *kr.right()
             {
                 break;
             }
@@ -252,27 +273,37 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
             let target_size = target.file_size(plan.next_level().level());
             builder.set_table_size(target_size);
             let cipher = context.kms.latest_cipher()?;
-            let writer = T::new_writer(builder.clone(), cipher.clone());
+            // the id is taken first as the blob file is named after the table.
+            let next_id: SSTableId =
+                self.next_id().fetch_add(1, Ordering::AcqRel).into();
+            let mut writer = T::new_writer(builder.clone(), cipher.clone());
+            writer.set_blob_file(next_id.into());
 
             let mut context = AddKeyContext {
                 last_key: Default::default(),
                 skip_key: Default::default(),
                 num_versions: Default::default(),
+                last_stripe: Default::default(),
                 discard_stats: &mut discard_stats,
                 first_key_has_discard_set: Default::default(),
                 ctl: &self,
                 kr: &kr,
                 is_intersect,
+                range_tombstones: &range_tombstones,
                 writer,
                 plan: &plan,
+                cipher_key_id: cipher
+                    .as_ref()
+                    .map(|c| c.cipher_key_id())
+                    .unwrap_or_default(),
+                retention: &retention,
+                gc_blobs: &gc_blobs,
             };
             context.push(&mut merge_iter)?;
-            let next_id: SSTableId =
-                self.next_id().fetch_add(1, Ordering::AcqRel).into();
 
             let path = next_id.join_dir(self.table_builder().dir());
             let mut writer = context.writer;
-            table_task.push(tokio::spawn(async move {
+            table_task.push(handle.spawn(async move {
                 writer.flush_to_disk(path).await?;
                 builder.open(next_id, cipher).await
             }));
@@ -298,22 +329,54 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
         }
         false
     }
+    // whether any table outside the plan, from `plan.this_level()` down,
+    // may hold keys covered by the tombstone.
+    fn check_tombstone_intersect(
+        &self,
+        tombstone: &RangeTombstone,
+        plan: &CompactPlan<T, K>,
+    ) -> bool {
+        let level = plan.this_level().level();
+        let inputs = plan
+            .top()
+            .iter()
+            .chain(plan.bottom())
+            .map(|t| t.id())
+            .collect::<HashSet<_>>();
+        (level.to_usize()..=self.max_level().to_usize()).any(|level| {
+            let handler = self.handler(level.into()).unwrap();
+            let tables = handler.read();
+            tables.tables().iter().any(|t| {
+                !inputs.contains(&t.id())
+                    && t.smallest().key() < tombstone.end()
+                    && t.biggest().key() >= tombstone.start()
+            })
+        })
+    }
 }
 
 struct AddKeyContext<'a, T: TableTrait<K::Cipher>, K: Kms> {
     last_key: KeyTs,
     skip_key: KeyTs,
     num_versions: usize,
+    // stripe of the last version of the key, see [`Retention::stripe`].
+    last_stripe: Option<usize>,
     discard_stats: &'a mut HashMap<u32, u64>,
     first_key_has_discard_set: bool,
     ctl: &'a LevelCtl<T, K>,
     plan: &'a CompactPlan<T, K>,
     kr: &'a KeyTsRange,
     is_intersect: bool,
+    range_tombstones: &'a [RangeTombstone],
     writer: T::TableWriter,
+    // data key of the table written.
+    cipher_key_id: CipherKeyId,
+    retention: &'a Retention,
+    // blob files whose values are moved into the one written.
+    gc_blobs: &'a HashSet<BlobFileId>,
 }
 impl<'a, T: TableTrait<K::Cipher>, K: Kms> AddKeyContext<'a, T, K> {
-    fn push(&mut self, iter: &mut KvCacheMergeIterator) -> Result<()> {
+    fn push(&mut self, iter: &mut KvCacheMergeIterator<'_>) -> Result<()> {
         let start = SystemTime::now();
         let mut num_keys = 0;
         let mut num_skips = 0;
@@ -330,14 +393,19 @@ impl<'a, T: TableTrait<K::Cipher>, K: Kms> AddKeyContext<'a, T, K> {
                 continue;
             };
 
+            let is_range_delete = value.meta().contains(Meta::RANGE_DELETE);
             if !self.skip_key.is_empty() {
                 if key.key() == self.skip_key.key() {
-                    num_skips += 1;
-                    self.update_discard(&value);
-                    iter.next()?;
-                    continue;
+                    // a range tombstone also covers the keys after its start key.
+                    if !is_range_delete {
+                        num_skips += 1;
+                        self.update_discard(&value);
+                        iter.next()?;
+                        continue;
+                    }
+                } else {
+                    self.skip_key = Default::default();
                 }
-                self.skip_key = Default::default();
             }
 
             if key.key() != self.last_key.key() {
@@ -352,6 +420,7 @@ impl<'a, T: TableTrait<K::Cipher>, K: Kms> AddKeyContext<'a, T, K> {
                 }
                 self.last_key = key.into();
                 self.num_versions = 0;
+                self.last_stripe = None;
                 self.first_key_has_discard_set =
                     value.meta().contains(Meta::DISCARD_EARLIER_VERSIONS);
                 if table_key_range.left().is_empty() {
@@ -385,28 +454,46 @@ impl<'a, T: TableTrait<K::Cipher>, K: Kms> AddKeyContext<'a, T, K> {
                 }
             }
 
-            let is_delete = value.is_deleted_or_expired();
-            if !value.meta().contains(Meta::MERGE_ENTRY) {
-                self.num_versions += 1;
-                let last_valid_version =
-                    value.meta().contains(Meta::DISCARD_EARLIER_VERSIONS)
-                        || self.num_versions
-                            == self.ctl.config().num_versions_to_keep();
-
-                if is_delete || last_valid_version {
-                    self.skip_key = key.into();
-
-                    if (is_delete || !last_valid_version) && !self.is_intersect
-                    {
-                        num_skips += 1;
-                        self.update_discard(&value);
-                        iter.next()?;
-                        continue;
-                    }
+            if is_range_delete {
+                let tombstone = RangeTombstone::new(
+                    key.key().to_vec().into(),
+                    value.value().clone(),
+                    key.txn_ts(),
+                );
+                // the data it covers in this compaction is dropped below, so the tombstone
+                // is only needed while the lower levels may still hold covered keys,
+                // or while a snapshot still reads below it.
+                if tombstone.version() > self.retention.discard_ts
+                    || self.ctl.check_tombstone_intersect(&tombstone, self.plan)
+                {
+                    num_keys += 1;
+                    self.writer.push(&key, &value, None);
+                } else {
+                    num_skips += 1;
                 }
+                iter.next()?;
+                continue;
+            }
+            if self.covered(&key) {
+                num_skips += 1;
+                self.update_discard(&value);
+                iter.next()?;
+                continue;
+            }
+
+            let value = self.filter(&key, value)?;
+            let is_delete = value.is_deleted_or_expired();
+            if !value.meta().contains(Meta::MERGE_ENTRY)
+                && !self.retain(&key, &value, is_delete)
+            {
+                num_skips += 1;
+                self.update_discard(&value);
+                iter.next()?;
+                continue;
             }
             num_keys += 1;
 
+            let value = self.restore_blob(value)?;
             let mut vptr_len = None;
             if value.meta().contains(Meta::VALUE_POINTER) {
                 vptr_len =
@@ -427,6 +514,100 @@ impl<'a, T: TableTrait<K::Cipher>, K: Kms> AddKeyContext<'a, T, K> {
         );
         Ok(())
     }
+    // whether a range tombstone no reader reads below deletes the version,
+    // a snapshot older than the tombstone still reads the version.
+    fn covered(&self, key: &KeyTsBorrow) -> bool {
+        let discard_ts = self.retention.discard_ts;
+        key.txn_ts() <= discard_ts
+            && self.range_tombstones.iter().any(|t| {
+                t.version() <= discard_ts && t.covers(key.key(), key.txn_ts())
+            })
+    }
+    // whether the version is written, the older versions of the key are
+    // skipped once no reader needs them.
+    fn retain(
+        &mut self,
+        key: &KeyTsBorrow,
+        value: &ValueMeta,
+        is_delete: bool,
+    ) -> bool {
+        let version = key.txn_ts();
+        let stripe = self.retention.stripe(version);
+        let newest_in_stripe = self.last_stripe != Some(stripe);
+        self.last_stripe = Some(stripe);
+        self.num_versions += 1;
+
+        let num_versions_to_keep = self.ctl.config().num_versions_to_keep();
+        let keep = newest_in_stripe
+            || self.num_versions <= num_versions_to_keep
+            || version > self.retention.retained_since;
+        if version > self.retention.discard_ts {
+            return keep;
+        }
+        // no reader reads below the newest version at or below the discard
+        // timestamp.
+        let last_valid_version =
+            value.meta().contains(Meta::DISCARD_EARLIER_VERSIONS)
+                || self.num_versions >= num_versions_to_keep;
+        if !keep || is_delete || last_valid_version {
+            self.skip_key = (*key).into();
+        }
+        keep && (!is_delete || self.is_intersect)
+    }
+    // lets the compaction filter keep, remove or rewrite a version no reader
+    // needs anymore, a removed version becomes a delete marker.
+    fn filter(
+        &mut self,
+        key: &KeyTsBorrow,
+        value: ValueMeta,
+    ) -> Result<ValueMeta> {
+        let Some(filter) = self.ctl.compaction_filter() else {
+            return Ok(value);
+        };
+        if key.txn_ts() > self.retention.discard_ts
+            || value.is_deleted_or_expired()
+        {
+            return Ok(value);
+        }
+        let resolved = self.ctl.resolve_blob(value.clone())?;
+        let level = self.plan.next_level().level();
+        match filter.filter(level, key.key(), key.txn_ts(), &resolved) {
+            CompactionDecision::Keep => Ok(value),
+            CompactionDecision::Remove => {
+                self.update_discard(&value);
+                let mut marker = ValueMeta::default();
+                marker.set_meta(Meta::DELETE);
+                Ok(marker)
+            }
+            CompactionDecision::Replace(new) => {
+                self.update_discard(&value);
+                let mut replaced = resolved;
+                replaced.set_value(new);
+                replaced.set_meta(replaced.meta() - Meta::VALUE_POINTER);
+                Ok(replaced)
+            }
+        }
+    }
+    // values in blob files of another data key or old enough to be
+    // collected are read back, the writer moves them into its own blob file.
+    fn restore_blob(&self, value: ValueMeta) -> Result<ValueMeta> {
+        if !value.meta().contains(Meta::BLOB_INDEX) {
+            return Ok(value);
+        }
+        let Some(vp) = ValuePointer::decode(value.value()) else {
+            return Ok(value);
+        };
+        let key_id = self
+            .ctl
+            .table_builder()
+            .blob_cipher_key_id(vp.fid().into())?;
+        if key_id == self.cipher_key_id
+            && !self.gc_blobs.contains(&vp.fid().into())
+        {
+            return Ok(value);
+        }
+        self.ctl.resolve_blob(value)
+    }
     fn update_discard(&mut self, value: &ValueMeta) {
         if value.meta().contains(Meta::VALUE_POINTER) {
             let vp = ValuePointer::decode(value.value()).unwrap();
@@ -441,3 +622,36 @@ impl<'a, T: TableTrait<K::Cipher>, K: Kms> AddKeyContext<'a, T, K> {
         }
     }
 }
+// the versions kept for the readers, taken once per sub-compaction.
+struct Retention {
+    // ascending read timestamps of the live snapshots.
+    snapshots: Vec<TxnTs>,
+    // every version above it is kept.
+    retained_since: TxnTs,
+    // no reader reads at or below it.
+    discard_ts: TxnTs,
+}
+impl Retention {
+    // without a snapshot list only the newest versions are kept.
+    fn new(list: Option<&dyn SnapshotList>) -> Self {
+        let (snapshots, retained_since) = match list {
+            Some(list) => (list.snapshots(), list.retained_since()),
+            None => (Vec::new(), TxnTs::from(u64::MAX)),
+        };
+        let below_oldest = snapshots
+            .first()
+            .map(|s| s.to_u64().saturating_sub(1))
+            .unwrap_or(u64::MAX);
+        let discard_ts = retained_since.min(below_oldest.into());
+        Self {
+            snapshots,
+            retained_since,
+            discard_ts,
+        }
+    }
+    // index of the oldest snapshot reading `version`, every snapshot only
+    // reads the newest version of its stripe.
+    fn stripe(&self, version: TxnTs) -> usize {
+        self.snapshots.partition_point(|s| *s < version)
+    }
+}
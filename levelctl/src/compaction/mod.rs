@@ -3,10 +3,14 @@ use std::time::Duration;
 use log::{debug, info, warn};
 use mors_common::closer::Closer;
 use mors_traits::{
-    kms::Kms, levelctl::LEVEL0, sstable::TableTrait, vlog::DiscardTrait,
+    kms::{Kms, KmsCipher},
+    levelctl::{Level, LEVEL0},
+    sstable::TableTrait,
+    vlog::DiscardTrait,
 };
 use rand::Rng;
 
+use plan::CompactPlan;
 use priority::{fmt_compact_priorities, CompactPriority};
 use tokio::{
     select,
@@ -16,7 +20,7 @@ use tokio::{
 use crate::{ctl::LevelCtl, error::MorsLevelCtlError, manifest::Manifest};
 
 mod compact;
-mod plan;
+pub(crate) mod plan;
 mod priority;
 pub mod status;
 pub type Result<T> = std::result::Result<T, MorsLevelCtlError>;
@@ -137,15 +141,20 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
                                 prios.insert(0,level0);
                             }
                         }
+                        let mut compacted = false;
                         for prio in prios{
                             if prio.adjusted() >= 1.0 || (task_id == 0 && prio.level() == LEVEL0) {
                                 if self.run_compact(task_id, prio, context.clone()).await {
+                                    compacted = true;
                                     break;
                                 }
                             } else {
                                 break;
                             }
                         }
+                        if !compacted && self.config().reencryption() {
+                            self.run_reencrypt(task_id, context.clone()).await;
+                        }
 
                     }
                 }
@@ -170,10 +179,40 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
         if priority.target().base_level() == LEVEL0 {
             priority.set_target(self.target())
         };
-        match self.gen_plan(task_id, priority) {
+        let plan = self.gen_plan(task_id, priority);
+        self.run_plan(task_id, priority_level, plan, context).await
+    }
+    // rewrites one table whose data key is not the latest one.
+    async fn run_reencrypt<D: DiscardTrait>(
+        &self,
+        task_id: usize,
+        context: CompactContext<K, D>,
+    ) -> bool {
+        let latest = match context.kms.latest_cipher() {
+            Ok(cipher) => cipher.map(|c| c.cipher_key_id()).unwrap_or_default(),
+            Err(e) => {
+                warn!("task {} latest cipher error: {}", task_id, e);
+                return false;
+            }
+        };
+        let plan = self.gen_reencrypt_plan(task_id, latest);
+        let level = plan
+            .as_ref()
+            .map(|p| p.this_level().level())
+            .unwrap_or_default();
+        self.run_plan(task_id, level, plan, context).await
+    }
+    async fn run_plan<D: DiscardTrait>(
+        &self,
+        task_id: usize,
+        level: Level,
+        plan: Result<CompactPlan<T, K>>,
+        context: CompactContext<K, D>,
+    ) -> bool {
+        match plan {
             Ok(mut plan) => {
                 let result = match self
-                    .compact(task_id, priority_level, &mut plan, context)
+                    .compact(task_id, level, &mut plan, context)
                     .await
                 {
                     Ok(_) => {
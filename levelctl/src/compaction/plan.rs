@@ -4,12 +4,17 @@ use std::{
 };
 
 use bytes::Bytes;
+use log::warn;
 use mors_common::ts::KeyTs;
-use mors_traits::{kms::Kms, levelctl::LEVEL0, sstable::TableTrait};
+use mors_traits::{
+    kms::{CipherKeyId, Kms},
+    levelctl::{Level, LEVEL0},
+    sstable::TableTrait,
+};
 use parking_lot::RwLockReadGuard;
 
 use crate::{
-    ctl::LevelCtl,
+    ctl::{cipher_key_id, LevelCtl},
     error::MorsLevelCtlError,
     handler::{LevelHandler, LevelHandlerTables},
 };
@@ -79,32 +84,70 @@ impl<T: TableTrait<K::Cipher>, K: Kms> CompactPlan<T, K> {
         &self.drop_prefixes
     }
     // addSplits can allow us to run multiple sub-compactions in parallel across the split key ranges.
-    pub(crate) fn add_splits(&mut self) {
+    // Split points are picked from the block boundaries of the input tables, so that every
+    // sub-compaction reads roughly the same number of bytes.
+    pub(crate) fn add_splits(&mut self, max_splits: usize) {
         self.splits.clear();
+        if self.bottom.is_empty() {
+            return;
+        }
 
-        // Let's say we have 10 tables in plan.bot and min width = 3. Then, we'll pick
-        // 0, 1, 2 (pick), 3, 4, 5 (pick), 6, 7, 8 (pick), 9 (pick, because last table).
-        // This gives us 4 picks for 10 tables.
-        // In an edge case, 142 tables in bottom led to 48 splits. That's too many splits, because it
-        // then uses up a lot of memory for table builder.
-        // We should keep it so we have at max 5 splits.
-        let width = ((self.bottom.len() as f64 / 5.0).ceil() as usize).max(3);
+        let mut blocks = Vec::new();
+        for t in self.top.iter().chain(self.bottom.iter()) {
+            match t.block_offsets() {
+                Ok(offsets) => blocks.extend(offsets),
+                Err(e) => {
+                    // fall back to the table boundary if the index is unreadable.
+                    warn!("{} can't get block offsets {}", t.id(), e);
+                    blocks.push((t.smallest().clone(), t.size()));
+                }
+            }
+        }
+        blocks.sort_by(|a, b| a.0.cmp(&b.0));
 
         let mut kr = self.this_range.clone();
         kr.extend(self.next_range.clone());
-
-        for t in self.bottom.chunks(width) {
-            let last = t.last().unwrap();
-            let right = KeyTs::new(last.biggest().key().clone(), 0.into());
-            kr.right = right.clone();
-            self.splits.push(kr.clone());
-            kr.left = right;
-        }
+        self.splits = split_blocks(&blocks, kr, max_splits);
     }
     pub(crate) fn push_split(&mut self, split: KeyTsRange) {
         self.splits.push(split);
     }
 }
+// splits `kr` at the block boundaries so every split covers about the same
+// number of bytes, blocks are the sorted (first key, size) of the inputs.
+fn split_blocks(
+    blocks: &[(KeyTs, usize)],
+    mut kr: KeyTsRange,
+    max_splits: usize,
+) -> Vec<KeyTsRange> {
+    let total_size = blocks.iter().fold(0, |acc, (_, len)| acc + len);
+    let split_size = total_size.div_ceil(max_splits.max(1)).max(1);
+
+    let mut splits = Vec::new();
+    let mut acc = 0;
+    for (i, (_, len)) in blocks.iter().enumerate() {
+        acc += len;
+        if acc < split_size || splits.len() + 1 >= max_splits {
+            continue;
+        }
+        let Some((next, _)) = blocks.get(i + 1) else {
+            break;
+        };
+        // all versions of the boundary key go to the next split.
+        if next.key() <= kr.left.key() {
+            continue;
+        }
+        let right = KeyTs::new(next.key().clone(), u64::MAX.into());
+        kr.right = right.clone();
+        splits.push(kr.clone());
+        kr.left = right;
+        acc = 0;
+    }
+    // the last split is open-ended, so keys beyond the bottom tables are not lost.
+    kr.right = KeyTs::default();
+    splits.push(kr);
+    splits
+}
 pub(crate) struct CompactPlanReadGuard<'a, T: TableTrait<K::Cipher>, K: Kms> {
     pub(crate) this_level: RwLockReadGuard<'a, LevelHandlerTables<T, K>>,
     pub(crate) next_level: RwLockReadGuard<'a, LevelHandlerTables<T, K>>,
@@ -427,6 +470,51 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
         self.compact_status().check_update(lock, plan)
     }
 }
+impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
+    // genReencryptPlan picks a table whose data key, or the one of a blob
+    // file it points into, is not `latest` and rewrites it alone into its own level, so only its encryption changes.
+    // Level0 tables overlap, so like L0->L0 the whole level is reserved and
+    // only the first compactor picks them.
+    pub(crate) fn gen_reencrypt_plan(
+        &self,
+        task_id: usize,
+        latest: CipherKeyId,
+    ) -> Result<CompactPlan<T, K>> {
+        let first = if task_id == 0 { 0 } else { 1 };
+        for level in (first..=self.max_level().to_u8()).rev() {
+            let level: Level = level.into();
+            let handler = self.handler(level).unwrap().clone();
+            let lock = CompactPlanReadGuard::<T, K> {
+                this_level: handler.read(),
+                next_level: handler.read(),
+            };
+            let mut plan = CompactPlan {
+                task_id,
+                priority: CompactPriority::new(level, self.target()),
+                this_level: handler.clone(),
+                next_level: handler.clone(),
+                ..Default::default()
+            };
+            for t in lock.this_level.tables().iter().filter(|t| {
+                cipher_key_id::<T, K>(t) != latest
+                    || self.has_stale_blobs(t, latest)
+            }) {
+                plan.this_range = if level == LEVEL0 {
+                    KeyTsRange::inf()
+                } else {
+                    KeyTsRange::from::<T, K>(t)
+                };
+                plan.next_range = plan.this_range.clone();
+                plan.this_size = t.size();
+                plan.top = vec![t.clone()];
+                if self.compact_status().check_update(&lock, &plan)? {
+                    return Ok(plan);
+                }
+            }
+        }
+        Err(MorsLevelCtlError::FillTablesError)
+    }
+}
 #[derive(Debug, Default, Clone, PartialEq)]
 pub(crate) struct KeyTsRange {
     left: KeyTs,
@@ -544,3 +632,46 @@ fn test_a() {
     let k = 1.0;
     assert!((0.0..=1.0).contains(&k));
 }
+#[test]
+fn test_split_blocks() {
+    let key =
+        |k: &str, ts: u64| KeyTs::new(Bytes::from(k.to_owned()), ts.into());
+    let blocks = ["a", "b", "c", "d", "e", "f"]
+        .into_iter()
+        .zip([10, 1, 1, 1, 10, 1])
+        .map(|(k, len)| (key(k, 1), len))
+        .collect::<Vec<_>>();
+    let kr = KeyTsRange {
+        left: key("a", u64::MAX),
+        right: key("f", 0),
+        inf: false,
+    };
+
+    // 24 bytes in 3 splits: the big blocks end a split each.
+    let splits = split_blocks(&blocks, kr.clone(), 3);
+    let bounds = splits
+        .iter()
+        .map(|s| (s.left().clone(), s.right().clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        bounds,
+        vec![
+            (key("a", u64::MAX), key("b", u64::MAX)),
+            (key("b", u64::MAX), key("f", u64::MAX)),
+            (key("f", u64::MAX), KeyTs::default()),
+        ]
+    );
+
+    // a block larger than the split size is never cut, so fewer splits.
+    let blocks = vec![(key("a", 1), 1), (key("b", 1), 100), (key("c", 1), 1)];
+    let splits = split_blocks(&blocks, kr.clone(), 4);
+    assert_eq!(splits.len(), 2);
+    assert_eq!(splits[0].right(), &key("c", u64::MAX));
+
+    // versions of one key are never split apart.
+    let blocks = vec![(key("a", 3), 10), (key("a", 2), 10), (key("a", 1), 10)];
+    let splits = split_blocks(&blocks, kr, 3);
+    assert_eq!(splits.len(), 1);
+    assert_eq!(splits[0].left(), &key("a", u64::MAX));
+    assert!(splits[0].right().is_empty());
+}
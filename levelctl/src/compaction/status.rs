@@ -94,8 +94,10 @@ impl CompactStatus {
         let this_found =
             inner_w.levels[this_level.to_usize()].remove(plan.this_range());
 
+        // a plan inside one level pushed both ranges too, unless the
+        // next range is empty like for L0->L0.
         let mut next_found = true;
-        if this_level != next_level && !plan.next_range().is_empty() {
+        if !plan.next_range().is_empty() {
             next_found =
                 inner_w.levels[next_level.to_usize()].remove(plan.next_range());
         }
@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     ops::{Deref, DerefMut},
     path::PathBuf,
     sync::{
@@ -12,22 +12,29 @@ use std::{
 use log::{debug, info};
 use mors_common::{
     closer::Closer,
+    compress::CompressionType,
     file_id::SSTableId,
-    kv::ValueMeta,
+    kv::{ColumnFamilyId, RangeTombstone, ValueMeta},
     ts::{KeyTs, TxnTs},
 };
 use mors_traits::{
     default::{WithDir, WithReadOnly, DEFAULT_DIR},
-    kms::Kms,
-    levelctl::{Level, LevelCtlBuilderTrait, LevelCtlError, LevelCtlTrait},
-    sstable::{TableBuilderTrait, TableTrait},
+    iter::KvCacheIterator,
+    kms::{CipherKeyId, Kms, KmsCipher},
+    levelctl::{
+        CompactionFilter, Level, LevelCtlBuilderTrait, LevelCtlError,
+        LevelCtlTrait, SnapshotList,
+    },
+    sstable::{TableBuilderTrait, TablePropertiesCollectorFactory, TableTrait},
 };
+use parking_lot::RwLock;
 
 type Result<T> = std::result::Result<T, MorsLevelCtlError>;
 
 use tokio::{select, task::JoinHandle};
 
 use crate::{
+    blob::BlobRefs,
     compaction::status::CompactStatus,
     error::MorsLevelCtlError,
     handler::LevelHandler,
@@ -47,6 +54,10 @@ pub struct LevelCtlInner<T: TableTrait<K::Cipher>, K: Kms> {
     max_level: Level,
     compact_status: CompactStatus,
     config: LevelCtlConfig,
+    kms: K,
+    blob_refs: BlobRefs,
+    compaction_filter: Option<Arc<dyn CompactionFilter>>,
+    snapshot_list: RwLock<Option<Arc<dyn SnapshotList>>>,
 }
 impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtlTrait<T, K> for LevelCtl<T, K> {
     type ErrorType = MorsLevelCtlError;
@@ -68,6 +79,20 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtlTrait<T, K> for LevelCtl<T, K> {
     fn next_id(&self) -> Arc<AtomicU32> {
         self.inner.next_id.clone()
     }
+    fn cipher_key_ids(&self) -> HashSet<CipherKeyId> {
+        self.inner
+            .handlers
+            .iter()
+            .flat_map(|h| {
+                h.read()
+                    .tables()
+                    .iter()
+                    .map(cipher_key_id::<T, K>)
+                    .collect::<Vec<_>>()
+            })
+            .chain(self.blob_cipher_key_ids())
+            .collect()
+    }
 
     async fn push_level0(
         &self,
@@ -75,6 +100,12 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtlTrait<T, K> for LevelCtl<T, K> {
     ) -> std::result::Result<(), LevelCtlError> {
         Ok(self.push_level0_impl(table).await?)
     }
+    async fn ingest(
+        &self,
+        tables: Vec<T>,
+    ) -> std::result::Result<(), LevelCtlError> {
+        Ok(self.ingest_impl(tables).await?)
+    }
     async fn get(
         &self,
         key: &KeyTs,
@@ -82,6 +113,23 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtlTrait<T, K> for LevelCtl<T, K> {
     {
         Ok(self.get_impl(key).await?)
     }
+    fn iters(
+        &self,
+    ) -> (Vec<Box<dyn KvCacheIterator<ValueMeta>>>, Vec<RangeTombstone>) {
+        self.iters_impl()
+    }
+    fn key_iters(
+        &self,
+        key: &KeyTs,
+    ) -> (Vec<Box<dyn KvCacheIterator<ValueMeta>>>, Vec<RangeTombstone>) {
+        self.key_iters_impl(key)
+    }
+    fn resolve_blob(
+        &self,
+        value: ValueMeta,
+    ) -> std::result::Result<ValueMeta, LevelCtlError> {
+        Ok(LevelCtl::resolve_blob(self, value)?)
+    }
     async fn spawn_compact<D: mors_traits::vlog::DiscardTrait>(
         self,
         closer: Closer,
@@ -92,6 +140,42 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtlTrait<T, K> for LevelCtl<T, K> {
             panic!("spawn_compact error:{}", e);
         }
     }
+    fn set_snapshot_list(&self, list: Arc<dyn SnapshotList>) {
+        *self.inner.snapshot_list.write() = Some(list);
+    }
+    async fn column_families(&self) -> BTreeMap<ColumnFamilyId, Arc<str>> {
+        self.manifest().lock().await.info().column_families().clone()
+    }
+    async fn create_column_family(
+        &self,
+        name: &str,
+    ) -> std::result::Result<ColumnFamilyId, LevelCtlError> {
+        let id = self
+            .manifest()
+            .create_column_family(name)
+            .await
+            .map_err(MorsLevelCtlError::from)?;
+        Ok(id)
+    }
+    async fn drop_column_family(
+        &self,
+        id: ColumnFamilyId,
+    ) -> std::result::Result<(), LevelCtlError> {
+        self.manifest()
+            .drop_column_family(id)
+            .await
+            .map_err(MorsLevelCtlError::from)?;
+        Ok(())
+    }
+}
+/// data key of `table`, the default id stands for plaintext.
+pub(crate) fn cipher_key_id<T: TableTrait<K::Cipher>, K: Kms>(
+    table: &T,
+) -> CipherKeyId {
+    table
+        .cipher()
+        .map(|c| c.cipher_key_id())
+        .unwrap_or_default()
 }
 impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
     pub(crate) fn manifest(&self) -> &Manifest {
@@ -103,6 +187,18 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
     pub(crate) fn compact_status(&self) -> &CompactStatus {
         &self.inner.compact_status
     }
+    pub(crate) fn kms(&self) -> &K {
+        &self.inner.kms
+    }
+    pub(crate) fn blob_refs(&self) -> &BlobRefs {
+        &self.inner.blob_refs
+    }
+    pub(crate) fn compaction_filter(&self) -> Option<&dyn CompactionFilter> {
+        self.inner.compaction_filter.as_deref()
+    }
+    pub(crate) fn snapshot_list(&self) -> Option<Arc<dyn SnapshotList>> {
+        self.inner.snapshot_list.read().clone()
+    }
     pub(crate) fn level0_stalls_ms(&self) -> &AtomicU64 {
         &self.inner.level0_stalls_ms
     }
@@ -138,6 +234,9 @@ pub struct LevelCtlConfig {
     level0_table_size: usize,
     level0_tables_len: usize,
     num_versions_to_keep: usize,
+    max_sub_compactions: usize,
+    reencryption: bool,
+    blob_gc_age_cutoff: f64,
 }
 impl LevelCtlConfig {
     /// Maximum number of levels of compaction allowed in the LSM.
@@ -214,7 +313,8 @@ impl LevelCtlConfig {
         self.level0_tables_len = level0_tables_len + 1;
         self
     }
-    /// the number of versions to keep.
+    /// the number of newest versions of every key always kept, the older
+    /// ones are only kept while a snapshot reads them.
     /// The default value of num_versions_to_keep is 1.
     pub fn set_num_versions_to_keep(
         &mut self,
@@ -223,6 +323,31 @@ impl LevelCtlConfig {
         self.num_versions_to_keep = num_versions_to_keep;
         self
     }
+    /// the maximum number of sub-compactions a single compaction is split into.
+    /// Each sub-compaction covers roughly the same number of bytes and runs on the rayon pool.
+    /// The default value of max_sub_compactions is 5.
+    pub fn set_max_sub_compactions(
+        &mut self,
+        max_sub_compactions: usize,
+    ) -> &mut Self {
+        self.max_sub_compactions = max_sub_compactions.max(1);
+        self
+    }
+    /// rewrites the tables not encrypted with the latest data key, one at a
+    /// time, whenever a compactor has nothing else to do.
+    /// The default value of reencryption is false.
+    pub fn set_reencryption(&mut self, reencryption: bool) -> &mut Self {
+        self.reencryption = reencryption;
+        self
+    }
+    /// compactions move the values they keep out of the oldest blob files,
+    /// this fraction of the live ones, into the blob files they write, so
+    /// partly dead blob files are eventually dropped. 0 turns it off.
+    /// The default value of blob_gc_age_cutoff is 0.25.
+    pub fn set_blob_gc_age_cutoff(&mut self, cutoff: f64) -> &mut Self {
+        self.blob_gc_age_cutoff = cutoff.clamp(0.0, 1.0);
+        self
+    }
     /// Maximum number of levels of compaction allowed in the LSM.
     pub fn max_level(&self) -> Level {
         self.max_level
@@ -257,10 +382,23 @@ impl LevelCtlConfig {
     pub fn level0_tables_len(&self) -> usize {
         self.level0_tables_len
     }
-    /// the number of versions to keep.
+    /// the number of newest versions of every key always kept.
     pub fn num_versions_to_keep(&self) -> usize {
         self.num_versions_to_keep
     }
+    /// the maximum number of sub-compactions a single compaction is split into.
+    pub fn max_sub_compactions(&self) -> usize {
+        self.max_sub_compactions
+    }
+    /// whether tables are re-encrypted with the latest data key.
+    pub fn reencryption(&self) -> bool {
+        self.reencryption
+    }
+    /// the fraction of the live blob files, oldest first, compactions move
+    /// values out of.
+    pub fn blob_gc_age_cutoff(&self) -> f64 {
+        self.blob_gc_age_cutoff
+    }
 }
 impl Default for LevelCtlConfig {
     fn default() -> Self {
@@ -275,6 +413,9 @@ impl Default for LevelCtlConfig {
             level0_table_size: 64 << 20,
             level0_tables_len: 5,
             num_versions_to_keep: 1,
+            max_sub_compactions: 5,
+            reencryption: false,
+            blob_gc_age_cutoff: 0.25,
         }
     }
 }
@@ -282,9 +423,22 @@ pub struct LevelCtlBuilder<T: TableTrait<K::Cipher>, K: Kms> {
     manifest: ManifestBuilder,
     table: T::TableBuilder,
     config: LevelCtlConfig,
+    compaction_filter: Option<Arc<dyn CompactionFilter>>,
     dir: PathBuf,
     read_only: bool,
 }
+impl<T: TableTrait<K::Cipher>, K: Kms> Clone for LevelCtlBuilder<T, K> {
+    fn clone(&self) -> Self {
+        Self {
+            manifest: self.manifest.clone(),
+            table: self.table.clone(),
+            config: self.config,
+            compaction_filter: self.compaction_filter.clone(),
+            dir: self.dir.clone(),
+            read_only: self.read_only,
+        }
+    }
+}
 impl<T: TableTrait<K::Cipher>, K: Kms> Deref for LevelCtlBuilder<T, K> {
     type Target = LevelCtlConfig;
 
@@ -303,6 +457,7 @@ impl<T: TableTrait<K::Cipher>, K: Kms> Default for LevelCtlBuilder<T, K> {
             manifest: ManifestBuilder::default(),
             table: T::TableBuilder::default(),
             config: LevelCtlConfig::default(),
+            compaction_filter: None,
             dir: PathBuf::from(DEFAULT_DIR),
             read_only: false,
         }
@@ -352,6 +507,42 @@ impl<T: TableTrait<K::Cipher>, K: Kms>
         self.config.set_level0_table_size(size);
         self
     }
+
+    fn set_compression(&mut self, compression: CompressionType) -> &mut Self {
+        self.table.set_compression(compression);
+        self
+    }
+
+    fn set_reencryption(&mut self, reencryption: bool) -> &mut Self {
+        self.config.set_reencryption(reencryption);
+        self
+    }
+
+    fn set_blob_threshold(&mut self, threshold: Option<usize>) -> &mut Self {
+        self.table.set_blob_threshold(threshold);
+        self
+    }
+
+    fn set_blob_gc_age_cutoff(&mut self, cutoff: f64) -> &mut Self {
+        self.config.set_blob_gc_age_cutoff(cutoff);
+        self
+    }
+
+    fn add_properties_collector(
+        &mut self,
+        factory: Arc<dyn TablePropertiesCollectorFactory>,
+    ) -> &mut Self {
+        self.table.add_properties_collector(factory);
+        self
+    }
+
+    fn set_compaction_filter(
+        &mut self,
+        filter: Option<Arc<dyn CompactionFilter>>,
+    ) -> &mut Self {
+        self.compaction_filter = filter;
+        self
+    }
 }
 impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtlBuilder<T, K> {
     pub fn set_level0_num_tables_stall(
@@ -371,8 +562,9 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtlBuilder<T, K> {
             CompactStatus::new(self.config.max_level.to_usize());
         let manifest = self.manifest.build()?;
 
-        let (max_id, handlers) =
-            self.open_tables_by_manifest(manifest.clone(), kms).await?;
+        let (max_id, handlers) = self
+            .open_tables_by_manifest(manifest.clone(), kms.clone())
+            .await?;
 
         let next_id = Arc::new(AtomicU32::new(1 + Into::<u32>::into(max_id)));
 
@@ -386,10 +578,21 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtlBuilder<T, K> {
             config: self.config,
             level0_stalls: Default::default(),
             max_level: self.config.max_level,
+            kms,
+            blob_refs: BlobRefs::default(),
+            compaction_filter: self.compaction_filter.clone(),
+            snapshot_list: Default::default(),
         };
-        Ok(LevelCtl {
+        let ctl = LevelCtl {
             inner: Arc::new(ctl),
-        })
+        };
+        for handler in ctl.inner.handlers.iter() {
+            ctl.add_blob_refs(handler.read().tables());
+        }
+        if !self.read_only {
+            ctl.remove_orphan_blobs()?;
+        }
+        Ok(ctl)
     }
 
     async fn open_tables_by_manifest(
@@ -417,10 +620,12 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtlBuilder<T, K> {
             max_id = max_id.max(*id);
 
             let cipher_id = table.key_id();
+            let encryption_algo = table.encryption_algo();
 
             let mut table_builder = self.table.clone();
 
             table_builder.set_compression(table.compress());
+            table_builder.set_global_version(table.global_version());
             table_builder.set_dir(self.dir.clone());
 
             let kms_clone = kms.clone();
@@ -432,6 +637,15 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtlBuilder<T, K> {
                     .map(|id| kms_clone.get_cipher(id))
                     .transpose()?
                     .flatten();
+                if let Some(c) = &cipher {
+                    if c.encryption_algo() != encryption_algo {
+                        return Err(MorsLevelCtlError::EncryptionAlgoMismatch(
+                            table_id,
+                            encryption_algo,
+                            c.encryption_algo(),
+                        ));
+                    }
+                }
                 let table = table_builder.open(table_id, cipher).await?;
                 num_opened_clone.fetch_add(1, Ordering::SeqCst);
                 Ok::<Option<T>, MorsLevelCtlError>(table)
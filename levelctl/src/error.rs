@@ -2,7 +2,7 @@ use std::sync::PoisonError;
 
 use mors_common::{file_id::SSTableId, ts::KeyTs};
 use mors_traits::{
-    iter::IterError, kms::KmsError, levelctl::{Level, LevelCtlError}, sstable::SSTableError
+    iter::IterError, kms::{EncryptionAlgo, KmsError}, levelctl::{Level, LevelCtlError}, sstable::SSTableError
 };
 use thiserror::Error;
 
@@ -31,6 +31,12 @@ pub enum MorsLevelCtlError {
     EmptyCompactTarget,
     #[error("Iter Error: {0}")]
     IterError(#[from] IterError),
+    #[error("SSTable {0:?} was encrypted with {1:?} but its data key is {2:?}")]
+    EncryptionAlgoMismatch(SSTableId, EncryptionAlgo, EncryptionAlgo),
+    #[error("Ingested SSTables {0:?} and {1:?} overlap")]
+    IngestOverlap(SSTableId, SSTableId),
+    #[error("Invalid blob index {0:?}")]
+    InvalidBlobIndex(bytes::Bytes),
 }
 
 impl<T> From<PoisonError<T>> for MorsLevelCtlError {
@@ -42,7 +48,7 @@ impl<T> From<PoisonError<T>> for MorsLevelCtlError {
 pub enum LevelHandlerError {
     #[error("SSTable Overlap Error:Level {0:?} Pre SSTable {1:?} biggest {2:?} > This SSTable {3:?} smallest {4:?}")]
     TableOverlapError(Level, SSTableId, KeyTs, SSTableId, KeyTs),
-    #[error("SSTable Inner Sort Error:Level {0:?} SSTable {1:?} smallest KeyTs {2:?} >= biggest {3:?}")]
+    #[error("SSTable Inner Sort Error:Level {0:?} SSTable {1:?} smallest KeyTs {2:?} > biggest {3:?}")]
     TableInnerSortError(Level, SSTableId, KeyTs, KeyTs),
 }
 unsafe impl Send for MorsLevelCtlError {}
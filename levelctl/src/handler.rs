@@ -1,5 +1,5 @@
 use log::error;
-use mors_common::{file_id::SSTableId, ts::TxnTs};
+use mors_common::{file_id::SSTableId, kv::RangeTombstone, ts::TxnTs};
 use mors_traits::{
     kms::Kms,
     levelctl::{Level, LEVEL0},
@@ -48,6 +48,7 @@ pub(crate) struct LevelHandlerTables<T: TableTrait<K::Cipher>, K: Kms> {
     tables: Vec<T>,
     total_size: usize,
     total_stale_size: usize,
+    range_tombstones: Vec<RangeTombstone>,
     k: PhantomData<K>,
 }
 impl<T: TableTrait<K::Cipher>, K: Kms> Default for LevelHandlerTables<T, K> {
@@ -56,6 +57,7 @@ impl<T: TableTrait<K::Cipher>, K: Kms> Default for LevelHandlerTables<T, K> {
             tables: Default::default(),
             total_size: Default::default(),
             total_stale_size: Default::default(),
+            range_tombstones: Default::default(),
             k: Default::default(),
         }
     }
@@ -128,6 +130,7 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelHandler<T, K> {
         inner_w.tables = new_tables;
         inner_w.total_size -= sub_total_size;
         inner_w.total_stale_size -= sub_total_stale_size;
+        inner_w.collect_range_tombstones();
     }
     pub(crate) fn validate(&self) -> Result<()> {
         let inner = self.0.table_handler.read();
@@ -135,7 +138,7 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelHandler<T, K> {
             return Ok(());
         }
 
-        while let Some(w) = inner.tables.windows(2).next() {
+        for w in inner.tables.windows(2) {
             if w[0].biggest() > w[1].smallest() {
                 return Err(LevelHandlerError::TableOverlapError(
                     self.0.level,
@@ -145,7 +148,7 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelHandler<T, K> {
                     w[1].smallest().to_owned(),
                 ));
             }
-            if w[1].smallest() >= w[1].biggest() {
+            if w[1].smallest() > w[1].biggest() {
                 return Err(LevelHandlerError::TableInnerSortError(
                     self.0.level,
                     w[1].id(),
@@ -176,6 +179,7 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelHandlerTables<T, K> {
         });
         self.total_size = total_size;
         self.total_stale_size = total_stale_size;
+        self.collect_range_tombstones();
         if level == LEVEL0 {
             self.tables.sort_by_key(|a| a.id());
         } else {
@@ -185,6 +189,17 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelHandlerTables<T, K> {
     pub(crate) fn tables(&self) -> &[T] {
         &self.tables
     }
+    /// range tombstones of all tables in this level.
+    pub(crate) fn range_tombstones(&self) -> &[RangeTombstone] {
+        &self.range_tombstones
+    }
+    fn collect_range_tombstones(&mut self) {
+        self.range_tombstones = self
+            .tables
+            .iter()
+            .flat_map(|t| t.range_tombstones().iter().cloned())
+            .collect();
+    }
 
     pub(crate) fn total_size(&self) -> usize {
         self.total_size
@@ -195,12 +210,15 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelHandlerTables<T, K> {
     pub(crate) fn push(&mut self, table: T) {
         self.total_size += table.size();
         self.total_stale_size += table.stale_data_size();
+        self.range_tombstones
+            .extend_from_slice(table.range_tombstones());
         self.tables.push(table);
     }
     pub(crate) fn pop(&mut self) -> Option<T> {
         self.tables.pop().map(|t| {
             self.total_size -= t.size();
             self.total_stale_size -= t.stale_data_size();
+            self.collect_range_tombstones();
             t
         })
     }
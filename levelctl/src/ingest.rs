@@ -0,0 +1,100 @@
+use log::info;
+use mors_traits::{
+    kms::{Kms, KmsCipher},
+    levelctl::{Level, LEVEL0},
+    sstable::TableTrait,
+};
+
+use crate::{
+    compaction::plan::KeyTsRange, ctl::LevelCtl, error::MorsLevelCtlError,
+    manifest::manifest_change::ManifestChange,
+};
+type Result<T> = std::result::Result<T, MorsLevelCtlError>;
+impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
+    pub(crate) async fn ingest_impl(&self, tables: Vec<T>) -> Result<()> {
+        if tables.is_empty() {
+            return Ok(());
+        }
+        let ranges = tables
+            .iter()
+            .map(KeyTsRange::from::<T, K>)
+            .collect::<Vec<_>>();
+        for (i, range) in ranges.iter().enumerate() {
+            if let Some(j) =
+                ranges[i + 1..].iter().position(|r| r.intersects(range))
+            {
+                return Err(MorsLevelCtlError::IngestOverlap(
+                    tables[i].id(),
+                    tables[i + 1 + j].id(),
+                ));
+            }
+        }
+
+        let levels = self.reserve_ingest_levels(&ranges)?;
+        let result = self.ingest_at(&tables, &levels).await;
+
+        let mut status_w = self.compact_status().write()?;
+        for (range, level) in ranges.iter().zip(levels.iter()) {
+            status_w.levels_mut()[level.to_usize()].remove(range);
+        }
+        result
+    }
+    // reserveIngestLevels picks the deepest level for each table such that
+    // neither that level nor any above it overlaps the table, and reserves
+    // its range there like a compaction does, so no compaction moves an
+    // overlapping table into the level until the table is added.
+    fn reserve_ingest_levels(
+        &self,
+        ranges: &[KeyTsRange],
+    ) -> Result<Vec<Level>> {
+        let handlers = (0..=self.max_level().to_u8())
+            .map(|level| self.handler(level.into()).unwrap().read())
+            .collect::<Vec<_>>();
+        let mut status_w = self.compact_status().write()?;
+        let mut levels = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            let mut target = LEVEL0;
+            for (level, handler) in handlers.iter().enumerate() {
+                let overlaps = status_w.levels_mut()[level].intersects(range)
+                    || handler
+                        .tables()
+                        .iter()
+                        .any(|t| KeyTsRange::from::<T, K>(t).intersects(range));
+                if overlaps {
+                    break;
+                }
+                target = level.into();
+            }
+            status_w.levels_mut()[target.to_usize()].push(range.clone());
+            levels.push(target);
+        }
+        Ok(levels)
+    }
+    async fn ingest_at(&self, tables: &[T], levels: &[Level]) -> Result<()> {
+        let changes = tables
+            .iter()
+            .zip(levels.iter())
+            .map(|(table, level)| {
+                ManifestChange::new_create(
+                    table.id(),
+                    *level,
+                    table.cipher().map(|k| k.cipher_key_id()),
+                    table
+                        .cipher()
+                        .map(|k| k.encryption_algo())
+                        .unwrap_or_default(),
+                    table.compression(),
+                    table.global_version(),
+                )
+            })
+            .collect::<Vec<_>>();
+        self.manifest().push_changes(changes).await?;
+        for (table, level) in tables.iter().zip(levels.iter()) {
+            info!("ingested table {} into {}", table.id(), level);
+            self.handler(*level)
+                .unwrap()
+                .replace(&[], std::slice::from_ref(table));
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,9 @@
+mod blob;
 mod compaction;
 pub mod ctl;
 mod error;
 mod handler;
+mod ingest;
 pub mod manifest;
 mod write;
 mod read;
\ No newline at end of file
@@ -1,4 +1,4 @@
-use mors_common::file_id::SSTableId;
+use mors_common::{file_id::SSTableId, kv::ColumnFamilyId};
 use thiserror::Error;
 use super::MAGIC_VERSION;
 #[derive(Error, Debug)]
@@ -25,4 +25,8 @@ pub enum ManifestError {
     NoManifest,
     #[error("Table {0} not found")]
     TableNotFound(SSTableId),
+    #[error("MANIFEST invalid, column family {0} exists")]
+    ColumnFamilyExists(ColumnFamilyId),
+    #[error("MANIFEST drops non-existing column family {0}")]
+    ColumnFamilyNotFound(ColumnFamilyId),
 }
\ No newline at end of file
@@ -5,7 +5,7 @@ pub struct ManifestChangeSet {
     #[prost(message, repeated, tag = "1")]
     pub changes: ::prost::alloc::vec::Vec<ManifestChange>,
 }
-#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ManifestChange {
     /// Table ID.
     #[prost(uint32, tag = "1")]
@@ -22,6 +22,12 @@ pub struct ManifestChange {
     /// Only used for CREATE Op.
     #[prost(uint32, tag = "6")]
     pub compression: u32,
+    /// Only used for CREATE of ingested tables.
+    #[prost(uint64, tag = "7")]
+    pub global_version: u64,
+    /// Name of the family, Id is its id.
+    #[prost(string, tag = "8")]
+    pub column_family: ::prost::alloc::string::String,
 }
 /// Nested message and enum types in `ManifestChange`.
 pub mod manifest_change {
@@ -40,6 +46,8 @@ pub mod manifest_change {
     pub enum Operation {
         Create = 0,
         Delete = 1,
+        CreateColumnFamily = 2,
+        DropColumnFamily = 3,
     }
     impl Operation {
         /// String value of the enum field names used in the ProtoBuf definition.
@@ -48,8 +56,10 @@ pub mod manifest_change {
         /// (if the ProtoBuf definition does not change) and safe for programmatic use.
         pub fn as_str_name(&self) -> &'static str {
             match self {
-                Operation::Create => "CREATE",
-                Operation::Delete => "DELETE",
+                Self::Create => "CREATE",
+                Self::Delete => "DELETE",
+                Self::CreateColumnFamily => "CREATE_COLUMN_FAMILY",
+                Self::DropColumnFamily => "DROP_COLUMN_FAMILY",
             }
         }
         /// Creates an enum from field names used in the ProtoBuf definition.
@@ -57,6 +67,8 @@ pub mod manifest_change {
             match value {
                 "CREATE" => Some(Self::Create),
                 "DELETE" => Some(Self::Delete),
+                "CREATE_COLUMN_FAMILY" => Some(Self::CreateColumnFamily),
+                "DROP_COLUMN_FAMILY" => Some(Self::DropColumnFamily),
                 _ => None,
             }
         }
@@ -66,6 +78,8 @@ pub mod manifest_change {
 #[repr(i32)]
 pub enum EncryptionAlgo {
     Aes = 0,
+    AesGcmSiv = 1,
+    Chacha20Poly1305 = 2,
 }
 impl EncryptionAlgo {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -74,13 +88,17 @@ impl EncryptionAlgo {
     /// (if the ProtoBuf definition does not change) and safe for programmatic use.
     pub fn as_str_name(&self) -> &'static str {
         match self {
-            EncryptionAlgo::Aes => "aes",
+            Self::Aes => "aes",
+            Self::AesGcmSiv => "aes_gcm_siv",
+            Self::Chacha20Poly1305 => "chacha20_poly1305",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
     pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
         match value {
             "aes" => Some(Self::Aes),
+            "aes_gcm_siv" => Some(Self::AesGcmSiv),
+            "chacha20_poly1305" => Some(Self::Chacha20Poly1305),
             _ => None,
         }
     }
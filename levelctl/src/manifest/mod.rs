@@ -6,11 +6,16 @@ use manifest_change::{
 use mors_common::{
     compress::CompressionType,
     file_id::{FileId, SSTableId},
+    kv::{ColumnFamilyId, DEFAULT_COLUMN_FAMILY},
+    ts::TxnTs,
+};
+use mors_traits::{
+    kms::{self, CipherKeyId},
+    levelctl::Level,
 };
-use mors_traits::{kms::CipherKeyId, levelctl::Level};
 use std::fmt::Display;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::Formatter,
     fs::{remove_file, rename, File, OpenOptions},
     io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
@@ -67,6 +72,10 @@ pub struct ManifestInner {
 pub struct ManifestInfo {
     levels: Vec<LevelManifest>,
     tables: HashMap<SSTableId, TableManifest>,
+    // column families besides the default one, recorded by its manifest.
+    column_families: BTreeMap<ColumnFamilyId, Arc<str>>,
+    // ids of dropped families are never reused, a rewrite keeps the highest.
+    next_column_family: ColumnFamilyId,
     creations: usize,
     deletions: usize,
 }
@@ -121,7 +130,9 @@ pub(crate) struct LevelManifest {
 pub(crate) struct TableManifest {
     level: Level,
     key_id: Option<CipherKeyId>,
+    encryption_algo: kms::EncryptionAlgo,
     compress: CompressionType,
+    global_version: Option<TxnTs>,
 }
 #[derive(Debug, Clone)]
 pub struct ManifestBuilder {
@@ -309,14 +320,41 @@ impl ManifestBuilder {
     }
 }
 impl ManifestInfo {
+    /// compression and data key of the live table `id`, the data key is
+    /// `None` for plaintext.
+    pub fn table_options(
+        &self,
+        id: SSTableId,
+    ) -> Option<(CompressionType, Option<CipherKeyId>)> {
+        self.tables.get(&id).map(|t| (t.compress, t.key_id))
+    }
+    /// column families recorded in this manifest, by id.
+    pub fn column_families(&self) -> &BTreeMap<ColumnFamilyId, Arc<str>> {
+        &self.column_families
+    }
     fn as_changes(&self) -> Vec<ManifestChange> {
-        let mut changes = Vec::with_capacity(self.tables.len());
+        let mut changes =
+            Vec::with_capacity(self.tables.len() + self.column_families.len());
+        for (id, name) in self.column_families.iter() {
+            changes.push(ManifestChange::new_create_column_family(*id, name));
+        }
+        // the highest id was dropped, recreating and dropping it again keeps
+        // the next id from going back to it.
+        let last = self.next_column_family.saturating_sub(1);
+        if last > DEFAULT_COLUMN_FAMILY
+            && !self.column_families.contains_key(&last)
+        {
+            changes.push(ManifestChange::new_create_column_family(last, ""));
+            changes.push(ManifestChange::new_drop_column_family(last));
+        }
         for (id, manifest) in self.tables.iter() {
             changes.push(ManifestChange::new_create(
                 *id,
                 manifest.level,
                 manifest.key_id,
+                manifest.encryption_algo,
                 manifest.compress,
+                manifest.global_version,
             ));
         }
         changes
@@ -341,15 +379,19 @@ impl ManifestInfo {
                 } else {
                     Some(change.key_id.into())
                 };
+                let global_version = (change.global_version != 0)
+                    .then(|| change.global_version.into());
                 self.tables.insert(
                     change.table_id(),
                     TableManifest {
                         level: change.level.into(),
                         key_id,
+                        encryption_algo: change.encryption_algo().into(),
                         compress: change.compression.into(),
+                        global_version,
                     },
                 );
-                
+
                 for _ in self.levels.len()..=change.level as usize {
                     self.levels.push(LevelManifest::default());
                 }
@@ -368,6 +410,22 @@ impl ManifestInfo {
                 self.tables.remove(&change.table_id());
                 self.deletions += 1;
             }
+            Operation::CreateColumnFamily => {
+                if change.id == DEFAULT_COLUMN_FAMILY
+                    || self.column_families.contains_key(&change.id)
+                {
+                    return Err(ManifestError::ColumnFamilyExists(change.id));
+                }
+                self.column_families
+                    .insert(change.id, change.column_family.as_str().into());
+                self.next_column_family =
+                    self.next_column_family.max(change.id + 1);
+            }
+            Operation::DropColumnFamily => {
+                if self.column_families.remove(&change.id).is_none() {
+                    return Err(ManifestError::ColumnFamilyNotFound(change.id));
+                }
+            }
         }
         Ok(())
     }
@@ -377,15 +435,19 @@ impl ManifestChange {
         table_id: SSTableId,
         level: Level,
         cipher_key_id: Option<CipherKeyId>,
+        encryption_algo: kms::EncryptionAlgo,
         compression: CompressionType,
+        global_version: Option<TxnTs>,
     ) -> Self {
         Self {
             id: table_id.into(),
             op: Operation::Create as i32,
             level: level.into(),
             key_id: cipher_key_id.unwrap_or_default().into(),
-            encryption_algo: EncryptionAlgo::Aes as i32,
+            encryption_algo: EncryptionAlgo::from(encryption_algo) as i32,
             compression: compression.into(),
+            global_version: global_version.unwrap_or_default().into(),
+            column_family: Default::default(),
         }
     }
     pub fn new_delete(table_id: SSTableId) -> Self {
@@ -396,12 +458,51 @@ impl ManifestChange {
             key_id: Default::default(),
             encryption_algo: Default::default(),
             compression: Default::default(),
+            global_version: Default::default(),
+            column_family: Default::default(),
+        }
+    }
+    pub fn new_create_column_family(id: ColumnFamilyId, name: &str) -> Self {
+        Self {
+            id,
+            op: Operation::CreateColumnFamily as i32,
+            column_family: name.to_string(),
+            ..Self::new_delete(Default::default())
+        }
+    }
+    pub fn new_drop_column_family(id: ColumnFamilyId) -> Self {
+        Self {
+            id,
+            op: Operation::DropColumnFamily as i32,
+            ..Self::new_delete(Default::default())
         }
     }
     pub fn table_id(&self) -> SSTableId {
         self.id.into()
     }
 }
+impl From<kms::EncryptionAlgo> for EncryptionAlgo {
+    fn from(value: kms::EncryptionAlgo) -> Self {
+        match value {
+            kms::EncryptionAlgo::AesGcm => EncryptionAlgo::Aes,
+            kms::EncryptionAlgo::AesGcmSiv => EncryptionAlgo::AesGcmSiv,
+            kms::EncryptionAlgo::ChaCha20Poly1305 => {
+                EncryptionAlgo::Chacha20Poly1305
+            }
+        }
+    }
+}
+impl From<EncryptionAlgo> for kms::EncryptionAlgo {
+    fn from(value: EncryptionAlgo) -> Self {
+        match value {
+            EncryptionAlgo::Aes => kms::EncryptionAlgo::AesGcm,
+            EncryptionAlgo::AesGcmSiv => kms::EncryptionAlgo::AesGcmSiv,
+            EncryptionAlgo::Chacha20Poly1305 => {
+                kms::EncryptionAlgo::ChaCha20Poly1305
+            }
+        }
+    }
+}
 impl Manifest {
     pub(crate) async fn revert(&self, dir: &PathBuf) -> Result<()> {
         let sst_id_set = SSTableId::parse_set_from_dir(dir);
@@ -432,6 +533,30 @@ impl Manifest {
         changes: Vec<ManifestChange>,
     ) -> Result<()> {
         let mut inner = self.lock().await;
+        inner.push_changes(changes)
+    }
+    /// records a new column family, its id is the next one never used.
+    pub(crate) async fn create_column_family(
+        &self,
+        name: &str,
+    ) -> Result<ColumnFamilyId> {
+        let mut inner = self.lock().await;
+        let id = inner.info.next_column_family.max(DEFAULT_COLUMN_FAMILY + 1);
+        let change = ManifestChange::new_create_column_family(id, name);
+        inner.push_changes(vec![change])?;
+        Ok(id)
+    }
+    pub(crate) async fn drop_column_family(
+        &self,
+        id: ColumnFamilyId,
+    ) -> Result<()> {
+        let mut inner = self.lock().await;
+        inner.push_changes(vec![ManifestChange::new_drop_column_family(id)])
+    }
+}
+impl ManifestInner {
+    fn push_changes(&mut self, changes: Vec<ManifestChange>) -> Result<()> {
+        let inner = self;
         let deletions_rewrite_threshold = inner.deletions_rewrite_threshold;
         let change_set = ManifestChangeSet { changes };
         inner.info.apply_change_set(&change_set)?;
@@ -473,9 +598,16 @@ impl TableManifest {
     pub(crate) fn key_id(&self) -> Option<CipherKeyId> {
         self.key_id
     }
+    pub(crate) fn encryption_algo(&self) -> kms::EncryptionAlgo {
+        self.encryption_algo
+    }
     pub(crate) fn level(&self) -> Level {
         self.level
     }
+    /// version every key of an ingested table is read at.
+    pub(crate) fn global_version(&self) -> Option<TxnTs> {
+        self.global_version
+    }
 }
 
 #[cfg(test)]
@@ -502,7 +634,9 @@ mod tests {
                     id.into(),
                     level.into(),
                     None,
+                    Default::default(),
                     CompressionType::None,
+                    None,
                 );
                 tables.push(id.into());
                 let r = manifest.push_changes(vec![change]).await;
@@ -521,4 +655,58 @@ mod tests {
             }
         }
     }
+    #[tokio::test]
+    async fn test_column_families() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut builder = ManifestBuilder::default();
+        builder.set_dir(temp_dir.path().to_path_buf());
+        let manifest = builder.build().unwrap();
+        let a = manifest.create_column_family("a").await.unwrap();
+        let b = manifest.create_column_family("b").await.unwrap();
+        assert_eq!((a, b), (1, 2));
+        manifest.drop_column_family(a).await.unwrap();
+        assert!(manifest.drop_column_family(a).await.is_err());
+        drop(manifest);
+
+        let manifest = builder.build().unwrap();
+        {
+            let inner = manifest.lock().await;
+            let families = inner.info().column_families();
+            assert_eq!(families.len(), 1);
+            assert_eq!(families[&b].as_ref(), "b");
+        }
+        // dropped ids are not reused.
+        assert_eq!(manifest.create_column_family("c").await.unwrap(), 3);
+
+        // a rewrite keeps the families.
+        let mut inner = manifest.lock().await;
+        let (file, _) = inner.builder.help_rewrite(&inner.info).unwrap();
+        inner.file = file;
+        drop(inner);
+        drop(manifest);
+        let manifest = builder.build().unwrap();
+        {
+            let inner = manifest.lock().await;
+            let families = inner.info().column_families();
+            assert_eq!(
+                families.keys().copied().collect::<Vec<_>>(),
+                vec![b, 3]
+            );
+        }
+
+        // nor forgets the dropped ids.
+        manifest.drop_column_family(3).await.unwrap();
+        let mut inner = manifest.lock().await;
+        let (file, _) = inner.builder.help_rewrite(&inner.info).unwrap();
+        inner.file = file;
+        drop(inner);
+        drop(manifest);
+        let manifest = builder.build().unwrap();
+        {
+            let inner = manifest.lock().await;
+            let families = inner.info().column_families();
+            assert_eq!(families.keys().copied().collect::<Vec<_>>(), vec![b]);
+        }
+        assert_eq!(manifest.create_column_family("d").await.unwrap(), 4);
+    }
 }
@@ -1,53 +1,110 @@
 use log::error;
 use mors_common::{
-    kv::ValueMeta,
+    kv::{RangeTombstone, ValueMeta},
     ts::{KeyTs, KeyTsBorrow, TxnTs},
 };
 use mors_traits::{
-    iter::{KvCacheIter, KvSeekIter},
+    iter::{KvCacheIter, KvCacheIterator, KvSeekIter},
     kms::Kms,
     levelctl::{Level, LEVEL0},
-    sstable::TableTrait,
+    sstable::{CacheTableConcatIter, TableTrait},
 };
 
 use crate::ctl::LevelCtl;
 use crate::error::MorsLevelCtlError;
 use crate::handler::LevelHandler;
 type Result<T> = std::result::Result<T, MorsLevelCtlError>;
+// a version read from a level with the table it was read from, which keeps
+// the blob file the value may point into until it is resolved.
+type LevelValue<T> = (TxnTs, Option<ValueMeta>, Option<T>);
 impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
     pub(crate) async fn get_impl(
         &self,
         key: &KeyTs,
     ) -> Result<Option<(TxnTs, Option<ValueMeta>)>> {
-        let mut max_txn = TxnTs::default();
-        let mut max_value = None;
+        let mut max: Option<LevelValue<T>> = None;
         for level in 0..=self.max_level().to_u8() {
             let level: Level = level.into();
             let handler = self.handler(level).unwrap();
-            if let Some((txn, value)) = handler.get(key).await? {
+            if let Some((txn, value, table)) = handler.get(key).await? {
                 if txn == key.txn_ts() {
+                    let value =
+                        value.map(|v| self.resolve_blob(v)).transpose()?;
                     return Ok(Some((txn, value)));
                 }
-                if txn > max_txn {
-                    max_txn = txn;
-                    max_value = value;
+                if txn > max.as_ref().map_or(TxnTs::default(), |m| m.0) {
+                    max = Some((txn, value, table));
                 }
             };
         }
-        if max_txn != TxnTs::default() {
-            return Ok(Some((max_txn, max_value)));
+        if let Some((txn, value, _table)) = max {
+            let value = value.map(|v| self.resolve_blob(v)).transpose()?;
+            return Ok(Some((txn, value)));
         }
         Ok(None)
     }
-}
-impl<T: TableTrait<K::Cipher>, K: Kms> LevelHandler<T, K> {
-    async fn get(
+    // level 0 is iterated newest table first, the other levels in one
+    // concatenated iterator each.
+    pub(crate) fn iters_impl(
+        &self,
+    ) -> (Vec<Box<dyn KvCacheIterator<ValueMeta>>>, Vec<RangeTombstone>) {
+        let mut iters: Vec<Box<dyn KvCacheIterator<ValueMeta>>> = Vec::new();
+        let mut tombstones = Vec::new();
+        for level in 0..=self.max_level().to_u8() {
+            let level: Level = level.into();
+            let handler = self.handler(level).unwrap().read();
+            tombstones.extend_from_slice(handler.range_tombstones());
+            if level == LEVEL0 {
+                for t in handler.tables().iter().rev() {
+                    iters.push(Box::new(t.iter(true)));
+                }
+            } else if !handler.tables().is_empty() {
+                let tables = handler.tables().to_vec();
+                iters.push(Box::new(CacheTableConcatIter::new(tables, true)));
+            }
+        }
+        (iters, tombstones)
+    }
+    pub(crate) fn key_iters_impl(
         &self,
         key: &KeyTs,
-    ) -> Result<Option<(TxnTs, Option<ValueMeta>)>> {
+    ) -> (Vec<Box<dyn KvCacheIterator<ValueMeta>>>, Vec<RangeTombstone>) {
+        let mut iters: Vec<Box<dyn KvCacheIterator<ValueMeta>>> = Vec::new();
+        let mut tombstones = Vec::new();
+        for level in 0..=self.max_level().to_u8() {
+            let handler = self.handler(level.into()).unwrap();
+            tombstones.extend(
+                handler
+                    .read()
+                    .range_tombstones()
+                    .iter()
+                    .filter(|t| t.contains(key.key()))
+                    .cloned(),
+            );
+            for table in handler.seek_table(key).unwrap_or_default() {
+                iters.push(Box::new(table.iter(true)));
+            }
+        }
+        (iters, tombstones)
+    }
+}
+impl<T: TableTrait<K::Cipher>, K: Kms> LevelHandler<T, K> {
+    async fn get(&self, key: &KeyTs) -> Result<Option<LevelValue<T>>> {
+        let point = self.get_point(key).await?;
+        // a newer range tombstone in this level hides the point version.
+        let tombstone =
+            RangeTombstone::max_covering(self.read().range_tombstones(), key);
+        if let Some(t_ts) = tombstone {
+            if point.as_ref().is_none_or(|(txn, _, _)| t_ts > *txn) {
+                let value = Some(RangeTombstone::value_meta());
+                return Ok(Some((t_ts, value, None)));
+            }
+        }
+        Ok(point)
+    }
+    async fn get_point(&self, key: &KeyTs) -> Result<Option<LevelValue<T>>> {
         if let Some(tables) = self.seek_table(key) {
-            let mut max_txn = None;
-            let mut max_value = None;
+            let mut max: Option<LevelValue<T>> = None;
 
             for table in tables {
                 let ks = key.encode();
@@ -59,17 +116,12 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelHandler<T, K> {
                             if let Some(seek_key) = iter.key() {
                                 if k.key() == seek_key.key() {
                                     let txn = seek_key.txn_ts();
-                                    match max_txn {
-                                        Some(m_txn) => {
-                                            if txn > m_txn {
-                                                max_txn = Some(txn);
-                                                max_value = iter.value();
-                                            }
-                                        }
-                                        None => {
-                                            max_txn = Some(txn);
-                                            max_value = iter.value();
-                                        }
+                                    if max.as_ref().is_none_or(|m| txn > m.0) {
+                                        max = Some((
+                                            txn,
+                                            iter.value(),
+                                            Some(table.clone()),
+                                        ));
                                     }
                                 }
                             }
@@ -80,9 +132,7 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelHandler<T, K> {
                     }
                 }
             }
-            if let Some(m_txn) = max_txn {
-                return Ok(Some((m_txn, max_value)));
-            }
+            return Ok(max);
         };
         Ok(None)
     }
@@ -97,9 +147,12 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelHandler<T, K> {
                 .cloned()
                 .collect::<Vec<_>>()
                 .into()
-        } else if let Ok(table_index) =
-            handler.tables().binary_search_by(|t| t.biggest().cmp(key))
-        {
+        } else {
+            // the first table whose biggest key is not smaller than `key`.
+            let table_index = handler
+                .tables()
+                .binary_search_by(|t| t.biggest().cmp(key))
+                .unwrap_or_else(|i| i);
             if table_index >= handler.tables().len() {
                 return None;
             }
@@ -108,8 +161,6 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelHandler<T, K> {
                 return None;
             }
             vec![t].into()
-        } else {
-            None
         }
     }
 }
@@ -17,10 +17,13 @@ impl<T: TableTrait<K::Cipher>, K: Kms> LevelCtl<T, K> {
             table.id(),
             LEVEL0,
             table.cipher().map(|k| k.cipher_key_id()),
+            table.cipher().map(|k| k.encryption_algo()).unwrap_or_default(),
             table.compression(),
+            table.global_version(),
         );
         self.manifest().push_changes(vec![change]).await?;
         self.next_id().fetch_max(Into::<u32>::into (table.id())+1,Ordering::AcqRel);
+        self.add_blob_refs(std::slice::from_ref(&table));
         let handler = self.handler(LEVEL0).unwrap();
         let level0_num_tables_stall = self.config().level0_num_tables_stall();
 
@@ -1,11 +1,13 @@
+use bytes::Bytes;
 use bytesize::ByteSize;
-use mors_common::kv::{Meta, ValueMeta};
+use mors_common::kv::{Meta, RangeTombstone, ValueMeta};
+use mors_common::ts::KeyTs;
 use mors_encrypt::cipher::AesCipher;
 use mors_sstable::table::Table;
 use mors_sstable::test_utils::generate_table;
 use mors_traits::iter::{
     generate_kv_slice, CacheIterator, KvCacheIter, KvCacheIterator,
-    KvCacheMergeIterator,
+    KvCacheMergeIterator, KvSeekIter, RangeDeleteIter,
 };
 use mors_traits::sstable::CacheTableConcatIter;
 use mors_traits::sstable::TableTrait;
@@ -120,3 +122,51 @@ async fn test_other_cache_merge_iter() {
     }
     assert!(!iter.next().unwrap());
 }
+#[tokio::test]
+async fn test_range_delete_iter() {
+    let dir = tempfile::tempdir().unwrap();
+    let (tables, range) = generate_table::<AesCipher>(
+        dir.into_path(),
+        10,
+        ByteSize::mib(2).as_u64() as usize,
+        "k",
+        "v",
+        Meta::default(),
+    )
+    .await;
+    let key = |i: u64| Bytes::from(format!("k{:20}", i));
+    // the keys are written at version 0, the tombstone spans tables.
+    let deleted = range.start + 100..range.end - 100;
+    let tombstone =
+        RangeTombstone::new(key(deleted.start), key(deleted.end), 1.into());
+    let new_iter = |max_version: Option<u64>| {
+        let iter = CacheTableConcatIter::new(tables.clone(), true);
+        let mut iter =
+            RangeDeleteIter::new(iter, vec![tombstone.clone()], 1.into());
+        if let Some(max_version) = max_version {
+            // the tombstone is newer than every key, its range is skipped
+            // with one seek.
+            iter.set_max_version(max_version.into());
+        }
+        iter
+    };
+    let kvs = generate_kv_slice(range, "k", "v", Meta::default());
+    for max_version in [None, Some(0)] {
+        let mut iter = new_iter(max_version);
+        for (k, v) in kvs.iter().filter(|(k, _)| {
+            !(key(deleted.start).as_ref()..key(deleted.end).as_ref())
+                .contains(&k.key().as_ref())
+        }) {
+            assert!(iter.next().unwrap());
+            assert_eq!(*k, iter.key().unwrap());
+            assert_eq!(*v, iter.value().unwrap());
+        }
+        assert!(!iter.next().unwrap());
+    }
+
+    // seeking into the range lands past it.
+    let mut iter = new_iter(None);
+    let seek = KeyTs::new(key(deleted.start + 1), 0.into()).encode();
+    assert!(iter.seek(seek.as_slice().into()).unwrap());
+    assert_eq!(iter.key().unwrap().key(), key(deleted.end).as_ref());
+}
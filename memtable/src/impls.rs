@@ -1,14 +1,15 @@
 use std::{collections::VecDeque, sync::Arc};
 
 use mors_common::{
-    file_id::MemtableId,
-    kv::{Entry, ValueMeta},
+    file_id::{MemtableId, VlogId},
+    kv::{ColumnFamilyId, Entry, RangeTombstone, ValueMeta},
     ts::{KeyTs, TxnTs},
 };
 use mors_traits::{
     file::StorageTrait,
-    kms::Kms,
+    kms::{CipherKeyId, Kms},
     memtable::{MemtableBuilderTrait, MemtableError, MemtableTrait},
+    recovery::LogRecovery,
     skip_list::SkipListTrait,
 };
 
@@ -25,8 +26,12 @@ impl<T: SkipListTrait, K: Kms, S: StorageTrait>
         Ok(self.open_impl(kms, id)?)
     }
 
-    fn open_exist(&self, kms: K) -> Result<VecDeque<Arc<Memtable<T, K, S>>>> {
-        Ok(self.open_exist_impl(kms)?)
+    fn open_exist(
+        &self,
+        kms: K,
+        vlog_tail: Option<(VlogId, usize)>,
+    ) -> Result<(VecDeque<Arc<Memtable<T, K, S>>>, Vec<LogRecovery>)> {
+        Ok(self.open_exist_impl(kms, vlog_tail)?)
     }
 
     fn build(&self, kms: K) -> Result<Memtable<T, K, S>> {
@@ -41,6 +46,10 @@ impl<T: SkipListTrait, K: Kms, S: StorageTrait>
         self.set_memtable_size_impl(memtable_size);
     }
 
+    fn set_list_options(&mut self, options: T::Options) {
+        MemtableBuilder::set_list_options(self, options);
+    }
+
     fn max_batch_size(&self) -> usize {
         self.max_batch_size_impl()
     }
@@ -59,12 +68,24 @@ impl<T: SkipListTrait, K: Kms, S: StorageTrait> MemtableTrait<T, K>
         Ok(self.push_impl(entry)?)
     }
 
+    fn push_batch(&self, entries: &[&Entry]) -> Result<()> {
+        Ok(self.push_batch_impl(entries)?)
+    }
+
     fn size(&self) -> usize {
-        self.skip_list.size()
+        self.families
+            .read()
+            .values()
+            .map(|f| f.skip_list.size())
+            .sum()
     }
 
-    fn get(&self, key: &KeyTs) -> Result<Option<(TxnTs, Option<ValueMeta>)>> {
-        Ok(self.get_impl(key)?)
+    fn get(
+        &self,
+        cf: ColumnFamilyId,
+        key: &KeyTs,
+    ) -> Result<Option<(TxnTs, Option<ValueMeta>)>> {
+        Ok(self.get_impl(cf, key)?)
     }
 
     fn max_version(&self) -> TxnTs {
@@ -72,6 +93,9 @@ impl<T: SkipListTrait, K: Kms, S: StorageTrait> MemtableTrait<T, K>
             .load(std::sync::atomic::Ordering::SeqCst)
             .into()
     }
+    fn cipher_key_id(&self) -> CipherKeyId {
+        self.wal.cipher_key_id()
+    }
 
     fn is_full(&self) -> bool {
         self.size() >= self.memtable_size
@@ -81,8 +105,20 @@ impl<T: SkipListTrait, K: Kms, S: StorageTrait> MemtableTrait<T, K>
         self.wal.id()
     }
 
-    fn skip_list(&self) -> T {
-        self.skip_list.clone()
+    fn range_tombstones(&self, cf: ColumnFamilyId) -> Vec<RangeTombstone> {
+        self.range_tombstones
+            .read()
+            .get(&cf)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn skip_lists(&self) -> Vec<(ColumnFamilyId, T)> {
+        self.families
+            .read()
+            .iter()
+            .map(|(cf, f)| (*cf, f.skip_list.clone()))
+            .collect()
     }
 
     fn flush(&self) -> std::result::Result<(), MemtableError> {
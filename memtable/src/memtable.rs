@@ -8,15 +8,19 @@ use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
 
 // use memmap2::Advice;
-use mors_common::file_id::{FileId, MemtableId};
+use mors_common::file_id::{FileId, MemtableId, VlogId};
 use mors_traits::memtable::MemtableBuilderTrait;
 // use mors_common::page_size;
+use mors_common::kv::{ColumnFamilyId, RangeTombstone, DEFAULT_COLUMN_FAMILY};
 use mors_common::ts::KeyTsBorrow;
 use mors_traits::{
     default::{WithDir, WithReadOnly, DEFAULT_DIR},
     kms::Kms,
+    recovery::LogRecovery,
     skip_list::SkipListTrait,
 };
+use parking_lot::RwLock;
+use std::collections::HashMap;
 
 use mors_wal::LogFile;
 
@@ -24,20 +28,81 @@ use crate::error::MorsMemtableError;
 use crate::Result;
 
 pub struct Memtable<T: SkipListTrait, K: Kms, S: StorageTrait> {
-    pub(crate) skip_list: T,
+    // column families share the wal, each one gets its own skip list.
+    pub(crate) families: RwLock<HashMap<ColumnFamilyId, MemFamily<T>>>,
+    // range tombstones of every family, a batch adds its own after its point
+    // writes are in the skip lists.
+    pub(crate) range_tombstones:
+        RwLock<HashMap<ColumnFamilyId, Vec<RangeTombstone>>>,
     pub(crate) wal: LogFile<MemtableId, K, S>,
     // pub(crate) max_version: TxnTs,
     pub(crate) max_txn_ts: AtomicU64,
     // pub(crate) buf: Vec<u8>,
     pub(crate) memtable_size: usize,
+    pub(crate) arena_size: usize,
+    pub(crate) list_options: T::Options,
     pub(crate) read_only: bool,
 }
+#[derive(Clone)]
+pub(crate) struct MemFamily<T: SkipListTrait> {
+    pub(crate) skip_list: T,
+}
+impl<T: SkipListTrait> MemFamily<T> {
+    pub(crate) fn new(arena_size: usize, options: &T::Options) -> Result<Self> {
+        Ok(Self {
+            skip_list: T::with_options(arena_size, KeyTsBorrow::cmp, options)?,
+        })
+    }
+}
+pub(crate) fn family_or_create<T: SkipListTrait>(
+    families: &RwLock<HashMap<ColumnFamilyId, MemFamily<T>>>,
+    arena_size: usize,
+    options: &T::Options,
+    cf: ColumnFamilyId,
+) -> Result<MemFamily<T>> {
+    if let Some(family) = families.read().get(&cf) {
+        return Ok(family.clone());
+    }
+    let mut families = families.write();
+    if let Some(family) = families.get(&cf) {
+        return Ok(family.clone());
+    }
+    let family = MemFamily::new(arena_size, options)?;
+    families.insert(cf, family.clone());
+    Ok(family)
+}
+impl<T: SkipListTrait, K: Kms, S: StorageTrait> Memtable<T, K, S> {
+    pub(crate) fn family(&self, cf: ColumnFamilyId) -> Option<MemFamily<T>> {
+        self.families.read().get(&cf).cloned()
+    }
+    pub(crate) fn family_or_create(
+        &self,
+        cf: ColumnFamilyId,
+    ) -> Result<MemFamily<T>> {
+        family_or_create(
+            &self.families,
+            self.arena_size,
+            &self.list_options,
+            cf,
+        )
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.families
+            .read()
+            .values()
+            .all(|f| f.skip_list.is_empty())
+    }
+}
+// memtables reopened from their wals, and what replaying each wal cut.
+type ReopenedMemtables<T, K, S> =
+    (VecDeque<Arc<Memtable<T, K, S>>>, Vec<LogRecovery>);
 pub struct MemtableBuilder<T: SkipListTrait> {
     dir: PathBuf,
     read_only: bool,
     memtable_size: usize,
     num_memtables: usize,
     next_fid: Arc<AtomicU32>,
+    list_options: T::Options,
     t: PhantomData<T>,
 }
 impl<T: SkipListTrait> Default for MemtableBuilder<T> {
@@ -48,6 +113,7 @@ impl<T: SkipListTrait> Default for MemtableBuilder<T> {
             memtable_size: 64 << 20,
             num_memtables: 5,
             next_fid: Default::default(),
+            list_options: Default::default(),
             t: Default::default(),
         }
     }
@@ -60,6 +126,7 @@ impl<T: SkipListTrait> Clone for MemtableBuilder<T> {
             memtable_size: self.memtable_size,
             num_memtables: self.num_memtables,
             next_fid: self.next_fid.clone(),
+            list_options: self.list_options.clone(),
             t: self.t,
         }
     }
@@ -85,6 +152,11 @@ impl<T: SkipListTrait> MemtableBuilder<T> {
     pub(crate) fn set_memtable_size_impl(&mut self, memtable_size: usize) {
         self.memtable_size = memtable_size;
     }
+    /// options of the skip lists of memtables built from now on.
+    pub fn set_list_options(&mut self, options: T::Options) -> &mut Self {
+        self.list_options = options;
+        self
+    }
 }
 impl<T: SkipListTrait> MemtableBuilder<T> {
     pub(crate) fn open_impl<K: Kms, S: StorageTrait>(
@@ -101,7 +173,10 @@ impl<T: SkipListTrait> MemtableBuilder<T> {
         // .advice(Advice::Sequential)
 
         let mem_path = id.join_dir(self.dir.clone());
-        let skip_list = T::new(self.arena_size(), KeyTsBorrow::cmp)?;
+        let families = HashMap::from([(
+            DEFAULT_COLUMN_FAMILY,
+            MemFamily::new(self.arena_size(), &self.list_options)?,
+        )]);
 
         let wal = LogFile::open(
             id,
@@ -111,11 +186,14 @@ impl<T: SkipListTrait> MemtableBuilder<T> {
             kms,
         )?;
         let memtable = Memtable {
-            skip_list,
+            families: RwLock::new(families),
+            range_tombstones: Default::default(),
             wal,
             // max_version: TxnTs::default(),
             // buf: Vec::with_capacity(page_size()),
             memtable_size: self.memtable_size,
+            arena_size: self.arena_size(),
+            list_options: self.list_options.clone(),
             read_only: self.read_only,
             max_txn_ts: AtomicU64::new(0),
         };
@@ -125,7 +203,8 @@ impl<T: SkipListTrait> MemtableBuilder<T> {
     pub fn open_exist_impl<K: Kms, S: StorageTrait>(
         &self,
         kms: K,
-    ) -> Result<VecDeque<Arc<Memtable<T, K, S>>>> {
+        vlog_tail: Option<(VlogId, usize)>,
+    ) -> Result<ReopenedMemtables<T, K, S>> {
         let mut ids = read_dir(&self.dir)?
             .filter_map(std::result::Result::ok)
             .filter_map(|e| MemtableId::parse(e.path()).ok())
@@ -134,11 +213,12 @@ impl<T: SkipListTrait> MemtableBuilder<T> {
 
         let mut immut_memtable = VecDeque::with_capacity(self.num_memtables);
 
+        let mut recovery = Vec::with_capacity(ids.len());
         let mut valid_ids = Vec::with_capacity(ids.len());
         for id in ids {
             let mut memtable = self.open(kms.clone(), id)?;
-            memtable.reload()?;
-            if memtable.skip_list.is_empty() {
+            recovery.push(memtable.reload(vlog_tail)?);
+            if memtable.is_empty() {
                 let path = id.join_dir(&self.dir);
                 info!("Empty memtable wal: {:?}, now delete it", path);
                 remove_file(&path)?;
@@ -154,7 +234,7 @@ impl<T: SkipListTrait> MemtableBuilder<T> {
                 .store((*valid_ids.last().unwrap()).into(), Ordering::SeqCst);
         }
         self.next_fid.fetch_add(1, Ordering::SeqCst);
-        Ok(immut_memtable)
+        Ok((immut_memtable, recovery))
     }
 
     pub fn build_impl<K: Kms, S: StorageTrait>(
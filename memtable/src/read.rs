@@ -1,18 +1,28 @@
 use crate::memtable::Memtable;
 use crate::Result;
 use mors_common::{
-    kv::ValueMeta,
+    kv::{ColumnFamilyId, RangeTombstone, ValueMeta},
     ts::{KeyTs, KeyTsBorrow, TxnTs},
 };
-use mors_traits::memtable::MemtableTrait;
 use mors_traits::{file::StorageTrait, kms::Kms, skip_list::SkipListTrait};
 impl<T: SkipListTrait, K: Kms, S: StorageTrait> Memtable<T, K, S> {
     pub fn get_impl(
         &self,
+        cf: ColumnFamilyId,
         key: &KeyTs,
     ) -> Result<Option<(TxnTs, Option<ValueMeta>)>> {
-        let v = self
-            .skip_list()
+        let Some(family) = self.family(cf) else {
+            return Ok(None);
+        };
+        // looked up before the point version, the tombstones of a batch are
+        // published after its point writes.
+        let tombstone = self
+            .range_tombstones
+            .read()
+            .get(&cf)
+            .and_then(|t| RangeTombstone::max_covering(t.iter(), key));
+        let v = family
+            .skip_list
             .get_key_value(&key.encode(), true)?
             .and_then(|(k, v)| {
                 let k: KeyTsBorrow = k.into();
@@ -22,6 +32,12 @@ impl<T: SkipListTrait, K: Kms, S: StorageTrait> Memtable<T, K, S> {
                     None
                 }
             });
+        // a newer range tombstone hides the point version found above.
+        if let Some(t_ts) = tombstone {
+            if v.as_ref().is_none_or(|(txn_ts, _)| t_ts > *txn_ts) {
+                return Ok(Some((t_ts, Some(RangeTombstone::value_meta()))));
+            }
+        }
         Ok(v)
     }
 }
@@ -1,51 +1,105 @@
 use std::sync::atomic::Ordering;
 
 use mors_common::{
-    file_id::MemtableId,
-    kv::{Entry, Meta},
+    file_id::{MemtableId, VlogId},
+    kv::{Entry, Meta, RangeTombstone, ValuePointer},
 };
-use mors_traits::{file::StorageTrait, kms::Kms, skip_list::SkipListTrait};
-use mors_wal::{read::LogFileIter, LogFile};
+use mors_traits::{
+    file::StorageTrait, kms::Kms, recovery::LogRecovery,
+    skip_list::SkipListTrait,
+};
+use mors_wal::read::LogFileIter;
 
 use crate::error::MorsMemtableError;
-use crate::memtable::Memtable;
+use crate::memtable::{family_or_create, Memtable};
 use crate::Result;
 
 impl<T: SkipListTrait, K: Kms, S: StorageTrait> Memtable<T, K, S> {
-    pub(crate) fn reload(&mut self) -> Result<()> {
-        let mut wal_iter = LogFileIter::<MemtableId, K, S>::new(
-            &mut self.wal,
-            LogFile::<MemtableId, K, S>::LOG_HEADER_SIZE,
-        );
+    /// replays the wal, batches with values past `vlog_tail`, the valid end
+    /// of the vlog file written last, lost them in a crash and are dropped.
+    pub(crate) fn reload(
+        &mut self,
+        vlog_tail: Option<(VlogId, usize)>,
+    ) -> Result<LogRecovery> {
+        let header_len = self.wal.header_len();
+        let mut wal_iter =
+            LogFileIter::<MemtableId, K, S>::new(&mut self.wal, header_len);
 
+        let mut end_offset = header_len;
+        let mut discarded_entries = 0;
+        let mut dangling = false;
         while let Some(next) = wal_iter.next_entry()? {
+            // commits after one that lost its values are dropped as well.
+            dangling =
+                dangling || next.iter().any(|(e, _)| is_dangling(e, vlog_tail));
+            if dangling {
+                discarded_entries += next.len();
+                continue;
+            }
             for (entry, _vptr) in next {
                 self.max_txn_ts
                     .fetch_max(entry.version().to_u64(), Ordering::Relaxed);
-                self.skip_list.push(
+                let family = family_or_create(
+                    &self.families,
+                    self.arena_size,
+                    &self.list_options,
+                    entry.column_family(),
+                )?;
+                if let Some(t) = RangeTombstone::from_entry(entry) {
+                    self.range_tombstones
+                        .get_mut()
+                        .entry(entry.column_family())
+                        .or_default()
+                        .push(t);
+                }
+                family.skip_list.push(
                     &entry.key_ts().encode(),
                     &entry.value_meta().encode(),
                 )?;
             }
+            end_offset = wal_iter.valid_end_offset();
         }
+        discarded_entries += wal_iter.discarded_entries();
 
-        let end_offset = wal_iter.valid_end_offset();
-        if end_offset < self.wal.len() && self.read_only {
-            return Err(MorsMemtableError::TruncateNeeded(
-                end_offset,
-                self.wal.len(),
-            ));
+        let len = self.wal.len();
+        if end_offset < len && self.read_only {
+            return Err(MorsMemtableError::TruncateNeeded(end_offset, len));
         }
 
         self.wal.set_len(end_offset)?;
-        Ok(())
+        Ok(LogRecovery {
+            path: self.wal.path().to_owned(),
+            valid_len: end_offset,
+            truncated: len.saturating_sub(end_offset),
+            discarded_entries,
+        })
     }
     pub fn push_impl(&self, entry: &Entry) -> Result<()> {
-        self.wal.append_entry(entry)?;
-        if entry.meta().contains(Meta::FIN_TXN) {
-            return Ok(());
+        self.push_batch_impl(&[entry])
+    }
+    pub fn push_batch_impl(&self, entries: &[&Entry]) -> Result<()> {
+        self.wal.append_batch(entries.iter().copied())?;
+        let mut range_tombstones = Vec::new();
+        for entry in entries {
+            self.insert(entry)?;
+            if let Some(t) = RangeTombstone::from_entry(entry) {
+                range_tombstones.push((entry.column_family(), t));
+            }
         }
-        self.skip_list
+        // published once the batch is inserted, a reader seeing them sees
+        // the point writes of the batch as well.
+        if !range_tombstones.is_empty() {
+            let mut tombstones = self.range_tombstones.write();
+            for (cf, t) in range_tombstones {
+                tombstones.entry(cf).or_default().push(t);
+            }
+        }
+        Ok(())
+    }
+    fn insert(&self, entry: &Entry) -> Result<()> {
+        let family = self.family_or_create(entry.column_family())?;
+        family
+            .skip_list
             .push(&entry.key_ts().encode(), &entry.value_meta().encode())?;
         self.max_txn_ts
             .fetch_max(entry.version().to_u64(), Ordering::Relaxed);
@@ -56,3 +110,16 @@ impl<T: SkipListTrait, K: Kms, S: StorageTrait> Memtable<T, K, S> {
         Ok(())
     }
 }
+// the value of `entry` is in the vlog file `id` but ends after its valid end.
+fn is_dangling(entry: &Entry, vlog_tail: Option<(VlogId, usize)>) -> bool {
+    let Some((id, valid_len)) = vlog_tail else {
+        return false;
+    };
+    if !entry.meta().contains(Meta::VALUE_POINTER) {
+        return false;
+    }
+    ValuePointer::decode(entry.value()).is_some_and(|vp| {
+        vp.fid() == u32::from(id)
+            && vp.offset() as usize + vp.size() as usize > valid_len
+    })
+}
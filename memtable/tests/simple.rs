@@ -1,32 +1,43 @@
 use std::collections::VecDeque;
 use std::sync::Arc;
 
+use mors_common::file_id::VlogId;
 use mors_common::kv::Entry;
 use mors_common::kv::Meta;
+use mors_common::kv::ValueMeta;
+use mors_common::kv::{RangeTombstone, ValuePointer, DEFAULT_COLUMN_FAMILY};
+use mors_common::ts::KeyTs;
 use mors_encrypt::registry::MorsKms;
 use mors_encrypt::registry::MorsKmsBuilder;
 use mors_memtable::memtable::Memtable;
 use mors_memtable::memtable::MemtableBuilder;
+use mors_skip_list::any::{AnyList, MemtableKind};
 use mors_skip_list::skip_list::SkipList;
 use mors_traits::default::WithDir;
+use mors_traits::iter::{
+    CacheIterator, KvCacheIter, KvCacheIterator, KvCacheMergeIterator,
+    KvSeekIter, RangeDeleteIter,
+};
 use mors_traits::kms::KmsBuilder;
 use mors_traits::memtable::MemtableBuilderTrait;
 use mors_traits::memtable::MemtableTrait;
+use mors_traits::skip_list::SkipListTrait;
 type TestMemtableBuilder = MemtableBuilder<SkipList>;
 use mors_wal::storage::mmap::MmapFile;
 use proptest::prelude::ProptestConfig;
 use proptest::proptest;
-fn build_reload(count: u32, table_num: u32) {
+fn build_reload(count: u32, table_num: u32, kind: MemtableKind) {
     let tempdir = tempfile::tempdir().unwrap();
     let mut kms_builder = MorsKmsBuilder::default();
     kms_builder.set_dir(tempdir.path().to_path_buf());
     let kms = kms_builder.build().unwrap();
 
-    let mut builder = TestMemtableBuilder::default();
+    let mut builder = MemtableBuilder::<AnyList>::default();
     builder.set_dir(tempdir.path().to_path_buf());
+    builder.set_list_options(kind);
 
     for i in 0..table_num {
-        let memtable: Memtable<SkipList, MorsKms, MmapFile> =
+        let memtable: Memtable<AnyList, MorsKms, MmapFile> =
             builder.build(kms.clone()).unwrap();
         let prefix = format!("table{}", i);
         let entries = generate_entries(count, &prefix);
@@ -35,19 +46,34 @@ fn build_reload(count: u32, table_num: u32) {
         }
     }
 
-    let memtables: VecDeque<Arc<Memtable<SkipList, MorsKms, MmapFile>>> =
-        builder.open_exist(kms).unwrap();
+    let (memtables, _): (
+        VecDeque<Arc<Memtable<AnyList, MorsKms, MmapFile>>>,
+        _,
+    ) = builder.open_exist(kms, None).unwrap();
     assert_eq!(memtables.len(), table_num as usize);
     for (i, memtable) in memtables.iter().enumerate() {
         let prefix = format!("table{}", i);
         let entries = generate_entries(count, &prefix);
         for (index, entry) in entries.iter().enumerate() {
             assert!(entry.key_ts().key().starts_with(prefix.as_bytes()));
-            let (txn, value) = memtable.get(entry.key_ts()).unwrap().unwrap();
+            let (txn, value) = memtable
+                .get(DEFAULT_COLUMN_FAMILY, entry.key_ts())
+                .unwrap()
+                .unwrap();
             assert_eq!(txn.to_u64() as usize, index);
             assert!(value.is_some());
             assert_eq!(value.unwrap(), *entry.value_meta());
         }
+        let (_, list) = memtable.skip_lists().pop().unwrap();
+        assert_eq!(list.kind(), kind);
+        let mut iter = list.iter();
+        let mut keys = 0;
+        while iter.next().unwrap() {
+            let key = iter.key().unwrap();
+            assert_eq!(key.key(), entries[keys].key_ts().key().as_ref());
+            keys += 1;
+        }
+        assert_eq!(keys, entries.len());
     }
 
     tempdir.close().unwrap();
@@ -56,7 +82,13 @@ proptest! {
     #![proptest_config(ProptestConfig::with_cases(20))]
     #[test]
     fn test_table_iter(count in 1..10000u32,table_num in 1..5u32) {
-        build_reload(count,table_num)
+        for kind in [
+            MemtableKind::SkipList,
+            MemtableKind::BTree,
+            MemtableKind::SortedVec,
+        ] {
+            build_reload(count, table_num, kind)
+        }
     }
 }
 fn generate_entries(count: u32, prefix: &str) -> Vec<Entry> {
@@ -73,3 +105,249 @@ fn generate_entries(count: u32, prefix: &str) -> Vec<Entry> {
     }
     entries
 }
+#[test]
+fn test_range_tombstone() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let mut kms_builder = MorsKmsBuilder::default();
+    kms_builder.set_dir(tempdir.path().to_path_buf());
+    let kms = kms_builder.build().unwrap();
+
+    let mut builder = TestMemtableBuilder::default();
+    builder.set_dir(tempdir.path().to_path_buf());
+    {
+        let memtable: Memtable<SkipList, MorsKms, MmapFile> =
+            builder.build(kms.clone()).unwrap();
+        for (key, version) in [("a", 1), ("b", 1), ("c", 1), ("c", 3)] {
+            let mut entry = Entry::new(key.into(), "v".into());
+            entry.set_version(version.into());
+            memtable.push(&entry).unwrap();
+        }
+        let tombstone = RangeTombstone::new("b".into(), "d".into(), 2.into());
+        memtable.push(&tombstone.to_entry()).unwrap();
+    }
+
+    let (memtables, _): (
+        VecDeque<Arc<Memtable<SkipList, MorsKms, MmapFile>>>,
+        _,
+    ) = builder.open_exist(kms, None).unwrap();
+    let memtable = &memtables[0];
+    let get = |key: &'static str, read_ts: u64| {
+        let (txn, value) = memtable
+            .get(
+                DEFAULT_COLUMN_FAMILY,
+                &KeyTs::new(key.into(), read_ts.into()),
+            )
+            .unwrap()
+            .unwrap();
+        (txn.to_u64(), value.unwrap().is_deleted_or_expired())
+    };
+    assert_eq!(get("a", 10), (1, false));
+    assert_eq!(get("b", 1), (1, false));
+    assert_eq!(get("b", 10), (2, true));
+    assert_eq!(get("c", 2), (2, true));
+    assert_eq!(get("c", 10), (3, false));
+    assert!(memtable
+        .get(DEFAULT_COLUMN_FAMILY, &KeyTs::new("d".into(), 10.into()))
+        .unwrap()
+        .is_none());
+
+    tempdir.close().unwrap();
+}
+#[test]
+fn test_range_tombstone_concurrent() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let mut kms_builder = MorsKmsBuilder::default();
+    kms_builder.set_dir(tempdir.path().to_path_buf());
+    let kms = kms_builder.build().unwrap();
+
+    let mut builder = TestMemtableBuilder::default();
+    builder.set_dir(tempdir.path().to_path_buf());
+    let memtable: Memtable<SkipList, MorsKms, MmapFile> =
+        builder.build(kms).unwrap();
+    let versions = 2000u64;
+    let key = KeyTs::new("b".into(), u64::MAX.into());
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            // every batch deletes "b" and writes it again at the same version,
+            // so the write is always the one read.
+            for version in 1..=versions {
+                let tombstone =
+                    RangeTombstone::new("a".into(), "c".into(), version.into());
+                let mut entry = Entry::new("b".into(), "v".into());
+                entry.set_version(version.into());
+                memtable
+                    .push_batch(&[&tombstone.to_entry(), &entry])
+                    .unwrap();
+            }
+        });
+        s.spawn(|| loop {
+            let Some((txn, value)) =
+                memtable.get(DEFAULT_COLUMN_FAMILY, &key).unwrap()
+            else {
+                continue;
+            };
+            assert!(!value.unwrap().is_deleted_or_expired());
+            if txn.to_u64() == versions {
+                break;
+            }
+        });
+    });
+}
+#[test]
+fn test_column_family() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let mut kms_builder = MorsKmsBuilder::default();
+    kms_builder.set_dir(tempdir.path().to_path_buf());
+    let kms = kms_builder.build().unwrap();
+
+    let mut builder = TestMemtableBuilder::default();
+    builder.set_dir(tempdir.path().to_path_buf());
+    {
+        let memtable: Memtable<SkipList, MorsKms, MmapFile> =
+            builder.build(kms.clone()).unwrap();
+        for (cf, value) in
+            [(DEFAULT_COLUMN_FAMILY, "v0"), (1, "v1"), (300, "v2")]
+        {
+            let mut entry = Entry::new("k".into(), value.into());
+            entry.set_version(1.into());
+            entry.set_column_family(cf);
+            memtable.push(&entry).unwrap();
+        }
+    }
+
+    let (memtables, _): (
+        VecDeque<Arc<Memtable<SkipList, MorsKms, MmapFile>>>,
+        _,
+    ) = builder.open_exist(kms, None).unwrap();
+    let memtable = &memtables[0];
+    assert_eq!(memtable.skip_lists().len(), 3);
+    let key = KeyTs::new("k".into(), 1.into());
+    for (cf, value) in [(DEFAULT_COLUMN_FAMILY, "v0"), (1, "v1"), (300, "v2")] {
+        let (_, v) = memtable.get(cf, &key).unwrap().unwrap();
+        assert_eq!(v.unwrap().value().as_ref(), value.as_bytes());
+    }
+    assert!(memtable.get(2, &key).unwrap().is_none());
+
+    tempdir.close().unwrap();
+}
+#[test]
+fn test_recovery() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let mut kms_builder = MorsKmsBuilder::default();
+    kms_builder.set_dir(tempdir.path().to_path_buf());
+    let kms = kms_builder.build().unwrap();
+
+    let mut builder = TestMemtableBuilder::default();
+    builder.set_dir(tempdir.path().to_path_buf());
+    {
+        let memtable: Memtable<SkipList, MorsKms, MmapFile> =
+            builder.build(kms.clone()).unwrap();
+        let mut entries = Vec::new();
+        for (key, version) in [("a", 1), ("b", 2), ("c", 2), ("d", 3)] {
+            let mut entry = Entry::new(key.into(), "v".into());
+            entry.set_version(version.into());
+            entries.push(entry);
+        }
+        // "b" has its value in the vlog, at the end of the first file.
+        let vptr = ValuePointer::new(VlogId::from(1), 50, 100);
+        entries[1].set_meta(Meta::VALUE_POINTER);
+        entries[1].set_value(vptr.encode());
+        memtable.push(&entries[0]).unwrap();
+        memtable.push_batch(&[&entries[1], &entries[2]]).unwrap();
+        memtable.push(&entries[3]).unwrap();
+    }
+
+    // the vlog tail holding the value of "b" was lost.
+    let (memtables, recovery): (
+        VecDeque<Arc<Memtable<SkipList, MorsKms, MmapFile>>>,
+        _,
+    ) = builder
+        .open_exist(kms.clone(), Some((VlogId::from(1), 120)))
+        .unwrap();
+    assert_eq!(recovery.len(), 1);
+    assert_eq!(recovery[0].discarded_entries, 3);
+    let valid_len = recovery[0].valid_len;
+    let memtable = &memtables[0];
+    for (key, found) in [("a", true), ("b", false), ("c", false), ("d", false)]
+    {
+        let key = KeyTs::new(key.into(), 10.into());
+        let get = memtable.get(DEFAULT_COLUMN_FAMILY, &key).unwrap();
+        assert_eq!(get.is_some(), found);
+    }
+    drop(memtables);
+
+    // the wal was truncated after "a".
+    let (_, recovery): (
+        VecDeque<Arc<Memtable<SkipList, MorsKms, MmapFile>>>,
+        _,
+    ) = builder.open_exist(kms, None).unwrap();
+    assert!(recovery[0].is_clean());
+    assert_eq!(recovery[0].valid_len, valid_len);
+
+    tempdir.close().unwrap();
+}
+#[test]
+fn test_range_tombstone_iter() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let mut kms_builder = MorsKmsBuilder::default();
+    kms_builder.set_dir(tempdir.path().to_path_buf());
+    let kms = kms_builder.build().unwrap();
+
+    let mut builder = TestMemtableBuilder::default();
+    builder.set_dir(tempdir.path().to_path_buf());
+    // the tombstone of the newer memtable deletes keys of the older one.
+    let older: Memtable<SkipList, MorsKms, MmapFile> =
+        builder.build(kms.clone()).unwrap();
+    for key in ["a", "b", "c", "d"] {
+        let mut entry = Entry::new(key.into(), "v".into());
+        entry.set_version(1.into());
+        older.push(&entry).unwrap();
+    }
+    let newer: Memtable<SkipList, MorsKms, MmapFile> =
+        builder.build(kms).unwrap();
+    let tombstone = RangeTombstone::new("b".into(), "d".into(), 2.into());
+    newer.push(&tombstone.to_entry()).unwrap();
+    let mut entry = Entry::new("c".into(), "v".into());
+    entry.set_version(3.into());
+    newer.push(&entry).unwrap();
+
+    let lists = [&newer, &older].map(|m| m.skip_lists().pop().unwrap().1);
+    let tombstones = [&newer, &older]
+        .iter()
+        .flat_map(|m| m.range_tombstones(DEFAULT_COLUMN_FAMILY))
+        .collect::<Vec<_>>();
+    let iter = |read_ts: u64| {
+        let iters: Vec<Box<dyn KvCacheIterator<ValueMeta>>> = lists
+            .iter()
+            .map(|l| Box::new(l.iter()) as Box<dyn KvCacheIterator<ValueMeta>>)
+            .collect();
+        let merge = KvCacheMergeIterator::new(iters).unwrap();
+        RangeDeleteIter::new(merge, tombstones.clone(), read_ts.into())
+    };
+    let collect = |read_ts: u64| {
+        let mut iter = iter(read_ts);
+        let mut out = Vec::new();
+        while iter.next().unwrap() {
+            let key = iter.key().unwrap();
+            out.push((key.key().to_vec(), key.txn_ts().to_u64()));
+        }
+        out
+    };
+    let keys = |keys: &[(&str, u64)]| {
+        keys.iter()
+            .map(|(k, ts)| (k.as_bytes().to_vec(), *ts))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(collect(2), keys(&[("a", 1), ("b", 2), ("c", 3), ("d", 1)]));
+    // the tombstone is not visible yet.
+    assert_eq!(
+        collect(1),
+        keys(&[("a", 1), ("b", 2), ("b", 1), ("c", 3), ("c", 1), ("d", 1)])
+    );
+
+    let mut iter = iter(2);
+    let seek = KeyTs::new("c".into(), 2.into()).encode();
+    assert!(iter.seek(seek.as_slice().into()).unwrap());
+    assert_eq!(iter.key().unwrap().key(), b"d");
+}
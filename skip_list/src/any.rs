@@ -0,0 +1,184 @@
+//! A list whose implementation is picked when it is created, so a db can
+//! switch the index of its memtables with an option instead of a type.
+use mors_common::{kv::ValueMeta, ts::KeyTsBorrow};
+use mors_traits::{
+    iter::{
+        CacheIterator, IterError, KvCacheIter, KvCacheIterator, KvSeekIter,
+    },
+    skip_list::{OptionKV, SkipListError, SkipListTrait},
+};
+
+use crate::{
+    error::MorsSkipListError,
+    iter::SkipListIter,
+    ordered::{
+        BTreeIndex, BTreeList, OrderedListIter, SortedVecIndex, SortedVecList,
+    },
+    skip_list::SkipList,
+};
+
+type Result<T> = std::result::Result<T, SkipListError>;
+type Cmp = fn(&[u8], &[u8]) -> std::cmp::Ordering;
+
+/// the implementation behind the memtables.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MemtableKind {
+    /// a lock-free skip list, writers never wait on each other.
+    #[default]
+    SkipList,
+    /// a b-tree behind a lock, for faster point lookups and scans.
+    BTree,
+    /// a sorted vector behind a lock, for keys written in sorted order
+    /// like bulk loads.
+    SortedVec,
+}
+
+#[derive(Clone)]
+pub enum AnyList {
+    SkipList(SkipList),
+    BTree(BTreeList),
+    SortedVec(SortedVecList),
+}
+impl AnyList {
+    pub fn kind(&self) -> MemtableKind {
+        match self {
+            AnyList::SkipList(_) => MemtableKind::SkipList,
+            AnyList::BTree(_) => MemtableKind::BTree,
+            AnyList::SortedVec(_) => MemtableKind::SortedVec,
+        }
+    }
+}
+macro_rules! dispatch {
+    ($list:expr, $l:ident => $e:expr) => {
+        match $list {
+            AnyList::SkipList($l) => $e,
+            AnyList::BTree($l) => $e,
+            AnyList::SortedVec($l) => $e,
+        }
+    };
+}
+const fn max(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+impl SkipListTrait for AnyList {
+    type ErrorType = MorsSkipListError;
+    type Options = MemtableKind;
+
+    fn new(max_size: usize, cmp: Cmp) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Self::with_options(max_size, cmp, &MemtableKind::default())
+    }
+
+    fn with_options(
+        max_size: usize,
+        cmp: Cmp,
+        options: &MemtableKind,
+    ) -> Result<Self> {
+        Ok(match options {
+            MemtableKind::SkipList => {
+                AnyList::SkipList(SkipList::new(max_size, cmp)?)
+            }
+            MemtableKind::BTree => {
+                AnyList::BTree(BTreeList::new(max_size, cmp)?)
+            }
+            MemtableKind::SortedVec => {
+                AnyList::SortedVec(SortedVecList::new(max_size, cmp)?)
+            }
+        })
+    }
+
+    fn size(&self) -> usize {
+        dispatch!(self, l => l.size())
+    }
+
+    fn push(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        dispatch!(self, l => l.push(key, value))
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<&[u8]>> {
+        dispatch!(self, l => l.get(key))
+    }
+
+    fn get_or_next(&self, key: &[u8]) -> Result<Option<&[u8]>> {
+        dispatch!(self, l => l.get_or_next(key))
+    }
+
+    fn get_key_value(
+        &self,
+        key: &[u8],
+        allow_next: bool,
+    ) -> Result<OptionKV<'_>> {
+        dispatch!(self, l => l.get_key_value(key, allow_next))
+    }
+
+    fn is_empty(&self) -> bool {
+        dispatch!(self, l => l.is_empty())
+    }
+
+    fn height(&self) -> usize {
+        dispatch!(self, l => l.height())
+    }
+
+    fn iter(&self) -> impl KvCacheIterator<ValueMeta> {
+        match self {
+            AnyList::SkipList(l) => {
+                AnyListIter::SkipList(SkipListIter::new(&l.inner))
+            }
+            AnyList::BTree(l) => {
+                AnyListIter::BTree(OrderedListIter::new(&l.inner))
+            }
+            AnyList::SortedVec(l) => {
+                AnyListIter::SortedVec(OrderedListIter::new(&l.inner))
+            }
+        }
+    }
+
+    const MAX_NODE_SIZE: usize = max(
+        SkipList::MAX_NODE_SIZE,
+        max(BTreeList::MAX_NODE_SIZE, SortedVecList::MAX_NODE_SIZE),
+    );
+}
+
+pub enum AnyListIter<'a> {
+    SkipList(SkipListIter<'a>),
+    BTree(OrderedListIter<'a, BTreeIndex>),
+    SortedVec(OrderedListIter<'a, SortedVecIndex>),
+}
+macro_rules! dispatch_iter {
+    ($iter:expr, $i:ident => $e:expr) => {
+        match $iter {
+            AnyListIter::SkipList($i) => $e,
+            AnyListIter::BTree($i) => $e,
+            AnyListIter::SortedVec($i) => $e,
+        }
+    };
+}
+impl CacheIterator for AnyListIter<'_> {
+    fn next(&mut self) -> std::result::Result<bool, IterError> {
+        dispatch_iter!(self, i => i.next())
+    }
+}
+impl KvSeekIter for AnyListIter<'_> {
+    fn seek(
+        &mut self,
+        k: KeyTsBorrow<'_>,
+    ) -> std::result::Result<bool, IterError> {
+        dispatch_iter!(self, i => i.seek(k))
+    }
+}
+impl KvCacheIter<ValueMeta> for AnyListIter<'_> {
+    fn key(&self) -> Option<KeyTsBorrow<'_>> {
+        dispatch_iter!(self, i => i.key())
+    }
+
+    fn value(&self) -> Option<ValueMeta> {
+        dispatch_iter!(self, i => i.value())
+    }
+}
+impl KvCacheIterator<ValueMeta> for AnyListIter<'_> {}
@@ -4,9 +4,10 @@ use std::mem::size_of;
 
 use std::pin::Pin;
 use std::ptr::{self, NonNull};
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
 
 use mors_common::page_size;
+use parking_lot::Mutex;
 
 use crate::error::ArenaError;
 
@@ -15,21 +16,35 @@ const CHUNK_ALIGN: usize = 16;
 const DEFAULT_ALIGN: usize = 8;
 
 type Result<T> = std::result::Result<T, ArenaError>;
+/// Bump allocator handing out offsets instead of pointers.
+///
+/// A chained arena adds a segment once the last one is full, so offsets
+/// stay valid while it grows. Offsets of the later segments continue after
+/// the earlier ones.
 #[derive(Debug)]
 pub struct Arena {
+    segments: Box<[AtomicPtr<Segment>]>,
+    // index of the segment allocations go to.
+    current: AtomicUsize,
+    grow_lock: Mutex<()>,
+    _pin: PhantomPinned,
+}
+#[derive(Debug)]
+struct Segment {
     start: NonNull<u8>,
-    ptr_offset: AtomicUsize,
     end: NonNull<u8>,
+    // offset of `start` in the arena.
+    base: usize,
+    ptr_offset: AtomicUsize,
     layout: Layout,
-    _pin:PhantomPinned,
 }
-impl Arena {
-    pub fn new(size: usize) -> Result<Pin<Box<Arena>>> {
+impl Segment {
+    fn new(size: usize, base: usize) -> Result<Box<Segment>> {
         let chunk_align = CHUNK_ALIGN;
-        let mut request_size = Self::round_up_to(size, chunk_align);
+        let mut request_size = Arena::round_up_to(size, chunk_align);
         debug_assert_eq!(chunk_align % CHUNK_ALIGN, 0);
         if request_size >= page_size() {
-            request_size = Self::round_up_to(request_size, page_size());
+            request_size = Arena::round_up_to(request_size, page_size());
         }
         debug_assert_eq!(request_size % CHUNK_ALIGN, 0);
 
@@ -47,17 +62,101 @@ impl Arena {
         };
         debug_assert_eq!((data.as_ptr() as usize) % layout.align(), 0);
         debug_assert_eq!((end.as_ptr() as usize) % CHUNK_ALIGN, 0);
-        let ptr_offset = AtomicUsize::new(0);
-        let s = Self {
+        Ok(Box::new(Self {
             start: data,
-            ptr_offset,
             end,
+            base,
+            ptr_offset: AtomicUsize::new(0),
             layout,
+        }))
+    }
+    fn size(&self) -> usize {
+        self.layout.size()
+    }
+    fn len(&self) -> usize {
+        self.ptr_offset.load(Ordering::Relaxed).min(self.size())
+    }
+    fn alloc(&self, layout: Layout, alloc_size: usize) -> Result<NonNull<u8>> {
+        let end_ptr = self.end.as_ptr();
+        let start_ptr = self.start.as_ptr();
+        let old_ptr = unsafe {
+            start_ptr
+                .add(self.ptr_offset.fetch_add(alloc_size, Ordering::AcqRel))
+        };
+        debug_assert_eq!(old_ptr as usize % 8, 0);
+        unsafe {
+            let new_ptr = old_ptr.add(alloc_size);
+            if new_ptr > end_ptr {
+                return Err(ArenaError::SizeTooSmall {
+                    to_write: layout.size(),
+                    new_total: self.base
+                        + new_ptr.offset_from(start_ptr) as usize,
+                    limit: self.base + self.size(),
+                });
+            }
+            Ok(NonNull::new_unchecked(old_ptr))
+        }
+    }
+    // the end is included for empty slices allocated at the very end.
+    fn contains(&self, ptr: *const u8) -> bool {
+        ptr >= self.start.as_ptr() as *const u8
+            && ptr <= self.end.as_ptr() as *const u8
+    }
+}
+impl Arena {
+    /// an arena of a single segment, allocations fail once it is full.
+    pub fn new(size: usize) -> Result<Pin<Box<Arena>>> {
+        Self::chained(size, 1)
+    }
+    /// an arena starting with a segment of `size` bytes, growing by at
+    /// least as much up to `max_segments` segments.
+    pub fn chained(
+        size: usize,
+        max_segments: usize,
+    ) -> Result<Pin<Box<Arena>>> {
+        debug_assert!(max_segments > 0);
+        let segments = (0..max_segments)
+            .map(|_| AtomicPtr::new(ptr::null_mut()))
+            .collect::<Box<[_]>>();
+        segments[0]
+            .store(Box::into_raw(Segment::new(size, 0)?), Ordering::Release);
+        let s = Self {
+            segments,
+            current: AtomicUsize::new(0),
+            grow_lock: Mutex::new(()),
             _pin: PhantomPinned,
         };
-        
+
         Ok(Box::pin(s))
     }
+    #[inline(always)]
+    fn segment(&self, index: usize) -> &Segment {
+        let segment = self.segments[index].load(Ordering::Acquire);
+        debug_assert!(!segment.is_null());
+        unsafe { &*segment }
+    }
+    fn segments(&self) -> impl Iterator<Item = &Segment> {
+        (0..=self.current.load(Ordering::Acquire)).map(|i| self.segment(i))
+    }
+    // pointer to `size` bytes at `offset`, which must not cross segments.
+    fn locate(&self, offset: usize, size: usize) -> Result<*mut u8> {
+        for segment in self.segments() {
+            let limit = segment.base + segment.size();
+            if offset < limit {
+                if offset + size > limit {
+                    break;
+                }
+                return Ok(unsafe {
+                    segment.start.as_ptr().add(offset - segment.base)
+                });
+            }
+        }
+        Err(ArenaError::OffsetOutOfBound {
+            offset,
+            size,
+            limit: self.max_size(),
+        })
+    }
     pub fn alloc<T>(&self, value: T) -> Result<&mut T> {
         self.alloc_with(|| value)
     }
@@ -82,87 +181,68 @@ impl Arena {
     }
 
     pub fn get_mut<T>(&self, offset: usize) -> Result<&mut T> {
-        unsafe {
-            let ptr = self.start.as_ptr().add(offset);
-
-            if ptr.add(size_of::<T>()) > self.end.as_ptr() {
-                Err(ArenaError::OffsetOutOfBound {
-                    offset,
-                    size: std::mem::size_of::<T>(),
-                    limit: self.layout.size(),
-                })
-            } else {
-                Ok(&mut *(ptr as *mut T))
-            }
-        }
+        let ptr = self.locate(offset, size_of::<T>())?;
+        unsafe { Ok(&mut *(ptr as *mut T)) }
     }
     pub fn get<T>(&self, offset: usize) -> Result<&T> {
-        unsafe {
-            let ptr = self.start.as_ptr().add(offset);
-            if ptr.add(size_of::<T>()) > self.end.as_ptr() {
-                Err(ArenaError::OffsetOutOfBound {
-                    offset,
-                    size: std::mem::size_of::<T>(),
-                    limit: self.layout.size(),
-                })
-            } else {
-                Ok(&*(ptr as *mut T))
-            }
-        }
+        let ptr = self.locate(offset, size_of::<T>())?;
+        unsafe { Ok(&*(ptr as *mut T)) }
     }
     pub fn get_slice<T>(&self, offset: usize, len: usize) -> Result<&[T]> {
         if len == 0 {
             return Err(ArenaError::ZeroLengthError);
         }
-        unsafe {
-            let ptr = self.start.as_ptr().add(offset);
-
-            if ptr.add(len * size_of::<T>()) > self.end.as_ptr() {
-                Err(ArenaError::OffsetOutOfBound {
-                    offset,
-                    size: len * size_of::<T>(),
-                    limit: self.layout.size(),
-                })
-            } else {
-                Ok(std::slice::from_raw_parts(ptr as *const T, len))
-            }
-        }
+        let ptr = self.locate(offset, len * size_of::<T>())?;
+        unsafe { Ok(std::slice::from_raw_parts(ptr as *const T, len)) }
     }
     pub fn offset<N>(&self, ptr: *const N) -> Result<usize> {
         if ptr.is_null() {
             return Err(ArenaError::NullPointerError);
         }
-
-        let ptr_addr = ptr as usize;
-        let start_addr = self.start.as_ptr() as usize;
-        debug_assert!(ptr_addr >= start_addr);
-        Ok(ptr_addr - start_addr)
+        self.offset_of(ptr as *const u8)
+            .ok_or(ArenaError::NullPointerError)
+    }
+    fn offset_of(&self, ptr: *const u8) -> Option<usize> {
+        self.segments()
+            .find(|s| s.contains(ptr))
+            .map(|s| s.base + (ptr as usize - s.start.as_ptr() as usize))
     }
     fn alloc_layout(&self, layout: Layout) -> Result<NonNull<u8>> {
         debug_assert!(DEFAULT_ALIGN.is_power_of_two());
         let layout = layout.align_to(DEFAULT_ALIGN).unwrap();
-        let end_ptr = self.end.as_ptr();
-        let start_ptr = self.start.as_ptr();
         let alloc_size = Self::round_up_to(layout.size(), layout.align());
-        let old_ptr = unsafe {
-            self.start.as_ptr().add(
-                self.ptr_offset
-                    .fetch_add(alloc_size, std::sync::atomic::Ordering::AcqRel),
-            )
-        };
-        debug_assert_eq!(old_ptr as usize % 8, 0);
-        unsafe {
-            let new_ptr = old_ptr.add(alloc_size);
-            if new_ptr > end_ptr {
-                return Err(ArenaError::SizeTooSmall {
-                    to_write: layout.size(),
-                    new_total: new_ptr.offset_from(start_ptr) as usize,
-                    limit: self.layout.size(),
-                });
+        loop {
+            let current = self.current.load(Ordering::Acquire);
+            match self.segment(current).alloc(layout, alloc_size) {
+                Ok(ptr) => return Ok(ptr),
+                Err(e) if current + 1 == self.segments.len() => return Err(e),
+                Err(_) => self.grow(current, alloc_size)?,
             }
-            Ok(NonNull::new_unchecked(old_ptr))
         }
     }
+    // chains a segment after the full one at `current`.
+    fn grow(&self, current: usize, alloc_size: usize) -> Result<()> {
+        let _guard = self.grow_lock.lock();
+        if self.current.load(Ordering::Acquire) != current {
+            return Ok(());
+        }
+        let last = self.segment(current);
+        let base = last.base + last.size();
+        let size = last.size().max(alloc_size);
+        // nodes keep the offsets of keys and values in u32.
+        if base + size > u32::MAX as usize {
+            return Err(ArenaError::SizeTooSmall {
+                to_write: alloc_size,
+                new_total: base + alloc_size,
+                limit: base,
+            });
+        }
+        let segment = Segment::new(size, base)?;
+        self.segments[current + 1]
+            .store(Box::into_raw(segment), Ordering::Release);
+        self.current.store(current + 1, Ordering::Release);
+        Ok(())
+    }
     #[inline(always)]
     pub fn alloc_slice_copy<T: Copy>(&self, src: &[T]) -> Result<NonNull<T>> {
         let layout = Layout::for_value(src);
@@ -173,22 +253,23 @@ impl Arena {
         Ok(dst)
     }
     pub(crate) fn offset_slice<T>(&self, ptr: NonNull<T>) -> usize {
-        let ptr_addr = ptr.as_ptr() as usize;
-        let start_addr = self.start.as_ptr() as usize;
-        debug_assert!(ptr_addr >= start_addr);
-        ptr_addr - start_addr
+        let offset = self.offset_of(ptr.as_ptr() as *const u8);
+        debug_assert!(offset.is_some());
+        offset.unwrap_or_default()
     }
+    /// bytes allocated in all segments.
     #[inline]
     pub fn len(&self) -> usize {
-        self.ptr_offset.load(std::sync::atomic::Ordering::Relaxed)
+        self.segments().map(Segment::len).sum()
     }
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
+    /// bytes of the segments chained so far.
     #[inline(always)]
     pub fn max_size(&self) -> usize {
-        self.layout.size()
+        self.segments().map(Segment::size).sum()
     }
 
     #[inline(always)]
@@ -212,15 +293,16 @@ impl Arena {
 }
 impl Drop for Arena {
     fn drop(&mut self) {
-        unsafe {
-            // 因为在这里指向的元素是u8 实现了 Trait Copy , 所以 drop_in_place 在这里不会有任何操作,所以直接用dealloc
-            // Because the element pointed to here is u8 that implements the Trait Copy, drop_in_place does nothing here,so use dealloc
-            // ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
-            //     self.start.as_ptr(),
-            //     self.layout.size(),
-            // ));
-
-            dealloc(self.start.as_ptr(), self.layout);
+        for segment in self.segments.iter() {
+            let segment = segment.load(Ordering::Acquire);
+            if segment.is_null() {
+                continue;
+            }
+            unsafe {
+                let segment = Box::from_raw(segment);
+                // Because the element pointed to here is u8 that implements the Trait Copy, drop_in_place does nothing here,so use dealloc
+                dealloc(segment.start.as_ptr(), segment.layout);
+            }
         }
     }
 }
@@ -341,3 +423,26 @@ fn test_slice_string() {
     let k = arena.get_slice::<u8>(offset, slice.len()).unwrap();
     assert_eq!(String::from_utf8_lossy(k), s);
 }
+#[test]
+fn test_chained() {
+    let arena = Arena::chained(64, 4).unwrap();
+    let mut offsets = Vec::new();
+    for i in 0..16_usize {
+        let ptr = arena.alloc(i).unwrap();
+        offsets.push(arena.offset(ptr).unwrap());
+    }
+    assert_eq!(arena.max_size(), 128);
+    // larger than a segment, it gets one of its own.
+    let p = arena.alloc_slice_copy(&[7_u8; 100]).unwrap();
+    let slice_offset = arena.offset_slice(p);
+    assert_eq!(slice_offset, 128);
+    for (i, offset) in offsets.into_iter().enumerate() {
+        assert_eq!(*arena.get::<usize>(offset).unwrap(), i);
+    }
+    assert_eq!(arena.get_slice::<u8>(slice_offset, 100).unwrap(), &[7; 100]);
+    // slices can't cross segments.
+    assert!(arena.get_slice::<u8>(120, 16).is_err());
+    // the fourth and last segment.
+    assert!(arena.alloc_slice_copy(&[0_u8; 4096]).is_ok());
+    assert!(arena.alloc_slice_copy(&[0_u8; 4096]).is_err());
+}
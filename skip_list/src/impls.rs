@@ -17,6 +17,7 @@ type Result<T> = std::result::Result<T, SkipListError>;
 
 impl SkipListTrait for SkipList {
     type ErrorType = MorsSkipListError;
+    type Options = ();
 
     fn new(
         max_size: usize,
@@ -30,6 +31,14 @@ impl SkipListTrait for SkipList {
         })
     }
 
+    fn with_options(
+        max_size: usize,
+        cmp: fn(&[u8], &[u8]) -> std::cmp::Ordering,
+        _options: &(),
+    ) -> Result<Self> {
+        Self::new(max_size, cmp)
+    }
+
     fn size(&self) -> usize {
         self.inner.arena().len()
     }
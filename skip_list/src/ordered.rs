@@ -0,0 +1,497 @@
+//! Lists that keep their order in an index behind a lock instead of a
+//! lock-free tower of nodes. Keys and values live in an arena like the
+//! ones of [`crate::skip_list::SkipList`], the index only points at them.
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    mem::size_of,
+    ops::Bound,
+    pin::Pin,
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicUsize, Ordering as AtomicOrdering},
+        Arc,
+    },
+};
+
+use log::error;
+use mors_common::{kv::ValueMeta, ts::KeyTsBorrow};
+use mors_traits::{
+    iter::{
+        CacheIter, CacheIterator, IterError, KvCacheIter, KvCacheIterator,
+        KvSeekIter,
+    },
+    skip_list::{OptionKV, SkipListError, SkipListTrait},
+};
+use parking_lot::RwLock;
+
+use crate::{arena::Arena, error::MorsSkipListError, Result};
+
+type Cmp = fn(&[u8], &[u8]) -> Ordering;
+/// grows like the arena of the skip list.
+const ARENA_MAX_SEGMENTS: usize = 64;
+
+/// bytes allocated in the arena of an [`OrderedList`].
+#[derive(Debug, Clone, Copy)]
+pub struct Slot {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+// a slot is only read while the arena it points into is alive.
+unsafe impl Send for Slot {}
+unsafe impl Sync for Slot {}
+impl Slot {
+    const EMPTY: Slot = Slot {
+        ptr: NonNull::dangling(),
+        len: 0,
+    };
+    fn bytes<'a>(&self) -> &'a [u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+/// the ordered index of an [`OrderedList`], from key slots to value slots.
+pub trait OrderedIndex: Default + Send + Sync + 'static {
+    /// memory one entry takes in the index.
+    const ENTRY_SIZE: usize;
+    fn value_mut(&mut self, key: &[u8], cmp: Cmp) -> Option<&mut Slot>;
+    /// inserts a key which is not in the index yet.
+    fn insert(&mut self, key: Slot, value: Slot, cmp: Cmp);
+    /// the first entry with a key >= `key`, or > `key` when `exclusive`.
+    fn seek(
+        &self,
+        key: &[u8],
+        exclusive: bool,
+        cmp: Cmp,
+    ) -> Option<(Slot, Slot)>;
+    fn first(&self) -> Option<(Slot, Slot)>;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// a key slot ordered by the compare function of the list.
+struct IndexKey {
+    key: Slot,
+    cmp: Cmp,
+}
+impl PartialEq for IndexKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for IndexKey {}
+impl PartialOrd for IndexKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for IndexKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.cmp)(self.key.bytes(), other.key.bytes())
+    }
+}
+impl IndexKey {
+    // a key to look up with, not to be kept past `key`.
+    fn probe(key: &[u8], cmp: Cmp) -> Self {
+        Self {
+            key: Slot {
+                ptr: NonNull::from(key).cast(),
+                len: key.len(),
+            },
+            cmp,
+        }
+    }
+}
+
+/// a b-tree index, for point lookups and scans of random writes.
+#[derive(Default)]
+pub struct BTreeIndex(BTreeMap<IndexKey, Slot>);
+impl OrderedIndex for BTreeIndex {
+    const ENTRY_SIZE: usize = size_of::<(IndexKey, Slot)>();
+
+    fn value_mut(&mut self, key: &[u8], cmp: Cmp) -> Option<&mut Slot> {
+        self.0.get_mut(&IndexKey::probe(key, cmp))
+    }
+    fn insert(&mut self, key: Slot, value: Slot, cmp: Cmp) {
+        self.0.insert(IndexKey { key, cmp }, value);
+    }
+    fn seek(
+        &self,
+        key: &[u8],
+        exclusive: bool,
+        cmp: Cmp,
+    ) -> Option<(Slot, Slot)> {
+        let probe = IndexKey::probe(key, cmp);
+        let lower = if exclusive {
+            Bound::Excluded(&probe)
+        } else {
+            Bound::Included(&probe)
+        };
+        self.0
+            .range((lower, Bound::Unbounded))
+            .next()
+            .map(|(k, v)| (k.key, *v))
+    }
+    fn first(&self) -> Option<(Slot, Slot)> {
+        self.0.first_key_value().map(|(k, v)| (k.key, *v))
+    }
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// a sorted vector, keys written in order are appended without a search.
+#[derive(Default)]
+pub struct SortedVecIndex(Vec<(Slot, Slot)>);
+impl SortedVecIndex {
+    fn position(&self, key: &[u8], exclusive: bool, cmp: Cmp) -> usize {
+        self.0.partition_point(|(k, _)| match cmp(k.bytes(), key) {
+            Ordering::Less => true,
+            Ordering::Equal => exclusive,
+            Ordering::Greater => false,
+        })
+    }
+}
+impl OrderedIndex for SortedVecIndex {
+    const ENTRY_SIZE: usize = size_of::<(Slot, Slot)>();
+
+    fn value_mut(&mut self, key: &[u8], cmp: Cmp) -> Option<&mut Slot> {
+        let index = match self.0.last() {
+            Some((last, _)) if cmp(last.bytes(), key) == Ordering::Less => {
+                return None
+            }
+            _ => self.position(key, false, cmp),
+        };
+        self.0
+            .get_mut(index)
+            .filter(|(k, _)| cmp(k.bytes(), key) == Ordering::Equal)
+            .map(|(_, v)| v)
+    }
+    fn insert(&mut self, key: Slot, value: Slot, cmp: Cmp) {
+        match self.0.last() {
+            Some((last, _))
+                if cmp(last.bytes(), key.bytes()) != Ordering::Less =>
+            {
+                let index = self.position(key.bytes(), false, cmp);
+                self.0.insert(index, (key, value));
+            }
+            _ => self.0.push((key, value)),
+        }
+    }
+    fn seek(
+        &self,
+        key: &[u8],
+        exclusive: bool,
+        cmp: Cmp,
+    ) -> Option<(Slot, Slot)> {
+        self.0.get(self.position(key, exclusive, cmp)).copied()
+    }
+    fn first(&self) -> Option<(Slot, Slot)> {
+        self.0.first().copied()
+    }
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+pub type BTreeList = OrderedList<BTreeIndex>;
+pub type SortedVecList = OrderedList<SortedVecIndex>;
+
+pub struct OrderedList<I: OrderedIndex> {
+    pub(crate) inner: Arc<OrderedListInner<I>>,
+}
+impl<I: OrderedIndex> Clone for OrderedList<I> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+pub(crate) struct OrderedListInner<I: OrderedIndex> {
+    ///the index of the list, entries point into the arena
+    index: RwLock<I>,
+    ///the number of entries in the index
+    len: AtomicUsize,
+    ///the memory pool of the list
+    arena: Pin<Box<Arena>>,
+    ///the compare function of the list
+    cmp: Cmp,
+}
+impl<I: OrderedIndex> OrderedListInner<I> {
+    fn new(max_size: usize, cmp: Cmp) -> Result<Self> {
+        Ok(Self {
+            index: RwLock::new(I::default()),
+            len: AtomicUsize::new(0),
+            arena: Arena::chained(max_size, ARENA_MAX_SEGMENTS)?,
+            cmp,
+        })
+    }
+    fn alloc(&self, data: &[u8]) -> Result<Slot> {
+        if data.is_empty() {
+            return Ok(Slot::EMPTY);
+        }
+        Ok(Slot {
+            ptr: self.arena.alloc_slice_copy(data)?,
+            len: data.len(),
+        })
+    }
+    // slots stay valid as long as the arena, that is as long as `self`.
+    fn key(&self, slot: Slot) -> &[u8] {
+        slot.bytes()
+    }
+    fn value(&self, slot: Slot) -> Option<&[u8]> {
+        if slot.len == 0 {
+            return None;
+        }
+        Some(slot.bytes())
+    }
+    fn push(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let value = self.alloc(value)?;
+        let mut index = self.index.write();
+        if let Some(slot) = index.value_mut(key, self.cmp) {
+            *slot = value;
+            return Ok(());
+        }
+        let key = self.alloc(key)?;
+        index.insert(key, value, self.cmp);
+        self.len.fetch_add(1, AtomicOrdering::Relaxed);
+        Ok(())
+    }
+    fn find_or_next(
+        &self,
+        key: &[u8],
+        allow_near: bool,
+    ) -> Option<(Slot, Slot)> {
+        let (k, v) = self.index.read().seek(key, false, self.cmp)?;
+        if allow_near || (self.cmp)(self.key(k), key) == Ordering::Equal {
+            return Some((k, v));
+        }
+        None
+    }
+    fn size(&self) -> usize {
+        self.arena.len()
+            + self.len.load(AtomicOrdering::Relaxed) * I::ENTRY_SIZE
+    }
+}
+
+impl<I: OrderedIndex> SkipListTrait for OrderedList<I> {
+    type ErrorType = MorsSkipListError;
+    type Options = ();
+
+    fn new(
+        max_size: usize,
+        cmp: Cmp,
+    ) -> std::result::Result<Self, SkipListError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            inner: Arc::new(OrderedListInner::new(max_size, cmp)?),
+        })
+    }
+
+    fn with_options(
+        max_size: usize,
+        cmp: Cmp,
+        _options: &(),
+    ) -> std::result::Result<Self, SkipListError> {
+        Self::new(max_size, cmp)
+    }
+
+    fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn push(
+        &self,
+        key: &[u8],
+        value: &[u8],
+    ) -> std::result::Result<(), SkipListError> {
+        Ok(self.inner.push(key, value)?)
+    }
+
+    fn get(
+        &self,
+        key: &[u8],
+    ) -> std::result::Result<Option<&[u8]>, SkipListError> {
+        Ok(self
+            .inner
+            .find_or_next(key, false)
+            .and_then(|(_, v)| self.inner.value(v)))
+    }
+
+    fn get_or_next(
+        &self,
+        key: &[u8],
+    ) -> std::result::Result<Option<&[u8]>, SkipListError> {
+        Ok(self
+            .inner
+            .find_or_next(key, true)
+            .and_then(|(_, v)| self.inner.value(v)))
+    }
+
+    fn get_key_value(
+        &self,
+        key: &[u8],
+        allow_next: bool,
+    ) -> std::result::Result<OptionKV<'_>, SkipListError> {
+        Ok(self
+            .inner
+            .find_or_next(key, allow_next)
+            .map(|(k, v)| (self.inner.key(k), self.inner.value(v))))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.inner.index.read().is_empty()
+    }
+
+    // the index has no levels to speak of.
+    fn height(&self) -> usize {
+        1
+    }
+
+    fn iter(&self) -> impl KvCacheIterator<ValueMeta> {
+        OrderedListIter::new(&self.inner)
+    }
+
+    const MAX_NODE_SIZE: usize = I::ENTRY_SIZE;
+}
+
+pub struct OrderedListIter<'a, I: OrderedIndex> {
+    inner: &'a OrderedListInner<I>,
+    entry: Option<(Slot, Slot)>,
+}
+impl<'a, I: OrderedIndex> OrderedListIter<'a, I> {
+    pub(crate) fn new(inner: &'a OrderedListInner<I>) -> Self {
+        Self { inner, entry: None }
+    }
+}
+impl<I: OrderedIndex> CacheIter for OrderedListIter<'_, I> {
+    type Item = (Slot, Slot);
+
+    fn item(&self) -> Option<&Self::Item> {
+        self.entry.as_ref()
+    }
+}
+impl<I: OrderedIndex> CacheIterator for OrderedListIter<'_, I> {
+    // the index may change between steps, each one seeks past the key
+    // it stands on.
+    fn next(&mut self) -> std::result::Result<bool, IterError> {
+        let next = {
+            let index = self.inner.index.read();
+            match self.entry {
+                Some((k, _)) => {
+                    index.seek(self.inner.key(k), true, self.inner.cmp)
+                }
+                None => index.first(),
+            }
+        };
+        match next {
+            Some(entry) => {
+                self.entry = Some(entry);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+impl<I: OrderedIndex> KvSeekIter for OrderedListIter<'_, I> {
+    fn seek(
+        &mut self,
+        k: KeyTsBorrow<'_>,
+    ) -> std::result::Result<bool, IterError> {
+        if let Some(entry) = self.inner.find_or_next(&k, true) {
+            self.entry = Some(entry);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+}
+impl<I: OrderedIndex> KvCacheIter<ValueMeta> for OrderedListIter<'_, I> {
+    fn key(&self) -> Option<KeyTsBorrow<'_>> {
+        self.item().map(|(k, _)| self.inner.key(*k).into())
+    }
+
+    fn value(&self) -> Option<ValueMeta> {
+        self.item().and_then(|(_, v)| {
+            let value = self.inner.value(*v)?;
+            let meta = ValueMeta::decode(value);
+            if meta.is_none() {
+                error!("OrderedListIter::value() undecodable value");
+            }
+            meta
+        })
+    }
+}
+impl<I: OrderedIndex> KvCacheIterator<ValueMeta> for OrderedListIter<'_, I> {}
+
+#[cfg(test)]
+mod tests {
+    use mors_common::ts::KeyTsBorrow;
+    use mors_traits::{
+        iter::{CacheIterator, KvCacheIter, KvSeekIter},
+        skip_list::SkipListTrait,
+    };
+
+    use super::{BTreeList, OrderedIndex, OrderedList, SortedVecList};
+
+    fn check_list<I: OrderedIndex>() {
+        let list = OrderedList::<I>::new(1 << 10, KeyTsBorrow::cmp).unwrap();
+        assert!(list.is_empty());
+        let key = |i: u64| {
+            let mut k = format!("key{:04}", i).into_bytes();
+            k.extend_from_slice(&0u64.to_be_bytes());
+            k
+        };
+        // out of order, with overwrites and more than the arena holds.
+        for i in (0..500).rev().filter(|i| i % 2 == 0) {
+            list.push(&key(i), b"even").unwrap();
+        }
+        for i in (0..500).filter(|i| i % 3 == 0) {
+            list.push(&key(i), b"third").unwrap();
+        }
+        for i in 0..500 {
+            let expected = match (i % 2, i % 3) {
+                (_, 0) => Some(b"third".as_slice()),
+                (0, _) => Some(b"even".as_slice()),
+                _ => None,
+            };
+            assert_eq!(list.get(&key(i)).unwrap(), expected);
+        }
+        list.push(&key(1000), b"").unwrap();
+        assert!(list.get(&key(1000)).unwrap().is_none());
+        let (k, _) = list.get_key_value(&key(1), true).unwrap().unwrap();
+        assert_eq!(k, key(2));
+        assert!(list.get_key_value(&key(1), false).unwrap().is_none());
+
+        let mut iter = list.iter();
+        let mut count = 0;
+        let mut last: Option<Vec<u8>> = None;
+        while iter.next().unwrap() {
+            let k = iter.key().unwrap().to_vec();
+            if let Some(last) = last {
+                assert!(KeyTsBorrow::cmp(&last, &k).is_lt());
+            }
+            last = Some(k);
+            count += 1;
+        }
+        assert_eq!(count, 334);
+        assert!(iter.seek(key(250).as_slice().into()).unwrap());
+        assert_eq!(iter.key().unwrap().to_vec(), key(250));
+        assert!(iter.next().unwrap());
+        assert_eq!(iter.key().unwrap().to_vec(), key(252));
+    }
+    #[test]
+    fn test_btree_list() {
+        check_list::<super::BTreeIndex>();
+        let _ = BTreeList::new(1 << 10, KeyTsBorrow::cmp).unwrap();
+    }
+    #[test]
+    fn test_sorted_vec_list() {
+        check_list::<super::SortedVecIndex>();
+        let _ = SortedVecList::new(1 << 10, KeyTsBorrow::cmp).unwrap();
+    }
+}
@@ -19,6 +19,9 @@ use crate::{arena, error::MorsSkipListError};
 ///3 <head> ----------> [2] --------------------------------------------------> [9] ---------->  
 
 const SKL_MAX_HEIGHT: usize = 20; //<20 !=20
+/// the arena chains segments of `max_size` bytes once the first is full,
+/// a batch larger than planned for never fails to be inserted.
+const ARENA_MAX_SEGMENTS: usize = 64;
 unsafe impl Send for SkipListInner {}
 unsafe impl Sync for SkipListInner {}
 #[derive(Clone)]
@@ -43,7 +46,7 @@ impl SkipListInner {
     where
         Self: Sized,
     {
-        let arena = Arena::new(max_size)?;
+        let arena = Arena::chained(max_size, ARENA_MAX_SEGMENTS)?;
         arena.alloc(0u8)?;
         let head: &mut Node = arena.alloc_with(Node::default)?;
         head.set_height(SKL_MAX_HEIGHT as u16);
@@ -665,4 +668,17 @@ mod tests {
 
         assert!(list.find_last().is_none());
     }
+    #[test]
+    fn test_arena_growth() {
+        // far more than the first arena segment holds.
+        let list = SkipListInner::new(16 << 10, |a, b| a.cmp(b)).unwrap();
+        let value = [7_u8; 512];
+        for i in 0..1000_u32 {
+            list.push(&i.to_be_bytes(), &value).unwrap();
+        }
+        assert!(list.size() > 1000 * value.len());
+        for i in 0..1000_u32 {
+            assert_eq!(list.get(&i.to_be_bytes()).unwrap(), Some(&value[..]));
+        }
+    }
 }
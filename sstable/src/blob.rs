@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Weak,
+    },
+};
+
+use bytes::Bytes;
+use log::{debug, error};
+use memmap2::Advice;
+use mors_common::{
+    file_id::{BlobFileId, FileId},
+    kv::ValuePointer,
+};
+use mors_traits::{
+    file::{StorageBuilderTrait, StorageTrait},
+    kms::{CipherKeyId, KmsCipher},
+};
+use mors_wal::storage::mmap::{MmapFile, MmapFileBuilder};
+use parking_lot::RwLock;
+
+use crate::{error::MorsTableError, Result};
+
+// +-------------+-------+-------+-------+-------+
+// | data key id | value | crc32 | value | crc32 |
+// +-------------+-------+-------+-------+-------+
+// values are encrypted one by one with the data key, the default key id
+// stands for plaintext.
+const HEADER_SIZE: usize = 8;
+const CRC_SIZE: usize = 4;
+
+/// values a table writer moves out of its table, written next to it once
+/// the table is done. The file is never changed afterwards.
+pub(crate) struct BlobWriter<K: KmsCipher> {
+    id: BlobFileId,
+    data: Vec<u8>,
+    cipher: Option<Arc<K>>,
+}
+impl<K: KmsCipher> BlobWriter<K> {
+    pub(crate) fn new(id: BlobFileId, cipher: Option<Arc<K>>) -> Self {
+        let key_id = cipher
+            .as_ref()
+            .map(|c| c.cipher_key_id())
+            .unwrap_or_default();
+        Self {
+            id,
+            data: u64::from(key_id).to_be_bytes().to_vec(),
+            cipher,
+        }
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.len() == HEADER_SIZE
+    }
+    pub(crate) fn push(&mut self, value: &[u8]) -> Result<ValuePointer> {
+        let data = match &self.cipher {
+            Some(c) => c.encrypt(value)?,
+            None => value.to_vec(),
+        };
+        let vp = ValuePointer::new(
+            self.id,
+            data.len() as u32,
+            self.data.len() as u64,
+        );
+        self.data.extend_from_slice(&data);
+        self.data
+            .extend_from_slice(&crc32fast::hash(&data).to_be_bytes());
+        Ok(vp)
+    }
+    pub(crate) fn write(&self, dir: &Path) -> Result<()> {
+        let path = self.id.join_dir(dir);
+        let mut builder = MmapFileBuilder::new();
+        builder.advice(Advice::Sequential);
+        builder.create_new(true).append(true).read(true);
+        debug!("write {} bytes to blob file: {:?} ", self.data.len(), path);
+        let mut mmap = builder.build(path, 2 * self.data.len() as u64)?;
+        mmap.append(&self.data, Ordering::Relaxed)?;
+        mmap.flush_range(0, self.data.len())?;
+        mmap.set_len(self.data.len() as u64)?;
+        mmap.sync_all()?;
+        Ok(())
+    }
+}
+#[derive(Debug)]
+pub(crate) struct BlobFile {
+    mmap: MmapFile,
+    cipher_key_id: CipherKeyId,
+}
+impl BlobFile {
+    fn open(path: PathBuf, read_only: bool) -> Result<Self> {
+        let mut builder = MmapFileBuilder::new();
+        builder.advice(Advice::Random);
+        builder.read(true).write(!read_only);
+        let mmap = builder.build(path, 0)?;
+        if (mmap.file_len()? as usize) < HEADER_SIZE {
+            return Err(MorsTableError::InvalidBlobFile);
+        }
+        let mut header = [0; HEADER_SIZE];
+        header.copy_from_slice(mmap.pread_ref(0, HEADER_SIZE));
+        Ok(Self {
+            mmap,
+            cipher_key_id: u64::from_be_bytes(header).into(),
+        })
+    }
+    fn read<K: KmsCipher>(
+        &self,
+        vp: &ValuePointer,
+        cipher: Option<&K>,
+    ) -> Result<Bytes> {
+        let offset = vp.offset() as usize;
+        let size = vp.size() as usize;
+        if offset < HEADER_SIZE
+            || offset + size + CRC_SIZE > self.mmap.file_len()? as usize
+        {
+            return Err(MorsTableError::InvalidBlob(vp.fid(), vp.offset()));
+        }
+        let data = self.mmap.pread_ref(offset, size);
+        let mut crc = [0; CRC_SIZE];
+        crc.copy_from_slice(self.mmap.pread_ref(offset + size, CRC_SIZE));
+        if crc32fast::hash(data) != u32::from_be_bytes(crc) {
+            return Err(MorsTableError::InvalidBlob(vp.fid(), vp.offset()));
+        }
+        match cipher {
+            Some(c) => Ok(c.decrypt(data)?.into()),
+            None => Ok(Bytes::copy_from_slice(data)),
+        }
+    }
+}
+/// a table's hold on a blob file it points into, a deleted blob file is
+/// unlinked once the last table holding it is dropped.
+#[derive(Debug)]
+pub(crate) struct BlobRef {
+    path: PathBuf,
+    deleted: AtomicBool,
+    files: BlobFiles,
+}
+impl Drop for BlobRef {
+    fn drop(&mut self) {
+        {
+            let mut inner = self.files.0.write();
+            if inner
+                .refs
+                .get(&self.path)
+                .is_some_and(|blob| blob.strong_count() == 0)
+            {
+                inner.refs.remove(&self.path);
+            }
+        }
+        if self.deleted.load(Ordering::Acquire) {
+            if let Err(e) = self.files.unlink(&self.path) {
+                error!("Delete blob file {:?} error: {}", self.path, e);
+            }
+        }
+    }
+}
+#[derive(Debug, Default)]
+struct BlobFilesInner {
+    files: HashMap<PathBuf, Arc<BlobFile>>,
+    refs: HashMap<PathBuf, Weak<BlobRef>>,
+}
+/// blob files opened so far by path, shared by the clones of a table
+/// builder, which may be set to other dirs.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct BlobFiles(Arc<RwLock<BlobFilesInner>>);
+impl BlobFiles {
+    fn get(
+        &self,
+        dir: &Path,
+        read_only: bool,
+        id: BlobFileId,
+    ) -> Result<Arc<BlobFile>> {
+        let path = id.join_dir(dir);
+        if let Some(file) = self.0.read().files.get(&path) {
+            return Ok(file.clone());
+        }
+        let mut inner = self.0.write();
+        if let Some(file) = inner.files.get(&path) {
+            return Ok(file.clone());
+        }
+        let file = Arc::new(BlobFile::open(path.clone(), read_only)?);
+        inner.files.insert(path, file.clone());
+        Ok(file)
+    }
+    /// the hold of a table pointing into the blob file `id`.
+    pub(crate) fn hold(&self, dir: &Path, id: BlobFileId) -> Arc<BlobRef> {
+        let path = id.join_dir(dir);
+        let mut inner = self.0.write();
+        if let Some(blob) = inner.refs.get(&path).and_then(Weak::upgrade) {
+            return blob;
+        }
+        let blob = Arc::new(BlobRef {
+            path: path.clone(),
+            deleted: AtomicBool::new(false),
+            files: self.clone(),
+        });
+        inner.refs.insert(path, Arc::downgrade(&blob));
+        blob
+    }
+    pub(crate) fn cipher_key_id(
+        &self,
+        dir: &Path,
+        read_only: bool,
+        id: BlobFileId,
+    ) -> Result<CipherKeyId> {
+        Ok(self.get(dir, read_only, id)?.cipher_key_id)
+    }
+    pub(crate) fn read<K: KmsCipher>(
+        &self,
+        dir: &Path,
+        read_only: bool,
+        vp: &ValuePointer,
+        cipher: Option<&K>,
+    ) -> Result<Bytes> {
+        self.get(dir, read_only, vp.fid().into())?.read(vp, cipher)
+    }
+    /// deletes the blob file `id`, it is unlinked when the last table
+    /// holding it is dropped, right away if none does.
+    pub(crate) fn delete(&self, dir: &Path, id: BlobFileId) -> Result<()> {
+        let path = id.join_dir(dir);
+        let held = self.0.read().refs.get(&path).and_then(Weak::upgrade);
+        match held {
+            Some(blob) => blob.deleted.store(true, Ordering::Release),
+            None => self.unlink(&path)?,
+        }
+        Ok(())
+    }
+    fn unlink(&self, path: &Path) -> Result<()> {
+        let file = self.0.write().files.remove(path);
+        match file {
+            Some(file) => file.mmap.delete()?,
+            None => std::fs::remove_file(path)?,
+        }
+        Ok(())
+    }
+}
@@ -6,9 +6,11 @@ use bytes::Buf;
 use mors_common::{file_id::SSTableId, util::BufExt};
 use mors_traits::sstable::{BlockIndex, BlockTrait};
 use prost::Message;
-use read::CacheBlockIter;
+use read::{BlockEntryHeader, CacheBlockIter};
 
-use crate::{error::MorsTableError, pb::proto::Checksum, Result};
+use crate::{
+    error::MorsTableError, pb::proto::Checksum, Result, TableFormat,
+};
 #[derive(Default, Clone)]
 pub struct Block(Arc<BlockInner>);
 #[derive(Default)]
@@ -16,9 +18,11 @@ struct BlockInner {
     table_id: SSTableId,
     block_index: BlockIndex,
     block_offset: u32,
-    data: Vec<u8>, //actual data + entry_offsets+num_entries;
+    data: Vec<u8>, //actual data + entry_offsets+num_entries+restarts+num;
     entries_index_start: usize,
     entry_offsets: Vec<u32>,
+    restarts: Vec<u32>,
+    format: TableFormat,
     checksum: Vec<u8>,
     checksum_len: usize,
 }
@@ -28,6 +32,7 @@ impl Block {
         block_index: BlockIndex,
         block_offset: u32,
         mut data: Vec<u8>,
+        format: TableFormat,
     ) -> Result<Self> {
         //read checksum len
         let mut read_pos = data.len() - 4;
@@ -43,6 +48,19 @@ impl Block {
         let checksum = data[read_pos..read_pos + checksum_len].to_vec();
         data.truncate(read_pos);
 
+        //read restarts, the entries holding a full key
+        let restarts = match format {
+            TableFormat::V0 => Vec::new(),
+            TableFormat::V1 | TableFormat::V2 => {
+                read_pos -= 4;
+                let mut num_restarts = &data[read_pos..read_pos + 4];
+                let num_restarts = num_restarts.get_u32() as usize;
+                read_pos -= num_restarts * 4;
+                let mut restarts = &data[read_pos..read_pos + num_restarts * 4];
+                restarts.get_vec_u32()
+            }
+        };
+
         //read num entries
         read_pos -= 4;
         let mut num_entries = &data[read_pos..read_pos + 4];
@@ -59,6 +77,8 @@ impl Block {
             data,
             entries_index_start,
             entry_offsets,
+            restarts,
+            format,
             checksum,
             checksum_len,
         })))
@@ -86,6 +106,47 @@ impl Block {
     pub(crate) fn entries_index_start(&self) -> usize {
         self.0.entries_index_start
     }
+    pub(crate) fn restarts(&self) -> &[u32] {
+        &self.0.restarts
+    }
+    pub(crate) fn format(&self) -> TableFormat {
+        self.0.format
+    }
+    /// header and key suffix of the entry `entry_index`.
+    pub(crate) fn entry(
+        &self,
+        entry_index: usize,
+    ) -> (BlockEntryHeader, &[u8]) {
+        let data = &self.0.data[self.0.entry_offsets[entry_index] as usize..];
+        let header =
+            BlockEntryHeader::decode(&data[..BlockEntryHeader::HEADER_SIZE]);
+        let diff = &data[BlockEntryHeader::HEADER_SIZE
+            ..BlockEntryHeader::HEADER_SIZE + header.diff() as usize];
+        (header, diff)
+    }
+    /// writes the full key of the entry `entry_index` to `key`.
+    pub(crate) fn key_at(&self, entry_index: usize, key: &mut Vec<u8>) {
+        key.clear();
+        match self.0.format {
+            TableFormat::V0 => {
+                let (header, diff) = self.entry(entry_index);
+                let (_, base_key) = self.entry(0);
+                key.extend_from_slice(&base_key[..header.overlap() as usize]);
+                key.extend_from_slice(diff);
+            }
+            TableFormat::V1 | TableFormat::V2 => {
+                let restart = self
+                    .0
+                    .restarts
+                    .partition_point(|r| *r as usize <= entry_index);
+                for i in self.0.restarts[restart - 1] as usize..=entry_index {
+                    let (header, diff) = self.entry(i);
+                    key.truncate(header.overlap() as usize);
+                    key.extend_from_slice(diff);
+                }
+            }
+        }
+    }
     pub(crate) fn checksum(&self) -> &[u8] {
         &self.0.checksum
     }
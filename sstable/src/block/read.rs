@@ -1,3 +1,5 @@
+use std::cmp::Ordering;
+
 use bytes::{Buf, BufMut};
 use mors_common::{kv::ValueMeta, ts::KeyTsBorrow};
 use mors_traits::{
@@ -9,7 +11,7 @@ use mors_traits::{
     sstable::BlockIndex,
 };
 
-use crate::block::Block;
+use crate::{block::Block, TableFormat};
 #[derive(Default)]
 pub(crate) struct BlockEntryHeader {
     overlap: u16,
@@ -32,6 +34,12 @@ impl BlockEntryHeader {
         let diff = buf.get_u16();
         Self { overlap, diff }
     }
+    pub(crate) fn overlap(&self) -> u16 {
+        self.overlap
+    }
+    pub(crate) fn diff(&self) -> u16 {
+        self.diff
+    }
 }
 #[derive(Default)]
 pub struct CacheBlockIter {
@@ -55,6 +63,18 @@ impl From<Block> for CacheBlockIter {
 }
 impl CacheBlockIter {
     fn set_entry_index(&mut self, entry_index: usize) {
+        if self.inner.format() >= TableFormat::V1 {
+            let (next_header, diff) = self.inner.entry(entry_index);
+            if self.entry_index.map(|i| i + 1) == Some(entry_index) {
+                self.key.truncate(next_header.overlap as usize);
+                self.key.extend_from_slice(diff);
+            } else {
+                self.inner.key_at(entry_index, &mut self.key);
+            }
+            self.entry_index = Some(entry_index);
+            self.header = next_header;
+            return;
+        }
         self.entry_index = Some(entry_index);
         let entry_offset = self.inner.entry_offsets()[entry_index] as usize;
         let data = &self.inner.data()[entry_offset..];
@@ -75,9 +95,34 @@ impl CacheBlockIter {
         );
         self.header = next_header;
     }
+    fn set_back_entry_index(&mut self, back_entry_index: usize) {
+        let (header, _) = self.inner.entry(back_entry_index);
+        self.inner.key_at(back_entry_index, &mut self.back_key);
+        self.back_header = header;
+        self.back_entry_index = Some(back_entry_index);
+    }
     pub(crate) fn block_index(&self) -> BlockIndex {
         self.inner.block_index()
     }
+    // binary searches the full keys at restart points, then scans the
+    // entries after the last one smaller than `k`.
+    fn seek_restarts(&mut self, k: KeyTsBorrow<'_>) -> Result<bool, IterError> {
+        let restarts = self.inner.restarts();
+        let restart = restarts.partition_point(|r| {
+            let (_, key) = self.inner.entry(*r as usize);
+            KeyTsBorrow::cmp(key, &k) == Ordering::Less
+        });
+        let mut entry_index = restarts[restart.saturating_sub(1)] as usize;
+        self.set_entry_index(entry_index);
+        while KeyTsBorrow::cmp(&self.key, &k) == Ordering::Less {
+            if entry_index + 1 == self.inner.entry_offsets().len() {
+                return Ok(false);
+            }
+            entry_index += 1;
+            self.set_entry_index(entry_index);
+        }
+        Ok(true)
+    }
 }
 impl CacheIter for CacheBlockIter {
     type Item = usize;
@@ -149,6 +194,10 @@ impl DoubleEndedCacheIterator for CacheBlockIter {
                     }
                 }
 
+                if self.inner.format() >= TableFormat::V1 {
+                    self.set_back_entry_index(back_id - 1);
+                    return Ok(true);
+                }
                 self.back_entry_index = Some(back_id - 1);
                 let next_back_entry_offset =
                     self.inner.entry_offsets()[back_id - 1] as usize;
@@ -192,6 +241,12 @@ impl DoubleEndedCacheIterator for CacheBlockIter {
                     self.header = header;
                 }
 
+                if self.inner.format() >= TableFormat::V1 {
+                    self.set_back_entry_index(
+                        self.inner.entry_offsets().len() - 1,
+                    );
+                    return Ok(true);
+                }
                 let last_offset =
                     *self.inner.entry_offsets().last().unwrap() as usize;
                 let data = &self.inner.data()[last_offset..];
@@ -270,6 +325,9 @@ impl KvSeekIter for CacheBlockIter {
         if self.entry_index.is_none() && !self.next()? {
             return Ok(false);
         }
+        if self.inner.format() >= TableFormat::V1 {
+            return self.seek_restarts(k);
+        }
 
         let search = self.inner.entry_offsets().binary_search_by(|offset| {
             let entry_offset = *offset as usize;
@@ -11,19 +11,29 @@ const BLOCK_PADDING: usize = 256;
 pub(crate) struct BlockWriter {
     data: Vec<u8>,
     base_keyts: Vec<u8>,
+    last_keyts: Vec<u8>,
     entry_offsets: Vec<u32>,
+    restart_interval: usize,
+    // indexes of the entries holding a full key.
+    restarts: Vec<u32>,
 }
 impl BlockWriter {
-    pub(crate) fn new(block_size: usize) -> Self {
+    pub(crate) fn new(block_size: usize, restart_interval: usize) -> Self {
         Self {
             data: Vec::with_capacity(block_size + BLOCK_PADDING),
             base_keyts: Default::default(),
+            last_keyts: Default::default(),
             entry_offsets: Default::default(),
+            restart_interval: restart_interval.max(1),
+            restarts: Default::default(),
         }
     }
     pub(crate) fn entry_offsets(&self) -> &[u32] {
         &self.entry_offsets
     }
+    pub(crate) fn restarts(&self) -> &[u32] {
+        &self.restarts
+    }
     pub(crate) fn data(&self) -> &[u8] {
         &self.data
     }
@@ -33,11 +43,11 @@ impl BlockWriter {
     pub(crate) fn base_keyts(&self) -> &[u8] {
         &self.base_keyts
     }
-    fn diff_base_key(&self, new_key: &[u8]) -> usize {
+    fn diff_last_key(&self, new_key: &[u8]) -> usize {
         let mut i = 0;
-        while i < self.base_keyts.len()
+        while i < self.last_keyts.len()
             && i < new_key.len()
-            && self.base_keyts[i] == new_key[i]
+            && self.last_keyts[i] == new_key[i]
         {
             i += 1;
         }
@@ -56,6 +66,8 @@ impl BlockWriter {
         debug_assert!((self.entry_offsets.len() as u32 + 1) * 4 + 4 + 8 + 4 < u32::MAX);
         let entries_offsets_size = (self.entry_offsets.len() + 1) * 4 
         + 4 //size of list
+        + (self.restarts.len() + 1) * 4
+        + 4 //size of restarts
         + 8 //sum64 in checksum proto
         + 4; //checksum length
         let mut estimate_size=self.data.len()+6+key.as_ref().len()+ value.encoded_size() + entries_offsets_size;
@@ -66,11 +78,14 @@ impl BlockWriter {
         estimate_size > block_size
     }
     pub(crate)  fn push_entry(&mut self,key_ts: &KeyTsBorrow,value: &ValueMeta){
-        let diff_key=if self.base_keyts.is_empty() {
+        if self.base_keyts.is_empty() {
             self.base_keyts=key_ts.to_vec();
+        }
+        let diff_key=if self.entry_offsets.len().is_multiple_of(self.restart_interval) {
+            self.restarts.push(self.entry_offsets.len() as u32);
             key_ts
         }else{
-            &key_ts[self.diff_base_key(key_ts)..]
+            &key_ts[self.diff_last_key(key_ts)..]
         };
         assert!(key_ts.len()-diff_key.len() <= u16::MAX as usize);
         assert!(diff_key.len() <= u16::MAX as usize);
@@ -79,11 +94,14 @@ impl BlockWriter {
         self.data.extend_from_slice(&entry_header.encode());
         self.data.extend_from_slice(diff_key);
         self.data.extend_from_slice(value.encode().as_ref());
-        
+        self.last_keyts.clear();
+        self.last_keyts.extend_from_slice(key_ts);
     }
     pub(crate) fn finish_block(&mut self,algo:Algorithm){
         self.data.extend_from_slice(&self.entry_offsets.encode());
         self.data.put_u32(self.entry_offsets.len() as u32);
+        self.data.extend_from_slice(&self.restarts.encode());
+        self.data.put_u32(self.restarts.len() as u32);
 
         let checksum = Checksum::new(algo, &self.data);
         self.data.extend_from_slice(&checksum.encode_to_vec());
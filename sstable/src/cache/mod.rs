@@ -12,7 +12,7 @@ use mors_common::file_id::SSTableId;
 use mors_traits::cache::{BlockCacheKey, CacheBuilder, CacheTrait};
 
 use crate::block::Block;
-use crate::table_index::TableIndexBuf;
+use crate::table_index::{IndexPartitionBuf, TableIndexBuf};
 type Result<T> = std::result::Result<T, MorsCacheError>;
 mod error;
 
@@ -20,6 +20,7 @@ mod error;
 pub struct Cache {
     block_cache: Option<MokaCache<BlockCacheKey, Block>>,
     index_cache: MokaCache<SSTableId, TableIndexBuf>,
+    partition_cache: MokaCache<BlockCacheKey, IndexPartitionBuf>,
 }
 impl Cache {
     #[cfg(not(feature = "sync"))]
@@ -65,6 +66,36 @@ impl Cache {
     pub(crate) fn insert_index(&self, key: SSTableId, index: TableIndexBuf) {
         self.index_cache.insert(key, index);
     }
+    #[cfg(not(feature = "sync"))]
+    pub(crate) async fn get_partition(
+        &self,
+        key: &BlockCacheKey,
+    ) -> Option<IndexPartitionBuf> {
+        self.partition_cache.get(key).await
+    }
+    #[cfg(feature = "sync")]
+    pub(crate) fn get_partition(
+        &self,
+        key: &BlockCacheKey,
+    ) -> Option<IndexPartitionBuf> {
+        self.partition_cache.get(key)
+    }
+    #[cfg(not(feature = "sync"))]
+    pub(crate) async fn insert_partition(
+        &self,
+        key: BlockCacheKey,
+        partition: IndexPartitionBuf,
+    ) {
+        self.partition_cache.insert(key, partition).await;
+    }
+    #[cfg(feature = "sync")]
+    pub(crate) fn insert_partition(
+        &self,
+        key: BlockCacheKey,
+        partition: IndexPartitionBuf,
+    ) {
+        self.partition_cache.insert(key, partition);
+    }
 }
 impl CacheTrait for Cache {
     type ErrorType = MorsCacheError;
@@ -76,6 +107,7 @@ pub struct MorsCacheBuilder {
     block_size: usize,
     index_cache_size: usize,
     index_size: usize,
+    index_partition_size: usize,
 }
 
 const DEFAULT_INDEX_SIZE: usize = ((64 << 20) as f64 * 0.05) as usize;
@@ -84,6 +116,7 @@ impl Default for MorsCacheBuilder {
         Self {
             index_cache_size: 16 << 20,
             index_size: DEFAULT_INDEX_SIZE,
+            index_partition_size: 4 * 1024,
             block_size: 4 * 1024,
             block_cache_size: 256 << 20,
         }
@@ -95,6 +128,11 @@ impl CacheBuilder<Cache> for MorsCacheBuilder {
         let index_cache = MokaCacheBuilder::new(num_in_cache as u64)
             .initial_capacity(num_in_cache / 2)
             .build();
+        let num_in_cache =
+            (self.index_cache_size / self.index_partition_size).max(1);
+        let partition_cache = MokaCacheBuilder::new(num_in_cache as u64)
+            .initial_capacity(num_in_cache / 2)
+            .build();
         if self.block_cache_size > 0 {
             let num_in_cache = (self.block_cache_size / self.block_size).max(1);
             let block_cache = MokaCacheBuilder::new(num_in_cache as u64)
@@ -103,11 +141,13 @@ impl CacheBuilder<Cache> for MorsCacheBuilder {
             Ok(Cache {
                 block_cache: Some(block_cache),
                 index_cache,
+                partition_cache,
             })
         } else {
             Ok(Cache {
                 block_cache: None,
                 index_cache,
+                partition_cache,
             })
         }
     }
@@ -120,6 +160,11 @@ impl MorsCacheBuilder {
     pub fn set_index_size(&mut self, index_size: usize) {
         self.index_size = index_size;
     }
+    /// expected size of the partitions of partitioned indexes, which are
+    /// cached apart from whole indexes with a budget of `index_cache_size`.
+    pub fn set_index_partition_size(&mut self, index_partition_size: usize) {
+        self.index_partition_size = index_partition_size;
+    }
     pub fn set_block_cache_size(&mut self, block_cache_size: usize) {
         self.block_cache_size = block_cache_size;
     }
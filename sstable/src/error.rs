@@ -1,3 +1,4 @@
+use bytes::Bytes;
 use mors_common::compress::CompressError;
 use mors_traits::{iter::IterError, kms::EncryptError, sstable::SSTableError};
 use prost::DecodeError;
@@ -30,6 +31,18 @@ pub enum MorsTableError {
     JoinError(#[from] tokio::task::JoinError),
     #[error("SSTableError: {0}")]
     SSTableError(#[from] SSTableError),
+    #[error("Keys of an external table cannot be empty")]
+    EmptyKey,
+    #[error("Key {0:?} is not bigger than the one added before it")]
+    UnsortedKey(Bytes),
+    #[error("External table has no keys")]
+    EmptyTable,
+    #[error("Unsupported table format {0}, the table is from a newer release")]
+    UnsupportedTableFormat(u32),
+    #[error("Blob file is shorter than its header")]
+    InvalidBlobFile,
+    #[error("Blob at offset {1} of blob file {0} is out of range or corrupt")]
+    InvalidBlob(u32, u64),
 }
 
 impl From<MorsTableError> for SSTableError {
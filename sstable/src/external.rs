@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use bytes::Bytes;
+use mors_common::{
+    compress::CompressionType,
+    kv::{Meta, ValueMeta},
+    ts::{KeyTs, KeyTsBorrow, TxnTs},
+};
+use mors_traits::{
+    kms::KmsCipher,
+    sstable::{ExternalFile, SSTableError, TableWriterTrait},
+};
+
+use crate::{error::MorsTableError, table::TableBuilder, write::TableWriter};
+type Result<T> = std::result::Result<T, SSTableError>;
+
+/// builds a table file outside the db from keys added in increasing order,
+/// to be ingested later. Keys are written without a version, ingesting
+/// assigns one to the whole file.
+///
+/// Every key is kept in a single table, whose offsets are 32 bits wide,
+/// so loads of more than a few GB should be split across files.
+pub struct SstFileWriter<K: KmsCipher> {
+    writer: TableWriter<K>,
+    compression: CompressionType,
+    cipher: Option<K>,
+    last_key: Option<Bytes>,
+}
+impl<K: KmsCipher> SstFileWriter<K> {
+    /// the block size and compression of `builder` are used, the file is
+    /// encrypted with `cipher` if any.
+    pub fn new(builder: TableBuilder<K>, cipher: Option<K>) -> Self {
+        Self {
+            compression: builder.compression(),
+            writer: TableWriter::new(builder, cipher.clone()),
+            cipher,
+            last_key: None,
+        }
+    }
+    pub fn put(&mut self, key: Bytes, value: Bytes) -> Result<()> {
+        let mut value_meta = ValueMeta::default();
+        value_meta.set_value(value);
+        self.push(key, value_meta)
+    }
+    /// hides the older versions of `key` in the db once ingested.
+    pub fn delete(&mut self, key: Bytes) -> Result<()> {
+        let mut value_meta = ValueMeta::default();
+        value_meta.set_meta(Meta::DELETE);
+        self.push(key, value_meta)
+    }
+    fn push(&mut self, key: Bytes, value: ValueMeta) -> Result<()> {
+        if key.is_empty() {
+            return Err(MorsTableError::EmptyKey.into());
+        }
+        if self.last_key.as_ref().is_some_and(|last| last >= &key) {
+            return Err(MorsTableError::UnsortedKey(key).into());
+        }
+        let key_ts = KeyTs::new(key.clone(), TxnTs::default()).encode();
+        self.writer
+            .push(&KeyTsBorrow::from(key_ts.as_ref()), &value, None);
+        self.last_key = Some(key);
+        Ok(())
+    }
+    /// whether no key was added yet.
+    pub fn is_empty(&self) -> bool {
+        self.last_key.is_none()
+    }
+    /// writes the table to `path`, which must not exist yet.
+    pub async fn finish(mut self, path: PathBuf) -> Result<ExternalFile> {
+        if self.is_empty() {
+            return Err(MorsTableError::EmptyTable.into());
+        }
+        self.writer.flush_to_disk(path.clone()).await?;
+        Ok(ExternalFile::new(
+            path,
+            self.compression,
+            self.cipher.as_ref().map(|c| c.cipher_key_id()),
+        ))
+    }
+}
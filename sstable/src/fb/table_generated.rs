@@ -32,6 +32,12 @@ impl<'a> TableIndex<'a> {
   pub const VT_UNCOMPRESSED_SIZE: flatbuffers::VOffsetT = 12;
   pub const VT_ON_DISK_SIZE: flatbuffers::VOffsetT = 14;
   pub const VT_STALE_DATA_SIZE: flatbuffers::VOffsetT = 16;
+  pub const VT_RANGE_TOMBSTONES: flatbuffers::VOffsetT = 18;
+  pub const VT_FORMAT_VERSION: flatbuffers::VOffsetT = 20;
+  pub const VT_PARTITIONS: flatbuffers::VOffsetT = 22;
+  pub const VT_BLOCKS_PER_PARTITION: flatbuffers::VOffsetT = 24;
+  pub const VT_BLOB_FILES: flatbuffers::VOffsetT = 26;
+  pub const VT_PROPERTIES: flatbuffers::VOffsetT = 28;
 
   #[inline]
   pub unsafe fn init_from_table(table: flatbuffers::Table<'a>) -> Self {
@@ -44,10 +50,16 @@ impl<'a> TableIndex<'a> {
   ) -> flatbuffers::WIPOffset<TableIndex<'bldr>> {
     let mut builder = TableIndexBuilder::new(_fbb);
     builder.add_max_version(args.max_version);
+    if let Some(x) = args.properties { builder.add_properties(x); }
+    if let Some(x) = args.blob_files { builder.add_blob_files(x); }
+    builder.add_blocks_per_partition(args.blocks_per_partition);
+    if let Some(x) = args.partitions { builder.add_partitions(x); }
+    builder.add_format_version(args.format_version);
     builder.add_stale_data_size(args.stale_data_size);
     builder.add_on_disk_size(args.on_disk_size);
     builder.add_uncompressed_size(args.uncompressed_size);
     builder.add_key_count(args.key_count);
+    if let Some(x) = args.range_tombstones { builder.add_range_tombstones(x); }
     if let Some(x) = args.bloom_filter { builder.add_bloom_filter(x); }
     if let Some(x) = args.offsets { builder.add_offsets(x); }
     builder.finish()
@@ -103,6 +115,48 @@ impl<'a> TableIndex<'a> {
     // which contains a valid value in this slot
     unsafe { self._tab.get::<u32>(TableIndex::VT_STALE_DATA_SIZE, Some(0)).unwrap()}
   }
+  #[inline]
+  pub fn range_tombstones(&self) -> Option<flatbuffers::Vector<'a, u8>> {
+    // Safety:
+    // Created from valid Table for this object
+    // which contains a valid value in this slot
+    unsafe { self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, u8>>>(TableIndex::VT_RANGE_TOMBSTONES, None)}
+  }
+  #[inline]
+  pub fn format_version(&self) -> u32 {
+    // Safety:
+    // Created from valid Table for this object
+    // which contains a valid value in this slot
+    unsafe { self._tab.get::<u32>(TableIndex::VT_FORMAT_VERSION, Some(0)).unwrap()}
+  }
+  #[inline]
+  pub fn partitions(&self) -> Option<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<BlockOffset<'a>>>> {
+    // Safety:
+    // Created from valid Table for this object
+    // which contains a valid value in this slot
+    unsafe { self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<BlockOffset>>>>(TableIndex::VT_PARTITIONS, None)}
+  }
+  #[inline]
+  pub fn blocks_per_partition(&self) -> u32 {
+    // Safety:
+    // Created from valid Table for this object
+    // which contains a valid value in this slot
+    unsafe { self._tab.get::<u32>(TableIndex::VT_BLOCKS_PER_PARTITION, Some(0)).unwrap()}
+  }
+  #[inline]
+  pub fn blob_files(&self) -> Option<flatbuffers::Vector<'a, u32>> {
+    // Safety:
+    // Created from valid Table for this object
+    // which contains a valid value in this slot
+    unsafe { self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, u32>>>(TableIndex::VT_BLOB_FILES, None)}
+  }
+  #[inline]
+  pub fn properties(&self) -> Option<flatbuffers::Vector<'a, u8>> {
+    // Safety:
+    // Created from valid Table for this object
+    // which contains a valid value in this slot
+    unsafe { self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, u8>>>(TableIndex::VT_PROPERTIES, None)}
+  }
 }
 
 impl flatbuffers::Verifiable for TableIndex<'_> {
@@ -119,6 +173,12 @@ impl flatbuffers::Verifiable for TableIndex<'_> {
      .visit_field::<u32>("uncompressed_size", Self::VT_UNCOMPRESSED_SIZE, false)?
      .visit_field::<u32>("on_disk_size", Self::VT_ON_DISK_SIZE, false)?
      .visit_field::<u32>("stale_data_size", Self::VT_STALE_DATA_SIZE, false)?
+     .visit_field::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'_, u8>>>("range_tombstones", Self::VT_RANGE_TOMBSTONES, false)?
+     .visit_field::<u32>("format_version", Self::VT_FORMAT_VERSION, false)?
+     .visit_field::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<BlockOffset>>>>("partitions", Self::VT_PARTITIONS, false)?
+     .visit_field::<u32>("blocks_per_partition", Self::VT_BLOCKS_PER_PARTITION, false)?
+     .visit_field::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'_, u32>>>("blob_files", Self::VT_BLOB_FILES, false)?
+     .visit_field::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'_, u8>>>("properties", Self::VT_PROPERTIES, false)?
      .finish();
     Ok(())
   }
@@ -131,6 +191,12 @@ pub struct TableIndexArgs<'a> {
     pub uncompressed_size: u32,
     pub on_disk_size: u32,
     pub stale_data_size: u32,
+    pub range_tombstones: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a, u8>>>,
+    pub format_version: u32,
+    pub partitions: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<BlockOffset<'a>>>>>,
+    pub blocks_per_partition: u32,
+    pub blob_files: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a, u32>>>,
+    pub properties: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a, u8>>>,
 }
 impl<'a> Default for TableIndexArgs<'a> {
   #[inline]
@@ -143,6 +209,12 @@ impl<'a> Default for TableIndexArgs<'a> {
       uncompressed_size: 0,
       on_disk_size: 0,
       stale_data_size: 0,
+      range_tombstones: None,
+      format_version: 0,
+      partitions: None,
+      blocks_per_partition: 0,
+      blob_files: None,
+      properties: None,
     }
   }
 }
@@ -181,6 +253,30 @@ impl<'a: 'b, 'b, A: flatbuffers::Allocator + 'a> TableIndexBuilder<'a, 'b, A> {
     self.fbb_.push_slot::<u32>(TableIndex::VT_STALE_DATA_SIZE, stale_data_size, 0);
   }
   #[inline]
+  pub fn add_range_tombstones(&mut self, range_tombstones: flatbuffers::WIPOffset<flatbuffers::Vector<'b , u8>>) {
+    self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(TableIndex::VT_RANGE_TOMBSTONES, range_tombstones);
+  }
+  #[inline]
+  pub fn add_format_version(&mut self, format_version: u32) {
+    self.fbb_.push_slot::<u32>(TableIndex::VT_FORMAT_VERSION, format_version, 0);
+  }
+  #[inline]
+  pub fn add_partitions(&mut self, partitions: flatbuffers::WIPOffset<flatbuffers::Vector<'b , flatbuffers::ForwardsUOffset<BlockOffset<'b >>>>) {
+    self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(TableIndex::VT_PARTITIONS, partitions);
+  }
+  #[inline]
+  pub fn add_blocks_per_partition(&mut self, blocks_per_partition: u32) {
+    self.fbb_.push_slot::<u32>(TableIndex::VT_BLOCKS_PER_PARTITION, blocks_per_partition, 0);
+  }
+  #[inline]
+  pub fn add_blob_files(&mut self, blob_files: flatbuffers::WIPOffset<flatbuffers::Vector<'b , u32>>) {
+    self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(TableIndex::VT_BLOB_FILES, blob_files);
+  }
+  #[inline]
+  pub fn add_properties(&mut self, properties: flatbuffers::WIPOffset<flatbuffers::Vector<'b , u8>>) {
+    self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(TableIndex::VT_PROPERTIES, properties);
+  }
+  #[inline]
   pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a, A>) -> TableIndexBuilder<'a, 'b, A> {
     let start = _fbb.start_table();
     TableIndexBuilder {
@@ -205,6 +301,12 @@ impl core::fmt::Debug for TableIndex<'_> {
       ds.field("uncompressed_size", &self.uncompressed_size());
       ds.field("on_disk_size", &self.on_disk_size());
       ds.field("stale_data_size", &self.stale_data_size());
+      ds.field("range_tombstones", &self.range_tombstones());
+      ds.field("format_version", &self.format_version());
+      ds.field("partitions", &self.partitions());
+      ds.field("blocks_per_partition", &self.blocks_per_partition());
+      ds.field("blob_files", &self.blob_files());
+      ds.field("properties", &self.properties());
       ds.finish()
   }
 }
@@ -339,6 +441,120 @@ impl core::fmt::Debug for BlockOffset<'_> {
       ds.finish()
   }
 }
+pub enum IndexPartitionOffset {}
+#[derive(Copy, Clone, PartialEq)]
+
+pub struct IndexPartition<'a> {
+  pub _tab: flatbuffers::Table<'a>,
+}
+
+impl<'a> flatbuffers::Follow<'a> for IndexPartition<'a> {
+  type Inner = IndexPartition<'a>;
+  #[inline]
+  unsafe fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+    Self { _tab: flatbuffers::Table::new(buf, loc) }
+  }
+}
+
+impl<'a> IndexPartition<'a> {
+  pub const VT_OFFSETS: flatbuffers::VOffsetT = 4;
+  pub const VT_BLOOM_FILTER: flatbuffers::VOffsetT = 6;
+
+  #[inline]
+  pub unsafe fn init_from_table(table: flatbuffers::Table<'a>) -> Self {
+    IndexPartition { _tab: table }
+  }
+  #[allow(unused_mut)]
+  pub fn create<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr, A: flatbuffers::Allocator + 'bldr>(
+    _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr, A>,
+    args: &'args IndexPartitionArgs<'args>
+  ) -> flatbuffers::WIPOffset<IndexPartition<'bldr>> {
+    let mut builder = IndexPartitionBuilder::new(_fbb);
+    if let Some(x) = args.bloom_filter { builder.add_bloom_filter(x); }
+    if let Some(x) = args.offsets { builder.add_offsets(x); }
+    builder.finish()
+  }
+
+
+  #[inline]
+  pub fn offsets(&self) -> Option<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<BlockOffset<'a>>>> {
+    // Safety:
+    // Created from valid Table for this object
+    // which contains a valid value in this slot
+    unsafe { self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<BlockOffset>>>>(IndexPartition::VT_OFFSETS, None)}
+  }
+  #[inline]
+  pub fn bloom_filter(&self) -> Option<flatbuffers::Vector<'a, u8>> {
+    // Safety:
+    // Created from valid Table for this object
+    // which contains a valid value in this slot
+    unsafe { self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, u8>>>(IndexPartition::VT_BLOOM_FILTER, None)}
+  }
+}
+
+impl flatbuffers::Verifiable for IndexPartition<'_> {
+  #[inline]
+  fn run_verifier(
+    v: &mut flatbuffers::Verifier, pos: usize
+  ) -> Result<(), flatbuffers::InvalidFlatbuffer> {
+    use self::flatbuffers::Verifiable;
+    v.visit_table(pos)?
+     .visit_field::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<BlockOffset>>>>("offsets", Self::VT_OFFSETS, false)?
+     .visit_field::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'_, u8>>>("bloom_filter", Self::VT_BLOOM_FILTER, false)?
+     .finish();
+    Ok(())
+  }
+}
+pub struct IndexPartitionArgs<'a> {
+    pub offsets: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<BlockOffset<'a>>>>>,
+    pub bloom_filter: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a, u8>>>,
+}
+impl<'a> Default for IndexPartitionArgs<'a> {
+  #[inline]
+  fn default() -> Self {
+    IndexPartitionArgs {
+      offsets: None,
+      bloom_filter: None,
+    }
+  }
+}
+
+pub struct IndexPartitionBuilder<'a: 'b, 'b, A: flatbuffers::Allocator + 'a> {
+  fbb_: &'b mut flatbuffers::FlatBufferBuilder<'a, A>,
+  start_: flatbuffers::WIPOffset<flatbuffers::TableUnfinishedWIPOffset>,
+}
+impl<'a: 'b, 'b, A: flatbuffers::Allocator + 'a> IndexPartitionBuilder<'a, 'b, A> {
+  #[inline]
+  pub fn add_offsets(&mut self, offsets: flatbuffers::WIPOffset<flatbuffers::Vector<'b , flatbuffers::ForwardsUOffset<BlockOffset<'b >>>>) {
+    self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(IndexPartition::VT_OFFSETS, offsets);
+  }
+  #[inline]
+  pub fn add_bloom_filter(&mut self, bloom_filter: flatbuffers::WIPOffset<flatbuffers::Vector<'b , u8>>) {
+    self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(IndexPartition::VT_BLOOM_FILTER, bloom_filter);
+  }
+  #[inline]
+  pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a, A>) -> IndexPartitionBuilder<'a, 'b, A> {
+    let start = _fbb.start_table();
+    IndexPartitionBuilder {
+      fbb_: _fbb,
+      start_: start,
+    }
+  }
+  #[inline]
+  pub fn finish(self) -> flatbuffers::WIPOffset<IndexPartition<'a>> {
+    let o = self.fbb_.end_table(self.start_);
+    flatbuffers::WIPOffset::new(o.value())
+  }
+}
+
+impl core::fmt::Debug for IndexPartition<'_> {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    let mut ds = f.debug_struct("IndexPartition");
+      ds.field("offsets", &self.offsets());
+      ds.field("bloom_filter", &self.bloom_filter());
+      ds.finish()
+  }
+}
 #[inline]
 /// Verifies that a buffer of bytes contains a `BlockOffset`
 /// and returns it.
@@ -1,11 +1,45 @@
+mod blob;
 mod block;
 pub mod cache;
 mod error;
+pub mod external;
 mod fb;
 mod pb;
+mod properties;
 mod read;
 pub mod table;
 mod table_index;
 pub mod test_utils;
 mod write;
 type Result<T> = std::result::Result<T, error::MorsTableError>;
+/// Format of a table, stored in its index and deciding how the keys of its
+/// blocks are encoded, so the block format can change without breaking old
+/// tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum TableFormat {
+    /// keys are encoded against the first key of their block.
+    #[default]
+    V0 = 0,
+    /// keys are encoded against the key before them, restarting from a full
+    /// key every few entries.
+    V1 = 1,
+    /// blocks as in V1, the index may be split into partitions read and
+    /// cached on their own.
+    V2 = 2,
+}
+impl TableFormat {
+    /// format of newly built tables.
+    pub const CURRENT: TableFormat = TableFormat::V2;
+}
+impl TryFrom<u32> for TableFormat {
+    type Error = u32;
+
+    fn try_from(value: u32) -> std::result::Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::V0),
+            1 => Ok(Self::V1),
+            2 => Ok(Self::V2),
+            v => Err(v),
+        }
+    }
+}
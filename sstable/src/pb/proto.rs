@@ -32,8 +32,8 @@ pub mod checksum {
         /// (if the ProtoBuf definition does not change) and safe for programmatic use.
         pub fn as_str_name(&self) -> &'static str {
             match self {
-                Algorithm::Crc32c => "CRC32C",
-                Algorithm::XxHash64 => "XXHash64",
+                Self::Crc32c => "CRC32C",
+                Self::XxHash64 => "XXHash64",
             }
         }
         /// Creates an enum from field names used in the ProtoBuf definition.
@@ -46,3 +46,24 @@ pub mod checksum {
         }
     }
 }
+/// statistics of a table, stored in its index.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TableProperties {
+    #[prost(uint32, tag = "1")]
+    pub num_deletions: u32,
+    #[prost(uint32, tag = "2")]
+    pub num_merge_entries: u32,
+    #[prost(uint64, tag = "3")]
+    pub min_version: u64,
+    #[prost(uint64, tag = "4")]
+    pub max_version: u64,
+    #[prost(uint64, tag = "5")]
+    pub min_expires_at: u64,
+    #[prost(uint64, tag = "6")]
+    pub max_expires_at: u64,
+    #[prost(map = "string, bytes", tag = "7")]
+    pub user_properties: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost::alloc::vec::Vec<u8>,
+    >,
+}
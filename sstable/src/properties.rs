@@ -0,0 +1,95 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use mors_common::{
+    kv::{Meta, ValueMeta},
+    ts::{KeyTsBorrow, TxnTs},
+};
+use mors_traits::sstable::{
+    TableProperties, TablePropertiesCollector, TablePropertiesCollectorFactory,
+};
+use prost::Message;
+
+use crate::pb::proto;
+
+/// gathers the [`TableProperties`] of a table being written.
+pub(crate) struct PropertiesCollector {
+    properties: TableProperties,
+    has_entries: bool,
+    collectors: Vec<Box<dyn TablePropertiesCollector>>,
+}
+impl PropertiesCollector {
+    pub(crate) fn new(
+        factories: &[Arc<dyn TablePropertiesCollectorFactory>],
+    ) -> Self {
+        Self {
+            properties: TableProperties::default(),
+            has_entries: false,
+            collectors: factories.iter().map(|f| f.create()).collect(),
+        }
+    }
+    pub(crate) fn add(&mut self, key: &KeyTsBorrow, value: &ValueMeta) {
+        let p = &mut self.properties;
+        if value.meta().contains(Meta::DELETE) {
+            p.num_deletions += 1;
+        }
+        if value.meta().contains(Meta::MERGE_ENTRY) {
+            p.num_merge_entries += 1;
+        }
+        let version = key.txn_ts();
+        if !self.has_entries {
+            p.min_version = version;
+        }
+        p.min_version = p.min_version.min(version);
+        p.max_version = p.max_version.max(version);
+        let expires_at = value.expires_at();
+        if *expires_at != 0 {
+            if *p.min_expires_at == 0 || expires_at < p.min_expires_at {
+                p.min_expires_at = expires_at;
+            }
+            p.max_expires_at = p.max_expires_at.max(expires_at);
+        }
+        self.has_entries = true;
+        for collector in self.collectors.iter_mut() {
+            collector.add(key, value);
+        }
+    }
+    /// the encoded properties, stored in the table index.
+    pub(crate) fn finish(&mut self) -> Vec<u8> {
+        let mut user_properties = BTreeMap::new();
+        for collector in self.collectors.iter_mut() {
+            collector.finish(&mut user_properties);
+        }
+        let p = &self.properties;
+        proto::TableProperties {
+            num_deletions: p.num_deletions as u32,
+            num_merge_entries: p.num_merge_entries as u32,
+            min_version: p.min_version.to_u64(),
+            max_version: p.max_version.to_u64(),
+            min_expires_at: p.min_expires_at.into(),
+            max_expires_at: p.max_expires_at.into(),
+            user_properties: user_properties
+                .into_iter()
+                .map(|(k, v)| (k, v.to_vec()))
+                .collect(),
+        }
+        .encode_to_vec()
+    }
+}
+/// decodes the properties stored in a table index, `None` if they are
+/// corrupted.
+pub(crate) fn decode_properties(data: &[u8]) -> Option<TableProperties> {
+    let p = proto::TableProperties::decode(data).ok()?;
+    Some(TableProperties {
+        num_deletions: p.num_deletions as usize,
+        num_merge_entries: p.num_merge_entries as usize,
+        min_version: TxnTs::from(p.min_version),
+        max_version: TxnTs::from(p.max_version),
+        min_expires_at: p.min_expires_at.into(),
+        max_expires_at: p.max_expires_at.into(),
+        user_properties: p
+            .user_properties
+            .into_iter()
+            .map(|(k, v)| (k, v.into()))
+            .collect(),
+    })
+}
@@ -1,13 +1,17 @@
 use std::cmp::Ordering;
 
 use flatbuffers::{Follow, Vector};
-use mors_common::{kv::ValueMeta, ts::KeyTsBorrow};
+use mors_common::{
+    kv::ValueMeta,
+    ts::{KeyTsBorrow, TxnTs},
+};
 use mors_traits::{
     iter::{
         CacheIter, CacheIterator, DoubleEndedCacheIter, IterError, KvCacheIter,
         KvCacheIterator, KvDoubleEndedCacheIter, KvSeekIter,
     },
     kms::KmsCipher,
+    sstable::TableTrait,
 };
 
 use crate::{block::read::CacheBlockIter, table::Table};
@@ -17,6 +21,8 @@ pub struct CacheTableIter<K: KmsCipher> {
     use_cache: bool,
     block_iter: Option<CacheBlockIter>,
     back_block_iter: Option<CacheBlockIter>,
+    // the current key with its version replaced by the global version.
+    global_key: Vec<u8>,
 }
 impl<K: KmsCipher> CacheTableIter<K> {
     pub fn new(inner: Table<K>, use_cache: bool) -> Self {
@@ -25,6 +31,21 @@ impl<K: KmsCipher> CacheTableIter<K> {
             use_cache,
             block_iter: None,
             back_block_iter: None,
+            global_key: Vec::new(),
+        }
+    }
+    fn global_version(&self) -> Option<TxnTs> {
+        self.inner.global_version()
+    }
+    fn set_global_key(&mut self) {
+        let Some(version) = self.global_version() else {
+            return;
+        };
+        self.global_key.clear();
+        if let Some(key) = self.block_iter.as_ref().and_then(|b| b.key()) {
+            self.global_key.extend_from_slice(key.key());
+            self.global_key
+                .extend_from_slice(&version.to_u64().to_be_bytes());
         }
     }
     fn double_ended_eq(&self) -> bool {
@@ -54,6 +75,10 @@ impl<K: KmsCipher> DoubleEndedCacheIter for CacheTableIter<K> {
 }
 impl<K: KmsCipher> KvCacheIter<ValueMeta> for CacheTableIter<K> {
     fn key(&self) -> Option<KeyTsBorrow<'_>> {
+        if self.global_version().is_some() {
+            return (!self.global_key.is_empty())
+                .then(|| self.global_key.as_slice().into());
+        }
         self.block_iter.as_ref().and_then(|b| b.key())
     }
 
@@ -72,6 +97,13 @@ impl<K: KmsCipher> KvDoubleEndedCacheIter<ValueMeta> for CacheTableIter<K> {
 }
 impl<K: KmsCipher> CacheIterator for CacheTableIter<K> {
     fn next(&mut self) -> Result<bool, IterError> {
+        let valid = self.next_entry()?;
+        self.set_global_key();
+        Ok(valid)
+    }
+}
+impl<K: KmsCipher> CacheTableIter<K> {
+    fn next_entry(&mut self) -> Result<bool, IterError> {
         if self.double_ended_eq() {
             return Ok(false);
         }
@@ -108,28 +140,39 @@ impl<K: KmsCipher> CacheIterator for CacheTableIter<K> {
 }
 impl<K: KmsCipher> KvSeekIter for CacheTableIter<K> {
     fn seek(&mut self, k: KeyTsBorrow<'_>) -> Result<bool, IterError> {
-        let indexbuf = self.inner.get_index()?;
-        // let index = match indexbuf
-        // .offsets()
-        // .binary_search_by(|b| b.key_ts().partial_cmp(&k).unwrap())
-        let index = match binary_search_by(&indexbuf.offsets(), |b| {
-            let b: KeyTsBorrow = b.key_ts().unwrap().bytes().into();
-            b.partial_cmp(&k).unwrap()
-        }) {
-            Ok(index) => index,
-            Err(index) => {
-                if index >= indexbuf.offsets().len() {
-                    return Ok(false);
-                }
-                if index == 0 {
-                    return Ok(false);
-                }
-                index - 1
+        let mut valid = self.seek_entry(k)?;
+        self.set_global_key();
+        // keys of an ingested table are all at the global version, which
+        // is newer than the one sought.
+        if let Some(version) = self.global_version() {
+            if valid
+                && version > k.txn_ts()
+                && self.key().is_some_and(|key| key.key() == k.key())
+            {
+                valid = self.next()?;
             }
-        };
+        }
+        Ok(valid)
+    }
+}
+impl<K: KmsCipher> CacheTableIter<K> {
+    fn seek_entry(&mut self, k: KeyTsBorrow<'_>) -> Result<bool, IterError> {
+        let offsets_len = self.inner.block_offsets_len();
+        // the block before the first one with a bigger base key holds `k`,
+        // or if all its keys are smaller, the first key of that block.
+        let index = self.inner.seek_block(k)?;
         let next_block = self.inner.get_block(index.into(), self.use_cache)?;
         self.block_iter = next_block.iter().into();
-        self.block_iter.as_mut().unwrap().seek(k)
+        if self.block_iter.as_mut().unwrap().seek(k)? {
+            return Ok(true);
+        }
+        if index + 1 >= offsets_len {
+            return Ok(false);
+        }
+        let next_block =
+            self.inner.get_block((index + 1).into(), self.use_cache)?;
+        self.block_iter = next_block.iter().into();
+        self.block_iter.as_mut().unwrap().next()
     }
 }
 impl<K: KmsCipher> KvCacheIterator<ValueMeta> for CacheTableIter<K> {}
@@ -176,3 +219,21 @@ where
     // Note that this is `<=`, unlike the assume in the `Ok` path.
     Err(left)
 }
+/// index of the first element for which `pred` is false, `v` being
+/// partitioned by it.
+pub fn partition_point<'a, T: Follow<'a> + 'a, P>(
+    v: &Vector<'a, T>,
+    mut pred: P,
+) -> usize
+where
+    P: FnMut(T::Inner) -> bool,
+{
+    binary_search_by(v, |x| {
+        if pred(x) {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        }
+    })
+    .unwrap_or_else(|i| i)
+}
@@ -1,17 +1,25 @@
 use std::{
-    fmt::Debug, marker::PhantomData, path::PathBuf, sync::Arc, time::SystemTime,
+    fmt::Debug,
+    marker::PhantomData,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::SystemTime,
 };
 
 use bytes::Buf;
+use flatbuffers::{ForwardsUOffset, Vector};
 use log::error;
 use memmap2::Advice;
 use mors_common::{
     bloom::{Bloom, BloomBorrow},
     compress::CompressionType,
-    file_id::{FileId, SSTableId},
-    kv::ValueMeta,
+    file_id::{BlobFileId, FileId, SSTableId},
+    kv::{RangeTombstone, ValueMeta, ValuePointer},
     page_size,
-    ts::{KeyTs, TxnTs},
+    ts::{KeyTs, KeyTsBorrow, TxnTs},
 };
 use mors_traits::file::StorageBuilderTrait;
 use mors_traits::file::StorageTrait;
@@ -22,21 +30,27 @@ use mors_traits::{
         CacheIterator, DoubleEndedCacheIterator, KvCacheIter, KvCacheIterator,
         KvDoubleEndedCacheIter,
     },
-    kms::KmsCipher,
-    sstable::{BlockIndex, SSTableError, TableBuilderTrait, TableTrait},
+    kms::{CipherKeyId, KmsCipher},
+    sstable::{
+        BlockIndex, SSTableError, TableBuilderTrait, TableProperties,
+        TablePropertiesCollectorFactory, TableTrait,
+    },
 };
 use mors_wal::storage::mmap::{MmapFile, MmapFileBuilder};
 use prost::Message;
 
 use crate::{
+    blob::{BlobFiles, BlobRef},
     block::Block,
     cache::Cache,
     error::MorsTableError,
+    fb::table_generated::BlockOffset,
     pb::proto::{checksum, Checksum},
-    read::CacheTableIter,
-    table_index::TableIndexBuf,
+    properties::decode_properties,
+    read::{binary_search_by, partition_point, CacheTableIter},
+    table_index::{IndexPartitionBuf, TableIndexBuf},
     write::TableWriter,
-    Result,
+    Result, TableFormat,
 };
 // ChecksumVerificationMode tells when should DB verify checksum for SSTable blocks.
 #[derive(Debug, Clone, Copy)]
@@ -75,10 +89,26 @@ pub struct TableBuilder<K: KmsCipher> {
     // BlockSize is the size of each block inside SSTable in bytes.
     block_size: usize,
 
+    // BlockRestartInterval is the number of keys between two full keys in a block.
+    block_restart_interval: usize,
+
+    // IndexPartitionSize is the size the index is split into partitions of, 0 never splits it.
+    index_partition_size: usize,
+
     // Compression indicates the compression algorithm used for block compression.
     compression: CompressionType,
 
+    // GlobalVersion is the version every key of an ingested table is read at.
+    global_version: Option<TxnTs>,
+
+    // BlobThreshold is the size values are moved into blob files from, None keeps them in the tables.
+    blob_threshold: Option<usize>,
+
+    // PropertiesCollectors create the collectors of user properties of every table written.
+    properties_collectors: Vec<Arc<dyn TablePropertiesCollectorFactory>>,
+
     cache: Option<Cache>,
+    blobs: BlobFiles,
     k: PhantomData<K>,
 }
 impl<K: KmsCipher> Default for TableBuilder<K> {
@@ -90,10 +120,16 @@ impl<K: KmsCipher> Default for TableBuilder<K> {
             checksum_algo: checksum::Algorithm::Crc32c,
             bloom_false_positive: 0.01,
             block_size: page_size() * 4,
+            block_restart_interval: 16,
+            index_partition_size: 0,
             compression: CompressionType::default(),
+            global_version: None,
+            blob_threshold: None,
+            properties_collectors: Vec::new(),
             read_only: false,
             dir: PathBuf::from(DEFAULT_DIR),
             cache: None,
+            blobs: BlobFiles::default(),
             k: PhantomData,
         }
     }
@@ -161,6 +197,50 @@ impl<K: KmsCipher> TableBuilderTrait<Table<K>, K> for TableBuilder<K> {
     fn table_size(&self) -> usize {
         self.table_size
     }
+
+    fn set_global_version(&mut self, version: Option<TxnTs>) -> &mut Self {
+        self.global_version = version;
+        self
+    }
+
+    fn set_blob_threshold(&mut self, threshold: Option<usize>) -> &mut Self {
+        self.blob_threshold = threshold;
+        self
+    }
+
+    fn blob_threshold(&self) -> Option<usize> {
+        self.blob_threshold
+    }
+
+    fn blob_cipher_key_id(
+        &self,
+        id: BlobFileId,
+    ) -> std::result::Result<CipherKeyId, SSTableError> {
+        Ok(self.blobs.cipher_key_id(&self.dir, self.read_only, id)?)
+    }
+
+    fn read_blob(
+        &self,
+        vp: &ValuePointer,
+        cipher: Option<&K>,
+    ) -> std::result::Result<bytes::Bytes, SSTableError> {
+        Ok(self.blobs.read(&self.dir, self.read_only, vp, cipher)?)
+    }
+
+    fn delete_blob(
+        &self,
+        id: BlobFileId,
+    ) -> std::result::Result<(), SSTableError> {
+        Ok(self.blobs.delete(&self.dir, id)?)
+    }
+
+    fn add_properties_collector(
+        &mut self,
+        factory: Arc<dyn TablePropertiesCollectorFactory>,
+    ) -> &mut Self {
+        self.properties_collectors.push(factory);
+        self
+    }
 }
 impl<K: KmsCipher> TableBuilder<K> {
     pub fn block_size(&self) -> usize {
@@ -170,6 +250,25 @@ impl<K: KmsCipher> TableBuilder<K> {
         self.block_size = block_size;
         self
     }
+    pub fn block_restart_interval(&self) -> usize {
+        self.block_restart_interval
+    }
+    /// keys between two keys stored in full in a block, seeks binary search
+    /// the full keys and scan at most this many keys after them.
+    pub fn set_block_restart_interval(&mut self, interval: usize) -> &mut Self {
+        self.block_restart_interval = interval;
+        self
+    }
+    pub fn index_partition_size(&self) -> usize {
+        self.index_partition_size
+    }
+    /// splits the block offsets and bloom filter of tables whose index
+    /// outgrows this size into partitions of about it, read and cached on
+    /// their own instead of the whole index, 0 keeps indexes whole.
+    pub fn set_index_partition_size(&mut self, size: usize) -> &mut Self {
+        self.index_partition_size = size;
+        self
+    }
     pub fn checksum_algo(&self) -> checksum::Algorithm {
         self.checksum_algo
     }
@@ -179,12 +278,26 @@ impl<K: KmsCipher> TableBuilder<K> {
     pub fn table_capacity(&self) -> usize {
         self.table_capacity
     }
+    pub(crate) fn properties_collectors(
+        &self,
+    ) -> &[Arc<dyn TablePropertiesCollectorFactory>] {
+        &self.properties_collectors
+    }
     pub(crate) fn create_bloom(&self, key_hashes: &[u32]) -> Option<Bloom> {
         if self.bloom_false_positive > 0.0 {
             return Some(Bloom::new(key_hashes, self.bloom_false_positive));
         }
         None
     }
+    /// size of the bloom filter of `keys` keys.
+    pub(crate) fn bloom_size(&self, keys: usize) -> usize {
+        if self.bloom_false_positive > 0.0 {
+            return keys
+                * Bloom::bits_per_key(self.bloom_false_positive) as usize
+                / 8;
+        }
+        0
+    }
     pub(crate) async fn open_impl(
         &self,
         id: SSTableId,
@@ -205,11 +318,39 @@ impl<K: KmsCipher> TableBuilder<K> {
 
         let (index_buf, index_start, index_len) =
             TableBuilder::init_index(&mmap, &cipher)?;
-
-        let (smallest, biggest) =
-            self.smallest_biggest(&index_buf, &mmap, &cipher)?;
-
-        let cheap_index = CheapTableIndex::from(&index_buf);
+        let format = TableFormat::try_from(index_buf.format_version())
+            .map_err(MorsTableError::UnsupportedTableFormat)?;
+
+        let (last_block, offsets_len) =
+            Self::last_block(&index_buf, &mmap, &cipher)?;
+        let (mut smallest, mut biggest) = self
+            .smallest_biggest(&index_buf, last_block, format, &mmap, &cipher)?;
+
+        let mut cheap_index = CheapTableIndex::from(&index_buf);
+        cheap_index.offsets_len = offsets_len;
+        if let Some(version) = self.global_version {
+            smallest.set_txn_ts(version);
+            biggest.set_txn_ts(version);
+            cheap_index.max_version = version;
+            cheap_index.properties.min_version = version;
+            cheap_index.properties.max_version = version;
+            cheap_index.range_tombstones = cheap_index
+                .range_tombstones
+                .iter()
+                .map(|t| {
+                    RangeTombstone::new(
+                        t.start().clone(),
+                        t.end().clone(),
+                        version,
+                    )
+                })
+                .collect();
+        }
+        let blobs = cheap_index
+            .blob_files
+            .iter()
+            .map(|blob| self.blobs.hold(&self.dir, *blob))
+            .collect();
         let table = Table(
             TableInner {
                 id,
@@ -226,6 +367,10 @@ impl<K: KmsCipher> TableBuilder<K> {
                 cipher,
                 checksum_verify_mode: self.checksum_verify_mode,
                 compression: self.compression,
+                global_version: self.global_version,
+                format,
+                blobs,
+                deleted: AtomicBool::new(false),
             }
             .into(),
         );
@@ -290,30 +435,55 @@ impl<K: KmsCipher> TableBuilder<K> {
             .unwrap_or(data);
         let index_buf = TableIndexBuf::from_vec(data)?;
 
-        debug_assert!(!index_buf.offsets().is_empty());
-
         Ok((index_buf, read_pos, index_len))
     }
+    /// offset and length of the last block, and the number of blocks.
+    fn last_block(
+        index_buf: &TableIndexBuf,
+        mmap: &MmapFile,
+        cipher: &Option<K>,
+    ) -> Result<((u32, u32), usize)> {
+        let Some(partitions) = index_buf.partitions() else {
+            if index_buf.offsets_len() == 0 {
+                return Err(MorsTableError::TableIndexOffsetEmpty);
+            }
+            let last = index_buf.offsets().get(index_buf.offsets_len() - 1);
+            return Ok(((last.offset(), last.len()), index_buf.offsets_len()));
+        };
+        if partitions.is_empty() {
+            return Err(MorsTableError::TableIndexOffsetEmpty);
+        }
+        let last = partitions.get(partitions.len() - 1);
+        let partition =
+            read_partition(mmap, cipher.as_ref(), last.offset(), last.len())?;
+        let offsets = partition.offsets();
+        if offsets.is_empty() {
+            return Err(MorsTableError::TableIndexOffsetEmpty);
+        }
+        let last = offsets.get(offsets.len() - 1);
+        let offsets_len = (partitions.len() - 1)
+            * index_buf.blocks_per_partition()
+            + offsets.len();
+        Ok(((last.offset(), last.len()), offsets_len))
+    }
     fn smallest_biggest(
         &self,
         index_buf: &TableIndexBuf,
+        (last_offset, last_len): (u32, u32),
+        format: TableFormat,
         mmap: &MmapFile,
         cipher: &Option<K>,
     ) -> Result<(KeyTs, KeyTs)> {
-        //get smallest
-        if index_buf.offsets().is_empty() {
-            return Err(MorsTableError::TableIndexOffsetEmpty);
-        }
-        let first_block_offset = index_buf.offsets().get(0);
+        //get smallest, the first key of the first block or partition
+        let first_block_offset = match index_buf.partitions() {
+            Some(partitions) => partitions.get(0),
+            None => index_buf.offsets().get(0),
+        };
         let smallest = first_block_offset.key_ts().unwrap().bytes().into();
 
         //get biggest
-        let last_block_offset =
-            index_buf.offsets().get(index_buf.offsets_len() - 1);
-
-        let last = last_block_offset.offset() as usize;
-        let data =
-            &mmap.as_ref()[last..last + last_block_offset.len() as usize];
+        let last = last_offset as usize;
+        let data = &mmap.as_ref()[last..last + last_len as usize];
 
         let plaintext = cipher
             .as_ref()
@@ -325,8 +495,9 @@ impl<K: KmsCipher> TableBuilder<K> {
         let block = Block::decode(
             0.into(),     //here don't care about it.
             0_u32.into(), //here don't care about it.
-            last_block_offset.offset(),
+            last_offset,
             uncompress_data,
+            format,
         )?;
 
         block.verify()?;
@@ -355,6 +526,22 @@ pub(crate) struct TableInner<K: KmsCipher> {
     cipher: Option<K>,
     checksum_verify_mode: ChecksumVerificationMode,
     compression: CompressionType,
+    global_version: Option<TxnTs>,
+    format: TableFormat,
+    // keeps the blob files the table points into until it is dropped.
+    blobs: Vec<Arc<BlobRef>>,
+    // set once the table left its level, the file is unlinked when the last
+    // reader drops it.
+    deleted: AtomicBool,
+}
+impl<K: KmsCipher> Drop for TableInner<K> {
+    fn drop(&mut self) {
+        if self.deleted.load(Ordering::Acquire) {
+            if let Err(e) = self.mmap.delete() {
+                error!("Delete table {} error: {}", self.id, e);
+            }
+        }
+    }
 }
 impl<K: KmsCipher> Debug for Table<K> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -371,6 +558,9 @@ impl<K: KmsCipher> Debug for Table<K> {
             .field("cheap_index", &self.0.cheap_index)
             .field("checksum_verify_mode", &self.0.checksum_verify_mode)
             .field("compression", &self.0.compression)
+            .field("global_version", &self.0.global_version)
+            .field("format", &self.0.format)
+            .field("blobs", &self.0.blobs)
             .finish()
     }
 }
@@ -404,6 +594,10 @@ impl<K: KmsCipher> TableTrait<K> for Table<K> {
         self.0.cheap_index.max_version
     }
 
+    fn global_version(&self) -> Option<TxnTs> {
+        self.0.global_version
+    }
+
     fn cipher(&self) -> Option<&K> {
         self.0.cipher.as_ref()
     }
@@ -433,27 +627,64 @@ impl<K: KmsCipher> TableTrait<K> for Table<K> {
     }
 
     fn delete(&self) -> std::result::Result<(), SSTableError> {
-        Ok(self.0.mmap.delete().map_err(MorsTableError::IoError)?)
+        self.0.deleted.store(true, Ordering::Release);
+        Ok(())
     }
 
     fn may_contain(&self, key: &[u8]) -> bool {
-        if self.0.cheap_index.bloom_filter_len == 0 {
+        if self.0.cheap_index.blocks_per_partition == 0
+            && self.0.cheap_index.bloom_filter_len == 0
+        {
             return true;
         }
-        match self.get_index() {
-            Ok(index) => {
-                let bloom = index.bloom_filter().unwrap();
-                let bloom: BloomBorrow = bloom.as_ref().into();
-                bloom.may_contain_key(key)
-            }
+        match self.may_contain_impl(key) {
+            Ok(may_contain) => may_contain,
             Err(e) => {
                 error!("{} can't get index {}", self.id(), e);
                 true
             }
         }
     }
+
+    fn range_tombstones(&self) -> &[RangeTombstone] {
+        &self.0.cheap_index.range_tombstones
+    }
+
+    fn blob_files(&self) -> &[BlobFileId] {
+        &self.0.cheap_index.blob_files
+    }
+
+    fn properties(&self) -> &TableProperties {
+        &self.0.cheap_index.properties
+    }
+
+    fn block_offsets(
+        &self,
+    ) -> std::result::Result<Vec<(KeyTs, usize)>, SSTableError> {
+        let index = self.get_index()?;
+        let to_key_len = |b: BlockOffset| {
+            let mut key: KeyTs = b.key_ts().unwrap().bytes().into();
+            if let Some(version) = self.0.global_version {
+                key.set_txn_ts(version);
+            }
+            (key, b.len() as usize)
+        };
+        let Some(partitions) = index.partitions() else {
+            return Ok(index.offsets().iter().map(to_key_len).collect());
+        };
+        let mut offsets = Vec::with_capacity(self.block_offsets_len());
+        for i in 0..partitions.len() {
+            let partition = self.get_partition(i, false)?;
+            offsets.extend(partition.offsets().iter().map(to_key_len));
+        }
+        Ok(offsets)
+    }
 }
 impl<K: KmsCipher> Table<K> {
+    /// format the blocks of this table were written in.
+    pub fn format(&self) -> TableFormat {
+        self.0.format
+    }
     #[cfg(not(feature = "sync"))]
     async fn verify(&self) -> Result<()> {
         for i in 0..self.0.cheap_index.offsets_len {
@@ -541,15 +772,9 @@ impl<K: KmsCipher> Table<K> {
             };
         }
 
-        let table_index = self.table_index().await?;
-
-        let block_id: usize = block_index.into();
-        let block = &table_index.offsets()[block_id];
+        let (offset, len) = self.block_handle(block_index.into()).await?;
 
-        let raw_data_ref = self
-            .0
-            .mmap
-            .pread_ref(block.offset() as usize, block.size() as usize);
+        let raw_data_ref = self.0.mmap.pread_ref(offset as usize, len as usize);
         let data = self
             .0
             .cipher
@@ -559,7 +784,7 @@ impl<K: KmsCipher> Table<K> {
             .unwrap_or_else(|| raw_data_ref.to_vec());
 
         let block =
-            Block::decode(self.0.id, block_index, block.offset(), data)?;
+            Block::decode(self.0.id, block_index, offset, data, self.0.format)?;
 
         match self.0.checksum_verify_mode {
             ChecksumVerificationMode::OnBlockRead
@@ -593,15 +818,9 @@ impl<K: KmsCipher> Table<K> {
             };
         }
 
-        let table_index = self.table_index()?;
-
-        let block_id: usize = block_index.into();
-        let block = &table_index.offsets().get(block_id);
+        let (offset, len) = self.block_handle(block_index.into())?;
 
-        let raw_data_ref = self
-            .0
-            .mmap
-            .pread_ref(block.offset() as usize, block.len() as usize);
+        let raw_data_ref = self.0.mmap.pread_ref(offset as usize, len as usize);
         let data = self
             .0
             .cipher
@@ -613,8 +832,9 @@ impl<K: KmsCipher> Table<K> {
         let block = Block::decode(
             self.0.id,
             block_index,
-            block.offset(),
+            offset,
             uncompress_data,
+            self.0.format,
         )?;
 
         match self.0.checksum_verify_mode {
@@ -678,10 +898,166 @@ impl<K: KmsCipher> Table<K> {
         }
         Ok(index_buf)
     }
+    /// offset and length of the block `block_index`.
+    #[cfg(not(feature = "sync"))]
+    async fn block_handle(&self, block_index: usize) -> Result<(u32, u32)> {
+        let blocks_per_partition = self.0.cheap_index.blocks_per_partition;
+        if blocks_per_partition == 0 {
+            let table_index = self.table_index().await?;
+            let block = table_index.offsets().get(block_index);
+            return Ok((block.offset(), block.len()));
+        }
+        let partition = self
+            .get_partition(block_index / blocks_per_partition, true)
+            .await?;
+        let block = partition.offsets().get(block_index % blocks_per_partition);
+        Ok((block.offset(), block.len()))
+    }
+    /// offset and length of the block `block_index`.
+    #[cfg(feature = "sync")]
+    fn block_handle(&self, block_index: usize) -> Result<(u32, u32)> {
+        let blocks_per_partition = self.0.cheap_index.blocks_per_partition;
+        if blocks_per_partition == 0 {
+            let table_index = self.table_index()?;
+            let block = table_index.offsets().get(block_index);
+            return Ok((block.offset(), block.len()));
+        }
+        let partition =
+            self.get_partition(block_index / blocks_per_partition, true)?;
+        let block = partition.offsets().get(block_index % blocks_per_partition);
+        Ok((block.offset(), block.len()))
+    }
+    #[cfg(not(feature = "sync"))]
+    pub(crate) async fn get_partition(
+        &self,
+        partition: usize,
+        insert_cache: bool,
+    ) -> Result<IndexPartitionBuf> {
+        let key: BlockCacheKey = (self.0.id, partition.into()).into();
+        if let Some(c) = self.0.cache.as_ref() {
+            if let Some(p) = c.get_partition(&key).await {
+                return Ok(p);
+            };
+        }
+
+        let table_index = self.table_index().await?;
+        let handle = table_index
+            .partitions()
+            .ok_or(MorsTableError::TableIndexOffsetEmpty)?
+            .get(partition);
+        let partition_buf = read_partition(
+            &self.0.mmap,
+            self.0.cipher.as_ref(),
+            handle.offset(),
+            handle.len(),
+        )?;
+        if insert_cache {
+            if let Some(c) = self.0.cache.as_ref() {
+                c.insert_partition(key, partition_buf.clone()).await;
+            }
+        }
+        Ok(partition_buf)
+    }
+    #[cfg(feature = "sync")]
+    pub(crate) fn get_partition(
+        &self,
+        partition: usize,
+        insert_cache: bool,
+    ) -> Result<IndexPartitionBuf> {
+        let key: BlockCacheKey = (self.0.id, partition.into()).into();
+        if let Some(c) = self.0.cache.as_ref() {
+            if let Some(p) = c.get_partition(&key) {
+                return Ok(p);
+            };
+        }
+
+        let table_index = self.table_index()?;
+        let handle = table_index
+            .partitions()
+            .ok_or(MorsTableError::TableIndexOffsetEmpty)?
+            .get(partition);
+        let partition_buf = read_partition(
+            &self.0.mmap,
+            self.0.cipher.as_ref(),
+            handle.offset(),
+            handle.len(),
+        )?;
+        if insert_cache {
+            if let Some(c) = self.0.cache.as_ref() {
+                c.insert_partition(key, partition_buf.clone());
+            }
+        }
+        Ok(partition_buf)
+    }
+    /// index of the block before the first one with a bigger base key than
+    /// `k`, or of the first block.
+    pub(crate) fn seek_block(&self, k: KeyTsBorrow<'_>) -> Result<usize> {
+        fn search(
+            offsets: &Vector<'_, ForwardsUOffset<BlockOffset<'_>>>,
+            k: KeyTsBorrow<'_>,
+        ) -> usize {
+            match binary_search_by(offsets, |b| {
+                let b: KeyTsBorrow = b.key_ts().unwrap().bytes().into();
+                b.partial_cmp(&k).unwrap()
+            }) {
+                Ok(index) => index,
+                Err(0) => 0,
+                Err(index) => index - 1,
+            }
+        }
+        let index = self.get_index()?;
+        let Some(partitions) = index.partitions() else {
+            return Ok(search(&index.offsets(), k));
+        };
+        // the first key of a partition is the base key of its first block.
+        let partition = search(&partitions, k);
+        let offsets = self.get_partition(partition, true)?;
+        Ok(partition * index.blocks_per_partition()
+            + search(&offsets.offsets(), k))
+    }
+    fn may_contain_impl(&self, key: &[u8]) -> Result<bool> {
+        let index = self.get_index()?;
+        let Some(partitions) = index.partitions() else {
+            let bloom: BloomBorrow = index.bloom_filter().unwrap().into();
+            return Ok(bloom.may_contain_key(key));
+        };
+        // versions of the key may go on from the partition before the first
+        // one starting with it, and span several partitions.
+        let start = partition_point(&partitions, |b| {
+            KeyTsBorrow::from(b.key_ts().unwrap().bytes()).key() < key
+        })
+        .saturating_sub(1);
+        let end = partition_point(&partitions, |b| {
+            KeyTsBorrow::from(b.key_ts().unwrap().bytes()).key() <= key
+        });
+        for i in start..end {
+            let partition = self.get_partition(i, true)?;
+            let Some(bloom) = partition.bloom_filter() else {
+                return Ok(true);
+            };
+            if BloomBorrow::from(bloom).may_contain_key(key) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
     pub(crate) fn block_offsets_len(&self) -> usize {
         self.0.cheap_index.offsets_len
     }
 }
+fn read_partition<K: KmsCipher>(
+    mmap: &MmapFile,
+    cipher: Option<&K>,
+    offset: u32,
+    len: u32,
+) -> Result<IndexPartitionBuf> {
+    let raw_data_ref = mmap.pread_ref(offset as usize, len as usize);
+    let data = cipher
+        .map(|c| c.decrypt(raw_data_ref))
+        .transpose()?
+        .unwrap_or_else(|| raw_data_ref.to_vec());
+    Ok(IndexPartitionBuf::from_vec(data)?)
+}
 #[derive(Debug)]
 struct CheapTableIndex {
     max_version: TxnTs,
@@ -691,6 +1067,10 @@ struct CheapTableIndex {
     stale_data_size: u32,
     offsets_len: usize,
     bloom_filter_len: usize,
+    blocks_per_partition: usize,
+    range_tombstones: Vec<RangeTombstone>,
+    blob_files: Vec<BlobFileId>,
+    properties: TableProperties,
 }
 impl From<&TableIndexBuf> for CheapTableIndex {
     fn from(value: &TableIndexBuf) -> Self {
@@ -700,11 +1080,25 @@ impl From<&TableIndexBuf> for CheapTableIndex {
             uncompressed_size: value.uncompressed_size(),
             on_disk_size: value.on_disk_size(),
             stale_data_size: value.stale_data_size(),
-            offsets_len: value.offsets().len(),
+            offsets_len: value.offsets_len(),
             bloom_filter_len: value
                 .bloom_filter()
                 .map(|x| x.len())
                 .unwrap_or(0),
+            blocks_per_partition: value.blocks_per_partition(),
+            range_tombstones: value
+                .range_tombstones()
+                .and_then(RangeTombstone::decode_slice)
+                .unwrap_or_default(),
+            blob_files: value
+                .blob_files()
+                .into_iter()
+                .map(BlobFileId::from)
+                .collect(),
+            properties: value
+                .properties()
+                .and_then(decode_properties)
+                .unwrap_or_default(),
         }
     }
 }
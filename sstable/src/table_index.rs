@@ -3,7 +3,7 @@ use std::sync::Arc;
 use flatbuffers::{ForwardsUOffset, InvalidFlatbuffer, Vector};
 use mors_traits::sstable::TableIndexBufTrait;
 
-use crate::fb::table_generated::{BlockOffset, TableIndex};
+use crate::fb::table_generated::{BlockOffset, IndexPartition, TableIndex};
 
 #[derive(Clone, Debug, Default)]
 pub struct TableIndexBuf(Arc<TableIndexBufInner>);
@@ -18,6 +18,7 @@ struct TableIndexBufInner {
     uncompressed_size: u32,
     on_disk_size: u32,
     stale_data_size: u32,
+    format_version: u32,
 }
 impl TableIndexBufTrait for TableIndexBuf {}
 
@@ -26,9 +27,8 @@ impl TableIndexBuf {
         let table_index =
             unsafe { flatbuffers::root_unchecked::<TableIndex>(&data) };
 
-        assert!(table_index.offsets().is_some());
-        let offsets = table_index.offsets().unwrap();
-        let offsets_len = offsets.len();
+        let offsets_len =
+            table_index.offsets().map(|x| x.len()).unwrap_or_default();
         Ok(Self(
             TableIndexBufInner {
                 max_version: table_index.max_version(),
@@ -36,6 +36,7 @@ impl TableIndexBuf {
                 uncompressed_size: table_index.uncompressed_size(),
                 on_disk_size: table_index.on_disk_size(),
                 stale_data_size: table_index.stale_data_size(),
+                format_version: table_index.format_version(),
                 data,
                 offsets_len,
             }
@@ -62,6 +63,11 @@ impl TableIndexBuf {
             unsafe { flatbuffers::root_unchecked::<TableIndex>(&self.0.data) };
         table_index.bloom_filter().map(|x| x.bytes())
     }
+    pub(crate) fn range_tombstones(&self) -> Option<&[u8]> {
+        let table_index =
+            unsafe { flatbuffers::root_unchecked::<TableIndex>(&self.0.data) };
+        table_index.range_tombstones().map(|x| x.bytes())
+    }
     pub(crate) fn max_version(&self) -> u64 {
         self.0.max_version
     }
@@ -77,4 +83,62 @@ impl TableIndexBuf {
     pub(crate) fn stale_data_size(&self) -> u32 {
         self.0.stale_data_size
     }
+    pub(crate) fn format_version(&self) -> u32 {
+        self.0.format_version
+    }
+    /// blocks indexed by each partition, 0 if the index is not partitioned.
+    pub(crate) fn blocks_per_partition(&self) -> usize {
+        let table_index =
+            unsafe { flatbuffers::root_unchecked::<TableIndex>(&self.0.data) };
+        table_index.blocks_per_partition() as usize
+    }
+    pub(crate) fn partitions(
+        &self,
+    ) -> Option<Vector<'_, ForwardsUOffset<BlockOffset<'_>>>> {
+        let table_index =
+            unsafe { flatbuffers::root_unchecked::<TableIndex>(&self.0.data) };
+        table_index.partitions()
+    }
+    pub(crate) fn blob_files(&self) -> Vec<u32> {
+        let table_index =
+            unsafe { flatbuffers::root_unchecked::<TableIndex>(&self.0.data) };
+        table_index
+            .blob_files()
+            .map(|x| x.iter().collect())
+            .unwrap_or_default()
+    }
+    pub(crate) fn properties(&self) -> Option<&[u8]> {
+        let table_index =
+            unsafe { flatbuffers::root_unchecked::<TableIndex>(&self.0.data) };
+        table_index.properties().map(|x| x.bytes())
+    }
+}
+/// A partition of a partitioned index, holding the offsets of its blocks and
+/// the bloom filter of their keys.
+#[derive(Clone, Debug, Default)]
+pub struct IndexPartitionBuf(Arc<Vec<u8>>);
+impl IndexPartitionBuf {
+    pub(crate) fn from_vec(data: Vec<u8>) -> Result<Self, InvalidFlatbuffer> {
+        // partitions are not covered by the checksum of the table index.
+        let partition = flatbuffers::root::<IndexPartition>(&data)?;
+        if partition.offsets().is_none() {
+            return Err(InvalidFlatbuffer::MissingRequiredField {
+                required: "offsets",
+                error_trace: Default::default(),
+            });
+        }
+        Ok(Self(data.into()))
+    }
+    pub(crate) fn offsets(
+        &self,
+    ) -> Vector<'_, ForwardsUOffset<BlockOffset<'_>>> {
+        let partition =
+            unsafe { flatbuffers::root_unchecked::<IndexPartition>(&self.0) };
+        partition.offsets().unwrap()
+    }
+    pub(crate) fn bloom_filter(&self) -> Option<&[u8]> {
+        let partition =
+            unsafe { flatbuffers::root_unchecked::<IndexPartition>(&self.0) };
+        partition.bloom_filter().map(|x| x.bytes())
+    }
 }
@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeSet,
     mem::replace,
     path::PathBuf,
     sync::{
@@ -7,14 +8,14 @@ use std::{
     },
 };
 
-use flatbuffers::FlatBufferBuilder;
-use log::debug;
+use flatbuffers::{FlatBufferBuilder, ForwardsUOffset, Vector, WIPOffset};
+use log::{debug, error};
 use memmap2::Advice;
 use mors_common::{
     bloom::Bloom,
     compress::CompressionType,
-    file_id::{FileId, SSTableId},
-    kv::{Meta, ValueMeta, ValuePointer},
+    file_id::{BlobFileId, FileId, SSTableId},
+    kv::{Meta, RangeTombstone, ValueMeta, ValuePointer},
     rayon::{self, AsyncRayonHandle},
     ts::{KeyTsBorrow, TxnTs},
 };
@@ -25,7 +26,7 @@ use mors_traits::{
     file::StorageTrait,
     iter::{CacheIterator, KvCacheIter},
     kms::KmsCipher,
-    sstable::{SSTableError, TableWriterTrait},
+    sstable::{SSTableError, TableBuilderTrait, TableWriterTrait},
 };
 use mors_wal::storage::mmap::MmapFileBuilder;
 use prost::Message;
@@ -33,11 +34,15 @@ use tokio::task::spawn_blocking;
 
 use crate::error::MorsTableError;
 use crate::fb::table_generated::{
-    BlockOffset, BlockOffsetArgs, TableIndex, TableIndexArgs,
+    BlockOffset, BlockOffsetArgs, IndexPartition, IndexPartitionArgs,
+    TableIndex, TableIndexArgs,
 };
 use crate::pb::proto::{checksum, Checksum};
-use crate::Result;
-use crate::{block::write::BlockWriter, table::TableBuilder};
+use crate::properties::PropertiesCollector;
+use crate::{blob::BlobWriter, block::write::BlockWriter, table::TableBuilder};
+use crate::{Result, TableFormat};
+// estimated size of a block offset in the index besides its key.
+const BLOCK_OFFSET_SIZE: usize = 24;
 pub struct TableWriter<K: KmsCipher> {
     tablebuilder: TableBuilder<K>,
     block_writer: BlockWriter,
@@ -48,8 +53,14 @@ pub struct TableWriter<K: KmsCipher> {
     cipher: Option<Arc<K>>,
     compress_task: Vec<AsyncRayonHandle<Result<BlockWriter>>>,
     key_hashes: Vec<u32>,
+    // number of keys in the finished blocks and the ones before them.
+    block_key_ends: Vec<usize>,
     max_version: TxnTs,
     on_disk_size: u32,
+    range_tombstones: Vec<RangeTombstone>,
+    blob: Option<BlobWriter<K>>,
+    blob_files: BTreeSet<BlobFileId>,
+    properties: PropertiesCollector,
 }
 impl<K: KmsCipher> TableWriterTrait for TableWriter<K> {
     fn reached_capacity(&self) -> bool {
@@ -63,6 +74,8 @@ impl<K: KmsCipher> TableWriterTrait for TableWriter<K> {
         let blocks_size = sum_block_sizes
             + (self.block_writer.entry_offsets().len() * 4) as u32
             + 4
+            + (self.block_writer.restarts().len() * 4) as u32
+            + 4
             + 8
             + 4;
         let estimate_size = blocks_size + 4 + self.len_offsets as u32;
@@ -86,12 +99,23 @@ impl<K: KmsCipher> TableWriterTrait for TableWriter<K> {
             key.len() as u32 + value.value().len() as u32 + 4;
         self.push_internal(key, value, vptr_len, true);
     }
+    fn set_blob_file(&mut self, id: BlobFileId) {
+        if self.tablebuilder.blob_threshold().is_some() {
+            self.blob = BlobWriter::new(id, self.cipher.clone()).into();
+        }
+    }
 
     async fn flush_to_disk(
         &mut self,
         path: PathBuf,
     ) -> std::result::Result<(), SSTableError> {
         let build_data = self.done().await?;
+        if let Some(blob) = self.blob.take().filter(|b| !b.is_empty()) {
+            let dir = path.parent().map(PathBuf::from).unwrap_or_default();
+            spawn_blocking(move || blob.write(&dir))
+                .await
+                .map_err(MorsTableError::from)??;
+        }
 
         fn write_data(path: PathBuf, data: TableBuildData) -> Result<()> {
             let mut builder = MmapFileBuilder::new();
@@ -116,7 +140,12 @@ impl<K: KmsCipher> TableWriterTrait for TableWriter<K> {
 }
 impl<K: KmsCipher> TableWriter<K> {
     pub(crate) fn new(builder: TableBuilder<K>, cipher: Option<K>) -> Self {
-        let block_writer = BlockWriter::new(builder.block_size());
+        let block_writer = BlockWriter::new(
+            builder.block_size(),
+            builder.block_restart_interval(),
+        );
+        let properties =
+            PropertiesCollector::new(builder.properties_collectors());
         Self {
             tablebuilder: builder,
             cipher: cipher.map(Arc::new),
@@ -127,8 +156,41 @@ impl<K: KmsCipher> TableWriter<K> {
             comressed_size: Arc::new(AtomicUsize::new(0)),
             compress_task: Vec::new(),
             key_hashes: Vec::new(),
+            block_key_ends: Vec::new(),
             max_version: TxnTs::default(),
             on_disk_size: 0,
+            range_tombstones: Vec::new(),
+            blob: None,
+            blob_files: BTreeSet::new(),
+            properties,
+        }
+    }
+    // moves `value` into the blob file if it is big enough.
+    fn separate(&mut self, value: &ValueMeta) -> Option<ValueMeta> {
+        let blob = self.blob.as_mut()?;
+        let threshold = self.tablebuilder.blob_threshold()?;
+        if value.value().len() < threshold
+            || value.meta().intersects(
+                Meta::DELETE
+                    | Meta::VALUE_POINTER
+                    | Meta::BLOB_INDEX
+                    | Meta::MERGE_ENTRY
+                    | Meta::RANGE_DELETE,
+            )
+        {
+            return None;
+        }
+        match blob.push(value.value()) {
+            Ok(vp) => {
+                let mut blob_value = value.clone();
+                blob_value.set_value(vp.encode().into());
+                blob_value.set_meta(value.meta() | Meta::BLOB_INDEX);
+                Some(blob_value)
+            }
+            Err(e) => {
+                error!("failed to move value to blob file: {}", e);
+                None
+            }
         }
     }
 
@@ -139,6 +201,14 @@ impl<K: KmsCipher> TableWriter<K> {
         vptr_len: Option<u32>,
         is_stale: bool,
     ) {
+        self.properties.add(key, value);
+        let blob_value = self.separate(value);
+        let value = blob_value.as_ref().unwrap_or(value);
+        if value.meta().contains(Meta::BLOB_INDEX) {
+            if let Some(vp) = ValuePointer::decode(value.value()) {
+                self.blob_files.insert(vp.fid().into());
+            }
+        }
         if self.block_writer.should_finish_block::<K>(
             key,
             value,
@@ -150,6 +220,13 @@ impl<K: KmsCipher> TableWriter<K> {
             }
             self.finish_block();
         }
+        if value.meta().contains(Meta::RANGE_DELETE) {
+            self.range_tombstones.push(RangeTombstone::new(
+                key.key().to_vec().into(),
+                value.value().clone(),
+                key.txn_ts(),
+            ));
+        }
         self.key_hashes.push(Bloom::hash(key.key()));
         self.max_version = self.max_version.max(key.txn_ts());
         self.block_writer.push_entry(key, value);
@@ -162,6 +239,7 @@ impl<K: KmsCipher> TableWriter<K> {
         }
 
         self.block_writer.finish_block(self.checksum_algo());
+        self.block_key_ends.push(self.key_hashes.len());
         self.uncompressed_size
             .fetch_add(self.block_writer.data().len() as u32, Ordering::AcqRel);
         self.len_offsets +=
@@ -169,7 +247,10 @@ impl<K: KmsCipher> TableWriter<K> {
 
         let mut finished_block = replace(
             &mut self.block_writer,
-            BlockWriter::new(self.tablebuilder.block_size()),
+            BlockWriter::new(
+                self.tablebuilder.block_size(),
+                self.tablebuilder.block_restart_interval(),
+            ),
         );
 
         let compression = self.compression();
@@ -195,62 +276,171 @@ impl<K: KmsCipher> TableWriter<K> {
         for task in self.compress_task.drain(..) {
             block_list.push(task.await?);
         }
-        let bloom = self.tablebuilder.create_bloom(&self.key_hashes);
-        let (index, data_size) =
-            self.build_index(&block_list, bloom.as_ref())?;
+        let (index, partitions, data_size) = self.build_index(&block_list)?;
         let checksum =
             Checksum::new(self.checksum_algo(), &index).encode_to_vec();
         let size = data_size as u64
+            + partitions.iter().map(|p| p.len() as u64).sum::<u64>()
             + index.len() as u64
             + 4
             + checksum.len() as u64
             + 4;
         let data = TableBuildData {
             block_list,
+            partitions,
             index,
             checksum,
             size,
         };
         Ok(data)
     }
+    /// builds the index and, when it is partitioned, its partitions to be
+    /// written after the blocks.
     fn build_index(
         &mut self,
-        block_list: &Vec<BlockWriter>,
-        bloom: Option<&Bloom>,
-    ) -> Result<(Vec<u8>, u32)> {
-        let mut builder = FlatBufferBuilder::with_capacity(3 << 20);
+        block_list: &[BlockWriter],
+    ) -> Result<(Vec<u8>, Vec<Vec<u8>>, u32)> {
+        let mut block_offsets = Vec::with_capacity(block_list.len());
         let mut data_size = 0;
-        let mut block_offset = Vec::new();
         for block in block_list {
-            let args = BlockOffsetArgs {
-                key_ts: builder
-                    .create_vector(block.base_keyts().as_ref())
-                    .into(),
-                offset: data_size,
-                len: block.data().len() as u32,
-            };
-
+            block_offsets.push(data_size);
             data_size += block.data().len() as u32;
-            block_offset.push(BlockOffset::create(&mut builder, &args));
         }
         self.on_disk_size += data_size;
+
+        let mut builder = FlatBufferBuilder::with_capacity(3 << 20);
+        let mut offsets = None;
+        let mut bloom_filter = None;
+        let mut partitions = None;
+        let mut partition_list = Vec::new();
+        let blocks_per_partition = self.blocks_per_partition(block_list);
+        if blocks_per_partition == 0 {
+            let bloom = self.tablebuilder.create_bloom(&self.key_hashes);
+            offsets =
+                Self::create_offsets(&mut builder, block_list, &block_offsets)
+                    .into();
+            bloom_filter = bloom.map(|x| builder.create_vector(&x));
+        } else {
+            let mut partition_offsets = Vec::new();
+            let mut offset = data_size;
+            for (i, blocks) in
+                block_list.chunks(blocks_per_partition).enumerate()
+            {
+                let first = i * blocks_per_partition;
+                let partition = self.build_partition(
+                    blocks,
+                    &block_offsets[first..],
+                    first,
+                )?;
+                let args = BlockOffsetArgs {
+                    key_ts: builder
+                        .create_vector(blocks[0].base_keyts().as_ref())
+                        .into(),
+                    offset,
+                    len: partition.len() as u32,
+                };
+                offset += partition.len() as u32;
+                partition_offsets
+                    .push(BlockOffset::create(&mut builder, &args));
+                partition_list.push(partition);
+            }
+            partitions = builder.create_vector(&partition_offsets).into();
+        }
+        let properties = self.properties.finish();
         let table_index_args = TableIndexArgs {
-            offsets: builder.create_vector(&block_offset).into(),
-            bloom_filter: bloom.and_then(|x| builder.create_vector(x).into()),
+            offsets,
+            bloom_filter,
             max_version: self.max_version.to_u64(),
             key_count: self.key_hashes.len() as u32,
             uncompressed_size: self.uncompressed_size.load(Ordering::Acquire),
             on_disk_size: self.on_disk_size,
             stale_data_size: self.stale_data_size,
+            range_tombstones: (!self.range_tombstones.is_empty()).then(|| {
+                builder.create_vector(&RangeTombstone::encode_slice(
+                    &self.range_tombstones,
+                ))
+            }),
+            format_version: TableFormat::CURRENT as u32,
+            partitions,
+            blocks_per_partition: blocks_per_partition as u32,
+            blob_files: (!self.blob_files.is_empty()).then(|| {
+                let ids = self.blob_files.iter().map(|id| u32::from(*id));
+                builder.create_vector(&ids.collect::<Vec<_>>())
+            }),
+            properties: builder.create_vector(&properties).into(),
         };
         let table_index = TableIndex::create(&mut builder, &table_index_args);
         builder.finish(table_index, None);
 
-        let data = match &self.cipher {
-            Some(c) => c.encrypt(builder.finished_data())?,
-            None => builder.finished_data().to_vec(),
+        let data = self.encrypt(builder.finished_data())?;
+        Ok((data, partition_list, data_size))
+    }
+    fn create_offsets<'a>(
+        builder: &mut FlatBufferBuilder<'a>,
+        blocks: &[BlockWriter],
+        block_offsets: &[u32],
+    ) -> WIPOffset<Vector<'a, ForwardsUOffset<BlockOffset<'a>>>> {
+        let mut offsets = Vec::with_capacity(blocks.len());
+        for (block, offset) in blocks.iter().zip(block_offsets) {
+            let args = BlockOffsetArgs {
+                key_ts: builder
+                    .create_vector(block.base_keyts().as_ref())
+                    .into(),
+                offset: *offset,
+                len: block.data().len() as u32,
+            };
+            offsets.push(BlockOffset::create(builder, &args));
+        }
+        builder.create_vector(&offsets)
+    }
+    /// builds the partition of `blocks`, the first of them being the block
+    /// `first_block` of the table.
+    fn build_partition(
+        &self,
+        blocks: &[BlockWriter],
+        block_offsets: &[u32],
+        first_block: usize,
+    ) -> Result<Vec<u8>> {
+        let key_start = first_block
+            .checked_sub(1)
+            .map(|i| self.block_key_ends[i])
+            .unwrap_or_default();
+        let key_end = self.block_key_ends[first_block + blocks.len() - 1];
+        let bloom = self
+            .tablebuilder
+            .create_bloom(&self.key_hashes[key_start..key_end]);
+
+        let mut builder = FlatBufferBuilder::new();
+        let offsets = Self::create_offsets(&mut builder, blocks, block_offsets);
+        let partition_args = IndexPartitionArgs {
+            offsets: offsets.into(),
+            bloom_filter: bloom.map(|x| builder.create_vector(&x)),
         };
-        Ok((data, data_size))
+        let partition = IndexPartition::create(&mut builder, &partition_args);
+        builder.finish(partition, None);
+        self.encrypt(builder.finished_data())
+    }
+    /// blocks indexed by each partition, 0 when the index fits in one.
+    fn blocks_per_partition(&self, block_list: &[BlockWriter]) -> usize {
+        let partition_size = self.tablebuilder.index_partition_size();
+        if partition_size == 0 || block_list.is_empty() {
+            return 0;
+        }
+        let index_size = block_list
+            .iter()
+            .map(|b| b.base_keyts().len() + BLOCK_OFFSET_SIZE)
+            .sum::<usize>()
+            + self.tablebuilder.bloom_size(self.key_hashes.len());
+        if index_size <= partition_size {
+            return 0;
+        }
+        (partition_size * block_list.len() / index_size).max(1)
+    }
+    fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match &self.cipher {
+            Some(c) => Ok(c.encrypt(data)?),
+            None => Ok(data.to_vec()),
+        }
     }
     fn is_empty(&self) -> bool {
         self.key_hashes.len() == 0
@@ -275,7 +465,10 @@ impl<K: KmsCipher> TableBuilder<K> {
         next_id: Arc<AtomicU32>,
         cipher: Option<K>,
     ) -> Result<Option<SSTableId>> {
+        // the id is taken first as the blob file is named after the table.
+        let id: SSTableId = next_id.fetch_add(1, Ordering::SeqCst).into();
         let mut writer = TableWriter::new(self.clone(), cipher);
+        writer.set_blob_file(id.into());
         while iter.next()? {
             if let (Some(k), Some(v)) = (iter.key(), iter.value()) {
                 let vptr_size = v
@@ -290,7 +483,6 @@ impl<K: KmsCipher> TableBuilder<K> {
         if writer.is_empty() {
             return Ok(None);
         }
-        let id: SSTableId = next_id.fetch_add(1, Ordering::SeqCst).into();
         let path = id.join_dir(self.dir());
 
         writer.flush_to_disk(path).await?;
@@ -299,6 +491,7 @@ impl<K: KmsCipher> TableBuilder<K> {
 }
 struct TableBuildData {
     block_list: Vec<BlockWriter>,
+    partitions: Vec<Vec<u8>>,
     index: Vec<u8>,
     checksum: Vec<u8>,
     size: u64,
@@ -308,6 +501,9 @@ impl TableBuildData {
         for block in self.block_list.iter() {
             writer.append(block.data(), Ordering::Relaxed)?;
         }
+        for partition in self.partitions.iter() {
+            writer.append(partition, Ordering::Relaxed)?;
+        }
         writer.append(&self.index, Ordering::Relaxed)?;
 
         writer.append(
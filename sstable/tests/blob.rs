@@ -0,0 +1,103 @@
+use std::path::Path;
+use std::sync::{atomic::AtomicU32, Arc};
+
+use mors_common::{
+    file_id::{BlobFileId, FileId},
+    kv::{Meta, ValueMeta, ValuePointer},
+    ts::KeyTs,
+};
+use mors_encrypt::cipher::AesCipher;
+use mors_sstable::table::{Table, TableBuilder};
+use mors_traits::{
+    default::WithDir,
+    iter::{CacheIterator, KvCacheIter, SeqIter},
+    sstable::{TableBuilderTrait, TableTrait},
+};
+
+type TestTableBuilder = TableBuilder<AesCipher>;
+
+// every other value is big enough to be moved into the blob file.
+fn kv() -> Vec<(KeyTs, ValueMeta)> {
+    let mut kv = Vec::new();
+    for i in 0..100u64 {
+        let mut value = ValueMeta::default();
+        let len = if i % 2 == 0 { 256 } else { 16 };
+        value.set_value(vec![i as u8; len].into());
+        kv.push((KeyTs::new(format!("key{i:03}").into(), 1.into()), value));
+    }
+    kv
+}
+async fn build(
+    dir: &Path,
+    kv: &[(KeyTs, ValueMeta)],
+    cipher: Option<AesCipher>,
+) -> (TestTableBuilder, Table<AesCipher>) {
+    let mut builder = TestTableBuilder::default();
+    builder
+        .set_blob_threshold(Some(128))
+        .set_dir(dir.to_path_buf());
+    let table = builder
+        .build_l0(
+            SeqIter::new_with_kv(&kv.to_vec()),
+            Arc::new(AtomicU32::new(1)),
+            cipher,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    (builder, table)
+}
+fn assert_blobs(
+    builder: &TestTableBuilder,
+    table: &Table<AesCipher>,
+    kv: &[(KeyTs, ValueMeta)],
+) {
+    let blob_id = BlobFileId::from(u32::from(table.id()));
+    assert_eq!(table.blob_files(), &[blob_id]);
+    assert!(blob_id.join_dir(builder.dir()).exists());
+
+    let mut iter = table.iter(true);
+    for (k, v) in kv {
+        assert!(iter.next().unwrap());
+        assert_eq!(KeyTs::from(iter.key().unwrap()), *k);
+        let value = iter.value().unwrap();
+        if v.value().len() < 128 {
+            assert!(!value.meta().contains(Meta::BLOB_INDEX));
+            assert_eq!(value.value(), v.value());
+            continue;
+        }
+        assert!(value.meta().contains(Meta::BLOB_INDEX));
+        let vp = ValuePointer::decode(value.value()).unwrap();
+        assert_eq!(vp.fid(), u32::from(blob_id));
+        assert_eq!(builder.read_blob(&vp, table.cipher()).unwrap(), v.value());
+    }
+    assert!(!iter.next().unwrap());
+}
+
+#[tokio::test]
+async fn test_blob_file() {
+    let kv = kv();
+    let dir = tempfile::tempdir().unwrap();
+    let (builder, table) = build(dir.path(), &kv, None).await;
+    assert_blobs(&builder, &table, &kv);
+
+    // the table still points into the deleted blob file.
+    let blob_id = table.blob_files()[0];
+    builder.delete_blob(blob_id).unwrap();
+    assert!(blob_id.join_dir(dir.path()).exists());
+    assert_blobs(&builder, &table, &kv);
+    drop(table);
+    assert!(!blob_id.join_dir(dir.path()).exists());
+}
+#[tokio::test]
+async fn test_blob_file_cipher() {
+    let kv = kv();
+    let cipher = AesCipher::new(&[7; 32], 1.into()).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let (builder, table) = build(dir.path(), &kv, cipher.into()).await;
+    assert_blobs(&builder, &table, &kv);
+    assert_eq!(
+        builder.blob_cipher_key_id(table.blob_files()[0]).unwrap(),
+        1.into()
+    );
+}
@@ -0,0 +1,109 @@
+use mors_common::{
+    compress::CompressionType,
+    file_id::{FileId, SSTableId},
+    ts::{KeyTs, KeyTsBorrow, TxnTs},
+};
+use mors_encrypt::cipher::AesCipher;
+use mors_sstable::{
+    external::SstFileWriter,
+    table::{Table, TableBuilder},
+};
+use mors_traits::{
+    default::WithDir,
+    iter::{CacheIterator, KvCacheIter, KvSeekIter},
+    sstable::{TableBuilderTrait, TableTrait},
+};
+
+type TestTableBuilder = TableBuilder<AesCipher>;
+
+fn key(i: usize) -> bytes::Bytes {
+    format!("key{i:05}").into()
+}
+fn builder(dir: &std::path::Path) -> TestTableBuilder {
+    let mut builder = TestTableBuilder::default();
+    builder.set_block_size(256);
+    builder.set_compression(CompressionType::ZSTD(3));
+    builder.set_dir(dir.to_path_buf());
+    builder
+}
+async fn open(
+    builder: &TestTableBuilder,
+    version: Option<TxnTs>,
+) -> Table<AesCipher> {
+    let mut builder = builder.clone();
+    builder.set_global_version(version);
+    builder.open(1.into(), None).await.unwrap().unwrap()
+}
+
+#[tokio::test]
+async fn test_sst_file_writer() {
+    let dir = tempfile::tempdir().unwrap();
+    let builder = builder(dir.path());
+    let mut writer = SstFileWriter::new(builder.clone(), None);
+    assert!(writer.put("".into(), "value".into()).is_err());
+    for i in 0..1000 {
+        writer.put(key(i), format!("value{i}").into()).unwrap();
+    }
+    assert!(writer.put(key(999), "value".into()).is_err());
+    assert!(writer.put(key(10), "value".into()).is_err());
+    let path = SSTableId::from(1).join_dir(dir.path());
+    let file = writer.finish(path.clone()).await.unwrap();
+    assert_eq!(file.path(), &path);
+    assert_eq!(file.compression(), CompressionType::ZSTD(3));
+    assert_eq!(file.cipher_key_id(), None);
+
+    let table = open(&builder, None).await;
+    assert_eq!(table.max_version(), TxnTs::default());
+    let mut iter = table.iter(false);
+    for i in 0..1000 {
+        assert!(iter.next().unwrap());
+        let k = iter.key().unwrap();
+        assert_eq!(k.key(), key(i).as_ref());
+        assert_eq!(k.txn_ts(), TxnTs::default());
+        assert_eq!(iter.value().unwrap().value(), &format!("value{i}"));
+    }
+    assert!(!iter.next().unwrap());
+
+    let writer = SstFileWriter::new(builder, None);
+    let path = SSTableId::from(2).join_dir(dir.path());
+    assert!(writer.finish(path).await.is_err());
+}
+#[tokio::test]
+async fn test_global_version() {
+    let dir = tempfile::tempdir().unwrap();
+    let builder = builder(dir.path());
+    let mut writer = SstFileWriter::new(builder.clone(), None);
+    for i in 0..1000 {
+        writer.put(key(i), format!("value{i}").into()).unwrap();
+    }
+    let path = SSTableId::from(1).join_dir(dir.path());
+    writer.finish(path).await.unwrap();
+
+    let version = TxnTs::from(42);
+    let table = open(&builder, Some(version)).await;
+    assert_eq!(table.global_version(), Some(version));
+    assert_eq!(table.max_version(), version);
+    assert_eq!(table.smallest(), &KeyTs::new(key(0), version));
+    assert_eq!(table.biggest(), &KeyTs::new(key(999), version));
+    assert!(table
+        .block_offsets()
+        .unwrap()
+        .iter()
+        .all(|(k, _)| k.txn_ts() == version));
+
+    // every key is found, the ones starting a block too.
+    let mut iter = table.iter(false);
+    for i in 0..1000 {
+        let seek = KeyTs::new(key(i), TxnTs::from(u64::MAX)).encode();
+        assert!(iter.seek(KeyTsBorrow::from(seek.as_ref())).unwrap());
+        let k = iter.key().unwrap();
+        assert_eq!(k.key(), key(i).as_ref());
+        assert_eq!(k.txn_ts(), version);
+    }
+    // reads older than the ingested version skip to the next key.
+    let seek = KeyTs::new(key(500), TxnTs::from(10)).encode();
+    assert!(iter.seek(KeyTsBorrow::from(seek.as_ref())).unwrap());
+    assert_eq!(iter.key().unwrap().key(), key(501).as_ref());
+    let seek = KeyTs::new(key(999), TxnTs::from(10)).encode();
+    assert!(!iter.seek(KeyTsBorrow::from(seek.as_ref())).unwrap());
+}
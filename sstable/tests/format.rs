@@ -0,0 +1,132 @@
+//! Tables of older formats under `tests/corpus` must stay readable.
+//!
+//! `corpus/v0` was written before blocks had restart points, from the keys
+//! of [`kv`] with a block size of 512 and no compression.
+use std::path::Path;
+
+use mors_common::{
+    compress::CompressionType,
+    file_id::{FileId, SSTableId},
+    kv::ValueMeta,
+    ts::{KeyTs, KeyTsBorrow, TxnTs},
+};
+use mors_encrypt::cipher::AesCipher;
+use mors_sstable::{
+    table::{Table, TableBuilder},
+    TableFormat,
+};
+use mors_traits::{
+    default::WithDir,
+    iter::{CacheIterator, KvCacheIter, KvSeekIter, SeqIter},
+    sstable::{TableBuilderTrait, TableTrait},
+};
+use std::sync::{atomic::AtomicU32, Arc};
+
+type TestTableBuilder = TableBuilder<AesCipher>;
+
+// long keys sharing most of their prefix.
+fn kv() -> Vec<(KeyTs, ValueMeta)> {
+    (0..300u64)
+        .map(|i| {
+            let key = format!("tenant/{}/user/{:04}", i / 100, i);
+            let mut value = ValueMeta::default();
+            value.set_value(format!("value{i}").into());
+            (KeyTs::new(key.into(), (i + 1).into()), value)
+        })
+        .collect()
+}
+fn builder(dir: &Path, restart_interval: usize) -> TestTableBuilder {
+    let mut builder = TestTableBuilder::default();
+    builder.set_block_size(512);
+    builder.set_block_restart_interval(restart_interval);
+    builder.set_compression(CompressionType::None);
+    builder.set_dir(dir.to_path_buf());
+    builder
+}
+async fn build(
+    dir: &Path,
+    kv: &[(KeyTs, ValueMeta)],
+    restart_interval: usize,
+) -> Table<AesCipher> {
+    builder(dir, restart_interval)
+        .build_l0(
+            SeqIter::new_with_kv(&kv.to_vec()),
+            Arc::new(AtomicU32::new(1)),
+            None,
+        )
+        .await
+        .unwrap()
+        .unwrap()
+}
+fn assert_table(table: &Table<AesCipher>) {
+    let kv = kv();
+    assert_eq!(table.smallest(), &kv[0].0);
+    assert_eq!(table.biggest(), &kv[kv.len() - 1].0);
+
+    let mut iter = table.iter(false);
+    for (k, v) in kv.iter() {
+        assert!(iter.next().unwrap());
+        assert_eq!(KeyTs::from(iter.key().unwrap()), *k);
+        assert_eq!(iter.value().unwrap().value(), v.value());
+    }
+    assert!(!iter.next().unwrap());
+
+    for (i, (k, _)) in kv.iter().enumerate() {
+        let seek = KeyTs::new(k.key().clone(), TxnTs::from(u64::MAX));
+        assert!(iter
+            .seek(KeyTsBorrow::from(seek.encode().as_ref()))
+            .unwrap());
+        assert_eq!(KeyTs::from(iter.key().unwrap()), *k);
+
+        // older than the only version, the next key is found.
+        let seek = KeyTs::new(k.key().clone(), TxnTs::default());
+        let found = iter
+            .seek(KeyTsBorrow::from(seek.encode().as_ref()))
+            .unwrap();
+        assert_eq!(found, i + 1 < kv.len());
+        if found {
+            assert_eq!(KeyTs::from(iter.key().unwrap()), kv[i + 1].0);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_read_v0_corpus() {
+    let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus/v0");
+    let dir = tempfile::tempdir().unwrap();
+    let path = SSTableId::from(1).join_dir(dir.path());
+    std::fs::copy(SSTableId::from(1).join_dir(&src), path).unwrap();
+
+    let table = builder(dir.path(), 16)
+        .open(1.into(), None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(table.format(), TableFormat::V0);
+    assert_table(&table);
+}
+#[tokio::test]
+async fn test_restart_points() {
+    for restart_interval in [1, 3, 16, 1000] {
+        let dir = tempfile::tempdir().unwrap();
+        let table = build(dir.path(), &kv(), restart_interval).await;
+        assert_eq!(table.format(), TableFormat::CURRENT);
+        assert_table(&table);
+    }
+}
+#[tokio::test]
+async fn test_prefix_compression() {
+    // keys diverge from the first key of their block early but share a
+    // long prefix with the key before them.
+    let kv = (0..300u64)
+        .map(|i| {
+            let key = format!("{:03}/{}/{}", i / 4, "path/".repeat(10), i % 4);
+            (KeyTs::new(key.into(), 1.into()), ValueMeta::default())
+        })
+        .collect::<Vec<_>>();
+    let dir = tempfile::tempdir().unwrap();
+    let full = build(dir.path(), &kv, 1).await;
+    let dir = tempfile::tempdir().unwrap();
+    let delta = build(dir.path(), &kv, 16).await;
+    assert!(delta.size() * 2 < full.size());
+}
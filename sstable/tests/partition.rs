@@ -0,0 +1,129 @@
+use std::path::Path;
+use std::sync::{atomic::AtomicU32, Arc};
+
+use mors_common::{
+    compress::CompressionType,
+    kv::ValueMeta,
+    ts::{KeyTs, KeyTsBorrow, TxnTs},
+};
+use mors_encrypt::cipher::AesCipher;
+use mors_sstable::{
+    cache::MorsCacheBuilder,
+    table::{Table, TableBuilder},
+    TableFormat,
+};
+use mors_traits::{
+    cache::CacheBuilder,
+    default::WithDir,
+    iter::{CacheIterator, KvCacheIter, KvSeekIter, SeqIter},
+    sstable::{TableBuilderTrait, TableTrait},
+};
+
+type TestTableBuilder = TableBuilder<AesCipher>;
+
+// every key has `versions` versions, newest first.
+fn kv(keys: u64, versions: u64) -> Vec<(KeyTs, ValueMeta)> {
+    let mut kv = Vec::new();
+    for i in 0..keys {
+        for v in (1..=versions).rev() {
+            let mut value = ValueMeta::default();
+            value.set_value(format!("value{i}/{v}").into());
+            let key = KeyTs::new(format!("key{:05}", i * 2).into(), v.into());
+            kv.push((key, value));
+        }
+    }
+    kv
+}
+async fn build(
+    dir: &Path,
+    kv: &[(KeyTs, ValueMeta)],
+    index_partition_size: usize,
+    cipher: Option<AesCipher>,
+) -> Table<AesCipher> {
+    let mut cache = MorsCacheBuilder::default();
+    cache.set_index_partition_size(index_partition_size.max(1));
+    let mut builder = TestTableBuilder::default();
+    builder
+        .set_block_size(256)
+        .set_index_partition_size(index_partition_size)
+        .set_compression(CompressionType::None)
+        .set_cache(cache.build().unwrap())
+        .set_dir(dir.to_path_buf());
+    builder
+        .build_l0(
+            SeqIter::new_with_kv(&kv.to_vec()),
+            Arc::new(AtomicU32::new(1)),
+            cipher,
+        )
+        .await
+        .unwrap()
+        .unwrap()
+}
+fn assert_table(table: &Table<AesCipher>, kv: &[(KeyTs, ValueMeta)]) {
+    assert_eq!(table.smallest(), &kv[0].0);
+    assert_eq!(table.biggest(), &kv[kv.len() - 1].0);
+
+    let mut iter = table.iter(true);
+    for (k, v) in kv.iter() {
+        assert!(iter.next().unwrap());
+        assert_eq!(KeyTs::from(iter.key().unwrap()), *k);
+        assert_eq!(iter.value().unwrap().value(), v.value());
+    }
+    assert!(!iter.next().unwrap());
+
+    for (i, (k, _)) in kv.iter().enumerate() {
+        assert!(table.may_contain(k.key()));
+        assert!(iter.seek(KeyTsBorrow::from(k.encode().as_ref())).unwrap());
+        assert_eq!(KeyTs::from(iter.key().unwrap()), *k);
+
+        let seek = KeyTs::new(k.key().clone(), TxnTs::default());
+        let found = iter
+            .seek(KeyTsBorrow::from(seek.encode().as_ref()))
+            .unwrap();
+        let next = kv[i..].iter().find(|(n, _)| n.key() != k.key());
+        assert_eq!(found, next.is_some());
+        if let Some((n, _)) = next {
+            assert_eq!(KeyTs::from(iter.key().unwrap()), *n);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_partitioned_index() {
+    let kv = kv(500, 3);
+    let dir = tempfile::tempdir().unwrap();
+    let whole = build(dir.path(), &kv, 0, None).await;
+    let dir = tempfile::tempdir().unwrap();
+    let partitioned = build(dir.path(), &kv, 512, None).await;
+    assert_eq!(partitioned.format(), TableFormat::CURRENT);
+    assert_table(&whole, &kv);
+    assert_table(&partitioned, &kv);
+    assert_eq!(
+        partitioned.block_offsets().unwrap(),
+        whole.block_offsets().unwrap()
+    );
+
+    // keys between the written ones are mostly filtered out.
+    let filtered = (0..500)
+        .filter(|i| {
+            !partitioned.may_contain(format!("key{:05}", i * 2 + 1).as_bytes())
+        })
+        .count();
+    assert!(filtered > 450);
+}
+#[tokio::test]
+async fn test_partitioned_versions() {
+    // the versions of a key span several blocks and partitions.
+    let kv = kv(20, 100);
+    let dir = tempfile::tempdir().unwrap();
+    let table = build(dir.path(), &kv, 256, None).await;
+    assert_table(&table, &kv);
+}
+#[tokio::test]
+async fn test_partitioned_cipher() {
+    let kv = kv(500, 1);
+    let cipher = AesCipher::new(&[7; 32], 1.into()).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let table = build(dir.path(), &kv, 512, cipher.into()).await;
+    assert_table(&table, &kv);
+}
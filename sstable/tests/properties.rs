@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+use std::sync::{atomic::AtomicU32, Arc};
+
+use bytes::Bytes;
+use mors_common::{
+    kv::{Meta, ValueMeta},
+    ts::{KeyTs, KeyTsBorrow},
+};
+use mors_encrypt::cipher::AesCipher;
+use mors_sstable::table::TableBuilder;
+use mors_traits::{
+    default::WithDir,
+    iter::SeqIter,
+    sstable::{
+        TableBuilderTrait, TableProperties, TablePropertiesCollector,
+        TablePropertiesCollectorFactory, TableTrait,
+    },
+};
+
+// sums the value sizes of the table.
+#[derive(Debug)]
+struct ValueSizeFactory;
+#[derive(Default)]
+struct ValueSize(u64);
+impl TablePropertiesCollector for ValueSize {
+    fn add(&mut self, _key: &KeyTsBorrow, value: &ValueMeta) {
+        self.0 += value.value().len() as u64;
+    }
+    fn finish(&mut self, properties: &mut BTreeMap<String, Bytes>) {
+        properties.insert(
+            "value_size".to_string(),
+            self.0.to_be_bytes().to_vec().into(),
+        );
+    }
+}
+impl TablePropertiesCollectorFactory for ValueSizeFactory {
+    fn create(&self) -> Box<dyn TablePropertiesCollector> {
+        Box::<ValueSize>::default()
+    }
+}
+
+#[tokio::test]
+async fn test_properties() {
+    let mut kv = Vec::new();
+    for i in 0..100u64 {
+        let mut value = ValueMeta::default();
+        value.set_value(vec![i as u8; 10].into());
+        match i % 4 {
+            0 => value.set_meta(Meta::DELETE),
+            1 => value.set_meta(Meta::MERGE_ENTRY),
+            2 => value.set_expires_at((1000 + i).into()),
+            _ => {}
+        }
+        let key = format!("key{i:03}").into();
+        kv.push((KeyTs::new(key, (i + 5).into()), value));
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let mut builder = TableBuilder::<AesCipher>::default();
+    builder
+        .add_properties_collector(Arc::new(ValueSizeFactory))
+        .set_dir(dir.path().to_path_buf());
+    let table = builder
+        .build_l0(SeqIter::new_with_kv(&kv), Arc::new(AtomicU32::new(1)), None)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let mut user_properties = BTreeMap::new();
+    user_properties.insert(
+        "value_size".to_string(),
+        1000u64.to_be_bytes().to_vec().into(),
+    );
+    let expected = TableProperties {
+        num_deletions: 25,
+        num_merge_entries: 25,
+        min_version: 5.into(),
+        max_version: 104.into(),
+        min_expires_at: 1002.into(),
+        max_expires_at: 1098.into(),
+        user_properties,
+    };
+    assert_eq!(table.properties(), &expected);
+
+    let reopened = builder.open(table.id(), None).await.unwrap().unwrap();
+    assert_eq!(reopened.properties(), &expected);
+}
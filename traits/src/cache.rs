@@ -1,9 +1,11 @@
+use std::error::Error;
+
 use mors_common::file_id::SSTableId;
 
 use crate::sstable::BlockIndex;
 
 pub trait CacheTrait: Sized + Send + Sync + Clone + 'static {
-    type ErrorType;
+    type ErrorType: Error + 'static;
     type CacheBuilder: CacheBuilder<Self>;
 }
 pub trait CacheBuilder<C: CacheTrait>: Default {
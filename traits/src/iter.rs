@@ -1,5 +1,5 @@
-use mors_common::kv::{Meta, ValueMeta};
-use mors_common::ts::{KeyTs, KeyTsBorrow};
+use mors_common::kv::{Meta, RangeTombstone, ValueMeta};
+use mors_common::ts::{KeyTs, KeyTsBorrow, TxnTs};
 use std::cmp::Ordering;
 use std::error::Error;
 use std::fmt::Display;
@@ -145,44 +145,47 @@ pub trait KvSeekIter: CacheIterator {
     fn seek(&mut self, k: KeyTsBorrow<'_>) -> Result<bool>;
 }
 
-pub struct KvCacheMergeNode {
+pub struct KvCacheMergeNode<'a> {
     valid: bool,
-    iter: Box<dyn KvCacheIterator<ValueMeta>>,
+    iter: Box<dyn KvCacheIterator<ValueMeta> + 'a>,
 }
-impl Deref for KvCacheMergeNode {
-    type Target = Box<dyn KvCacheIterator<ValueMeta>>;
+impl<'a> Deref for KvCacheMergeNode<'a> {
+    type Target = Box<dyn KvCacheIterator<ValueMeta> + 'a>;
 
     fn deref(&self) -> &Self::Target {
         &self.iter
     }
 }
-impl DerefMut for KvCacheMergeNode {
+impl<'a> DerefMut for KvCacheMergeNode<'a> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.iter
     }
 }
-impl From<Box<dyn KvCacheIterator<ValueMeta>>> for KvCacheMergeNode {
-    fn from(iter: Box<dyn KvCacheIterator<ValueMeta>>) -> Self {
+impl<'a> From<Box<dyn KvCacheIterator<ValueMeta> + 'a>>
+    for KvCacheMergeNode<'a>
+{
+    fn from(iter: Box<dyn KvCacheIterator<ValueMeta> + 'a>) -> Self {
         Self { valid: true, iter }
     }
 }
-impl From<KvCacheMergeIterator> for KvCacheMergeNode {
-    fn from(value: KvCacheMergeIterator) -> Self {
+impl<'a> From<KvCacheMergeIterator<'a>> for KvCacheMergeNode<'a> {
+    fn from(value: KvCacheMergeIterator<'a>) -> Self {
         Self {
             valid: true,
             iter: Box::new(value),
         }
     }
 }
-pub struct KvCacheMergeIterator {
-    left: KvCacheMergeNode,
-    right: Option<KvCacheMergeNode>,
+/// merges iterators borrowing for `'a`, like the ones of skip lists.
+pub struct KvCacheMergeIterator<'a> {
+    left: KvCacheMergeNode<'a>,
+    right: Option<KvCacheMergeNode<'a>>,
     temp_key: Vec<u8>,
     left_small: bool,
 }
-impl KvCacheMergeIterator {
+impl<'a> KvCacheMergeIterator<'a> {
     pub fn new(
-        mut iters: Vec<Box<dyn KvCacheIterator<ValueMeta>>>,
+        mut iters: Vec<Box<dyn KvCacheIterator<ValueMeta> + 'a>>,
     ) -> Option<Self> {
         let new = |left, right| Self {
             left,
@@ -210,7 +213,7 @@ impl KvCacheMergeIterator {
             }
         }
     }
-    fn smaller(&self) -> &KvCacheMergeNode {
+    fn smaller(&self) -> &KvCacheMergeNode<'a> {
         if self.left_small {
             &self.left
         } else {
@@ -220,21 +223,21 @@ impl KvCacheMergeIterator {
     pub fn valid(&self) -> bool {
         self.smaller().valid
     }
-    fn smaller_mut(&mut self) -> &mut KvCacheMergeNode {
+    fn smaller_mut(&mut self) -> &mut KvCacheMergeNode<'a> {
         if self.left_small {
             &mut self.left
         } else {
             self.right.as_mut().unwrap()
         }
     }
-    fn bigger(&self) -> &KvCacheMergeNode {
+    fn bigger(&self) -> &KvCacheMergeNode<'a> {
         if self.left_small {
             self.right.as_ref().unwrap()
         } else {
             &self.left
         }
     }
-    fn bigger_mut(&mut self) -> &mut KvCacheMergeNode {
+    fn bigger_mut(&mut self) -> &mut KvCacheMergeNode<'a> {
         if self.left_small {
             self.right.as_mut().unwrap()
         } else {
@@ -242,7 +245,7 @@ impl KvCacheMergeIterator {
         }
     }
 }
-impl CacheIterator for KvCacheMergeIterator {
+impl CacheIterator for KvCacheMergeIterator<'_> {
     fn next(&mut self) -> Result<bool> {
         while self.smaller().valid {
             if let Some(k) = self.smaller().key() {
@@ -280,7 +283,7 @@ impl CacheIterator for KvCacheMergeIterator {
         Ok(false)
     }
 }
-impl KvCacheIter<ValueMeta> for KvCacheMergeIterator {
+impl KvCacheIter<ValueMeta> for KvCacheMergeIterator<'_> {
     fn key(&self) -> Option<KeyTsBorrow<'_>> {
         self.smaller().key()
     }
@@ -289,15 +292,15 @@ impl KvCacheIter<ValueMeta> for KvCacheMergeIterator {
         self.smaller().value()
     }
 }
-impl KvSeekIter for KvCacheMergeIterator {
+impl KvSeekIter for KvCacheMergeIterator<'_> {
     fn seek(&mut self, k: KeyTsBorrow<'_>) -> Result<bool> {
-        let left = self.left.seek(k)?;
-        let right = match self.right.as_mut() {
-            Some(r) => r.seek(k)?,
-            None => false,
-        };
+        self.left.valid = self.left.seek(k)?;
+        if let Some(right) = self.right.as_mut() {
+            right.valid = right.seek(k)?;
+        }
+        self.left_small = true;
 
-        if self.bigger().valid {
+        if self.right.is_some() && self.bigger().valid {
             if !self.smaller().valid {
                 self.left_small = !self.left_small;
             } else {
@@ -306,7 +309,8 @@ impl KvSeekIter for KvCacheMergeIterator {
                 match smaller_key.cmp(&bigger_key) {
                     Ordering::Less => {}
                     Ordering::Equal => {
-                        self.bigger_mut().next()?;
+                        let valid = self.bigger_mut().next()?;
+                        self.bigger_mut().valid = valid;
                     }
                     Ordering::Greater => {
                         self.left_small = !self.left_small;
@@ -315,15 +319,146 @@ impl KvSeekIter for KvCacheMergeIterator {
             }
         }
 
-        if left || right {
-            self.temp_key = k.as_ref().to_vec();
-            Ok(true)
-        } else {
-            Ok(false)
+        // the next call moves past the key sought to.
+        match self.smaller().key() {
+            Some(key) if self.smaller().valid => {
+                self.temp_key = key.to_vec();
+                Ok(true)
+            }
+            _ => Ok(false),
         }
     }
 }
-impl KvCacheIterator<ValueMeta> for KvCacheMergeIterator {}
+impl KvCacheIterator<ValueMeta> for KvCacheMergeIterator<'_> {}
+
+/// hides the versions deleted by a range tombstone visible at `read_ts`.
+///
+/// Tombstones of one memtable or level also delete keys of the older ones,
+/// so readers wrap the merge of every source with the tombstones of all of
+/// them. Compaction iterates every version and decides itself.
+pub struct RangeDeleteIter<I> {
+    iter: I,
+    // the tombstones visible at the read timestamp, by start.
+    tombstones: Vec<RangeTombstone>,
+    // tombstones[..next] start at or before the current key.
+    next: usize,
+    // those of them still containing the current key.
+    active: Vec<RangeTombstone>,
+    max_version: Option<TxnTs>,
+}
+impl<I: KvCacheIterator<ValueMeta>> RangeDeleteIter<I> {
+    pub fn new(
+        iter: I,
+        mut tombstones: Vec<RangeTombstone>,
+        read_ts: TxnTs,
+    ) -> Self {
+        tombstones.retain(|t| t.version() <= read_ts);
+        tombstones.sort_by(|a, b| a.start().cmp(b.start()));
+        Self {
+            iter,
+            tombstones,
+            next: 0,
+            active: Vec::new(),
+            max_version: None,
+        }
+    }
+    /// the newest version `iter` yields. A tombstone above it deletes
+    /// everything in its range, which is then skipped with one seek
+    /// instead of one per key.
+    pub fn set_max_version(&mut self, max_version: TxnTs) -> &mut Self {
+        self.max_version = Some(max_version);
+        self
+    }
+    // the tombstones containing `key` when the iterator moved forward to it.
+    fn advance(&mut self, key: &[u8]) {
+        while let Some(t) = self.tombstones.get(self.next) {
+            if t.start().as_ref() > key {
+                break;
+            }
+            self.active.push(t.clone());
+            self.next += 1;
+        }
+        self.active.retain(|t| t.contains(key));
+    }
+    // the tombstones containing `key` after a seek, backward or forward.
+    fn reset(&mut self, key: &[u8]) {
+        self.next = self
+            .tombstones
+            .partition_point(|t| t.start().as_ref() <= key);
+        self.active = self.tombstones[..self.next]
+            .iter()
+            .filter(|t| t.contains(key))
+            .cloned()
+            .collect();
+    }
+    // moves past the covered versions, from the current one on. The older
+    // versions of a covered key are covered too, so it seeks to the next
+    // key, or past the range if nothing in it is newer than the tombstone.
+    fn skip_covered(&mut self) -> Result<bool> {
+        loop {
+            let Some(k) = self.iter.key() else {
+                return Ok(false);
+            };
+            let (mut key, txn_ts) = (k.key().to_vec(), k.txn_ts());
+            self.advance(&key);
+            let mut covering = self
+                .active
+                .iter()
+                .filter(|t| t.version() > txn_ts)
+                .peekable();
+            if covering.peek().is_none() {
+                return Ok(true);
+            }
+            let max_version = self.max_version;
+            let end = covering
+                .filter(|t| max_version.is_some_and(|m| t.version() > m))
+                .map(|t| t.end().clone())
+                .max();
+            let seek = match end {
+                Some(end) => KeyTs::new(end, u64::MAX.into()),
+                None => {
+                    key.push(0);
+                    KeyTs::new(key.into(), u64::MAX.into())
+                }
+            };
+            if !self.iter.seek(seek.encode().as_slice().into())? {
+                return Ok(false);
+            }
+        }
+    }
+}
+impl<I: KvCacheIterator<ValueMeta>> CacheIterator for RangeDeleteIter<I> {
+    fn next(&mut self) -> Result<bool> {
+        if !self.iter.next()? {
+            return Ok(false);
+        }
+        self.skip_covered()
+    }
+}
+impl<I: KvCacheIterator<ValueMeta>> KvCacheIter<ValueMeta>
+    for RangeDeleteIter<I>
+{
+    fn key(&self) -> Option<KeyTsBorrow<'_>> {
+        self.iter.key()
+    }
+
+    fn value(&self) -> Option<ValueMeta> {
+        self.iter.value()
+    }
+}
+impl<I: KvCacheIterator<ValueMeta>> KvSeekIter for RangeDeleteIter<I> {
+    fn seek(&mut self, k: KeyTsBorrow<'_>) -> Result<bool> {
+        self.reset(k.key());
+        if !self.iter.seek(k)? {
+            return Ok(false);
+        }
+        self.skip_covered()
+    }
+}
+impl<I: KvCacheIterator<ValueMeta>> KvCacheIterator<ValueMeta>
+    for RangeDeleteIter<I>
+{
+}
 
 #[derive(Error, Debug)]
 pub struct IterError(Box<dyn Error>);
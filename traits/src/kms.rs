@@ -4,6 +4,7 @@ use std::fmt::Debug;
 use std::{
     fmt::Display,
     ops::{Add, AddAssign},
+    str::FromStr,
 };
 use thiserror::Error;
 
@@ -37,6 +38,49 @@ impl AddAssign<u64> for CipherKeyId {
         self.0 += rhs
     }
 }
+/// Authenticated cipher a data key is used with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EncryptionAlgo {
+    #[default]
+    AesGcm = 0,
+    /// nonce-misuse-resistant AES-GCM-SIV.
+    AesGcmSiv = 1,
+    /// faster than AES on CPUs without AES-NI, only takes 32 byte keys.
+    ChaCha20Poly1305 = 2,
+}
+impl From<EncryptionAlgo> for u8 {
+    fn from(value: EncryptionAlgo) -> Self {
+        value as u8
+    }
+}
+/// parses the names taken by the cmd flags, like `aes-gcm-siv`.
+impl FromStr for EncryptionAlgo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "aes-gcm" => Ok(Self::AesGcm),
+            "aes-gcm-siv" => Ok(Self::AesGcmSiv),
+            "chacha20-poly1305" => Ok(Self::ChaCha20Poly1305),
+            s => Err(format!(
+                "unknown encryption algorithm {s}, expected aes-gcm, \
+                 aes-gcm-siv or chacha20-poly1305"
+            )),
+        }
+    }
+}
+impl TryFrom<u8> for EncryptionAlgo {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::AesGcm),
+            1 => Ok(Self::AesGcmSiv),
+            2 => Ok(Self::ChaCha20Poly1305),
+            v => Err(v),
+        }
+    }
+}
 pub trait Kms: Clone + Send + Debug + Sync + 'static {
     type ErrorType: Into<KmsError>;
     type Cipher: KmsCipher;
@@ -46,14 +90,24 @@ pub trait Kms: Clone + Send + Debug + Sync + 'static {
         key_id: CipherKeyId,
     ) -> Result<Option<Self::Cipher>, KmsError>;
     fn latest_cipher(&self) -> Result<Option<Self::Cipher>, KmsError>;
+    /// ids of all data keys in the registry.
+    fn data_key_ids(&self) -> Result<Vec<CipherKeyId>, KmsError>;
+    /// drops `key_ids` from the registry, the latest data key is always kept
+    /// and so are the keys a cipher returned above is still alive of, like
+    /// the ones of tables being written.
+    fn retire_data_keys(&self, key_ids: &[CipherKeyId])
+        -> Result<(), KmsError>;
 }
 pub trait KmsCipher: Sized + Send + Sync + 'static + Clone {
     type ErrorType: Into<EncryptError>;
 
     fn cipher_key_id(&self) -> CipherKeyId;
+    fn encryption_algo(&self) -> EncryptionAlgo;
 
     fn generate_nonce() -> Vec<u8>;
     const NONCE_SIZE: usize;
+    /// bytes the ciphertext is longer than the plaintext.
+    const TAG_SIZE: usize;
     fn decrypt_with_slice(
         &self,
         nonce: &[u8],
@@ -71,6 +125,8 @@ pub trait KmsCipher: Sized + Send + Sync + 'static + Clone {
 }
 pub trait KmsBuilder<K: Kms>: Default + WithDir + WithReadOnly {
     fn build(&self) -> Result<K, KmsError>;
+    /// algorithm of newly created data keys.
+    fn set_encryption_algo(&mut self, algo: EncryptionAlgo) -> &mut Self;
 }
 #[derive(Error, Debug)]
 pub struct KmsError(Box<dyn Error>);
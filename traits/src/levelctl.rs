@@ -1,14 +1,21 @@
 use crate::default::{WithDir, WithReadOnly};
+use crate::iter::KvCacheIterator;
 use crate::vlog::DiscardTrait;
-use crate::{kms::Kms, sstable::TableTrait};
+use crate::{
+    kms::{CipherKeyId, Kms},
+    sstable::{TablePropertiesCollectorFactory, TableTrait},
+};
+use bytes::Bytes;
 use mors_common::closer::Closer;
-use mors_common::kv::ValueMeta;
+use mors_common::compress::CompressionType;
+use mors_common::kv::{ColumnFamilyId, RangeTombstone, ValueMeta};
 use mors_common::ts::{KeyTs, TxnTs};
+use std::collections::{BTreeMap, HashSet};
 use std::error::Error;
 use std::sync::atomic::AtomicU32;
 use std::sync::Arc;
 use std::{
-    fmt::Display,
+    fmt::{Debug, Display},
     ops::{Add, AddAssign, Sub},
 };
 use thiserror::Error;
@@ -21,28 +28,77 @@ pub trait LevelCtlTrait<T: TableTrait<K::Cipher>, K: Kms>:
     fn max_version(&self) -> TxnTs;
     fn table_builder(&self) -> &T::TableBuilder;
     fn next_id(&self) -> Arc<AtomicU32>;
+    /// data keys of the live tables and blob files, the default id stands
+    /// for plaintext.
+    fn cipher_key_ids(&self) -> HashSet<CipherKeyId>;
     fn push_level0(
         &self,
         table: T,
     ) -> impl std::future::Future<Output = Result<(), LevelCtlError>> + Send;
+    /// adds tables built outside the db, each to the deepest level that
+    /// neither it nor any level above it overlaps with, or level 0.
+    /// The tables must not overlap each other.
+    fn ingest(
+        &self,
+        tables: Vec<T>,
+    ) -> impl std::future::Future<Output = Result<(), LevelCtlError>> + Send;
     fn get(
         &self,
         key: &KeyTs,
     ) -> impl std::future::Future<
         Output = Result<Option<(TxnTs, Option<ValueMeta>)>, LevelCtlError>,
     > + Send;
+    /// iterators over the tables of every level, newest first, and the
+    /// range tombstones of the levels. Readers hide what these and the
+    /// ones of the memtables cover with [`RangeDeleteIter`].
+    ///
+    /// [`RangeDeleteIter`]: crate::iter::RangeDeleteIter
+    fn iters(
+        &self,
+    ) -> (Vec<Box<dyn KvCacheIterator<ValueMeta>>>, Vec<RangeTombstone>);
+    /// like [`Self::iters`], over the tables that may hold `key` only and
+    /// the range tombstones containing it.
+    fn key_iters(
+        &self,
+        key: &KeyTs,
+    ) -> (Vec<Box<dyn KvCacheIterator<ValueMeta>>>, Vec<RangeTombstone>);
+    /// replaces a value the iterators yield pointing into a blob file by
+    /// the value itself.
+    fn resolve_blob(
+        &self,
+        value: ValueMeta,
+    ) -> Result<ValueMeta, LevelCtlError>;
     fn spawn_compact<D: DiscardTrait>(
         self,
         closer: Closer,
         kms: K,
         discard: D,
     ) -> impl std::future::Future<Output = ()> + Send;
+    /// tells compaction which versions the readers still need, until it is
+    /// set only the newest versions of every key are kept.
+    fn set_snapshot_list(&self, list: Arc<dyn SnapshotList>);
+    /// column families recorded in the manifest and not dropped, by id.
+    /// Only the level controller of the default family records them.
+    fn column_families(
+        &self,
+    ) -> impl std::future::Future<Output = BTreeMap<ColumnFamilyId, Arc<str>>>
+           + Send;
+    /// records a column family in the manifest, returning the new id.
+    fn create_column_family(
+        &self,
+        name: &str,
+    ) -> impl std::future::Future<Output = Result<ColumnFamilyId, LevelCtlError>>
+           + Send;
+    fn drop_column_family(
+        &self,
+        id: ColumnFamilyId,
+    ) -> impl std::future::Future<Output = Result<(), LevelCtlError>> + Send;
 }
 pub trait LevelCtlBuilderTrait<
     L: LevelCtlTrait<T, K>,
     T: TableTrait<K::Cipher>,
     K: Kms,
->: Default + WithDir + WithReadOnly
+>: Default + WithDir + WithReadOnly + Clone + Send + Sync
 {
     fn build(
         &self,
@@ -50,6 +106,69 @@ pub trait LevelCtlBuilderTrait<
     ) -> impl std::future::Future<Output = Result<L, LevelCtlError>>;
     fn set_cache(&mut self, cache: T::Cache) -> &mut Self;
     fn set_level0_table_size(&mut self, size: usize) -> &mut Self;
+    /// compression of the tables built by this level controller.
+    fn set_compression(&mut self, compression: CompressionType) -> &mut Self;
+    /// rewrites the tables not encrypted with the latest data key
+    /// whenever the compactors are idle, default false.
+    fn set_reencryption(&mut self, reencryption: bool) -> &mut Self;
+    /// moves values of at least `threshold` bytes out of the tables into
+    /// blob files when flushing and compacting, default None.
+    fn set_blob_threshold(&mut self, threshold: Option<usize>) -> &mut Self;
+    /// compactions move the values they keep out of the oldest `cutoff`
+    /// of the live blob files, so partly dead ones are eventually dropped,
+    /// default 0.25.
+    fn set_blob_gc_age_cutoff(&mut self, cutoff: f64) -> &mut Self;
+    /// gathers user properties of every table written, see
+    /// [`TableBuilderTrait::add_properties_collector`].
+    ///
+    /// [`TableBuilderTrait::add_properties_collector`]: crate::sstable::TableBuilderTrait::add_properties_collector
+    fn add_properties_collector(
+        &mut self,
+        factory: Arc<dyn TablePropertiesCollectorFactory>,
+    ) -> &mut Self;
+    /// decides what compaction does with the versions at or below the
+    /// discard timestamp it would otherwise keep, default None.
+    /// See [`SnapshotList`].
+    fn set_compaction_filter(
+        &mut self,
+        filter: Option<Arc<dyn CompactionFilter>>,
+    ) -> &mut Self;
+}
+/// the versions compaction keeps besides the newest ones of every key.
+///
+/// Of the versions at or below a snapshot only the newest is read by it.
+/// The discard timestamp is the lower of the retained timestamp and the one
+/// below the oldest snapshot, no reader reads at or below it anymore.
+pub trait SnapshotList: Send + Sync + 'static {
+    /// read timestamps of the live snapshots, ascending.
+    fn snapshots(&self) -> Vec<TxnTs>;
+    /// every version above it is kept, `u64::MAX` if none has to be.
+    fn retained_since(&self) -> TxnTs;
+}
+/// what compaction does with a version given to a [`CompactionFilter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompactionDecision {
+    Keep,
+    /// drops the version and the older ones of the key, like a delete.
+    Remove,
+    /// keeps the version with another value.
+    Replace(Bytes),
+}
+/// drops or rewrites versions while compacting, without writing to the db.
+pub trait CompactionFilter: Debug + Send + Sync + 'static {
+    /// called for the versions that are neither deleted nor expired, in key
+    /// order, newest version first. Values in the vlog are given as a
+    /// [`ValuePointer`] with [`Meta::VALUE_POINTER`] set.
+    ///
+    /// [`ValuePointer`]: mors_common::kv::ValuePointer
+    /// [`Meta::VALUE_POINTER`]: mors_common::kv::Meta::VALUE_POINTER
+    fn filter(
+        &self,
+        level: Level,
+        key: &[u8],
+        version: TxnTs,
+        value: &ValueMeta,
+    ) -> CompactionDecision;
 }
 #[derive(Error, Debug)]
 pub struct LevelCtlError(Box<dyn Error>);
@@ -4,6 +4,7 @@ pub mod iter;
 pub mod kms;
 pub mod levelctl;
 pub mod memtable;
+pub mod recovery;
 pub mod skip_list;
 pub mod sstable;
 pub mod vlog;
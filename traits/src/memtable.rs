@@ -1,8 +1,9 @@
 use crate::default::{WithDir, WithReadOnly};
-use crate::kms::Kms;
+use crate::kms::{CipherKeyId, Kms};
+use crate::recovery::LogRecovery;
 use crate::skip_list::SkipListTrait;
-use mors_common::file_id::MemtableId;
-use mors_common::kv::{Entry, ValueMeta};
+use mors_common::file_id::{MemtableId, VlogId};
+use mors_common::kv::{ColumnFamilyId, Entry, RangeTombstone, ValueMeta};
 use mors_common::ts::{KeyTs, TxnTs};
 use std::collections::VecDeque;
 use std::error::Error;
@@ -17,14 +18,25 @@ pub trait MemtableTrait<T: SkipListTrait, K: Kms>:
     type MemtableBuilder: MemtableBuilderTrait<Self, T, K>;
     fn get(
         &self,
+        cf: ColumnFamilyId,
         key: &KeyTs,
     ) -> Result<Option<(TxnTs, Option<ValueMeta>)>, MemtableError>;
     fn push(&self, entry: &Entry) -> Result<(), MemtableError>;
+    /// pushes `entries` as one wal batch, replayed all or nothing.
+    fn push_batch(&self, entries: &[&Entry]) -> Result<(), MemtableError>;
     fn size(&self) -> usize;
     fn is_full(&self) -> bool;
     fn id(&self) -> MemtableId;
     fn max_version(&self) -> TxnTs;
-    fn skip_list(&self) -> T;
+    /// data key of the wal, the default id stands for plaintext.
+    fn cipher_key_id(&self) -> CipherKeyId;
+    /// range tombstones written to `cf`, readers hide what they cover with
+    /// [`RangeDeleteIter`].
+    ///
+    /// [`RangeDeleteIter`]: crate::iter::RangeDeleteIter
+    fn range_tombstones(&self, cf: ColumnFamilyId) -> Vec<RangeTombstone>;
+    /// skip list of every column family written to this memtable.
+    fn skip_lists(&self) -> Vec<(ColumnFamilyId, T)>;
     fn flush(&self) -> Result<(), MemtableError>;
     fn delete_wal(&self) -> Result<(), MemtableError>;
 }
@@ -36,13 +48,22 @@ pub trait MemtableBuilderTrait<
 {
     fn open(&self, kms: K, id: MemtableId) -> Result<M, MemtableError>;
 
-    fn open_exist(&self, kms: K) -> Result<VecDeque<Arc<M>>, MemtableError>;
+    /// reopens the memtables left by the last run from their wals, cutting
+    /// what a crash left incomplete. `vlog_tail` is the valid end of the
+    /// vlog file written last, batches with values past it are dropped.
+    fn open_exist(
+        &self,
+        kms: K,
+        vlog_tail: Option<(VlogId, usize)>,
+    ) -> Result<(VecDeque<Arc<M>>, Vec<LogRecovery>), MemtableError>;
 
     fn build(&self, kms: K) -> Result<M, MemtableError>;
     fn max_batch_size(&self) -> usize;
     fn max_batch_count(&self) -> usize;
     fn set_num_memtables(&mut self, num_memtables: usize);
     fn set_memtable_size(&mut self, memtable_size: usize);
+    /// options of the skip lists of memtables built from now on.
+    fn set_list_options(&mut self, options: T::Options);
 }
 #[derive(Error, Debug)]
 pub struct MemtableError(Box<dyn Error>);
@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+
+/// What replaying a log file on open cut from it.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LogRecovery {
+    pub path: PathBuf,
+    /// offset after the last complete record, the file is truncated there.
+    pub valid_len: usize,
+    /// bytes cut from the end of the file, after a crash this includes the
+    /// preallocated space.
+    pub truncated: usize,
+    /// entries dropped because their transaction was not complete or
+    /// their values were in the cut vlog tail.
+    pub discarded_entries: usize,
+}
+impl LogRecovery {
+    pub fn is_clean(&self) -> bool {
+        self.discarded_entries == 0
+    }
+}
+/// What was discarded while reopening the db.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// memtable wals replayed, including the empty ones that got deleted.
+    pub memtables: Vec<LogRecovery>,
+    /// the vlog file written last before the db was closed.
+    pub vlog: Option<LogRecovery>,
+}
+impl RecoveryReport {
+    /// no entry was discarded.
+    pub fn is_clean(&self) -> bool {
+        self.memtables
+            .iter()
+            .chain(self.vlog.iter())
+            .all(|r| r.is_clean())
+    }
+    pub fn discarded_entries(&self) -> usize {
+        self.memtables
+            .iter()
+            .chain(self.vlog.iter())
+            .map(|r| r.discarded_entries)
+            .sum()
+    }
+}
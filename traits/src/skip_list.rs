@@ -9,10 +9,21 @@ use thiserror::Error;
 pub type OptionKV<'a> =Option<(&'a[u8], Option<&'a[u8]>)>;
 pub trait SkipListTrait: Send + Sync + Clone + 'static {
     type ErrorType: Into<SkipListError>;
+    /// picks between variants of the list, like the index behind it.
+    type Options: Default + Clone + Send + Sync + 'static;
+    /// `max_size` is what the list is sized for, pushes past it may grow
+    /// the list instead of failing.
     fn new(
         max_size: usize,
         cmp: fn(&[u8], &[u8]) -> Ordering,
     ) -> Result<Self, SkipListError>
+    where
+        Self: Sized;
+    fn with_options(
+        max_size: usize,
+        cmp: fn(&[u8], &[u8]) -> Ordering,
+        options: &Self::Options,
+    ) -> Result<Self, SkipListError>
     where
         Self: Sized;
     fn size(&self) -> usize;
@@ -5,15 +5,17 @@ use crate::{
         CacheIter, CacheIterator, DoubleEndedCacheIter, IterError, KvCacheIter,
         KvCacheIterator, KvDoubleEndedCacheIter, KvSeekIter,
     },
-    kms::KmsCipher,
+    kms::{CipherKeyId, KmsCipher},
 };
+use bytes::Bytes;
 use mors_common::{
     compress::CompressionType,
-    file_id::SSTableId,
-    kv::ValueMeta,
-    ts::{KeyTs, KeyTsBorrow, TxnTs},
+    file_id::{BlobFileId, SSTableId},
+    kv::{RangeTombstone, ValueMeta, ValuePointer},
+    ts::{KeyTs, KeyTsBorrow, PhyTs, TxnTs},
 };
 use std::{
+    collections::BTreeMap,
     error::Error,
     fmt::{Debug, Display},
     marker::PhantomData,
@@ -38,6 +40,9 @@ pub trait TableTrait<K: KmsCipher>:
     fn smallest(&self) -> &KeyTs;
     fn biggest(&self) -> &KeyTs;
     fn max_version(&self) -> TxnTs;
+    /// version every key of an ingested table is read at, see
+    /// [`TableBuilderTrait::set_global_version`].
+    fn global_version(&self) -> Option<TxnTs>;
     fn create_time(&self) -> SystemTime;
     fn cipher(&self) -> Option<&K>;
     fn compression(&self) -> CompressionType;
@@ -45,12 +50,23 @@ pub trait TableTrait<K: KmsCipher>:
         builder: Self::TableBuilder,
         cipher: Option<K>,
     ) -> Self::TableWriter;
+    /// deletes the table file, once the readers still holding a clone of
+    /// the table drop it.
     fn delete(&self) -> Result<(), SSTableError>;
     fn iter(
         &self,
         use_cache: bool,
     ) -> impl KvCacheIterator<ValueMeta> + 'static;
     fn may_contain(&self, key: &[u8]) -> bool;
+    /// range tombstones written to this table, see [`RangeTombstone`].
+    fn range_tombstones(&self) -> &[RangeTombstone];
+    /// blob files values of this table point into, see
+    /// [`TableBuilderTrait::set_blob_threshold`].
+    fn blob_files(&self) -> &[BlobFileId];
+    /// statistics gathered while the table was written.
+    fn properties(&self) -> &TableProperties;
+    /// first key and on-disk length of every block, in key order.
+    fn block_offsets(&self) -> Result<Vec<(KeyTs, usize)>, SSTableError>;
 }
 pub trait TableBuilderTrait<T: TableTrait<K>, K: KmsCipher>:
     Default + Clone + Send + Sync + 'static + WithDir + WithReadOnly
@@ -59,6 +75,34 @@ pub trait TableBuilderTrait<T: TableTrait<K>, K: KmsCipher>:
     fn set_cache(&mut self, cache: T::Cache) -> &mut Self;
     fn set_table_size(&mut self, size: usize) -> &mut Self;
     fn table_size(&self) -> usize;
+    /// tables opened from now on read every key at `version` instead of
+    /// the one written, for tables built outside the db and ingested.
+    fn set_global_version(&mut self, version: Option<TxnTs>) -> &mut Self;
+    /// writers move values of at least `threshold` bytes into a blob file
+    /// written next to their table, `None` keeps values in the tables.
+    fn set_blob_threshold(&mut self, threshold: Option<usize>) -> &mut Self;
+    fn blob_threshold(&self) -> Option<usize>;
+    /// data key the blob file `id` is encrypted with, the default id
+    /// stands for plaintext.
+    fn blob_cipher_key_id(
+        &self,
+        id: BlobFileId,
+    ) -> Result<CipherKeyId, SSTableError>;
+    /// the value `vp` points to in its blob file.
+    fn read_blob(
+        &self,
+        vp: &ValuePointer,
+        cipher: Option<&K>,
+    ) -> Result<Bytes, SSTableError>;
+    /// deletes the blob file `id`, the tables opened pointing into it keep
+    /// it until they are dropped.
+    fn delete_blob(&self, id: BlobFileId) -> Result<(), SSTableError>;
+    /// writers store what the collectors created by `factory` gather in
+    /// [`TableProperties::user_properties`].
+    fn add_properties_collector(
+        &mut self,
+        factory: Arc<dyn TablePropertiesCollectorFactory>,
+    ) -> &mut Self;
     fn open(
         &self,
         id: SSTableId,
@@ -86,11 +130,77 @@ pub trait TableWriterTrait: Send + Sync + 'static {
         value: &ValueMeta,
         vptr_len: Option<u32>,
     );
+    /// values of at least the blob threshold go to the blob file `id`,
+    /// written by [`Self::flush_to_disk`] before the table.
+    fn set_blob_file(&mut self, id: BlobFileId);
     fn flush_to_disk(
         &mut self,
         path: PathBuf,
     ) -> impl std::future::Future<Output = Result<(), SSTableError>> + Send;
 }
+/// statistics of a table, written with its index.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TableProperties {
+    /// entries with [`Meta::DELETE`], range tombstones included.
+    ///
+    /// [`Meta::DELETE`]: mors_common::kv::Meta::DELETE
+    pub num_deletions: usize,
+    /// entries with [`Meta::MERGE_ENTRY`].
+    ///
+    /// [`Meta::MERGE_ENTRY`]: mors_common::kv::Meta::MERGE_ENTRY
+    pub num_merge_entries: usize,
+    pub min_version: TxnTs,
+    pub max_version: TxnTs,
+    /// bounds of the expiration of the entries with a ttl, zero if none.
+    pub min_expires_at: PhyTs,
+    pub max_expires_at: PhyTs,
+    /// what the collectors added to the table builder gathered.
+    pub user_properties: BTreeMap<String, Bytes>,
+}
+/// gathers user defined statistics over the entries of one table.
+pub trait TablePropertiesCollector: Send + Sync {
+    /// called for every entry written, in key order.
+    fn add(&mut self, key: &KeyTsBorrow, value: &ValueMeta);
+    /// called once the table is done, to put what was gathered in
+    /// `properties`.
+    fn finish(&mut self, properties: &mut BTreeMap<String, Bytes>);
+}
+/// creates a collector for every table written.
+pub trait TablePropertiesCollectorFactory:
+    Debug + Send + Sync + 'static
+{
+    fn create(&self) -> Box<dyn TablePropertiesCollector>;
+}
+/// a table file built outside the db, with what is needed to open it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalFile {
+    path: PathBuf,
+    compression: CompressionType,
+    cipher_key_id: Option<CipherKeyId>,
+}
+impl ExternalFile {
+    pub fn new(
+        path: PathBuf,
+        compression: CompressionType,
+        cipher_key_id: Option<CipherKeyId>,
+    ) -> Self {
+        Self {
+            path,
+            compression,
+            cipher_key_id,
+        }
+    }
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+    pub fn compression(&self) -> CompressionType {
+        self.compression
+    }
+    /// data key the file is encrypted with, `None` for plaintext.
+    pub fn cipher_key_id(&self) -> Option<CipherKeyId> {
+        self.cipher_key_id
+    }
+}
 pub struct CacheTableConcatIter<T: TableTrait<K>, K: KmsCipher> {
     index: Option<usize>,
     back_index: Option<usize>,
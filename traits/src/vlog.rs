@@ -1,9 +1,17 @@
 use crate::{
     default::{WithDir, WithReadOnly},
-    kms::Kms,
+    kms::{CipherKeyId, Kms},
+    recovery::LogRecovery,
+};
+use bytes::Bytes;
+use mors_common::{
+    file_id::VlogId,
+    kv::{Entry, ValuePointer},
+    ts::TxnTs,
+};
+use std::{
+    collections::HashSet, error::Error, fmt::Display, io, slice::IterMut,
 };
-use mors_common::kv::{Entry, ValuePointer};
-use std::{error::Error, fmt::Display, io, slice::IterMut};
 use thiserror::Error;
 
 pub trait VlogCtlTrait<K: Kms>: Sized + Send + Sync + 'static {
@@ -14,10 +22,41 @@ pub trait VlogCtlTrait<K: Kms>: Sized + Send + Sync + 'static {
     fn writeable_offset(&self) -> usize;
     fn vlog_file_size(&self) -> usize;
     fn value_threshold(&self) -> usize;
+    /// the policy deciding which values go to the vlog.
+    fn value_separation(&self) -> &ValueSeparation;
     fn write<'a>(
         &self,
         iter_mut: Vec<IterMut<'a, (Entry, ValuePointer)>>,
     ) -> impl std::future::Future<Output = Result<(), VlogError>> + Send;
+    /// data keys of the vlog files, the default id stands for plaintext.
+    fn cipher_key_ids(&self) -> Result<HashSet<CipherKeyId>, VlogError>;
+    /// vlog files not encrypted with `latest`, except the one being written,
+    /// which is rolled over on the next write instead.
+    fn stale_files(
+        &self,
+        latest: CipherKeyId,
+    ) -> Result<Vec<VlogId>, VlogError>;
+    /// entries of the vlog file `id` from `offset` on, at least one and about
+    /// `max_size` bytes, and the offset after them. Empty at the end of file.
+    fn read_entries(
+        &self,
+        id: VlogId,
+        offset: usize,
+        max_size: usize,
+    ) -> Result<(Vec<(Entry, ValuePointer)>, usize), VlogError>;
+    /// removes the vlog file `id`, which must not be the one being written,
+    /// from the live files. Readers at or below `read_ts` may still hold
+    /// pointers into it, it is unlinked by [`Self::purge_deleted`].
+    fn delete_file(&self, id: VlogId, read_ts: TxnTs) -> Result<(), VlogError>;
+    /// unlinks the deleted files no reader can point into anymore, given
+    /// the read timestamp of the oldest reader, None if there is none.
+    fn purge_deleted(
+        &self,
+        oldest_read_ts: Option<TxnTs>,
+    ) -> Result<(), VlogError>;
+    /// what opening cut from the vlog file written last, with its id.
+    /// None if there was none or the vlog is read only.
+    fn recovery(&self) -> Option<(VlogId, LogRecovery)>;
     const MAX_VLOG_SIZE: usize;
     const MAX_VLOG_FILE_SIZE: usize;
 }
@@ -29,6 +68,78 @@ pub trait VlogCtlBuilderTrait<V: VlogCtlTrait<K>, K: Kms>:
         kms: K,
     ) -> impl std::future::Future<Output = Result<V, VlogError>>;
     fn build_discard(&self) -> Result<V::Discard, VlogError>;
+    fn set_value_separation(
+        &mut self,
+        separation: ValueSeparation,
+    ) -> &mut Self;
+}
+/// Where the values of a key prefix are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Separation {
+    /// kept in the LSM tree next to their key, for keys scanned often.
+    Inline,
+    /// written to the vlog, the LSM tree holding a pointer to them.
+    Separate,
+}
+/// Decides which values are written to the vlog, on top of the value
+/// threshold the vlog adjusts to the sizes of the values written.
+#[derive(Debug, Clone, Default)]
+pub struct ValueSeparation {
+    disabled: bool,
+    min_value_size: usize,
+    max_value_size: Option<usize>,
+    prefixes: Vec<(Bytes, Separation)>,
+}
+impl ValueSeparation {
+    /// keeps every value inline, nothing is written to the vlog.
+    pub fn set_disabled(&mut self, disabled: bool) -> &mut Self {
+        self.disabled = disabled;
+        self
+    }
+    /// values smaller than `size` stay inline whatever the threshold.
+    pub fn set_min_value_size(&mut self, size: usize) -> &mut Self {
+        self.min_value_size = size;
+        self
+    }
+    /// values of `size` or more go to the vlog whatever the threshold,
+    /// over `min_value_size`.
+    pub fn set_max_value_size(&mut self, size: Option<usize>) -> &mut Self {
+        self.max_value_size = size;
+        self
+    }
+    /// the non empty values of keys starting with `prefix` are written as
+    /// `separation` says whatever their size, the longest matching prefix
+    /// wins.
+    pub fn add_prefix(
+        &mut self,
+        prefix: Bytes,
+        separation: Separation,
+    ) -> &mut Self {
+        self.prefixes.retain(|(p, _)| p != &prefix);
+        self.prefixes.push((prefix, separation));
+        self
+    }
+    /// the size from which the value of `key` goes to the vlog, `threshold`
+    /// being the one of the vlog.
+    pub fn threshold(&self, key: &[u8], threshold: usize) -> usize {
+        if self.disabled {
+            return usize::MAX;
+        }
+        let rule = self
+            .prefixes
+            .iter()
+            .filter(|(p, _)| key.starts_with(p))
+            .max_by_key(|(p, _)| p.len());
+        match rule {
+            Some((_, Separation::Inline)) => usize::MAX,
+            Some((_, Separation::Separate)) => 1,
+            None => {
+                let threshold = threshold.max(self.min_value_size);
+                self.max_value_size
+                    .map_or(threshold, |max| threshold.min(max))
+            }
+        }
+    }
 }
 
 pub trait DiscardTrait: Clone + Send + Sync + 'static {
@@ -15,6 +15,8 @@ pub enum MorsVlogError {
     PoisonError(String),
     #[error("Log not found: {0}")]
     LogNotFound(VlogId),
+    #[error("Log is being written: {0}")]
+    LogInUse(VlogId),
     #[error("Threshold error: {0}")]
     ThresholdError(String),
     #[error("Send error: {0}")]
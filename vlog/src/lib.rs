@@ -4,6 +4,7 @@ pub mod vlogctl;
 pub mod error;
 pub mod discard;
 pub mod write;
+mod rewrite;
 mod threshold;
 
 type Result<T> = std::result::Result<T, MorsVlogError>;
\ No newline at end of file
@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+use std::io;
+use std::sync::atomic::Ordering;
+
+use mors_common::{
+    file_id::VlogId,
+    kv::{Entry, ValuePointer},
+    ts::TxnTs,
+};
+use mors_traits::{
+    file::StorageTrait,
+    kms::{CipherKeyId, Kms},
+};
+use mors_wal::{error::MorsWalError, read::LogFileIter};
+
+use crate::error::MorsVlogError;
+use crate::vlogctl::VlogCtl;
+use crate::Result;
+
+impl<K: Kms, S: StorageTrait> VlogCtl<K, S> {
+    pub(crate) fn cipher_key_ids_impl(&self) -> Result<HashSet<CipherKeyId>> {
+        let id_logfile = self.id_logfile().read()?;
+        let deleted = self.deleted().read()?;
+        // the deleted files are read until they are unlinked.
+        Ok(id_logfile
+            .values()
+            .chain(deleted.iter().map(|(_, log)| log))
+            .map(|log| log.cipher_key_id())
+            .collect())
+    }
+    pub(crate) fn stale_files_impl(
+        &self,
+        latest: CipherKeyId,
+    ) -> Result<Vec<VlogId>> {
+        let max_id = self.max_id()?;
+        let id_logfile = self.id_logfile().read()?;
+        let mut stale = Vec::new();
+        for (id, log) in id_logfile.iter() {
+            if log.cipher_key_id() == latest {
+                continue;
+            }
+            if *id == max_id {
+                // only the write task may switch the file being written.
+                self.roll_over().store(true, Ordering::SeqCst);
+            } else {
+                stale.push(*id);
+            }
+        }
+        Ok(stale)
+    }
+    pub(crate) fn read_entries_impl(
+        &self,
+        id: VlogId,
+        offset: usize,
+        max_size: usize,
+    ) -> Result<(Vec<(Entry, ValuePointer)>, usize)> {
+        if !self.id_logfile().read()?.contains_key(&id) {
+            return Err(MorsVlogError::LogNotFound(id));
+        }
+        // the shared handle can't be read concurrently, open another one.
+        let mut log =
+            self.builder().open_logfile::<S>(id, self.kms().clone())?;
+        let mut next = offset.max(log.header_len());
+        let mut iter = LogFileIter::new(&mut log, next);
+        let mut entries = Vec::new();
+        let mut size = 0;
+        while size < max_size {
+            match iter.read_entry() {
+                Ok((entry, vp)) => {
+                    size += vp.size() as usize;
+                    next = vp.offset() as usize + vp.size() as usize;
+                    entries.push((entry, vp));
+                }
+                Err(MorsWalError::IoError(e))
+                    if e.kind() == io::ErrorKind::UnexpectedEof =>
+                {
+                    break
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok((entries, next))
+    }
+    pub(crate) fn delete_file_impl(
+        &self,
+        id: VlogId,
+        read_ts: TxnTs,
+    ) -> Result<()> {
+        if id == self.max_id()? {
+            return Err(MorsVlogError::LogInUse(id));
+        }
+        if let Some(log) = self.id_logfile().write()?.remove(&id) {
+            self.deleted().write()?.push((read_ts, log));
+        }
+        Ok(())
+    }
+    pub(crate) fn purge_deleted_impl(
+        &self,
+        oldest_read_ts: Option<TxnTs>,
+    ) -> Result<()> {
+        let purged = {
+            let mut deleted = self.deleted().write()?;
+            let (kept, purged): (Vec<_>, Vec<_>) =
+                deleted.drain(..).partition(|(read_ts, _)| {
+                    oldest_read_ts.is_some_and(|oldest| oldest <= *read_ts)
+                });
+            *deleted = kept;
+            purged
+        };
+        for (_, log) in purged {
+            log.delete()?;
+        }
+        Ok(())
+    }
+}
@@ -1,9 +1,9 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashSet},
     marker::PhantomData,
     path::PathBuf,
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc, RwLock,
     },
 };
@@ -12,15 +12,18 @@ use log::info;
 // use memmap2::Advice;
 use mors_common::{
     file_id::{FileId, VlogId},
+    kv::{Entry, ValuePointer},
+    ts::TxnTs,
     // mmap::MmapFileBuilder,
 };
 use mors_traits::{
     default::{WithDir, WithReadOnly, DEFAULT_DIR},
     file::{StorageBuilderTrait, StorageTrait},
-    kms::Kms,
-    vlog::{VlogCtlBuilderTrait, VlogCtlTrait, VlogError},
+    kms::{CipherKeyId, Kms},
+    recovery::LogRecovery,
+    vlog::{ValueSeparation, VlogCtlBuilderTrait, VlogCtlTrait, VlogError},
 };
-use mors_wal::LogFile;
+use mors_wal::{read::LogFileIter, LogFile};
 
 use crate::Result;
 use crate::{
@@ -34,11 +37,17 @@ pub struct VlogCtl<K: Kms, S: StorageTrait> {
 }
 struct VlogCtlInner<K: Kms, S: StorageTrait> {
     id_logfile: RwLock<BTreeMap<VlogId, LogFileWrapper<K, S>>>,
+    // files removed from the live ones, with the read timestamp at or
+    // below which readers may still point into them.
+    deleted: RwLock<Vec<(TxnTs, LogFileWrapper<K, S>)>>,
     max_id: RwLock<VlogId>,
     writeable_offset: AtomicUsize,
+    // the file being written uses an old data key, roll it over.
+    roll_over: AtomicBool,
     kms: K,
     vlog_threshold: VlogThreshold,
     builder: VlogCtlBuilder<K>,
+    recovery: Option<(VlogId, LogRecovery)>,
 }
 impl<K: Kms, S: StorageTrait> VlogCtlTrait<K> for VlogCtl<K, S> {
     type ErrorType = MorsVlogError;
@@ -56,6 +65,9 @@ impl<K: Kms, S: StorageTrait> VlogCtlTrait<K> for VlogCtl<K, S> {
     fn value_threshold(&self) -> usize {
         self.inner.vlog_threshold.value_threshold()
     }
+    fn value_separation(&self) -> &ValueSeparation {
+        &self.inner.builder.value_separation
+    }
 
     const MAX_VLOG_SIZE: usize = 22;
 
@@ -72,6 +84,42 @@ impl<K: Kms, S: StorageTrait> VlogCtlTrait<K> for VlogCtl<K, S> {
     ) -> std::result::Result<(), VlogError> {
         Ok(self.write_impl(iter_mut).await?)
     }
+    fn cipher_key_ids(
+        &self,
+    ) -> std::result::Result<HashSet<CipherKeyId>, VlogError> {
+        Ok(self.cipher_key_ids_impl()?)
+    }
+    fn stale_files(
+        &self,
+        latest: CipherKeyId,
+    ) -> std::result::Result<Vec<VlogId>, VlogError> {
+        Ok(self.stale_files_impl(latest)?)
+    }
+    fn read_entries(
+        &self,
+        id: VlogId,
+        offset: usize,
+        max_size: usize,
+    ) -> std::result::Result<(Vec<(Entry, ValuePointer)>, usize), VlogError>
+    {
+        Ok(self.read_entries_impl(id, offset, max_size)?)
+    }
+    fn delete_file(
+        &self,
+        id: VlogId,
+        read_ts: TxnTs,
+    ) -> std::result::Result<(), VlogError> {
+        Ok(self.delete_file_impl(id, read_ts)?)
+    }
+    fn purge_deleted(
+        &self,
+        oldest_read_ts: Option<TxnTs>,
+    ) -> std::result::Result<(), VlogError> {
+        Ok(self.purge_deleted_impl(oldest_read_ts)?)
+    }
+    fn recovery(&self) -> Option<(VlogId, LogRecovery)> {
+        self.inner.recovery.clone()
+    }
 }
 impl<K: Kms, S: StorageTrait> VlogCtlInner<K, S> {
     fn latest_logfile(&self) -> Result<LogFileWrapper<K, S>> {
@@ -107,7 +155,7 @@ impl<K: Kms, S: StorageTrait> VlogCtl<K, S> {
         *max_id_w = id;
         self.inner
             .writeable_offset
-            .store(LogFile::<VlogId, K, S>::LOG_HEADER_SIZE, Ordering::SeqCst);
+            .store(log.header_len(), Ordering::SeqCst);
         Ok(log)
     }
     pub fn woffset(&self) -> usize {
@@ -122,6 +170,28 @@ impl<K: Kms, S: StorageTrait> VlogCtl<K, S> {
     pub(crate) fn threshold(&self) -> &VlogThreshold {
         &self.inner.vlog_threshold
     }
+    pub(crate) fn roll_over(&self) -> &AtomicBool {
+        &self.inner.roll_over
+    }
+    pub(crate) fn id_logfile(
+        &self,
+    ) -> &RwLock<BTreeMap<VlogId, LogFileWrapper<K, S>>> {
+        &self.inner.id_logfile
+    }
+    pub(crate) fn deleted(
+        &self,
+    ) -> &RwLock<Vec<(TxnTs, LogFileWrapper<K, S>)>> {
+        &self.inner.deleted
+    }
+    pub(crate) fn max_id(&self) -> Result<VlogId> {
+        Ok(*self.inner.max_id.read()?)
+    }
+    pub(crate) fn kms(&self) -> &K {
+        &self.inner.kms
+    }
+    pub(crate) fn builder(&self) -> &VlogCtlBuilder<K> {
+        &self.inner.builder
+    }
 }
 #[derive(Debug, Clone)]
 pub struct VlogCtlBuilder<K: Kms> {
@@ -130,6 +200,7 @@ pub struct VlogCtlBuilder<K: Kms> {
     vlog_file_size: usize,
     vlog_max_entries: usize,
     vlog_threshold: VlogThresholdConfig,
+    value_separation: ValueSeparation,
     kms: PhantomData<K>,
 }
 impl<K: Kms> Default for VlogCtlBuilder<K> {
@@ -141,6 +212,7 @@ impl<K: Kms> Default for VlogCtlBuilder<K> {
             vlog_max_entries: 1_000_000,
             kms: PhantomData,
             vlog_threshold: VlogThresholdConfig::default(),
+            value_separation: ValueSeparation::default(),
         }
     }
 }
@@ -162,6 +234,14 @@ impl<K: Kms, S: StorageTrait> VlogCtlBuilderTrait<VlogCtl<K, S>, K>
     > {
         Discard::new(&self.vlog_dir).map_err(|e| e.into())
     }
+
+    fn set_value_separation(
+        &mut self,
+        separation: ValueSeparation,
+    ) -> &mut Self {
+        self.value_separation = separation;
+        self
+    }
 }
 impl<K: Kms> WithDir for VlogCtlBuilder<K> {
     fn set_dir(&mut self, dir: PathBuf) -> &mut Self {
@@ -192,10 +272,19 @@ impl<K: Kms> VlogCtlBuilder<K> {
         let vlog_threshold = VlogThreshold::new(self.vlog_threshold);
         let mut id_logfile = BTreeMap::new();
         let ids = VlogId::parse_set_from_dir(&self.vlog_dir);
+        // only the file written last can have a torn tail.
+        let last = ids.iter().max().copied();
+        let mut recovery = None;
 
         for id in ids {
-            let log = self.open_logfile(id, kms.clone())?;
-            if log.is_empty() {
+            let mut log = self.open_logfile(id, kms.clone())?;
+            let mut empty = log.is_empty();
+            if Some(id) == last && !self.read_only {
+                let r = recover(&mut log)?;
+                empty = empty || r.valid_len == log.header_len();
+                recovery = Some((id, r));
+            }
+            if empty {
                 info!("Empty log file: {:?}", &id.join_dir(&self.vlog_dir));
                 log.delete()?;
             } else {
@@ -213,11 +302,14 @@ impl<K: Kms> VlogCtlBuilder<K> {
         let vlog_ctl = VlogCtl {
             inner: Arc::new(VlogCtlInner {
                 id_logfile: RwLock::new(id_logfile),
+                deleted: Default::default(),
                 builder: self.clone(),
                 kms,
                 max_id: RwLock::new(max_id),
                 writeable_offset: AtomicUsize::new(0),
+                roll_over: AtomicBool::new(false),
                 vlog_threshold,
+                recovery,
             }),
         };
 
@@ -232,7 +324,7 @@ impl<K: Kms> VlogCtlBuilder<K> {
         vlog_ctl.create_new()?;
         Ok(vlog_ctl)
     }
-    fn open_logfile<S: StorageTrait>(
+    pub(crate) fn open_logfile<S: StorageTrait>(
         &self,
         id: VlogId,
         kms: K,
@@ -255,6 +347,28 @@ impl<K: Kms> VlogCtlBuilder<K> {
         Ok(log)
     }
 }
+// cuts what a crash left after the last complete record of `log`.
+fn recover<K: Kms, S: StorageTrait>(
+    log: &mut LogFile<VlogId, K, S>,
+) -> Result<LogRecovery> {
+    let header_len = log.header_len();
+    let mut iter = LogFileIter::new(log, header_len);
+    while iter.next_entry()?.is_some() {}
+    let valid_len = iter.valid_end_offset();
+    let discarded_entries = iter.discarded_entries();
+
+    let len = log.len();
+    log.set_len(valid_len)?;
+    if len > valid_len {
+        info!("Truncated vlog {:?} to {}", log.path(), valid_len);
+    }
+    Ok(LogRecovery {
+        path: log.path().to_owned(),
+        valid_len,
+        truncated: len.saturating_sub(valid_len),
+        discarded_entries,
+    })
+}
 // pub(crate) fn reset_valid_len<F: FileId, K: Kms, S: StorageTrait>(
 //     log: &mut LogFile<F, K, S>,
 // ) -> Result<()> {
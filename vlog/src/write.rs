@@ -1,4 +1,5 @@
 use std::slice::IterMut;
+use std::sync::atomic::Ordering;
 
 use mors_common::kv::{Entry, Meta, ValuePointer};
 use mors_traits::file::StorageTrait;
@@ -15,6 +16,11 @@ impl<K: Kms, S: StorageTrait> VlogCtl<K, S> {
     ) -> Result<()> {
         // let mut buf = Vec::with_capacity(page_size());
         let mut latest = self.latest_logfile()?;
+        if self.roll_over().swap(false, Ordering::SeqCst) && !latest.is_empty()
+        {
+            latest.flush()?;
+            latest = self.create_new()?;
+        }
 
         let threshold_sender = self.threshold().sender();
         for iter in iter_mut {
@@ -24,8 +30,16 @@ impl<K: Kms, S: StorageTrait> VlogCtl<K, S> {
                 for (entry, vp) in iter {
                     // buf.clear();
                     value_sizes.push(entry.value().len());
-                    entry.set_value_threshold(self.value_threshold());
-                    if entry.value().len() < entry.value_threshold() {
+                    entry.set_value_threshold(
+                        self.value_separation()
+                            .threshold(entry.key(), self.value_threshold()),
+                    );
+                    // tombstones stay inline, a range one's value is the end key.
+                    if entry.value().len() < entry.value_threshold()
+                        || entry
+                            .meta()
+                            .intersects(Meta::DELETE | Meta::RANGE_DELETE)
+                    {
                         *vp = ValuePointer::default();
                         continue;
                     }
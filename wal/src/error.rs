@@ -12,6 +12,10 @@ pub enum MorsWalError {
     IoError(#[from] std::io::Error),
     #[error("Invalid log header {0:?}, you may need to delete the file and try again.")]
     InvalidLogHeader(PathBuf),
+    #[error("Log file {0:?} was written with encryption algorithm {1}, which its data key does not use")]
+    EncryptionAlgoMismatch(PathBuf, u8),
+    #[error("Log file {0:?} has format version {1}, which is newer than this build supports")]
+    UnsupportedLogFormat(PathBuf, u16),
     #[error("Log file is full")]
     StorageFull,
 }
@@ -1,7 +1,7 @@
 use bytes::{Buf, BufMut};
 use integer_encoding::{VarInt, VarIntReader};
 use mors_common::{
-    kv::{Entry, Meta},
+    kv::{ColumnFamilyId, Entry, Meta, DEFAULT_COLUMN_FAMILY},
     ts::PhyTs,
 };
 use std::io::{self, Read};
@@ -13,28 +13,39 @@ pub struct LogEntryHeader {
     expires_at: PhyTs,
     meta: Meta,
     user_meta: u8,
+    column_family: ColumnFamilyId,
 }
 impl LogEntryHeader {
-    pub const MAX_HEADER_SIZE: usize = 22;
+    pub const MAX_HEADER_SIZE: usize = 27;
     pub fn new(e: &Entry) -> Self {
+        let mut meta = e.value_meta().meta();
+        meta.set(
+            Meta::COLUMN_FAMILY,
+            e.column_family() != DEFAULT_COLUMN_FAMILY,
+        );
         Self {
             key_len: e.key_ts().len() as u32,
             value_len: e.value_meta().value().len() as u32,
             expires_at: e.value_meta().expires_at(),
-            meta: e.value_meta().meta(),
+            meta,
             user_meta: e.value_meta().user_meta(),
+            column_family: e.column_family(),
         }
     }
-    // +------+----------+------------+--------------+-----------+
-    // | Meta | UserMeta | Key Length | Value Length | ExpiresAt |
-    // +------+----------+------------+--------------+-----------+
+    // +------+----------+------------+--------------+-----------+----------------+
+    // | Meta | UserMeta | Key Length | Value Length | ExpiresAt | (ColumnFamily) |
+    // +------+----------+------------+--------------+-----------+----------------+
+    // the column family is only written when Meta has COLUMN_FAMILY set.
     pub fn encode(&self) -> Vec<u8> {
-        let mut out = Vec::with_capacity(22);
+        let mut out = Vec::with_capacity(Self::MAX_HEADER_SIZE);
         out.put_u8(self.meta.bits());
         out.put_u8(self.user_meta);
         out.put_slice(self.key_len.encode_var_vec().as_ref());
         out.put_slice(self.value_len.encode_var_vec().as_ref());
         out.put_slice(self.expires_at.to_u64().encode_var_vec().as_ref());
+        if self.meta.contains(Meta::COLUMN_FAMILY) {
+            out.put_slice(self.column_family.encode_var_vec().as_ref());
+        }
         out
     }
     pub fn decode(mut buf: &[u8]) -> (LogEntryHeader, usize) {
@@ -52,12 +63,21 @@ impl LogEntryHeader {
 
         let (expires_at, count) = u64::decode_var(buf).unwrap();
         index += count;
+        buf.advance(count);
+
+        let mut column_family = DEFAULT_COLUMN_FAMILY;
+        if meta.contains(Meta::COLUMN_FAMILY) {
+            let (cf, count) = u32::decode_var(buf).unwrap();
+            index += count;
+            column_family = cf;
+        }
         let e = Self {
             key_len,
             value_len,
             expires_at: expires_at.into(),
             meta,
             user_meta,
+            column_family,
         };
         (e, index)
     }
@@ -69,6 +89,11 @@ impl LogEntryHeader {
         let key_len = reader.read_varint::<u32>()?;
         let value_len = reader.read_varint::<u32>()?;
         let expires_at = reader.read_varint::<u64>()?;
+        let column_family = if meta.contains(Meta::COLUMN_FAMILY) {
+            reader.read_varint::<u32>()?
+        } else {
+            DEFAULT_COLUMN_FAMILY
+        };
 
         Ok(Self {
             key_len,
@@ -76,6 +101,7 @@ impl LogEntryHeader {
             expires_at: expires_at.into(),
             meta,
             user_meta,
+            column_family,
         })
     }
 
@@ -98,6 +124,10 @@ impl LogEntryHeader {
     pub fn expires_at(&self) -> PhyTs {
         self.expires_at
     }
+
+    pub fn column_family(&self) -> ColumnFamilyId {
+        self.column_family
+    }
     pub fn check_key_len(&self) -> Result<(), io::Error> {
         if self.key_len() > 1 << 16_u32 {
             return Err(io::Error::new(
@@ -2,7 +2,7 @@ use crate::error::MorsWalError;
 use bytes::{Buf, BufMut};
 use mors_common::file_id::FileId;
 use mors_traits::file::{StorageBuilderTrait, StorageTrait};
-use mors_traits::kms::{CipherKeyId, Kms, KmsCipher};
+use mors_traits::kms::{CipherKeyId, EncryptionAlgo, Kms, KmsCipher};
 use std::{
     fs::remove_file,
     path::{Path, PathBuf},
@@ -15,10 +15,38 @@ pub mod read;
 pub mod storage;
 pub mod write;
 type Result<T> = std::result::Result<T, MorsWalError>;
+/// Format of a log file, stored in its header and deciding how its entries
+/// are encoded, so the entry format can change without breaking old files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogFormat {
+    /// files written before the header had a version.
+    V0 = 0,
+    V1 = 1,
+    /// entries are appended in checksummed batches.
+    V2 = 2,
+}
+impl LogFormat {
+    /// format of newly created log files.
+    pub const CURRENT: LogFormat = LogFormat::V2;
+}
+impl TryFrom<u16> for LogFormat {
+    type Error = u16;
+
+    fn try_from(value: u16) -> std::result::Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::V0),
+            1 => Ok(Self::V1),
+            2 => Ok(Self::V2),
+            v => Err(v),
+        }
+    }
+}
 pub struct LogFile<F: FileId, K: Kms, S: StorageTrait> {
     id: F,
     kms: K,
     cipher: Option<K::Cipher>,
+    format: LogFormat,
+    header_len: usize,
     storage: S,
     size: AtomicUsize,
     path_buf: PathBuf,
@@ -29,6 +57,9 @@ impl<F: FileId, K: Kms, S: StorageTrait> LogFile<F, K, S> {
     pub fn id(&self) -> F {
         self.id
     }
+    pub fn path(&self) -> &Path {
+        &self.path_buf
+    }
 }
 impl<F: FileId, K: Kms, S: StorageTrait> LogFile<F, K, S> {
     pub fn open<P: AsRef<Path>>(
@@ -44,6 +75,8 @@ impl<F: FileId, K: Kms, S: StorageTrait> LogFile<F, K, S> {
             id,
             kms,
             cipher: None,
+            format: LogFormat::CURRENT,
+            header_len: Self::HEADER_SIZE,
             storage: mmap,
             path_buf: path_buf.as_ref().to_owned(),
             size: AtomicUsize::new(0),
@@ -58,54 +91,125 @@ impl<F: FileId, K: Kms, S: StorageTrait> LogFile<F, K, S> {
                 remove_file(&log_file.path_buf)?;
                 result?;
             }
-            log_file
-                .size
-                .store(Self::LOG_HEADER_SIZE, Ordering::Relaxed);
+            log_file.size.store(Self::HEADER_SIZE, Ordering::Relaxed);
         }
         log_file
             .size
             .store(log_file.storage.file_len()? as usize, Ordering::Relaxed);
 
-        let mut buf = vec![0; Self::LOG_HEADER_SIZE];
-        if log_file.storage.read(&mut buf)? != Self::LOG_HEADER_SIZE {
-            return Err(MorsWalError::InvalidLogHeader(
-                path_buf.as_ref().to_owned(),
-            ));
+        let mut buf = vec![0; Self::HEADER_SIZE];
+        let len = log_file.storage.read(&mut buf)?;
+        let (algo, key_id) = if buf.starts_with(Self::MAGIC) {
+            log_file.decode_header(&buf[..len], path_buf.as_ref())?
+        } else {
+            log_file.decode_legacy_header(&buf[..len], path_buf.as_ref())?
         };
-
-        let mut buf_ref = buf.as_slice();
-        let key_id: CipherKeyId = buf_ref.get_u64().into();
         log_file.cipher = log_file.kms.get_cipher(key_id)?;
-
-        debug_assert_eq!(buf_ref.len(), 12);
-        log_file.base_nonce = buf_ref.to_vec();
+        if let Some(c) = &log_file.cipher {
+            if EncryptionAlgo::try_from(algo) != Ok(c.encryption_algo()) {
+                return Err(MorsWalError::EncryptionAlgoMismatch(
+                    path_buf.as_ref().to_owned(),
+                    algo,
+                ));
+            }
+        }
 
         Ok(log_file)
     }
-    // bootstrap will initialize the log file with key id and baseIV.
+    // bootstrap will initialize the log file with its format version,
+    // key id and baseIV.
     // The below figure shows the layout of log file.
-    // +----------------+------------------+------------------+
-    // | keyID(8 bytes) |  baseIV(12 bytes)|	 entry...     |
-    // +----------------+------------------+------------------+
-    pub const LOG_HEADER_SIZE: usize = 20;
+    // +-------------+------------------+---------------+----------------+
+    // | Magic(4)    | Version(2 bytes) | algo(1 byte)  | keyID(8 bytes) |
+    // +-------------+------------------+---------------+----------------+
+    // +------------------+-----------------+----------+
+    // | baseIV(12 bytes) | Crc32(4 bytes)  | entry... |
+    // +------------------+-----------------+----------+
+    // algo is the EncryptionAlgo of the data key, the crc covers the header.
+    pub const HEADER_SIZE: usize = 31;
+    const MAGIC: &'static [u8] = b"MLOG";
 
     fn bootstrap(&mut self) -> Result<()> {
         self.cipher = self.kms.latest_cipher()?;
         self.base_nonce = K::Cipher::generate_nonce();
 
-        let mut buf = Vec::with_capacity(Self::LOG_HEADER_SIZE);
+        let mut buf = Vec::with_capacity(Self::HEADER_SIZE);
+        let algo = self
+            .cipher
+            .as_ref()
+            .map(|c| c.encryption_algo())
+            .unwrap_or_default();
+        buf.put_slice(Self::MAGIC);
+        buf.put_u16(self.format as u16);
+        buf.put_u8(algo.into());
         buf.put_u64(self.cipher_key_id().into());
         buf.put(self.base_nonce.as_ref());
+        buf.put_u32(crc32fast::hash(&buf));
 
-        debug_assert_eq!(buf.len(), Self::LOG_HEADER_SIZE);
+        debug_assert_eq!(buf.len(), Self::HEADER_SIZE);
         debug_assert_eq!(
             self.storage.append(&buf, Ordering::Relaxed)?,
-            Self::LOG_HEADER_SIZE
+            Self::HEADER_SIZE
         );
-        self.storage.flush_range(0, Self::LOG_HEADER_SIZE)?;
+        self.storage.flush_range(0, Self::HEADER_SIZE)?;
         Ok(())
     }
-    fn cipher_key_id(&self) -> CipherKeyId {
+    fn decode_header(
+        &mut self,
+        buf: &[u8],
+        path: &Path,
+    ) -> Result<(u8, CipherKeyId)> {
+        if buf.len() != Self::HEADER_SIZE
+            || crc32fast::hash(&buf[..Self::HEADER_SIZE - 4])
+                != (&buf[Self::HEADER_SIZE - 4..]).get_u32()
+        {
+            return Err(MorsWalError::InvalidLogHeader(path.to_owned()));
+        }
+        let mut buf_ref = &buf[Self::MAGIC.len()..];
+        let version = buf_ref.get_u16();
+        self.format = LogFormat::try_from(version).map_err(|v| {
+            MorsWalError::UnsupportedLogFormat(path.to_owned(), v)
+        })?;
+        let algo = buf_ref.get_u8();
+        let key_id = buf_ref.get_u64().into();
+        self.base_nonce = buf_ref[..12].to_vec();
+        self.header_len = Self::HEADER_SIZE;
+        Ok((algo, key_id))
+    }
+    // Layout of log files written before the header was versioned,
+    // their entries are in the V0 format.
+    // +---------------+----------------+------------------+----------+
+    // | algo(1 byte)  | keyID(7 bytes) |  baseIV(12 bytes)| entry... |
+    // +---------------+----------------+------------------+----------+
+    // files written before algo was recorded have 0 there, aes-gcm.
+    const LEGACY_HEADER_SIZE: usize = 20;
+    fn decode_legacy_header(
+        &mut self,
+        buf: &[u8],
+        path: &Path,
+    ) -> Result<(u8, CipherKeyId)> {
+        if buf.len() < Self::LEGACY_HEADER_SIZE {
+            return Err(MorsWalError::InvalidLogHeader(path.to_owned()));
+        }
+        let mut buf_ref = &buf[..Self::LEGACY_HEADER_SIZE];
+        let algo = buf_ref.get_u8();
+        let key_id = buf_ref.get_uint(7).into();
+        debug_assert_eq!(buf_ref.len(), 12);
+        self.base_nonce = buf_ref.to_vec();
+        self.format = LogFormat::V0;
+        self.header_len = Self::LEGACY_HEADER_SIZE;
+        Ok((algo, key_id))
+    }
+    /// format the entries of this file are encoded in.
+    pub fn format(&self) -> LogFormat {
+        self.format
+    }
+    /// offset of the first entry.
+    pub fn header_len(&self) -> usize {
+        self.header_len
+    }
+    /// data key the entries are encrypted with, default if plaintext.
+    pub fn cipher_key_id(&self) -> CipherKeyId {
         self.cipher
             .as_ref()
             .map(|c| c.cipher_key_id())
@@ -143,7 +247,7 @@ impl<F: FileId, K: Kms, S: StorageTrait> LogFile<F, K, S> {
     //     self.storage.len().unwrap_or(0)
     // }
     pub fn is_empty(&self) -> bool {
-        self.storage.load_append_pos(Ordering::Relaxed) == Self::LOG_HEADER_SIZE
+        self.storage.load_append_pos(Ordering::Relaxed) == self.header_len
     }
     pub fn delete(&self) -> Result<()> {
         Ok(self.storage.delete()?)
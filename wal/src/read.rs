@@ -1,9 +1,10 @@
 use crate::{
     error::MorsWalError::{self},
     header::LogEntryHeader,
-    LogFile, Result,
+    LogFile, LogFormat, Result,
 };
 use bytes::Buf;
+use integer_encoding::VarIntReader;
 use mors_common::{
     file_id::FileId,
     kv::{Entry, Meta, ValuePointer},
@@ -14,6 +15,7 @@ use mors_traits::{
     kms::{Kms, KmsCipher},
 };
 use std::{
+    collections::VecDeque,
     hash::Hasher,
     io::{self, BufReader, Read},
 };
@@ -21,11 +23,17 @@ pub struct LogFileIter<'a, F: FileId, K: Kms, S: StorageTrait> {
     // log_file: &'a LogFile<F, K, S>,
     cipher: &'a Option<K::Cipher>,
     base_nonce: &'a Vec<u8>,
+    format: LogFormat,
     id: F,
     record_offset: usize,
     reader: BufReader<&'a mut S>,
     entries_vptrs: Vec<(Entry, ValuePointer)>,
+    // entries of the last batch read_entry has not returned yet.
+    pending: VecDeque<(Entry, ValuePointer)>,
     valid_end_offset: usize,
+    discarded_entries: usize,
+    // entries of the batch being read, discarded if it is torn.
+    batch_count: usize,
 }
 impl<'a, F: FileId, K: Kms, S: StorageTrait> LogFileIter<'a, F, K, S> {
     pub fn new(log_file: &'a mut LogFile<F, K, S>, offset: usize) -> Self {
@@ -42,81 +50,129 @@ impl<'a, F: FileId, K: Kms, S: StorageTrait> LogFileIter<'a, F, K, S> {
             record_offset: offset,
             reader,
             entries_vptrs: Vec::new(),
+            pending: VecDeque::new(),
             valid_end_offset: offset,
+            discarded_entries: 0,
+            batch_count: 0,
             cipher: p,
+            format: log_file.format,
             id,
             base_nonce: &log_file.base_nonce,
         }
     }
 
+    /// reads the next entry, entries of a batch share the value pointer
+    /// of the whole record.
     pub fn read_entry(&mut self) -> Result<(Entry, ValuePointer)> {
+        match self.format {
+            LogFormat::V0 | LogFormat::V1 => self.read_single(),
+            LogFormat::V2 => {
+                if self.pending.is_empty() {
+                    let batch = self.read_batch()?;
+                    self.pending.extend(batch);
+                }
+                Ok(self.pending.pop_front().unwrap())
+            }
+        }
+    }
+    // +-------------+----------+-----------------+
+    // | EntryHeader | Key,Value| Crc32 (4 bytes) |
+    // +-------------+----------+-----------------+
+    fn read_single(&mut self) -> Result<(Entry, ValuePointer)> {
         let mut hash_reader = HashReader {
             reader: &mut self.reader,
             hasher: crc32fast::Hasher::new(),
             len: 0,
         };
+        let (entry_header, kv_buf) =
+            read_raw::<K, _>(&mut hash_reader, self.cipher)?;
+        check_crc(&mut hash_reader)?;
 
-        let entry_header = LogEntryHeader::decode_from(&mut hash_reader)?;
-        let header_len = hash_reader.len;
-        entry_header.check_key_len()?;
-
-        let key_len = entry_header.key_len() as usize;
-        let value_len = entry_header.value_len() as usize;
-
-        let mut kv_buf = vec![0; key_len + value_len];
-        hash_reader.read_exact(&mut kv_buf)?;
-
-        if kv_buf.is_empty() {
-            return Err(io::Error::new(
-                io::ErrorKind::UnexpectedEof,
-                "kv len can't be zero",
-            )
-            .into());
-        }
-
-        let kv_buf = match self.cipher.as_ref() {
-            Some(c) => c.decrypt_with_slice(self.base_nonce, &kv_buf)?,
-            None => kv_buf,
-        };
-        // kv_buf = self.log_file.decrypt(&kv_buf)?.unwrap_or(kv_buf);
-
-        let mut entry = Entry::from_log(
-            &kv_buf[..key_len],
-            &kv_buf[key_len..],
+        let size = hash_reader.len;
+        let entry = decode(
+            entry_header,
+            kv_buf,
+            self.cipher,
+            self.base_nonce,
             self.record_offset,
-        );
-        let value_meta = entry.value_meta_mut();
-        value_meta.set_meta(entry_header.meta());
-        value_meta.set_user_meta(entry_header.user_meta());
-        value_meta.set_expires_at(entry_header.expires_at());
-
-        let hash = hash_reader.hasher.finalize();
-        let mut crc_buf = 0_u32.to_be_bytes();
-        hash_reader.reader.read_exact(&mut crc_buf)?;
-
-        let crc = crc_buf.as_slice().get_u32();
-        if hash != crc {
+        )?;
+        let v_ptr =
+            ValuePointer::new(self.id, size as u32, self.record_offset as u64);
+        self.record_offset += size;
+        Ok((entry, v_ptr))
+    }
+    // A batch is written by one append and accepted or rejected as a whole,
+    // its entries have no crc of their own.
+    // +----------------+---------------------+-----------------+
+    // | Count (varint) | Entry1 ... EntryN   | Crc32 (4 bytes) |
+    // +----------------+---------------------+-----------------+
+    fn read_batch(&mut self) -> Result<Vec<(Entry, ValuePointer)>> {
+        let mut hash_reader = HashReader {
+            reader: &mut self.reader,
+            hasher: crc32fast::Hasher::new(),
+            len: 0,
+        };
+        self.batch_count = 0;
+        let count = hash_reader.read_varint::<u32>()? as usize;
+        if count == 0 {
             return Err(io::Error::new(
                 io::ErrorKind::UnexpectedEof,
-                "failed to checksum crc32",
+                "batch can't be empty",
             )
             .into());
-        };
-
-        let size = header_len + key_len + value_len + crc_buf.len();
-        debug_assert!(size == hash_reader.len + 4);
+        }
+        self.batch_count = count;
+        let mut raw = Vec::with_capacity(count.min(1024));
+        for _ in 0..count {
+            raw.push(read_raw::<K, _>(&mut hash_reader, self.cipher)?);
+        }
+        check_crc(&mut hash_reader)?;
 
-        let v_ptr =
-            ValuePointer::new(self.id, size as u32, self.record_offset as u64);
+        let size = hash_reader.len;
+        let mut batch = Vec::with_capacity(raw.len());
+        for (entry_header, kv_buf) in raw {
+            let entry = decode(
+                entry_header,
+                kv_buf,
+                self.cipher,
+                self.base_nonce,
+                self.record_offset,
+            )?;
+            let v_ptr = ValuePointer::new(
+                self.id,
+                size as u32,
+                self.record_offset as u64,
+            );
+            batch.push((entry, v_ptr));
+        }
         self.record_offset += size;
-        Ok((entry, v_ptr))
+        Ok(batch)
     }
     //
     pub fn next_entry(
         &mut self,
     ) -> Result<Option<&Vec<(Entry, ValuePointer)>>> {
-        let mut last_commit = TxnTs::default();
         self.entries_vptrs.clear();
+        if self.format >= LogFormat::V2 {
+            return match self.read_batch() {
+                Ok(batch) => {
+                    self.entries_vptrs = batch;
+                    self.valid_end_offset = self.record_offset;
+                    Ok(Some(&self.entries_vptrs))
+                }
+                Err(MorsWalError::IoError(io))
+                    if io.kind() == io::ErrorKind::UnexpectedEof =>
+                {
+                    // the count was written, the rest of the batch was not.
+                    self.discarded_entries += self.batch_count;
+                    Ok(None)
+                }
+                Err(e) => Err(e),
+            };
+        }
+        // older formats mark the entries of a transaction with TXN and
+        // end it with a FIN_TXN entry.
+        let mut last_commit = TxnTs::default();
         loop {
             match self.read_entry() {
                 Ok((entry, v_ptr)) => {
@@ -126,18 +182,21 @@ impl<'a, F: FileId, K: Kms, S: StorageTrait> LogFileIter<'a, F, K, S> {
                             last_commit = txn_ts;
                         }
                         if last_commit != txn_ts {
+                            self.discarded_entries += 1;
                             break;
                         }
                         self.entries_vptrs.push((entry, v_ptr));
                     } else if entry.meta().contains(Meta::FIN_TXN) {
                         let txn_ts = entry.version();
                         if last_commit != txn_ts {
+                            self.discarded_entries += 1;
                             break;
                         }
                         self.valid_end_offset = self.record_offset;
                         return Ok(Some(&self.entries_vptrs));
                     } else {
                         if last_commit != TxnTs::default() {
+                            self.discarded_entries += 1;
                             break;
                         }
                         self.entries_vptrs.push((entry, v_ptr));
@@ -153,12 +212,85 @@ impl<'a, F: FileId, K: Kms, S: StorageTrait> LogFileIter<'a, F, K, S> {
                 Err(e) => return Err(e),
             }
         }
+        // a transaction without its FIN_TXN entry was cut by a crash.
+        self.discarded_entries += self.entries_vptrs.len();
+        self.entries_vptrs.clear();
         Ok(None)
     }
     //
     pub fn valid_end_offset(&self) -> usize {
         self.valid_end_offset
     }
+    /// entries read after the valid end offset, those of transactions
+    /// missing their FIN_TXN entry or of a torn batch.
+    pub fn discarded_entries(&self) -> usize {
+        self.discarded_entries
+    }
+}
+// reads an entry header and its still encrypted key and value.
+fn read_raw<K: Kms, R: Read>(
+    reader: &mut R,
+    cipher: &Option<K::Cipher>,
+) -> Result<(LogEntryHeader, Vec<u8>)> {
+    let entry_header = LogEntryHeader::decode_from(reader)?;
+    entry_header.check_key_len()?;
+
+    let key_len = entry_header.key_len() as usize;
+    let value_len = entry_header.value_len() as usize;
+    if key_len + value_len == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "kv len can't be zero",
+        )
+        .into());
+    }
+    // the lengths are of the plaintext, the stored kv has the tag too.
+    let kv_len = match cipher {
+        Some(_) => key_len + value_len + K::Cipher::TAG_SIZE,
+        None => key_len + value_len,
+    };
+    let mut kv_buf = vec![0; kv_len];
+    reader.read_exact(&mut kv_buf)?;
+    Ok((entry_header, kv_buf))
+}
+// compares the crc that follows the record with the hash of the record.
+fn check_crc<B: Read>(
+    hash_reader: &mut HashReader<'_, B, crc32fast::Hasher>,
+) -> Result<()> {
+    let hash = hash_reader.hasher.clone().finalize();
+    let mut crc_buf = 0_u32.to_be_bytes();
+    hash_reader.reader.read_exact(&mut crc_buf)?;
+    hash_reader.len += crc_buf.len();
+
+    if hash != crc_buf.as_slice().get_u32() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "failed to checksum crc32",
+        )
+        .into());
+    };
+    Ok(())
+}
+fn decode<C: KmsCipher>(
+    entry_header: LogEntryHeader,
+    kv_buf: Vec<u8>,
+    cipher: &Option<C>,
+    base_nonce: &[u8],
+    offset: usize,
+) -> Result<Entry> {
+    let kv_buf = match cipher.as_ref() {
+        Some(c) => c.decrypt_with_slice(base_nonce, &kv_buf)?,
+        None => kv_buf,
+    };
+    let key_len = entry_header.key_len() as usize;
+    let mut entry =
+        Entry::from_log(&kv_buf[..key_len], &kv_buf[key_len..], offset);
+    entry.set_column_family(entry_header.column_family());
+    let value_meta = entry.value_meta_mut();
+    value_meta.set_meta(entry_header.meta() - Meta::COLUMN_FAMILY);
+    value_meta.set_user_meta(entry_header.user_meta());
+    value_meta.set_expires_at(entry_header.expires_at());
+    Ok(entry)
 }
 
 pub struct HashReader<'a, B: Read, T: Hasher> {
@@ -1,4 +1,5 @@
 use bytes::BufMut;
+use integer_encoding::VarInt;
 use mors_common::{file_id::FileId, kv::Entry};
 use mors_traits::file::StorageTrait;
 use mors_traits::kms::Kms;
@@ -8,7 +9,9 @@ use std::{
     sync::atomic::Ordering,
 };
 
-use crate::{error::MorsWalError, header::LogEntryHeader, LogFile, Result};
+use crate::{
+    error::MorsWalError, header::LogEntryHeader, LogFile, LogFormat, Result,
+};
 impl<F: FileId, K: Kms, S: StorageTrait> LogFile<F, K, S> {
     pub fn set_len(&mut self, end_offset: usize) -> io::Result<()> {
         let file_size = self.storage.file_len()? as usize;
@@ -20,7 +23,24 @@ impl<F: FileId, K: Kms, S: StorageTrait> LogFile<F, K, S> {
         Ok(())
     }
     pub fn append_entry(&self, entry: &Entry) -> Result<usize> {
-        let encode = self.encode_entry(entry)?;
+        self.append_batch([entry])
+    }
+    /// appends `entries` with a single write, in the V2 format they are one
+    /// record replayed all or nothing.
+    pub fn append_batch<'a>(
+        &self,
+        entries: impl IntoIterator<Item = &'a Entry>,
+    ) -> Result<usize> {
+        let encode = match self.format {
+            LogFormat::V0 | LogFormat::V1 => {
+                let mut buf = Vec::new();
+                for entry in entries {
+                    buf.extend(self.encode_entry(entry)?);
+                }
+                buf
+            }
+            LogFormat::V2 => self.encode_batch(entries)?,
+        };
         if let Err(e) = self.storage.append(&encode, Ordering::Relaxed) {
             if e.kind() == io::ErrorKind::Other {
                 return Err(MorsWalError::StorageFull);
@@ -33,31 +53,65 @@ impl<F: FileId, K: Kms, S: StorageTrait> LogFile<F, K, S> {
             .storage
             .flush_range(0, self.storage.load_append_pos(Ordering::Relaxed))?)
     }
+    /// encodes `entry` as a record of its own, with its own crc.
     pub fn encode_entry(&self, entry: &Entry) -> Result<Vec<u8>> {
-        let header = LogEntryHeader::new(entry);
-        let header_encode = header.encode();
-
-        let mut kv_buf = entry.key_ts().encode();
-        kv_buf.extend_from_slice(entry.value_meta().value());
+        let mut buf = Vec::new();
+        let mut hash_writer = HashWriter {
+            writer: &mut buf,
+            hasher: crc32fast::Hasher::new(),
+        };
+        let len = self.encode_to(&mut hash_writer, entry)?;
+        let crc = hash_writer.hasher.finalize();
 
+        buf.put_u32(crc);
+        debug_assert_eq!(buf.len(), len + size_of::<u32>());
+        Ok(buf)
+    }
+    /// encodes `entries` as one record with an entry count and a crc
+    /// covering all of them.
+    pub fn encode_batch<'a>(
+        &self,
+        entries: impl IntoIterator<Item = &'a Entry>,
+    ) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        let mut count = 0_u32;
+        for entry in entries {
+            self.encode_to(&mut body, entry)?;
+            count += 1;
+        }
         let mut buf = Vec::with_capacity(
-            header_encode.len() + kv_buf.len() + size_of::<u32>(),
+            count.required_space() + body.len() + size_of::<u32>(),
         );
         let mut hash_writer = HashWriter {
             writer: &mut buf,
             hasher: crc32fast::Hasher::new(),
         };
-
-        let header_len = hash_writer.write(&header_encode)?;
-
-        kv_buf = self.encrypt(&kv_buf)?.unwrap_or(kv_buf);
-        let kv_len = hash_writer.write(&kv_buf)?;
+        hash_writer.write_all(&count.encode_var_vec())?;
+        hash_writer.write_all(&body)?;
         let crc = hash_writer.hasher.finalize();
-
         buf.put_u32(crc);
-        debug_assert_eq!(buf.len(), header_len + kv_len + size_of::<u32>());
         Ok(buf)
     }
+    // writes the entry header and the, maybe encrypted, key and value.
+    fn encode_to<W: Write>(
+        &self,
+        writer: &mut W,
+        entry: &Entry,
+    ) -> Result<usize> {
+        let header = LogEntryHeader::new(entry);
+        // entries are appended in the format of the file, not the current one.
+        let header_encode = match self.format {
+            LogFormat::V0 | LogFormat::V1 | LogFormat::V2 => header.encode(),
+        };
+
+        let mut kv_buf = entry.key_ts().encode();
+        kv_buf.extend_from_slice(entry.value_meta().value());
+        kv_buf = self.encrypt(&kv_buf)?.unwrap_or(kv_buf);
+
+        writer.write_all(&header_encode)?;
+        writer.write_all(&kv_buf)?;
+        Ok(header_encode.len() + kv_buf.len())
+    }
 }
 pub(crate) struct HashWriter<'a, T: Hasher> {
     writer: &'a mut Vec<u8>,
@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use mors_common::{
+    file_id::{FileId, MemtableId},
+    kv::Entry,
+    ts::TxnTs,
+};
+use mors_encrypt::registry::{MorsKms, MorsKmsBuilder};
+use mors_traits::{
+    default::WithDir,
+    file::{StorageBuilderTrait, StorageTrait},
+    kms::KmsBuilder,
+};
+use mors_wal::{read::LogFileIter, storage::mmap::MmapFile, LogFile};
+
+type Log = LogFile<MemtableId, MorsKms, MmapFile>;
+
+fn open(dir: &Path) -> Log {
+    let mut kms = MorsKmsBuilder::new(vec![1; 32]);
+    kms.set_dir(dir.to_path_buf());
+    let mut builder = <MmapFile as StorageTrait>::StorageBuilder::default();
+    builder.read(true).write(true).create(true);
+    let id = MemtableId::from(1);
+    Log::open(id, id.join_dir(dir), 1 << 16, builder, kms.build().unwrap())
+        .unwrap()
+}
+fn entries(version: u64, count: usize) -> Vec<Entry> {
+    (0..count)
+        .map(|i| {
+            let mut e = Entry::new(format!("key{i}").into(), "value".into());
+            e.set_version(TxnTs::from(version));
+            e
+        })
+        .collect()
+}
+
+#[test]
+fn test_torn_batch() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = open(dir.path());
+    let first = log.append_batch(&entries(1, 3)).unwrap();
+    log.append_batch(&entries(2, 2)).unwrap();
+    let header_len = log.header_len();
+    drop(log);
+
+    // a crash in the middle of the second batch.
+    let path = MemtableId::from(1).join_dir(dir.path());
+    let len = std::fs::metadata(&path).unwrap().len();
+    std::fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_len(len - 2)
+        .unwrap();
+
+    let mut log = open(dir.path());
+    let mut iter = LogFileIter::new(&mut log, header_len);
+    let batch = iter.next_entry().unwrap().unwrap();
+    assert_eq!(batch.len(), 3);
+    assert!(batch.iter().all(|(e, _)| e.version() == TxnTs::from(1)));
+    assert!(iter.next_entry().unwrap().is_none());
+    assert_eq!(iter.valid_end_offset(), header_len + first);
+    assert_eq!(iter.discarded_entries(), 2);
+}
+#[test]
+fn test_torn_batch_mid_record() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = open(dir.path());
+    let first = log.append_batch(&entries(1, 3)).unwrap();
+    log.append_batch(&entries(2, 2)).unwrap();
+    let header_len = log.header_len();
+    drop(log);
+
+    // the second batch ends inside its first entry.
+    let path = MemtableId::from(1).join_dir(dir.path());
+    std::fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_len((header_len + first + 6) as u64)
+        .unwrap();
+
+    let mut log = open(dir.path());
+    let mut iter = LogFileIter::new(&mut log, header_len);
+    assert_eq!(iter.next_entry().unwrap().unwrap().len(), 3);
+    assert!(iter.next_entry().unwrap().is_none());
+    assert_eq!(iter.valid_end_offset(), header_len + first);
+    assert_eq!(iter.discarded_entries(), 2);
+
+    // nothing is discarded at the end of a complete wal.
+    let dir = tempfile::tempdir().unwrap();
+    let log = open(dir.path());
+    log.append_batch(&entries(1, 3)).unwrap();
+    drop(log);
+    let mut log = open(dir.path());
+    let header_len = log.header_len();
+    let mut iter = LogFileIter::new(&mut log, header_len);
+    assert!(iter.next_entry().unwrap().is_some());
+    assert!(iter.next_entry().unwrap().is_none());
+    assert_eq!(iter.discarded_entries(), 0);
+}
@@ -0,0 +1,190 @@
+//! Log files of older formats under `tests/corpus` must stay readable.
+//!
+//! `corpus/v0` was written before log files had a versioned header, both
+//! in plaintext and encrypted with aes-gcm under the master key `[1; 16]`.
+use std::path::Path;
+
+use bytes::Bytes;
+use mors_common::{
+    file_id::{FileId, MemtableId, VlogId},
+    kv::{Entry, Meta},
+    ts::{PhyTs, TxnTs},
+};
+use mors_encrypt::registry::{MorsKms, MorsKmsBuilder};
+use mors_traits::{
+    default::WithDir,
+    file::{StorageBuilderTrait, StorageTrait},
+    kms::KmsBuilder,
+};
+use mors_wal::{
+    error::MorsWalError, read::LogFileIter, storage::mmap::MmapFile, LogFile,
+    LogFormat,
+};
+
+type Log<F> = LogFile<F, MorsKms, MmapFile>;
+
+// entries every file of the corpus holds, in order.
+fn corpus_entries() -> Vec<Entry> {
+    let entry = |key: &'static str, value: &'static str, version: u64| {
+        let mut e = Entry::new(Bytes::from(key), Bytes::from(value));
+        e.set_version(TxnTs::from(version));
+        e
+    };
+    let mut entries = vec![entry("key1", "value1", 1)];
+    let mut e = entry("key2", "value2", 2);
+    e.set_user_meta(7)
+        .set_expires_at(PhyTs::from(1_700_000_000));
+    entries.push(e);
+    let mut e = entry("key3", "", 3);
+    e.set_meta(Meta::DELETE);
+    entries.push(e);
+    let mut e = entry("key4", "family", 4);
+    e.set_column_family(3);
+    entries.push(e);
+    for key in ["txn1", "txn2"] {
+        let mut e = entry(key, "in txn", 5);
+        e.set_meta(Meta::TXN);
+        entries.push(e);
+    }
+    let mut e = entry("!mors!txn", "5", 5);
+    e.set_meta(Meta::FIN_TXN);
+    entries.push(e);
+    entries
+}
+fn open<F: FileId>(dir: &Path, id: F, master_key: &[u8]) -> Log<F> {
+    let mut kms = MorsKmsBuilder::new(master_key.to_vec());
+    kms.set_dir(dir.to_path_buf());
+    let mut builder = <MmapFile as StorageTrait>::StorageBuilder::default();
+    builder.read(true).write(true).create(true);
+    Log::open(id, id.join_dir(dir), 1 << 16, builder, kms.build().unwrap())
+        .unwrap()
+}
+fn read_all<F: FileId>(log: &mut Log<F>) -> Vec<Entry> {
+    let mut iter = LogFileIter::new(log, log.header_len());
+    let mut entries = Vec::new();
+    loop {
+        match iter.read_entry() {
+            Ok((entry, _)) => entries.push(entry),
+            Err(MorsWalError::IoError(e))
+                if e.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                return entries
+            }
+            Err(e) => panic!("{e}"),
+        }
+    }
+}
+fn assert_entries(read: &[Entry], expected: &[Entry]) {
+    assert_eq!(read.len(), expected.len());
+    for (r, e) in read.iter().zip(expected) {
+        assert_eq!(r.key_ts(), e.key_ts());
+        assert_eq!(r.value(), e.value());
+        assert_eq!(r.meta(), e.meta());
+        assert_eq!(r.user_meta(), e.user_meta());
+        assert_eq!(r.expires_at(), e.expires_at());
+        assert_eq!(r.column_family(), e.column_family());
+    }
+}
+// copies a corpus directory, opening a log file may write to it.
+fn copy_corpus(name: &str) -> tempfile::TempDir {
+    let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let dir = tempfile::tempdir().unwrap();
+    for file in std::fs::read_dir(src.join(name)).unwrap() {
+        let file = file.unwrap();
+        std::fs::copy(file.path(), dir.path().join(file.file_name())).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn test_read_v0_corpus() {
+    let dir = copy_corpus("v0/plain");
+    let mut log = open(dir.path(), MemtableId::from(1), &[]);
+    assert_eq!(log.format(), LogFormat::V0);
+    assert_entries(&read_all(&mut log), &corpus_entries());
+
+    let dir = copy_corpus("v0/aes");
+    let mut log = open(dir.path(), VlogId::from(1), &[1; 16]);
+    assert_eq!(log.format(), LogFormat::V0);
+    assert_entries(&read_all(&mut log), &corpus_entries());
+}
+#[test]
+fn test_append_to_v0() {
+    // reopened old files keep their format for new entries.
+    let dir = copy_corpus("v0/aes");
+    let log = open(dir.path(), VlogId::from(1), &[1; 16]);
+    let mut entry = Entry::new("key5".into(), "appended".into());
+    entry.set_version(TxnTs::from(6));
+    log.append_entry(&entry).unwrap();
+    drop(log);
+
+    let mut log = open(dir.path(), VlogId::from(1), &[1; 16]);
+    assert_eq!(log.format(), LogFormat::V0);
+    let mut expected = corpus_entries();
+    expected.push(entry);
+    assert_entries(&read_all(&mut log), &expected);
+}
+#[test]
+fn test_current_format() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = open(dir.path(), MemtableId::from(1), &[1; 32]);
+    assert_eq!(log.format(), LogFormat::CURRENT);
+    assert_eq!(log.header_len(), Log::<MemtableId>::HEADER_SIZE);
+    for entry in corpus_entries() {
+        log.append_entry(&entry).unwrap();
+    }
+    drop(log);
+    let mut log = open(dir.path(), MemtableId::from(1), &[1; 32]);
+    assert_entries(&read_all(&mut log), &corpus_entries());
+    drop(log);
+
+    // a format from a newer release is refused instead of misread.
+    let path = MemtableId::from(1).join_dir(dir.path());
+    let mut data = std::fs::read(&path).unwrap();
+    let header_len = Log::<MemtableId>::HEADER_SIZE;
+    data[4..6].copy_from_slice(&u16::MAX.to_be_bytes());
+    let crc = crc32fast::hash(&data[..header_len - 4]);
+    data[header_len - 4..header_len].copy_from_slice(&crc.to_be_bytes());
+    std::fs::write(&path, data).unwrap();
+    let mut kms = MorsKmsBuilder::new(vec![1; 32]);
+    kms.set_dir(dir.path().to_path_buf());
+    let mut builder = <MmapFile as StorageTrait>::StorageBuilder::default();
+    builder.read(true).write(true);
+    let result = Log::open(
+        MemtableId::from(1),
+        &path,
+        1 << 16,
+        builder,
+        kms.build().unwrap(),
+    );
+    assert!(matches!(
+        result,
+        Err(MorsWalError::UnsupportedLogFormat(_, u16::MAX))
+    ));
+}
+#[test]
+fn test_incomplete_v0_txn() {
+    // a crash before the FIN_TXN entry was written.
+    let dir = copy_corpus("v0/plain");
+    let mut log = open(dir.path(), MemtableId::from(1), &[]);
+    let header_len = log.header_len();
+    let mut iter = LogFileIter::new(&mut log, header_len);
+    let mut ends = Vec::new();
+    while let Ok((_, vptr)) = iter.read_entry() {
+        ends.push((vptr.offset() + vptr.size() as u64) as usize);
+    }
+    drop(log);
+    let path = MemtableId::from(1).join_dir(dir.path());
+    let data = std::fs::read(&path).unwrap();
+    std::fs::write(&path, &data[..ends[ends.len() - 2]]).unwrap();
+
+    let mut log = open(dir.path(), MemtableId::from(1), &[]);
+    let mut iter = LogFileIter::new(&mut log, header_len);
+    let mut read = 0;
+    while let Some(entries) = iter.next_entry().unwrap() {
+        read += entries.len();
+    }
+    assert_eq!(read, 4);
+    assert_eq!(iter.discarded_entries(), 2);
+    assert_eq!(iter.valid_end_offset(), ends[3]);
+}